@@ -0,0 +1,2 @@
+-- Thêm cột color vào bảng todos (màu để phân loại trên giao diện: #rrggbb hoặc tên trong bảng màu)
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS color TEXT DEFAULT NULL;
@@ -0,0 +1,2 @@
+-- Thêm cột completion_note vào bảng todos (ghi chú cách todo được hoàn thành, xóa khi mở lại)
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS completion_note TEXT DEFAULT NULL;
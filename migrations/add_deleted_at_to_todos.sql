@@ -0,0 +1,4 @@
+-- Thêm cột deleted_at vào bảng todos (xóa mềm, job dọn dẹp xóa hẳn sau thời gian lưu giữ)
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP WITH TIME ZONE DEFAULT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_todos_deleted_at ON todos (deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,2 @@
+-- Thêm cột email_changed_at vào bảng users (giới hạn tần suất đổi email)
+ALTER TABLE users ADD COLUMN IF NOT EXISTS email_changed_at TIMESTAMP WITH TIME ZONE DEFAULT NULL;
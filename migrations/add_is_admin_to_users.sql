@@ -0,0 +1,3 @@
+-- Thêm cột is_admin vào bảng users (quyền truy cập các endpoint /admin)
+ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS is_admin BOOLEAN NOT NULL DEFAULT FALSE;
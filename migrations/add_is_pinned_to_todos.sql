@@ -0,0 +1,2 @@
+-- Thêm cột is_pinned vào bảng todos (todo được ghim luôn đứng đầu danh sách)
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS is_pinned BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,5 @@
+-- Thêm cột position vào bảng todos (thứ tự do user tự sắp, nhỏ hơn đứng trước)
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS position BIGINT NOT NULL DEFAULT 0;
+
+-- Đưa lên đầu/xuống cuối cần MIN/MAX position của từng user
+CREATE INDEX IF NOT EXISTS idx_todos_owner_position ON todos (owner_id, position);
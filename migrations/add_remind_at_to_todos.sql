@@ -0,0 +1,7 @@
+-- Thêm cột remind_at và reminded_at vào bảng todos (job nhắc việc gửi nhắc một lần khi tới remind_at)
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS remind_at TIMESTAMP WITH TIME ZONE DEFAULT NULL;
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS reminded_at TIMESTAMP WITH TIME ZONE DEFAULT NULL;
+
+-- Job chỉ quét các nhắc việc chưa gửi
+CREATE INDEX IF NOT EXISTS idx_todos_pending_reminders ON todos (remind_at)
+    WHERE reminded_at IS NULL AND deleted_at IS NULL;
@@ -0,0 +1,2 @@
+-- Thêm cột snoozed_until vào bảng todos (todo bị ẩn khỏi danh sách tới thời điểm này)
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS snoozed_until TIMESTAMP WITH TIME ZONE DEFAULT NULL;
@@ -0,0 +1,4 @@
+-- Thêm cột tags vào bảng todos
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS tags TEXT[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX IF NOT EXISTS idx_todos_tags ON todos USING GIN (tags);
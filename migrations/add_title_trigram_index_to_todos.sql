@@ -0,0 +1,4 @@
+-- Thêm chỉ mục trigram cho cột title vào bảng todos (autocomplete /todos/suggest)
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS idx_todos_title_trgm ON todos USING GIN (title gin_trgm_ops);
@@ -0,0 +1,2 @@
+-- Thêm chỉ mục cho thùng rác của từng user (liệt kê todo đã xóa mềm, mới xóa trước)
+CREATE INDEX IF NOT EXISTS idx_todos_owner_deleted_at ON todos (owner_id, deleted_at DESC) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,2 @@
+-- Thêm cột two_factor_pending_secret vào bảng users
+ALTER TABLE users ADD COLUMN IF NOT EXISTS two_factor_pending_secret TEXT DEFAULT NULL;
@@ -0,0 +1,2 @@
+-- Thêm cột two_factor_pending_since vào bảng users
+ALTER TABLE users ADD COLUMN IF NOT EXISTS two_factor_pending_since TIMESTAMP WITH TIME ZONE DEFAULT NULL;
@@ -0,0 +1,3 @@
+-- Tùy chọn: chỉ chạy khi bật UNIQUE_TODO_TITLES, chặn hai todo chưa xóa cùng tiêu đề của một người dùng
+-- Các tiêu đề đang trùng phải được sửa trước, nếu không lệnh tạo chỉ mục sẽ thất bại
+CREATE UNIQUE INDEX IF NOT EXISTS idx_todos_owner_title_unique ON todos (owner_id, lower(title)) WHERE deleted_at IS NULL;
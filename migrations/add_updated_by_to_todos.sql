@@ -0,0 +1,2 @@
+-- Thêm cột updated_by vào bảng todos (user sửa todo lần cuối, NULL nghĩa là chủ sở hữu)
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS updated_by VARCHAR(255) DEFAULT NULL;
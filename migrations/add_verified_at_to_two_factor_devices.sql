@@ -0,0 +1,4 @@
+-- Thêm cột verified_at vào bảng two_factor_devices (NULL khi thiết bị chưa được xác minh)
+-- Các thiết bị thêm trước đó vẫn hoạt động nên được coi là đã xác minh
+ALTER TABLE two_factor_devices ADD COLUMN IF NOT EXISTS verified_at TIMESTAMP WITH TIME ZONE DEFAULT now();
+ALTER TABLE two_factor_devices ALTER COLUMN verified_at SET DEFAULT NULL;
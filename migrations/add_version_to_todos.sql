@@ -0,0 +1,2 @@
+-- Thêm cột version vào bảng todos (tăng mỗi lần todo thay đổi, dùng làm khóa cache từng todo)
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 1;
@@ -0,0 +1,20 @@
+-- Tạo bảng backup_code_sets: mỗi lần tạo mã backup là một bộ riêng, thu hồi được từng bộ
+CREATE TABLE IF NOT EXISTS backup_code_sets
+(
+    id         SERIAL PRIMARY KEY,
+    uuid       VARCHAR(255) UNIQUE      NOT NULL,
+    user_id    VARCHAR(255)             NOT NULL,
+    codes      TEXT[]                   NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    CONSTRAINT fk_user FOREIGN KEY (user_id) REFERENCES users (uuid) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_backup_code_sets_user_id ON backup_code_sets (user_id);
+
+-- Chuyển mã backup đang lưu trong users thành bộ đầu tiên của mỗi user
+INSERT INTO backup_code_sets (uuid, user_id, codes, created_at)
+SELECT gen_random_uuid()::TEXT, uuid, backup_codes, updated_at
+FROM users
+WHERE cardinality(backup_codes) > 0;
+
+UPDATE users SET backup_codes = NULL WHERE backup_codes IS NOT NULL;
@@ -0,0 +1,11 @@
+-- Tạo bảng password_history (các hash mật khẩu cũ, dùng để chặn dùng lại mật khẩu)
+CREATE TABLE IF NOT EXISTS password_history
+(
+    id            SERIAL PRIMARY KEY,
+    user_id       VARCHAR(255)             NOT NULL,
+    password_hash VARCHAR(255)             NOT NULL,
+    created_at    TIMESTAMP WITH TIME ZONE NOT NULL,
+    CONSTRAINT fk_user FOREIGN KEY (user_id) REFERENCES users (uuid) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_password_history_user_id ON password_history (user_id, created_at);
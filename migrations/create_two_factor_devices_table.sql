@@ -0,0 +1,13 @@
+-- Tạo bảng two_factor_devices cho các thiết bị xác thực bổ sung
+CREATE TABLE IF NOT EXISTS two_factor_devices
+(
+    id         SERIAL PRIMARY KEY,
+    uuid       VARCHAR(255) UNIQUE      NOT NULL,
+    user_id    VARCHAR(255)             NOT NULL,
+    name       VARCHAR(50)              NOT NULL,
+    secret     TEXT                     NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    CONSTRAINT fk_user FOREIGN KEY (user_id) REFERENCES users (uuid) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_two_factor_devices_user_id ON two_factor_devices (user_id);
@@ -0,0 +1,10 @@
+-- Tạo bảng user_preferences (sắp xếp và kích thước trang mặc định cho danh sách todo)
+CREATE TABLE IF NOT EXISTS user_preferences
+(
+    user_id    VARCHAR(255) PRIMARY KEY,
+    sort_by    VARCHAR(50)              DEFAULT NULL,
+    sort_order VARCHAR(4)               DEFAULT NULL,
+    page_size  BIGINT                   DEFAULT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    CONSTRAINT fk_user FOREIGN KEY (user_id) REFERENCES users (uuid) ON DELETE CASCADE
+);
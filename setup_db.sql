@@ -10,7 +10,10 @@ CREATE TABLE IF NOT EXISTS users
     password           VARCHAR(255)             NOT NULL,
     two_factor_enabled BOOLEAN                  NOT NULL DEFAULT FALSE,
     two_factor_secret  TEXT,
-    backup_codes       TEXT[]                            DEFAULT NULL,
+    two_factor_pending_secret TEXT                     DEFAULT NULL,
+    two_factor_pending_since  TIMESTAMP WITH TIME ZONE DEFAULT NULL,
+    is_admin           BOOLEAN                  NOT NULL DEFAULT FALSE,
+    email_changed_at   TIMESTAMP WITH TIME ZONE          DEFAULT NULL,
     created_at         TIMESTAMP WITH TIME ZONE NOT NULL,
     updated_at         TIMESTAMP WITH TIME ZONE NOT NULL
 );
@@ -26,5 +29,85 @@ CREATE TABLE IF NOT EXISTS todos
     owner_id     VARCHAR(255)             NOT NULL,
     created_at   TIMESTAMP WITH TIME ZONE NOT NULL,
     updated_at   TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_by   VARCHAR(255)                      DEFAULT NULL,
+    tags         TEXT[]                   NOT NULL DEFAULT '{}',
+    is_pinned    BOOLEAN                  NOT NULL DEFAULT FALSE,
+    completion_note TEXT                           DEFAULT NULL,
+    version      BIGINT                   NOT NULL DEFAULT 1,
+    snoozed_until TIMESTAMP WITH TIME ZONE         DEFAULT NULL,
+    remind_at    TIMESTAMP WITH TIME ZONE          DEFAULT NULL,
+    reminded_at  TIMESTAMP WITH TIME ZONE          DEFAULT NULL,
+    position     BIGINT                   NOT NULL DEFAULT 0,
+    color        TEXT                              DEFAULT NULL,
+    deleted_at   TIMESTAMP WITH TIME ZONE          DEFAULT NULL,
+    search_vector TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('english', coalesce(title, '')), 'A') ||
+        setweight(to_tsvector('english', coalesce(description, '')), 'B')
+    ) STORED,
     CONSTRAINT fk_owner FOREIGN KEY (owner_id) REFERENCES users (uuid)
-); 
\ No newline at end of file
+);
+
+CREATE INDEX IF NOT EXISTS idx_todos_search_vector ON todos USING GIN (search_vector);
+
+CREATE INDEX IF NOT EXISTS idx_todos_tags ON todos USING GIN (tags);
+
+CREATE INDEX IF NOT EXISTS idx_todos_deleted_at ON todos (deleted_at) WHERE deleted_at IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_todos_owner_deleted_at ON todos (owner_id, deleted_at DESC) WHERE deleted_at IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_todos_owner_position ON todos (owner_id, position);
+
+CREATE INDEX IF NOT EXISTS idx_todos_pending_reminders ON todos (remind_at)
+    WHERE reminded_at IS NULL AND deleted_at IS NULL;
+
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE INDEX IF NOT EXISTS idx_todos_title_trgm ON todos USING GIN (title gin_trgm_ops);
+
+-- Create two_factor_devices table if not exists
+CREATE TABLE IF NOT EXISTS two_factor_devices
+(
+    id          SERIAL PRIMARY KEY,
+    uuid        VARCHAR(255) UNIQUE      NOT NULL,
+    user_id     VARCHAR(255)             NOT NULL,
+    name        VARCHAR(50)              NOT NULL,
+    secret      TEXT                     NOT NULL,
+    created_at  TIMESTAMP WITH TIME ZONE NOT NULL,
+    verified_at TIMESTAMP WITH TIME ZONE DEFAULT NULL,
+    CONSTRAINT fk_user FOREIGN KEY (user_id) REFERENCES users (uuid) ON DELETE CASCADE
+);
+
+-- Create backup_code_sets table if not exists
+CREATE TABLE IF NOT EXISTS backup_code_sets
+(
+    id         SERIAL PRIMARY KEY,
+    uuid       VARCHAR(255) UNIQUE      NOT NULL,
+    user_id    VARCHAR(255)             NOT NULL,
+    codes      TEXT[]                   NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    CONSTRAINT fk_user FOREIGN KEY (user_id) REFERENCES users (uuid) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_backup_code_sets_user_id ON backup_code_sets (user_id);
+
+-- Create user_preferences table if not exists
+CREATE TABLE IF NOT EXISTS user_preferences
+(
+    user_id    VARCHAR(255) PRIMARY KEY,
+    sort_by    VARCHAR(50)              DEFAULT NULL,
+    sort_order VARCHAR(4)               DEFAULT NULL,
+    page_size  BIGINT                   DEFAULT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    CONSTRAINT fk_user FOREIGN KEY (user_id) REFERENCES users (uuid) ON DELETE CASCADE
+);
+
+-- Create password_history table if not exists
+CREATE TABLE IF NOT EXISTS password_history
+(
+    id            SERIAL PRIMARY KEY,
+    user_id       VARCHAR(255)             NOT NULL,
+    password_hash VARCHAR(255)             NOT NULL,
+    created_at    TIMESTAMP WITH TIME ZONE NOT NULL,
+    CONSTRAINT fk_user FOREIGN KEY (user_id) REFERENCES users (uuid) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_password_history_user_id ON password_history (user_id, created_at);
@@ -0,0 +1,283 @@
+use super::features::Features;
+use super::limits::Limits;
+use crate::error::AppError;
+use crate::services::maintenance_service::MaintenanceMode;
+use crate::services::password_service::{PasswordAlgorithm, DEFAULT_BCRYPT_COST};
+use crate::services::reminder_service::ReminderChannel;
+use crate::services::self_check_service::SelfCheckMode;
+use actix_web::http::Method;
+use ipnet::IpNet;
+use log::warn;
+use std::env;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+const DEFAULT_TODO_COLOR_PALETTE: &str = "red,orange,yellow,green,blue,purple,pink,gray";
+
+/// Runtime settings read from the environment once at startup
+#[derive(Clone, Debug)]
+pub struct AppConfig {
+    /// CIDR ranges allowed to reach the API, empty means allow all
+    pub ip_allowlist: Vec<IpNet>,
+    /// Proxies whose X-Forwarded-For header we trust
+    pub trusted_proxies: Vec<IpNet>,
+    /// max-age for Cache-Control on cacheable reads, defaults to the Redis TTL
+    pub cache_max_age_seconds: u64,
+    /// Algorithm for new password hashes; existing hashes are upgraded on login
+    pub password_algorithm: PasswordAlgorithm,
+    /// Only rotate refresh tokens this close to expiry; `None` rotates on every refresh
+    pub refresh_rotation_window_seconds: Option<u64>,
+    /// Refresh tokens one user may hold, the oldest is revoked on the next login; 0 disables the cap
+    pub max_sessions_per_user: usize,
+    /// Sign a session out after this long without an authenticated request, `None` disables it
+    pub idle_session_timeout_seconds: Option<u64>,
+    /// Methods allowed in CORS preflight responses
+    pub cors_allowed_methods: Vec<Method>,
+    /// How long browsers may cache a CORS preflight response
+    pub cors_max_age_seconds: usize,
+    /// Todo requests one user may have in flight on this instance, 0 disables the limit
+    pub max_concurrent_requests_per_user: usize,
+    /// Identical todo writes from one user within this window get the first one's response,
+    /// `None` disables deduplication
+    pub request_dedup_window_seconds: Option<u64>,
+    /// Previous passwords a user may not reuse, 0 disables the check
+    pub password_history_size: usize,
+    /// Drop null fields from responses unless the client sends `X-Omit-Nulls: false`
+    pub omit_null_fields: bool,
+    /// Shared secret required for the detailed /health fields, `None` keeps them public
+    pub health_token: Option<String>,
+    /// DB operations per request above which debug builds log an N+1 warning
+    pub db_query_warn_threshold: u32,
+    /// Log redacted request/response bodies at debug level; ignored in release builds
+    pub body_log_enabled: bool,
+    /// Path prefixes whose bodies are logged, empty logs every /api route
+    pub body_log_routes: Vec<String>,
+    /// Logged bodies are cut to this many bytes
+    pub body_log_max_bytes: usize,
+    /// Record DB query durations and serve them on /metrics
+    pub metrics_enabled: bool,
+    /// Send a `Server-Timing` header with each /api response's DB, cache and total time
+    pub server_timing_enabled: bool,
+    /// Maintenance mode to start in; the admin endpoint overrides it at runtime via Redis
+    pub maintenance_mode: MaintenanceMode,
+    /// Retry-After sent with maintenance 503s
+    pub maintenance_retry_after_seconds: u64,
+    /// Redis stream domain events are published to, `None` disables publishing
+    pub events_stream: Option<String>,
+    /// Approximate number of entries kept in the events stream
+    pub events_stream_max_len: usize,
+    /// Days deleted todos stay in the trash before being purged, 0 disables the purge job
+    pub trash_retention_days: u64,
+    /// How often the purge job runs
+    pub trash_purge_interval_seconds: u64,
+    /// Rows hard-deleted per statement, the job pauses between batches
+    pub trash_purge_batch_size: i64,
+    /// Where due reminders are sent, `Off` disables the reminder job
+    pub reminder_channel: ReminderChannel,
+    /// How often the reminder job looks for due todos
+    pub reminder_scan_interval_seconds: u64,
+    /// Reminders claimed per statement
+    pub reminder_batch_size: i64,
+    /// Issuer shown next to the account in authenticator apps
+    pub totp_issuer: String,
+    /// Digits per authenticator code (6-8); apps enrolled under another value stop matching
+    pub totp_digits: usize,
+    /// Minimum time between two email changes on the same account, 0 disables the cooldown
+    pub email_change_cooldown_seconds: u64,
+    /// Input length, page size and batch caps enforced by every endpoint
+    pub limits: Limits,
+    /// What a failed startup self-check does: log only, refuse to start, or skip the check
+    pub self_check_mode: SelfCheckMode,
+    /// Admins without 2FA are refused by admin endpoints until they enroll
+    pub require_admin_2fa: bool,
+    /// Reject a new todo whose title matches one of the user's todos, ignoring case
+    pub unique_todo_titles: bool,
+    /// Color names a todo may use besides `#rrggbb`, lowercase
+    pub todo_color_palette: Vec<String>,
+    /// Endpoints switched off with `FEATURE_<NAME>=false`
+    pub features: Features,
+}
+
+impl AppConfig {
+    pub fn from_env() -> Self {
+        Self {
+            ip_allowlist: parse_cidr_list("IP_ALLOWLIST"),
+            trusted_proxies: parse_cidr_list("TRUSTED_PROXIES"),
+            cache_max_age_seconds: parse_or("CACHE_MAX_AGE_SECONDS", 300),
+            password_algorithm: parse_password_algorithm(),
+            refresh_rotation_window_seconds: Some(parse_or(
+                "REFRESH_ROTATION_WINDOW_SECONDS",
+                0u64,
+            ))
+            .filter(|&seconds| seconds > 0),
+            cors_allowed_methods: parse_methods(
+                "CORS_ALLOWED_METHODS",
+                "GET,POST,PUT,PATCH,DELETE,OPTIONS",
+            ),
+            max_sessions_per_user: parse_or("MAX_SESSIONS_PER_USER", 0),
+            idle_session_timeout_seconds: Some(parse_or("IDLE_SESSION_TIMEOUT_SECONDS", 0u64))
+                .filter(|&seconds| seconds > 0),
+            cors_max_age_seconds: parse_or("CORS_MAX_AGE_SECONDS", 3600),
+            max_concurrent_requests_per_user: parse_or("MAX_CONCURRENT_REQUESTS_PER_USER", 0),
+            request_dedup_window_seconds: Some(parse_or("REQUEST_DEDUP_WINDOW_SECONDS", 0u64))
+                .filter(|&seconds| seconds > 0),
+            password_history_size: parse_or("PASSWORD_HISTORY_SIZE", 0),
+            omit_null_fields: parse_or("OMIT_NULL_FIELDS", false),
+            health_token: env::var("HEALTH_TOKEN")
+                .ok()
+                .map(|token| token.trim().to_string())
+                .filter(|token| !token.is_empty()),
+            db_query_warn_threshold: parse_or("DB_QUERY_WARN_THRESHOLD", 10),
+            body_log_enabled: parse_or("BODY_LOG_ENABLED", false),
+            body_log_routes: env::var("BODY_LOG_ROUTES")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|route| !route.is_empty())
+                .map(str::to_string)
+                .collect(),
+            body_log_max_bytes: parse_or("BODY_LOG_MAX_BYTES", 4096).max(1),
+            metrics_enabled: parse_or("METRICS_ENABLED", false),
+            server_timing_enabled: parse_or("SERVER_TIMING_ENABLED", false),
+            maintenance_mode: parse_maintenance_mode(),
+            maintenance_retry_after_seconds: parse_or("MAINTENANCE_RETRY_AFTER_SECONDS", 300),
+            events_stream: env::var("EVENTS_REDIS_STREAM")
+                .ok()
+                .map(|stream| stream.trim().to_string())
+                .filter(|stream| !stream.is_empty()),
+            events_stream_max_len: parse_or("EVENTS_STREAM_MAX_LEN", 100_000),
+            trash_retention_days: parse_or("TRASH_RETENTION_DAYS", 30),
+            trash_purge_interval_seconds: parse_or("TRASH_PURGE_INTERVAL_SECONDS", 3600).max(1),
+            trash_purge_batch_size: parse_or("TRASH_PURGE_BATCH_SIZE", 500).max(1),
+            reminder_channel: parse_reminder_channel(),
+            reminder_scan_interval_seconds: parse_or("REMINDER_SCAN_INTERVAL_SECONDS", 60).max(1),
+            reminder_batch_size: parse_or("REMINDER_BATCH_SIZE", 100).max(1),
+            totp_issuer: env::var("TOTP_ISSUER")
+                .ok()
+                .map(|issuer| issuer.trim().to_string())
+                .filter(|issuer| !issuer.is_empty())
+                .unwrap_or_else(|| "Todo App".to_string()),
+            totp_digits: parse_or("TOTP_DIGITS", 6).clamp(6, 8),
+            email_change_cooldown_seconds: parse_or("EMAIL_CHANGE_COOLDOWN_SECONDS", 86_400),
+            limits: Limits::from_env(),
+            self_check_mode: parse_self_check_mode(),
+            require_admin_2fa: parse_or("REQUIRE_ADMIN_2FA", false),
+            unique_todo_titles: parse_or("UNIQUE_TODO_TITLES", false),
+            todo_color_palette: env::var("TODO_COLOR_PALETTE")
+                .unwrap_or_else(|_| DEFAULT_TODO_COLOR_PALETTE.to_string())
+                .split(',')
+                .map(|name| name.trim().to_lowercase())
+                .filter(|name| !name.is_empty())
+                .collect(),
+            features: Features::from_env(),
+        }
+    }
+}
+
+impl AppConfig {
+    /// Normalizes a todo color to lowercase, accepting `#rrggbb` or a palette name;
+    /// an empty string is passed through, it means no color
+    pub fn check_todo_color(&self, color: &str) -> Result<String, AppError> {
+        let color = color.trim().to_lowercase();
+        let is_hex = color.len() == 7
+            && color.starts_with('#')
+            && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+
+        if color.is_empty() || is_hex || self.todo_color_palette.contains(&color) {
+            Ok(color)
+        } else {
+            Err(AppError::bad_request(format!(
+                "color must be a hex color like #1e90ff or one of: {}",
+                self.todo_color_palette.join(", ")
+            )))
+        }
+    }
+}
+
+fn parse_password_algorithm() -> PasswordAlgorithm {
+    let bcrypt_cost = parse_or("BCRYPT_COST", DEFAULT_BCRYPT_COST).clamp(4, 31);
+    let default = PasswordAlgorithm::Bcrypt { cost: bcrypt_cost };
+
+    match env::var("PASSWORD_HASH_ALGORITHM") {
+        Ok(name) => PasswordAlgorithm::from_name(&name, bcrypt_cost).unwrap_or_else(|| {
+            warn!("Unknown PASSWORD_HASH_ALGORITHM '{}', using bcrypt", name);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+fn parse_maintenance_mode() -> MaintenanceMode {
+    match env::var("MAINTENANCE_MODE") {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            warn!("Unknown MAINTENANCE_MODE '{}', using off", value);
+            MaintenanceMode::Off
+        }),
+        Err(_) => MaintenanceMode::Off,
+    }
+}
+
+fn parse_reminder_channel() -> ReminderChannel {
+    match env::var("REMINDER_CHANNEL") {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            warn!("Unknown REMINDER_CHANNEL '{}', using off", value);
+            ReminderChannel::Off
+        }),
+        Err(_) => ReminderChannel::Off,
+    }
+}
+
+fn parse_self_check_mode() -> SelfCheckMode {
+    match env::var("SELF_CHECK_MODE") {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            warn!("Unknown SELF_CHECK_MODE '{}', using warn", value);
+            SelfCheckMode::Warn
+        }),
+        Err(_) => SelfCheckMode::Warn,
+    }
+}
+
+/// Reads an env var, falling back to the default when unset or unparsable
+pub(super) fn parse_or<T: FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+/// Parses a comma separated list of HTTP methods, panicking on an unknown one
+fn parse_methods(key: &str, default: &str) -> Vec<Method> {
+    let value = env::var(key).unwrap_or_else(|_| default.to_string());
+
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            Method::from_str(&entry.to_uppercase())
+                .unwrap_or_else(|_| panic!("Invalid HTTP method '{}' in {}", entry, key))
+        })
+        .collect()
+}
+
+/// Parses a comma separated list of CIDRs (or bare IPs) from an env var
+///
+/// Panics on an invalid entry so a typo can't silently widen access.
+fn parse_cidr_list(key: &str) -> Vec<IpNet> {
+    let value = match env::var(key) {
+        Ok(value) => value,
+        Err(_) => return Vec::new(),
+    };
+
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                .unwrap_or_else(|_| panic!("Invalid CIDR '{}' in {}", entry, key))
+        })
+        .collect()
+}
@@ -0,0 +1,54 @@
+use super::app_config::parse_or;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::env;
+
+/// `POST /register`, off for invite-only instances
+pub const REGISTRATION: &str = "registration";
+/// `POST /todos/import`
+pub const TODO_IMPORT: &str = "todo_import";
+/// `GET /todos/search`
+pub const TODO_SEARCH: &str = "todo_search";
+
+const KNOWN_FEATURES: &[&str] = &[REGISTRATION, TODO_IMPORT, TODO_SEARCH];
+
+/// Endpoints that can be switched off per deployment with `FEATURE_<NAME>=false`
+///
+/// Checked when routes are registered, so a change needs a restart.
+#[derive(Clone, Debug)]
+pub struct Features {
+    flags: HashMap<&'static str, bool>,
+}
+
+impl Features {
+    pub fn from_env() -> Self {
+        let flags: HashMap<&'static str, bool> = KNOWN_FEATURES
+            .iter()
+            .map(|&name| (name, parse_or(&env_key(name), true)))
+            .collect();
+
+        for (key, _) in env::vars() {
+            if key.starts_with("FEATURE_")
+                && !KNOWN_FEATURES.iter().any(|&name| env_key(name) == key)
+            {
+                warn!("Unknown feature flag {}, ignoring it", key);
+            }
+        }
+        for name in KNOWN_FEATURES {
+            if !flags[name] {
+                info!("Feature {} disabled", name);
+            }
+        }
+
+        Self { flags }
+    }
+
+    /// Unknown names count as enabled
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.flags.get(name).copied().unwrap_or(true)
+    }
+}
+
+fn env_key(name: &str) -> String {
+    format!("FEATURE_{}", name.to_uppercase())
+}
@@ -0,0 +1,100 @@
+use super::app_config::parse_or;
+use crate::error::AppError;
+
+/// Size limits shared by every endpoint so create, update, list and batch can't disagree
+#[derive(Clone, Debug)]
+pub struct Limits {
+    pub max_title_length: usize,
+    pub max_description_length: usize,
+    pub max_page_size: i64,
+    /// Page size used when neither the request nor the user's preferences set one
+    pub default_page_size: i64,
+    /// Most todos a single bulk request may touch
+    pub max_batch_size: usize,
+    pub max_search_length: usize,
+    pub max_completion_note_length: usize,
+    /// Most rows a single import may create
+    pub max_import_rows: usize,
+}
+
+impl Limits {
+    pub fn from_env() -> Self {
+        let max_page_size = parse_or("MAX_PAGE_SIZE", 100).max(1);
+        Self {
+            max_title_length: parse_or("MAX_TITLE_LENGTH", 200).max(1),
+            max_description_length: parse_or("MAX_DESCRIPTION_LENGTH", 10_000).max(1),
+            max_page_size,
+            default_page_size: parse_or("DEFAULT_PAGE_SIZE", 10).clamp(1, max_page_size),
+            max_batch_size: parse_or("MAX_BATCH_SIZE", 100).max(1),
+            max_search_length: parse_or("MAX_SEARCH_LENGTH", 200).max(1),
+            max_completion_note_length: parse_or("MAX_COMPLETION_NOTE_LENGTH", 1000).max(1),
+            max_import_rows: parse_or("MAX_IMPORT_ROWS", 1000).max(1),
+        }
+    }
+
+    pub fn check_title(&self, title: &str) -> Result<(), AppError> {
+        check_length("title", title, self.max_title_length)
+    }
+
+    pub fn check_description(&self, description: &str) -> Result<(), AppError> {
+        check_length("description", description, self.max_description_length)
+    }
+
+    pub fn check_search(&self, search: &str) -> Result<(), AppError> {
+        check_length("search query", search, self.max_search_length)
+    }
+
+    pub fn check_completion_note(&self, note: &str) -> Result<(), AppError> {
+        check_length("completion note", note, self.max_completion_note_length)
+    }
+
+    /// `None` is fine, the caller falls back to a default within the limit
+    pub fn check_page_size(&self, page_size: Option<i64>) -> Result<(), AppError> {
+        match page_size {
+            Some(size) if size < 1 || size > self.max_page_size => {
+                Err(AppError::bad_request(format!(
+                    "page_size must be between 1 and {} (max page size)",
+                    self.max_page_size
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The page size actually served, a stored preference can predate a lowered maximum
+    pub fn page_size(&self, requested: Option<i64>) -> i64 {
+        requested
+            .unwrap_or(self.default_page_size)
+            .clamp(1, self.max_page_size)
+    }
+
+    pub fn check_batch_size(&self, count: usize) -> Result<(), AppError> {
+        if count > self.max_batch_size {
+            return Err(AppError::bad_request(format!(
+                "batch of {} todos exceeds the limit of {} (max batch size)",
+                count, self.max_batch_size
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn check_import_rows(&self, count: usize) -> Result<(), AppError> {
+        if count > self.max_import_rows {
+            return Err(AppError::bad_request(format!(
+                "import of {} rows exceeds the limit of {} (max import rows)",
+                count, self.max_import_rows
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn check_length(field: &str, value: &str, max: usize) -> Result<(), AppError> {
+    if value.chars().count() > max {
+        return Err(AppError::bad_request(format!(
+            "{} exceeds the limit of {} characters",
+            field, max
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,6 @@
+pub mod app_config;
+pub mod features;
+pub mod limits;
+
+pub use app_config::AppConfig;
+pub use features::Features;
@@ -1,14 +1,90 @@
+use crate::config::limits::Limits;
+use crate::db::data_trait::user_data_trait::UserData;
 use crate::db::database::Database;
+use crate::db::paginated_query::{total_pages, PaginatedQuery};
+use crate::error::app_error::field_error;
 use crate::error::AppError;
 use crate::models::todo::{
-    CreateTodoRequest, DeleteTodoResponse, PaginationParams, Todo, TodoFilter, TodoResponse,
-    TodoResponseList,
+    Agenda, CreateTodoRequest, DeleteTodoResponse, ExportedTodo, GroupedTodos, ImportedTodo,
+    MovePosition, PaginationParams, PossibleDuplicate, SortField, SortOrder, Todo, TodoCalendar,
+    TodoFilter, TodoResponse, TodoResponseList, TodoSearchResponse, TodoSearchResult,
+    TransitionFilter, TrashedTodo, TrashedTodoList, UpdateTodoRequest,
 };
+use crate::models::user::UserPreferences;
+use crate::services::metrics_service::QueryTimer;
 use async_trait::async_trait;
-use chrono::Utc;
-use sqlx::Row;
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::postgres::PgRow;
+use sqlx::{PgPool, Row};
 use uuid::Uuid;
 
+const TODO_COLUMNS: &str = "uuid, title, description, is_completed, owner_id, created_at, updated_at, tags, is_pinned, completion_note, version, snoozed_until, remind_at, position, color, updated_by";
+
+/// Tên chỉ mục trong migrations/add_unique_title_index_to_todos.sql
+const UNIQUE_TITLE_INDEX: &str = "idx_todos_owner_title_unique";
+/// Độ tương đồng trigram tối thiểu để hai tiêu đề được coi là có thể trùng
+const DUPLICATE_SIMILARITY_THRESHOLD: f32 = 0.5;
+const DUPLICATE_TITLE_MESSAGE: &str = "A todo with this title already exists";
+
+/// 409 kèm id của todo đã có để client gợi ý mở todo đó
+fn duplicate_title_error(existing_id: String) -> AppError {
+    AppError::conflict(DUPLICATE_TITLE_MESSAGE)
+        .with_error_code("duplicate_title")
+        .with_existing_id(existing_id)
+}
+
+/// Lỗi do chỉ mục tiêu đề duy nhất, chỉ xảy ra khi đã áp dụng migration của UNIQUE_TODO_TITLES
+fn is_duplicate_title(error: &sqlx::Error) -> bool {
+    matches!(error, sqlx::Error::Database(e) if e.constraint() == Some(UNIQUE_TITLE_INDEX))
+}
+
+/// 409 cho một câu lệnh bị chỉ mục tiêu đề duy nhất chặn, kèm id của todo chưa xóa đầu tiên
+/// trùng một trong `titles`; không có id khi các tiêu đề chỉ trùng lẫn nhau
+async fn duplicate_title_conflict(pool: &PgPool, user_id: &str, titles: &[String]) -> AppError {
+    let existing_id: Result<Option<String>, sqlx::Error> = sqlx::query_scalar(
+        "SELECT uuid FROM todos \
+        WHERE owner_id = $1 AND deleted_at IS NULL \
+            AND lower(title) IN (SELECT lower(t) FROM unnest($2::TEXT[]) AS t) \
+        ORDER BY created_at LIMIT 1",
+    )
+    .bind(user_id)
+    .bind(titles)
+    .fetch_optional(pool)
+    .await;
+
+    match existing_id {
+        Ok(Some(existing_id)) => duplicate_title_error(existing_id),
+        Ok(None) => AppError::conflict(DUPLICATE_TITLE_MESSAGE).with_error_code("duplicate_title"),
+        Err(e) => {
+            eprintln!("Error looking up duplicate title: {:?}", e);
+            AppError::conflict(DUPLICATE_TITLE_MESSAGE).with_error_code("duplicate_title")
+        }
+    }
+}
+
+fn todo_from_row(row: &PgRow) -> Todo {
+    Todo {
+        tags: row.get("tags"),
+        is_pinned: row.get("is_pinned"),
+        completion_note: row.get("completion_note"),
+        version: row.get("version"),
+        snoozed_until: row.get("snoozed_until"),
+        remind_at: row.get("remind_at"),
+        position: row.get("position"),
+        color: row.get("color"),
+        updated_by: row.get("updated_by"),
+        ..Todo::new(
+            row.get("uuid"),
+            row.get("title"),
+            row.get("description"),
+            row.get("is_completed"),
+            row.get("owner_id"),
+            row.get("created_at"),
+            row.get("updated_at"),
+        )
+    }
+}
+
 #[async_trait]
 pub trait TodoData {
     async fn get_all_todos(
@@ -16,21 +92,224 @@ pub trait TodoData {
         user_id: String,
         pagination: PaginationParams,
         filter: TodoFilter,
+        limits: &Limits,
     ) -> Result<TodoResponseList, AppError>;
+    async fn count_todos(&self, user_id: String, filter: TodoFilter) -> Result<i64, AppError>;
     async fn get_one_todo(&self, todo_id: String) -> Result<TodoResponse, AppError>;
+    /// Todo chưa hoãn của user chia theo nhóm, mỗi nhóm tối đa `page_size`
+    async fn get_grouped_todos(
+        &self,
+        user_id: String,
+        page_size: i64,
+    ) -> Result<GroupedTodos, AppError>;
+    /// Todo chưa xong và chưa hoãn của user chia theo ngày đến hạn (`remind_at`) ở múi giờ `tz`
+    async fn get_agenda(
+        &self,
+        user_id: String,
+        tz: String,
+        page_size: i64,
+    ) -> Result<Agenda, AppError>;
+    /// Todo của user có `remind_at` trong tháng bắt đầu từ `month_start`, tính theo múi giờ `tz`
+    async fn get_calendar(
+        &self,
+        user_id: String,
+        month_start: NaiveDate,
+        tz: String,
+    ) -> Result<TodoCalendar, AppError>;
+    async fn search_todos(
+        &self,
+        user_id: String,
+        search: String,
+        page: i64,
+        page_size: i64,
+    ) -> Result<TodoSearchResponse, AppError>;
+    async fn suggest_todo_titles(
+        &self,
+        user_id: String,
+        prefix: String,
+        limit: i64,
+    ) -> Result<Vec<String>, AppError>;
+    /// Todo chưa xóa có tiêu đề gần giống `title` theo trigram, giống nhất trước
+    async fn find_similar_titles(
+        &self,
+        user_id: &str,
+        title: &str,
+        exclude_uuid: &str,
+        limit: i64,
+    ) -> Result<Vec<PossibleDuplicate>, AppError>;
+    /// `unique_title` từ chối tiêu đề trùng (không phân biệt hoa thường) với một todo chưa xóa
     async fn add_todo(
         &self,
         user_id: String,
         todo: CreateTodoRequest,
+        unique_title: bool,
     ) -> Result<TodoResponse, AppError>;
+    /// Todo chưa xóa có tiêu đề trùng, không phân biệt hoa thường
+    async fn find_todo_by_title(
+        &self,
+        user_id: &str,
+        title: &str,
+    ) -> Result<Option<String>, AppError>;
+    /// Thêm mọi todo trong một câu lệnh, hoặc tất cả hoặc không todo nào
+    async fn import_todos(
+        &self,
+        user_id: String,
+        todos: Vec<ImportedTodo>,
+    ) -> Result<Vec<Todo>, AppError>;
     async fn update_todo(
         &self,
+        user_id: String,
+        todo_uuid: String,
+        changes: UpdateTodoRequest,
+    ) -> Result<Todo, AppError>;
+    async fn bulk_update_tags(
+        &self,
+        user_id: String,
+        todo_uuids: Vec<String>,
+        add: Vec<String>,
+        remove: Vec<String>,
+    ) -> Result<Vec<Todo>, AppError>;
+    async fn set_todo_pinned(
+        &self,
+        user_id: String,
+        todo_uuid: String,
+        is_pinned: bool,
+    ) -> Result<Todo, AppError>;
+    /// `None` bỏ hoãn todo
+    async fn set_todo_snoozed(
+        &self,
+        user_id: String,
+        todo_uuid: String,
+        snoozed_until: Option<chrono::DateTime<Utc>>,
+    ) -> Result<Todo, AppError>;
+    /// Đặt lại thời điểm nhắc, `None` bỏ nhắc; todo được nhắc lại từ đầu
+    async fn set_todo_reminder(
+        &self,
+        user_id: String,
+        todo_uuid: String,
+        remind_at: Option<chrono::DateTime<Utc>>,
+    ) -> Result<Todo, AppError>;
+    /// Đánh dấu đã nhắc tối đa `batch_size` todo tới hạn nhắc và trả về chúng
+    async fn claim_due_reminders(&self, batch_size: i64) -> Result<Vec<Todo>, AppError>;
+    /// Đưa todo lên trước hoặc xuống sau mọi todo khác của user
+    async fn move_todo(
+        &self,
+        user_id: String,
         todo_uuid: String,
-        title: Option<String>,
-        description: Option<String>,
-        is_completed: Option<bool>,
+        position: MovePosition,
     ) -> Result<Todo, AppError>;
-    async fn delete_todo(&self, todo_uuid: String) -> Result<DeleteTodoResponse, AppError>;
+    /// Đổi trạng thái mọi todo của user khớp với filter, trả về các todo đã thay đổi
+    async fn transition_todos(
+        &self,
+        user_id: String,
+        filter: TransitionFilter,
+        is_completed: bool,
+    ) -> Result<Vec<Todo>, AppError>;
+    async fn delete_todo(
+        &self,
+        user_id: String,
+        todo_uuid: String,
+    ) -> Result<DeleteTodoResponse, AppError>;
+    async fn delete_todos(
+        &self,
+        user_id: String,
+        todo_uuids: Vec<String>,
+    ) -> Result<Vec<String>, AppError>;
+    /// Những uuid trong danh sách là todo chưa xóa của user, kiểm tra bằng một truy vấn
+    async fn filter_owned(
+        &self,
+        user_id: &str,
+        todo_uuids: &[String],
+    ) -> Result<Vec<String>, AppError>;
+    /// Một trang todo của user cho bản xuất dữ liệu, kể cả todo đã xóa, theo thứ tự uuid
+    /// sau `after_uuid`
+    async fn export_todos(
+        &self,
+        user_id: &str,
+        after_uuid: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<ExportedTodo>, AppError>;
+    /// Mọi tag user đã dùng, kể cả trên todo đã xóa, sắp xếp theo tên
+    async fn get_user_tags(&self, user_id: &str) -> Result<Vec<String>, AppError>;
+    /// Thùng rác của user, todo mới xóa nằm trước
+    async fn get_trashed_todos(
+        &self,
+        user_id: String,
+        page: i64,
+        page_size: i64,
+    ) -> Result<TrashedTodoList, AppError>;
+    /// Khôi phục mọi todo trong thùng rác của user
+    async fn restore_all_todos(&self, user_id: String) -> Result<Vec<Todo>, AppError>;
+    /// Xóa hẳn mọi todo trong thùng rác của user
+    async fn empty_trash(&self, user_id: String) -> Result<u64, AppError>;
+    /// Xóa hẳn tối đa `batch_size` todo đã bị xóa mềm trước `deleted_before`
+    async fn purge_deleted_todos(
+        &self,
+        deleted_before: chrono::DateTime<Utc>,
+        batch_size: i64,
+    ) -> Result<u64, AppError>;
+}
+
+// Ký tự điều khiển đánh dấu từ khớp trong ts_headline, đổi thành <mark> sau khi escape HTML
+const HIGHLIGHT_START: char = '\u{2}';
+const HIGHLIGHT_STOP: char = '\u{3}';
+const TITLE_HEADLINE_OPTIONS: &str = "StartSel=\"\u{2}\", StopSel=\"\u{3}\", HighlightAll=true";
+const SNIPPET_HEADLINE_OPTIONS: &str =
+    "StartSel=\"\u{2}\", StopSel=\"\u{3}\", MaxWords=35, MinWords=15, MaxFragments=2";
+
+/// Escape HTML trong kết quả ts_headline rồi chèn thẻ <mark> quanh từ khớp
+fn render_highlight(raw: &str) -> String {
+    let mut rendered = String::with_capacity(raw.len());
+    for c in raw.chars() {
+        match c {
+            HIGHLIGHT_START => rendered.push_str("<mark>"),
+            HIGHLIGHT_STOP => rendered.push_str("</mark>"),
+            '&' => rendered.push_str("&amp;"),
+            '<' => rendered.push_str("&lt;"),
+            '>' => rendered.push_str("&gt;"),
+            '"' => rendered.push_str("&quot;"),
+            '\'' => rendered.push_str("&#39;"),
+            _ => rendered.push(c),
+        }
+    }
+    rendered
+}
+
+/// Điều kiện lọc dùng chung cho danh sách, đếm và chuyển trạng thái todo
+///
+/// Trả về placeholder của từ khóa tìm kiếm, nếu có, để dùng lại khi xếp hạng.
+fn apply_filter(
+    query: &mut PaginatedQuery,
+    user_id: String,
+    search: Option<&str>,
+    is_completed: Option<bool>,
+    include_snoozed: bool,
+) -> Option<String> {
+    let owner = query.bind(user_id);
+    query
+        .condition(format!("owner_id = {}", owner))
+        .condition("deleted_at IS NULL");
+
+    let search_param = search.map(|search| {
+        let param = query.bind(search);
+        query.condition(format!(
+            "search_vector @@ websearch_to_tsquery('english', {})",
+            param
+        ));
+        param
+    });
+
+    if let Some(is_completed) = is_completed {
+        let param = query.bind(is_completed);
+        query.condition(format!("is_completed = {}", param));
+    }
+
+    // Todo đang được hoãn bị ẩn cho tới khi hết thời gian hoãn
+    if !include_snoozed {
+        query.condition("(snoozed_until IS NULL OR snoozed_until <= NOW())");
+    }
+
+    search_param
 }
 
 #[async_trait]
@@ -40,112 +319,178 @@ impl TodoData for Database {
         user_id: String,
         pagination: PaginationParams,
         filter: TodoFilter,
+        limits: &Limits,
     ) -> Result<TodoResponseList, AppError> {
-        let page = pagination.page.unwrap_or(1);
-        let page_size = pagination.page_size.unwrap_or(10);
-        let offset = (page - 1) * page_size;
-
-        let mut count_query = "SELECT COUNT(*) as total FROM todos WHERE owner_id = $1".to_string();
-        let mut query = "SELECT uuid, title, description, is_completed, owner_id, created_at, updated_at FROM todos WHERE owner_id = $1".to_string();
-
-        let mut params: Vec<String> = vec![user_id.clone()];
-        let mut param_index = 2; // Bắt đầu từ $2
-
-        if let Some(search) = filter.search {
-            let search_condition = format!(
-                " AND (title ILIKE ${} OR description ILIKE ${})",
-                param_index, param_index
-            );
-            count_query.push_str(&search_condition);
-            query.push_str(&search_condition);
-            params.push(format!("%{}%", search));
-            param_index += 1;
-        }
+        let _timer = QueryTimer::start("get_all_todos");
+        let search = filter.search.filter(|s| !s.trim().is_empty());
 
-        if let Some(is_completed) = filter.is_completed {
-            let completed_condition = format!(" AND is_completed = ${}", param_index);
-            count_query.push_str(&completed_condition);
-            query.push_str(&completed_condition);
-            params.push(is_completed.to_string());
-            param_index += 1;
+        // Cột sắp xếp đã được kiểm tra khi đọc tham số, chỉ còn relevance phụ thuộc vào search
+        if filter.sort_by == Some(SortField::Relevance) && search.is_none() {
+            return Err(AppError::validation(field_error(
+                "sort_by",
+                "sort_by=relevance needs a search",
+            )));
         }
 
-        let sort_by = filter.sort_by.unwrap_or_else(|| "created_at".to_string());
-        let sort_order = filter.sort_order.unwrap_or_else(|| "desc".to_string());
-
-        let valid_sort_columns = vec!["created_at", "updated_at", "title", "is_completed"];
-        let sort_by = if valid_sort_columns.contains(&sort_by.as_str()) {
-            sort_by
-        } else {
-            "created_at".to_string()
+        let truncate_description = match filter.truncate_description {
+            Some(max_chars) if max_chars < 1 => {
+                return Err(AppError::validation(field_error(
+                    "truncate_description",
+                    "truncate_description must be at least 1",
+                )))
+            }
+            max_chars => max_chars.map(|max_chars| max_chars as usize),
         };
 
-        let sort_order = if sort_order.to_lowercase() == "asc" {
-            "ASC"
+        // Giá trị không có trong request lấy từ tùy chọn của user, sau đó mới tới mặc định
+        let preferences = if filter.sort_by.is_none()
+            || filter.sort_order.is_none()
+            || pagination.page_size.is_none()
+        {
+            self.get_user_preferences(&user_id).await.map_err(|e| {
+                eprintln!("Error loading user preferences: {:?}", e);
+                AppError::internal_server_error("Failed to load user preferences")
+            })?
         } else {
-            "DESC"
+            UserPreferences::default()
         };
 
-        query.push_str(&format!(
-            " ORDER BY {} {} LIMIT ${} OFFSET ${}",
-            sort_by,
-            sort_order,
-            param_index,
-            param_index + 1
-        ));
+        let mut query = PaginatedQuery::new("todos", TODO_COLUMNS);
+        let search_param = apply_filter(
+            &mut query,
+            user_id,
+            search.as_deref(),
+            filter.is_completed,
+            filter.include_snoozed.unwrap_or(false),
+        );
 
-        let mut count_query_builder = sqlx::query(&count_query);
-        for param in &params {
-            count_query_builder = count_query_builder.bind(param);
+        // Chỉ tra bảng users khi được yêu cầu, trường hợp thường gặp không tốn thêm truy vấn
+        let include_owner = filter.include_owner.unwrap_or(false);
+        if include_owner {
+            query
+                .select("(SELECT email FROM users WHERE users.uuid = todos.owner_id) AS owner_email")
+                .select("(SELECT name FROM users WHERE users.uuid = todos.owner_id) AS owner_name")
+                .select(
+                    "(SELECT name FROM users \
+                        WHERE users.uuid = COALESCE(todos.updated_by, todos.owner_id)) AS updated_by_name",
+                );
         }
 
-        let total: i64 = count_query_builder
-            .fetch_one(&self.pool)
-            .await?
-            .get("total");
+        // Full-text search: tiêu đề (trọng số A) được xếp hạng cao hơn mô tả (trọng số B)
+        if let Some(param) = &search_param {
+            query.select(format!(
+                "ts_rank_cd(search_vector, websearch_to_tsquery('english', {})) AS relevance",
+                param
+            ));
+        }
 
-        let total_pages = (total + page_size - 1) / page_size;
+        // Khi đang tìm kiếm, mặc định sắp xếp theo độ liên quan
+        let default_sort = if search.is_some() {
+            SortField::Relevance
+        } else {
+            SortField::CreatedAt
+        };
+        let sort_by = filter
+            .sort_by
+            .or(preferences.sort_by)
+            .unwrap_or(default_sort);
+        let sort_order = filter
+            .sort_order
+            .or(preferences.sort_order)
+            .unwrap_or(SortOrder::Desc);
 
-        let mut query_builder = sqlx::query(&query);
-        for param in &params {
-            query_builder = query_builder.bind(param);
+        // Todo được ghim luôn đứng đầu trừ khi client yêu cầu sắp xếp thuần theo sort_by
+        if filter.pinned_first.unwrap_or(true) {
+            query.order_by("is_pinned DESC");
+        }
+        // Tên cột lấy từ enum nên giá trị của client không bao giờ vào thẳng câu SQL
+        query.order_by(format!("{} {}", sort_by.column(), sort_order.sql()));
+        // Todo chưa từng được di chuyển đều có position 0, giữ chúng theo thứ tự mới nhất trước
+        if sort_by == SortField::Position {
+            query.order_by("created_at DESC");
         }
 
-        query_builder = query_builder.bind(page_size).bind(offset);
+        query.paginate(
+            pagination.page.unwrap_or(1),
+            limits.page_size(pagination.page_size.or(preferences.page_size)),
+        );
 
-        let rows = query_builder.fetch_all(&self.pool).await?;
+        let total = query.fetch_count(&self.read_pool).await?;
+        let rows = query.fetch_page(&self.read_pool).await?;
 
         let mut todos = Vec::new();
         for row in rows {
-            let created_at: chrono::DateTime<Utc> = row.get("created_at");
-            let updated_at: chrono::DateTime<Utc> = row.get("updated_at");
+            let relevance: Option<f32> = if search.is_some() {
+                Some(row.get("relevance"))
+            } else {
+                None
+            };
 
-            todos.push(TodoResponse {
-                uuid: row.get("uuid"),
-                title: row.get("title"),
-                description: row.get("description"),
-                is_completed: row.get("is_completed"),
-                user_id: row.get("owner_id"),
-                created_at: created_at.to_string(),
-                updated_at: updated_at.to_string(),
-            });
+            let mut todo = TodoResponse {
+                owner_email: if include_owner {
+                    row.get("owner_email")
+                } else {
+                    None
+                },
+                owner_name: if include_owner {
+                    row.get("owner_name")
+                } else {
+                    None
+                },
+                updated_by_name: if include_owner {
+                    row.get("updated_by_name")
+                } else {
+                    None
+                },
+                relevance,
+                ..TodoResponse::from(todo_from_row(&row))
+            };
+            // Danh sách chỉ cần bản xem trước, mô tả đầy đủ lấy qua GET từng todo
+            if let Some(max_chars) = truncate_description {
+                todo.truncate_description(max_chars);
+            }
+            todos.push(todo);
         }
 
         Ok(TodoResponseList {
             todos,
             total,
-            page,
-            page_size,
-            total_pages,
+            page: query.page(),
+            page_size: query.page_size(),
+            total_pages: total_pages(total, query.page_size()),
+            max_page_size: limits.max_page_size,
+        })
+    }
+
+    async fn count_todos(&self, user_id: String, filter: TodoFilter) -> Result<i64, AppError> {
+        let _timer = QueryTimer::start("count_todos");
+        let search = filter.search.filter(|s| !s.trim().is_empty());
+
+        let mut query = PaginatedQuery::new("todos", TODO_COLUMNS);
+        apply_filter(
+            &mut query,
+            user_id,
+            search.as_deref(),
+            filter.is_completed,
+            filter.include_snoozed.unwrap_or(false),
+        );
+
+        query.fetch_count(&self.pool).await.map_err(|e| {
+            eprintln!("Error counting todos: {:?}", e);
+            AppError::internal_server_error("Failed to count todos")
         })
     }
 
     async fn get_one_todo(&self, todo_id: String) -> Result<TodoResponse, AppError> {
-        let query = "SELECT uuid, title, description, is_completed, owner_id, created_at, updated_at FROM todos WHERE uuid = $1";
+        let _timer = QueryTimer::start("get_one_todo");
+        let query = format!(
+            "SELECT {} FROM todos WHERE uuid = $1 AND deleted_at IS NULL",
+            TODO_COLUMNS
+        );
 
-        let row = sqlx::query(query)
+        let row = sqlx::query(&query)
             .bind(&todo_id)
-            .fetch_one(&self.pool)
+            .fetch_one(&self.read_pool)
             .await
             .map_err(|e| match e {
                 sqlx::Error::RowNotFound => AppError::not_found("Todo not found"),
@@ -155,29 +500,340 @@ impl TodoData for Database {
                 }
             })?;
 
-        let created_at: chrono::DateTime<Utc> = row.get("created_at");
-        let updated_at: chrono::DateTime<Utc> = row.get("updated_at");
-
-        Ok(TodoResponse {
-            uuid: row.get("uuid"),
-            title: row.get("title"),
-            description: row.get("description"),
-            is_completed: row.get("is_completed"),
-            user_id: row.get("owner_id"),
-            created_at: created_at.to_string(),
-            updated_at: updated_at.to_string(),
+        Ok(TodoResponse::from(todo_from_row(&row)))
+    }
+
+    async fn get_grouped_todos(
+        &self,
+        user_id: String,
+        page_size: i64,
+    ) -> Result<GroupedTodos, AppError> {
+        let _timer = QueryTimer::start("get_grouped_todos");
+        // Một query cho cả ba nhóm: đánh số trong từng nhóm rồi chỉ lấy `page_size` dòng đầu
+        let query = format!(
+            "SELECT * FROM ( \
+                SELECT {columns}, grp, \
+                    COUNT(*) OVER (PARTITION BY grp) AS group_total, \
+                    ROW_NUMBER() OVER (PARTITION BY grp ORDER BY is_pinned DESC, updated_at DESC) AS group_rank \
+                FROM ( \
+                    SELECT *, CASE WHEN is_completed THEN 'completed' \
+                        WHEN remind_at <= NOW() THEN 'overdue' ELSE 'pending' END AS grp \
+                    FROM todos WHERE owner_id = $1 AND deleted_at IS NULL \
+                    AND (snoozed_until IS NULL OR snoozed_until <= NOW()) \
+                ) grouped \
+            ) ranked WHERE group_rank <= $2 ORDER BY group_rank",
+            columns = TODO_COLUMNS
+        );
+
+        let rows = sqlx::query(&query)
+            .bind(&user_id)
+            .bind(page_size)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error fetching grouped todos: {:?}", e);
+                AppError::internal_server_error("Failed to fetch grouped todos")
+            })?;
+
+        let mut grouped = GroupedTodos::default();
+        for row in &rows {
+            let group = match row.get::<String, _>("grp").as_str() {
+                "completed" => &mut grouped.completed,
+                "overdue" => &mut grouped.overdue,
+                _ => &mut grouped.pending,
+            };
+            group.total = row.get("group_total");
+            group.todos.push(TodoResponse::from(todo_from_row(row)));
+        }
+
+        Ok(grouped)
+    }
+
+    async fn get_agenda(
+        &self,
+        user_id: String,
+        tz: String,
+        page_size: i64,
+    ) -> Result<Agenda, AppError> {
+        let _timer = QueryTimer::start("get_agenda");
+        // So sánh theo ngày ở múi giờ của user, tuần kết thúc vào Chủ nhật như date_trunc('week')
+        let query = format!(
+            "SELECT * FROM ( \
+                SELECT {columns}, bucket, \
+                    COUNT(*) OVER (PARTITION BY bucket) AS bucket_total, \
+                    ROW_NUMBER() OVER (PARTITION BY bucket \
+                        ORDER BY remind_at ASC, is_pinned DESC, created_at DESC) AS bucket_rank \
+                FROM ( \
+                    SELECT *, CASE \
+                        WHEN remind_at IS NULL THEN 'no_due_date' \
+                        WHEN due_day < today THEN 'overdue' \
+                        WHEN due_day = today THEN 'today' \
+                        WHEN due_day = today + 1 THEN 'tomorrow' \
+                        WHEN due_day < date_trunc('week', today)::DATE + 7 THEN 'this_week' \
+                        ELSE 'later' END AS bucket \
+                    FROM ( \
+                        SELECT *, (remind_at AT TIME ZONE $3)::DATE AS due_day, \
+                            (NOW() AT TIME ZONE $3)::DATE AS today \
+                        FROM todos WHERE owner_id = $1 AND deleted_at IS NULL \
+                        AND is_completed = FALSE \
+                        AND (snoozed_until IS NULL OR snoozed_until <= NOW()) \
+                    ) dated \
+                ) bucketed \
+            ) ranked WHERE bucket_rank <= $2 ORDER BY bucket_rank",
+            columns = TODO_COLUMNS
+        );
+
+        let rows = sqlx::query(&query)
+            .bind(&user_id)
+            .bind(page_size)
+            .bind(&tz)
+            .fetch_all(&self.read_pool)
+            .await
+            .map_err(|e| match e {
+                // Postgres không nhận ra tên múi giờ
+                sqlx::Error::Database(db_error) if db_error.code().as_deref() == Some("22023") => {
+                    AppError::validation(field_error("tz", format!("unknown time zone: {}", tz)))
+                }
+                _ => {
+                    eprintln!("Error fetching agenda: {:?}", e);
+                    AppError::internal_server_error("Failed to fetch agenda")
+                }
+            })?;
+
+        let mut agenda = Agenda {
+            tz,
+            ..Agenda::default()
+        };
+        for row in &rows {
+            let bucket = match row.get::<String, _>("bucket").as_str() {
+                "overdue" => &mut agenda.overdue,
+                "today" => &mut agenda.today,
+                "tomorrow" => &mut agenda.tomorrow,
+                "this_week" => &mut agenda.this_week,
+                "later" => &mut agenda.later,
+                _ => &mut agenda.no_due_date,
+            };
+            bucket.total = row.get("bucket_total");
+            bucket.todos.push(TodoResponse::from(todo_from_row(row)));
+        }
+
+        Ok(agenda)
+    }
+
+    async fn get_calendar(
+        &self,
+        user_id: String,
+        month_start: NaiveDate,
+        tz: String,
+    ) -> Result<TodoCalendar, AppError> {
+        let _timer = QueryTimer::start("get_calendar");
+        // Đổi đầu và cuối tháng theo giờ địa phương sang mốc thời gian để lọc thẳng trên remind_at
+        let query = format!(
+            "SELECT {}, to_char(remind_at AT TIME ZONE $3, 'YYYY-MM-DD') AS due_day \
+            FROM todos WHERE owner_id = $1 AND deleted_at IS NULL \
+                AND remind_at >= ($2::DATE::TIMESTAMP AT TIME ZONE $3) \
+                AND remind_at < (($2::DATE + INTERVAL '1 month') AT TIME ZONE $3) \
+            ORDER BY remind_at ASC, is_pinned DESC, created_at DESC",
+            TODO_COLUMNS
+        );
+
+        let rows = sqlx::query(&query)
+            .bind(&user_id)
+            .bind(month_start)
+            .bind(&tz)
+            .fetch_all(&self.read_pool)
+            .await
+            .map_err(|e| match e {
+                // Postgres không nhận ra tên múi giờ
+                sqlx::Error::Database(db_error) if db_error.code().as_deref() == Some("22023") => {
+                    AppError::validation(field_error("tz", format!("unknown time zone: {}", tz)))
+                }
+                _ => {
+                    eprintln!("Error fetching calendar: {:?}", e);
+                    AppError::internal_server_error("Failed to fetch calendar")
+                }
+            })?;
+
+        let mut calendar = TodoCalendar::new();
+        for row in &rows {
+            calendar
+                .entry(row.get("due_day"))
+                .or_default()
+                .push(TodoResponse::from(todo_from_row(row)));
+        }
+
+        Ok(calendar)
+    }
+
+    async fn search_todos(
+        &self,
+        user_id: String,
+        search: String,
+        page: i64,
+        page_size: i64,
+    ) -> Result<TodoSearchResponse, AppError> {
+        let _timer = QueryTimer::start("search_todos");
+        let mut query = PaginatedQuery::new("todos", TODO_COLUMNS);
+        // Tìm kiếm là yêu cầu tường minh nên vẫn trả về todo đang được hoãn
+        let search_param =
+            apply_filter(&mut query, user_id, Some(&search), None, true).unwrap_or_default();
+        query
+            .select(format!(
+                "ts_rank_cd(search_vector, websearch_to_tsquery('english', {})) AS score",
+                search_param
+            ))
+            .order_by("score DESC")
+            .order_by("created_at DESC")
+            .paginate(page, page_size);
+
+        let total = query.fetch_count(&self.pool).await?;
+
+        // Tham số của ts_headline chỉ có trong câu lấy trang nên bind sau khi đã đếm
+        let title_options = query.bind(TITLE_HEADLINE_OPTIONS);
+        let snippet_options = query.bind(SNIPPET_HEADLINE_OPTIONS);
+
+        // ts_headline tốn kém nên chỉ chạy trên các dòng của trang hiện tại
+        let sql = format!(
+            "SELECT page.*, \
+                ts_headline('english', title, websearch_to_tsquery('english', {search}), {title_options}) AS title_highlight, \
+                ts_headline('english', description, websearch_to_tsquery('english', {search}), {snippet_options}) AS snippet \
+            FROM ({page_sql}) AS page \
+            ORDER BY score DESC, created_at DESC",
+            search = search_param,
+            title_options = title_options,
+            snippet_options = snippet_options,
+            page_sql = query.page_sql()
+        );
+
+        let rows = query
+            .bind_to(sqlx::query(&sql))
+            .bind(query.page_size())
+            .bind(query.offset())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error searching todos: {:?}", e);
+                AppError::internal_server_error("Failed to search todos")
+            })?;
+
+        let results = rows
+            .iter()
+            .map(|row| {
+                let title_highlight: String = row.get("title_highlight");
+                let snippet: String = row.get("snippet");
+
+                TodoSearchResult {
+                    todo: TodoResponse::from(todo_from_row(row)),
+                    title_highlight: render_highlight(&title_highlight),
+                    snippet: render_highlight(&snippet),
+                    score: row.get("score"),
+                }
+            })
+            .collect();
+
+        Ok(TodoSearchResponse {
+            results,
+            total,
+            page,
+            page_size,
+            total_pages: total_pages(total, page_size),
         })
     }
 
+    async fn suggest_todo_titles(
+        &self,
+        user_id: String,
+        prefix: String,
+        limit: i64,
+    ) -> Result<Vec<String>, AppError> {
+        let _timer = QueryTimer::start("suggest_todo_titles");
+        // Escape ký tự đặc biệt của LIKE để chuỗi người dùng nhập được so khớp nguyên văn
+        let escaped = prefix
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+
+        // Ưu tiên tiêu đề bắt đầu bằng chuỗi đã nhập, sau đó theo độ tương đồng trigram
+        let query = "SELECT title FROM todos \
+            WHERE owner_id = $1 AND deleted_at IS NULL AND title ILIKE $2 \
+            GROUP BY title \
+            ORDER BY title ILIKE $3 DESC, similarity(title, $4) DESC, title \
+            LIMIT $5";
+
+        let rows = sqlx::query(query)
+            .bind(&user_id)
+            .bind(format!("%{}%", escaped))
+            .bind(format!("{}%", escaped))
+            .bind(&prefix)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error suggesting todos: {:?}", e);
+                AppError::internal_server_error("Failed to suggest todos")
+            })?;
+
+        Ok(rows.iter().map(|row| row.get("title")).collect())
+    }
+
+    async fn find_similar_titles(
+        &self,
+        user_id: &str,
+        title: &str,
+        exclude_uuid: &str,
+        limit: i64,
+    ) -> Result<Vec<PossibleDuplicate>, AppError> {
+        let _timer = QueryTimer::start("find_similar_titles");
+        // `%` dùng được chỉ mục trigram với ngưỡng mặc định 0.3, sau đó chỉ giữ cặp rất giống nhau
+        let query =
+            "SELECT uuid, title, is_completed, similarity(title, $2) AS similarity FROM todos \
+            WHERE owner_id = $1 AND deleted_at IS NULL AND uuid <> $3 \
+            AND title % $2 AND similarity(title, $2) >= $4 \
+            ORDER BY similarity DESC, created_at DESC \
+            LIMIT $5";
+
+        let rows = sqlx::query(query)
+            .bind(user_id)
+            .bind(title)
+            .bind(exclude_uuid)
+            .bind(DUPLICATE_SIMILARITY_THRESHOLD)
+            .bind(limit)
+            // Bản sao đọc có thể chưa có todo vừa tạo vài giây trước, đúng loại trùng hay gặp nhất
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error finding similar todos: {:?}", e);
+                AppError::internal_server_error("Failed to find similar todos")
+            })?;
+
+        Ok(rows
+            .iter()
+            .map(|row| PossibleDuplicate {
+                uuid: row.get("uuid"),
+                title: row.get("title"),
+                is_completed: row.get("is_completed"),
+                similarity: row.get("similarity"),
+            })
+            .collect())
+    }
+
     async fn add_todo(
         &self,
         user_id: String,
         todo: CreateTodoRequest,
+        unique_title: bool,
     ) -> Result<TodoResponse, AppError> {
+        if unique_title {
+            if let Some(existing_id) = self.find_todo_by_title(&user_id, &todo.title).await? {
+                return Err(duplicate_title_error(existing_id));
+            }
+        }
+
+        let _timer = QueryTimer::start("add_todo");
         let uuid = Uuid::new_v4().to_string();
         let now = Utc::now();
 
-        let query = "INSERT INTO todos (uuid, title, description, is_completed, owner_id, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *";
+        let query = "INSERT INTO todos (uuid, title, description, is_completed, owner_id, created_at, updated_at, color) VALUES ($1, $2, $3, $4, $5, $6, $7, NULLIF($8, '')) RETURNING *";
 
         let row = sqlx::query(query)
             .bind(&uuid)
@@ -187,99 +843,806 @@ impl TodoData for Database {
             .bind(&user_id)
             .bind(now)
             .bind(now)
+            .bind(&todo.color)
             .fetch_one(&self.pool)
-            .await
-            .map_err(|e| {
+            .await;
+
+        let row = match row {
+            Ok(row) => row,
+            // Hai yêu cầu cùng tiêu đề vượt qua bước kiểm tra trên cùng lúc, chỉ mục duy nhất chặn cái sau
+            Err(e) if is_duplicate_title(&e) => {
+                return Err(duplicate_title_conflict(&self.pool, &user_id, &[todo.title]).await);
+            }
+            Err(e) => {
                 eprintln!("Error adding todo: {:?}", e);
-                AppError::internal_server_error("Failed to add todo")
-            })?;
+                return Err(AppError::internal_server_error("Failed to add todo"));
+            }
+        };
 
-        let created_todo = Todo::new(
-            row.get("uuid"),
-            row.get("title"),
-            row.get("description"),
-            row.get("is_completed"),
-            row.get("owner_id"),
-            row.get("created_at"),
-            row.get("updated_at"),
+        Ok(TodoResponse::from(todo_from_row(&row)))
+    }
+
+    async fn find_todo_by_title(
+        &self,
+        user_id: &str,
+        title: &str,
+    ) -> Result<Option<String>, AppError> {
+        let _timer = QueryTimer::start("find_todo_by_title");
+        let existing_id = sqlx::query_scalar(
+            "SELECT uuid FROM todos \
+            WHERE owner_id = $1 AND lower(title) = lower($2) AND deleted_at IS NULL \
+            ORDER BY created_at LIMIT 1",
+        )
+        .bind(user_id)
+        .bind(title)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(existing_id)
+    }
+
+    async fn import_todos(
+        &self,
+        user_id: String,
+        todos: Vec<ImportedTodo>,
+    ) -> Result<Vec<Todo>, AppError> {
+        let _timer = QueryTimer::start("import_todos");
+        let now = Utc::now();
+        let mut uuids = Vec::with_capacity(todos.len());
+        let mut titles = Vec::with_capacity(todos.len());
+        let mut descriptions = Vec::with_capacity(todos.len());
+        let mut completed = Vec::with_capacity(todos.len());
+        for todo in todos {
+            uuids.push(Uuid::new_v4().to_string());
+            titles.push(todo.title);
+            descriptions.push(todo.description);
+            completed.push(todo.is_completed);
+        }
+
+        // UNNEST chèn cả file bằng một câu lệnh nên lỗi ở bất kỳ dòng nào cũng không để lại gì;
+        // WITH ORDINALITY giữ thứ tự các dòng như trong file
+        let query = format!(
+            "INSERT INTO todos (uuid, title, description, is_completed, owner_id, created_at, updated_at) \
+            SELECT uuid, title, description, is_completed, $5, $6, $6 \
+            FROM UNNEST($1::TEXT[], $2::TEXT[], $3::TEXT[], $4::BOOLEAN[]) \
+                WITH ORDINALITY AS rows(uuid, title, description, is_completed, ord) \
+            ORDER BY ord RETURNING {}",
+            TODO_COLUMNS
         );
 
-        Ok(TodoResponse {
-            uuid: created_todo.uuid,
-            title: created_todo.title,
-            description: created_todo.description,
-            is_completed: created_todo.is_completed,
-            user_id: created_todo.owner_id,
-            created_at: created_todo.created_at.to_string(),
-            updated_at: created_todo.updated_at.to_string(),
-        })
+        let rows = sqlx::query(&query)
+            .bind(&uuids)
+            .bind(&titles)
+            .bind(&descriptions)
+            .bind(&completed)
+            .bind(&user_id)
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await;
+
+        let rows = match rows {
+            Ok(rows) => rows,
+            // Một dòng trùng tiêu đề với todo đã có, hoặc hai dòng trong file trùng nhau
+            Err(e) if is_duplicate_title(&e) => {
+                return Err(duplicate_title_conflict(&self.pool, &user_id, &titles).await);
+            }
+            Err(e) => {
+                eprintln!("Error importing todos: {:?}", e);
+                return Err(AppError::internal_server_error("Failed to import todos"));
+            }
+        };
+
+        // RETURNING không đảm bảo thứ tự, sắp lại theo thứ tự trong file
+        let mut imported: Vec<Todo> = rows.iter().map(todo_from_row).collect();
+        imported.sort_by_key(|todo| uuids.iter().position(|uuid| *uuid == todo.uuid));
+        Ok(imported)
     }
 
     async fn update_todo(
         &self,
+        user_id: String,
         todo_uuid: String,
-        title: Option<String>,
-        description: Option<String>,
-        is_completed: Option<bool>,
+        changes: UpdateTodoRequest,
     ) -> Result<Todo, AppError> {
-        let existing_todo = self.get_one_todo(todo_uuid.clone()).await?;
-
-        let title = title.unwrap_or(existing_todo.title);
-        let description = description.unwrap_or(existing_todo.description);
-        let is_completed = is_completed.unwrap_or(existing_todo.is_completed);
-        let now = Utc::now();
+        let _timer = QueryTimer::start("update_todo");
+        let UpdateTodoRequest {
+            title,
+            description,
+            is_completed,
+            completion_note,
+            color,
+        } = changes;
+        let note_given = completion_note.is_some();
+        // Chuỗi rỗng (hoặc chỉ có khoảng trắng) nghĩa là xóa ghi chú
+        let completion_note = completion_note.map(|note| {
+            if note.trim().is_empty() {
+                String::new()
+            } else {
+                note
+            }
+        });
 
-        let query = "UPDATE todos SET title = $1, description = $2, is_completed = $3, updated_at = $4 WHERE uuid = $5 RETURNING *";
+        // Gộp giá trị mới với giá trị hiện có ngay trong một câu UPDATE, không đọc trước,
+        // nên hai request cập nhật đồng thời các trường khác nhau không ghi đè lẫn nhau.
+        // Ghi chú chỉ có nghĩa khi todo đã hoàn thành, mở lại todo thì xóa ghi chú.
+        let query = "UPDATE todos SET \
+                title = COALESCE($1, title), \
+                description = COALESCE($2, description), \
+                is_completed = COALESCE($3, is_completed), \
+                completion_note = CASE \
+                    WHEN NOT COALESCE($3, is_completed) THEN NULL \
+                    WHEN $4::TEXT IS NULL THEN completion_note \
+                    ELSE NULLIF($4, '') END, \
+                color = CASE WHEN $8::TEXT IS NULL THEN color ELSE NULLIF($8, '') END, \
+                updated_at = $5, updated_by = $7, version = version + 1 \
+            WHERE uuid = $6 AND owner_id = $7 AND deleted_at IS NULL \
+                AND ($4::TEXT IS NULL OR COALESCE($3, is_completed)) \
+            RETURNING *";
 
         let row = sqlx::query(query)
             .bind(&title)
             .bind(&description)
             .bind(is_completed)
-            .bind(now)
+            .bind(&completion_note)
+            .bind(Utc::now())
             .bind(&todo_uuid)
-            .fetch_one(&self.pool)
+            .bind(&user_id)
+            .bind(&color)
+            .fetch_optional(&self.pool)
+            .await;
+
+        let row = match (row, title) {
+            (Ok(row), _) => row,
+            // Đổi tên trùng với một todo khác chưa xóa
+            (Err(e), Some(title)) if is_duplicate_title(&e) => {
+                return Err(duplicate_title_conflict(&self.pool, &user_id, &[title]).await);
+            }
+            (Err(e), _) => {
+                eprintln!("Error updating todo: {:?}", e);
+                return Err(AppError::internal_server_error("Failed to update todo"));
+            }
+        };
+
+        match row {
+            Some(row) => Ok(todo_from_row(&row)),
+            // Không có dòng nào: todo không tồn tại (hoặc của user khác), hoặc ghi chú gửi kèm
+            // một todo chưa hoàn thành
+            None if note_given
+                && self
+                    .get_one_todo(todo_uuid)
+                    .await
+                    .is_ok_and(|todo| todo.user_id == user_id) =>
+            {
+                Err(AppError::bad_request(
+                    "completion_note can only be set on a completed todo",
+                ))
+            }
+            None => Err(AppError::not_found("Todo not found")),
+        }
+    }
+
+    async fn bulk_update_tags(
+        &self,
+        user_id: String,
+        todo_uuids: Vec<String>,
+        add: Vec<String>,
+        remove: Vec<String>,
+    ) -> Result<Vec<Todo>, AppError> {
+        let _timer = QueryTimer::start("bulk_update_tags");
+        // Một câu lệnh duy nhất nên toàn bộ batch được áp dụng nguyên tử, chỉ trên todo của user
+        let query = format!(
+            "UPDATE todos SET \
+                tags = ARRAY(SELECT DISTINCT t FROM unnest(array_cat(tags, $3::TEXT[])) AS t \
+                    WHERE NOT (t = ANY($4::TEXT[])) ORDER BY t), \
+                updated_at = $5, updated_by = $1, version = version + 1 \
+            WHERE owner_id = $1 AND uuid = ANY($2) AND deleted_at IS NULL \
+            RETURNING {}",
+            TODO_COLUMNS
+        );
+
+        let rows = sqlx::query(&query)
+            .bind(&user_id)
+            .bind(&todo_uuids)
+            .bind(&add)
+            .bind(&remove)
+            .bind(Utc::now())
+            .fetch_all(&self.pool)
             .await
             .map_err(|e| {
-                eprintln!("Error updating todo: {:?}", e);
-                AppError::internal_server_error("Failed to update todo")
+                eprintln!("Error updating todo tags: {:?}", e);
+                AppError::internal_server_error("Failed to update todo tags")
             })?;
 
-        Ok(Todo::new(
-            row.get("uuid"),
-            row.get("title"),
-            row.get("description"),
-            row.get("is_completed"),
-            row.get("owner_id"),
-            row.get("created_at"),
-            row.get("updated_at"),
-        ))
+        Ok(rows.iter().map(todo_from_row).collect())
     }
 
-    async fn delete_todo(&self, todo_uuid: String) -> Result<DeleteTodoResponse, AppError> {
-        let check_query = "SELECT uuid FROM todos WHERE uuid = $1";
-        let todo_exists = sqlx::query(check_query)
+    async fn set_todo_pinned(
+        &self,
+        user_id: String,
+        todo_uuid: String,
+        is_pinned: bool,
+    ) -> Result<Todo, AppError> {
+        let _timer = QueryTimer::start("set_todo_pinned");
+        // Ghim không phải là chỉnh sửa nội dung nên không đổi updated_at
+        let query = "UPDATE todos SET is_pinned = $1, version = version + 1 \
+            WHERE uuid = $2 AND owner_id = $3 AND deleted_at IS NULL RETURNING *";
+
+        let row = sqlx::query(query)
+            .bind(is_pinned)
+            .bind(&todo_uuid)
+            .bind(&user_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error pinning todo: {:?}", e);
+                AppError::internal_server_error("Failed to pin todo")
+            })?
+            .ok_or_else(|| AppError::not_found("Todo not found"))?;
+
+        Ok(todo_from_row(&row))
+    }
+
+    async fn set_todo_snoozed(
+        &self,
+        user_id: String,
+        todo_uuid: String,
+        snoozed_until: Option<chrono::DateTime<Utc>>,
+    ) -> Result<Todo, AppError> {
+        let _timer = QueryTimer::start("set_todo_snoozed");
+        // Giống ghim, hoãn không phải là chỉnh sửa nội dung nên không đổi updated_at
+        let query = "UPDATE todos SET snoozed_until = $1, version = version + 1 \
+            WHERE uuid = $2 AND owner_id = $3 AND deleted_at IS NULL RETURNING *";
+
+        let row = sqlx::query(query)
+            .bind(snoozed_until)
             .bind(&todo_uuid)
+            .bind(&user_id)
             .fetch_optional(&self.pool)
-            .await?;
+            .await
+            .map_err(|e| {
+                eprintln!("Error snoozing todo: {:?}", e);
+                AppError::internal_server_error("Failed to snooze todo")
+            })?
+            .ok_or_else(|| AppError::not_found("Todo not found"))?;
+
+        Ok(todo_from_row(&row))
+    }
+
+    async fn set_todo_reminder(
+        &self,
+        user_id: String,
+        todo_uuid: String,
+        remind_at: Option<chrono::DateTime<Utc>>,
+    ) -> Result<Todo, AppError> {
+        let _timer = QueryTimer::start("set_todo_reminder");
+        // Xóa reminded_at để lịch nhắc mới được gửi dù todo đã từng được nhắc
+        let query = "UPDATE todos SET remind_at = $1, reminded_at = NULL, version = version + 1 \
+            WHERE uuid = $2 AND owner_id = $3 AND deleted_at IS NULL RETURNING *";
+
+        let row = sqlx::query(query)
+            .bind(remind_at)
+            .bind(&todo_uuid)
+            .bind(&user_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error setting todo reminder: {:?}", e);
+                AppError::internal_server_error("Failed to set todo reminder")
+            })?
+            .ok_or_else(|| AppError::not_found("Todo not found"))?;
+
+        Ok(todo_from_row(&row))
+    }
+
+    async fn claim_due_reminders(&self, batch_size: i64) -> Result<Vec<Todo>, AppError> {
+        let _timer = QueryTimer::start("claim_due_reminders");
+        // Đánh dấu trước khi gửi: lỗi gửi thì bỏ lỡ một lần nhắc chứ không nhắc trùng
+        let query = format!(
+            "UPDATE todos SET reminded_at = NOW() WHERE uuid IN ( \
+                SELECT uuid FROM todos WHERE remind_at <= NOW() AND reminded_at IS NULL \
+                AND is_completed = FALSE AND deleted_at IS NULL \
+                ORDER BY remind_at LIMIT $1 FOR UPDATE SKIP LOCKED) \
+            RETURNING {}",
+            TODO_COLUMNS
+        );
+
+        let rows = sqlx::query(&query)
+            .bind(batch_size)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error claiming due reminders: {:?}", e);
+                AppError::internal_server_error("Failed to claim due reminders")
+            })?;
+
+        Ok(rows.iter().map(todo_from_row).collect())
+    }
+
+    async fn move_todo(
+        &self,
+        user_id: String,
+        todo_uuid: String,
+        position: MovePosition,
+    ) -> Result<Todo, AppError> {
+        let _timer = QueryTimer::start("move_todo");
+        // Chỉ đổi position của một dòng thay vì đánh số lại cả danh sách
+        let new_position = match position {
+            MovePosition::Top => "COALESCE(MIN(position), 0) - 1",
+            MovePosition::Bottom => "COALESCE(MAX(position), 0) + 1",
+        };
+        let query = format!(
+            "UPDATE todos SET position = ( \
+                SELECT {} FROM todos WHERE owner_id = $2 AND deleted_at IS NULL \
+            ), version = version + 1 \
+            WHERE uuid = $1 AND owner_id = $2 AND deleted_at IS NULL RETURNING {}",
+            new_position, TODO_COLUMNS
+        );
+
+        let row = sqlx::query(&query)
+            .bind(&todo_uuid)
+            .bind(&user_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error moving todo: {:?}", e);
+                AppError::internal_server_error("Failed to move todo")
+            })?
+            .ok_or_else(|| AppError::not_found("Todo not found"))?;
+
+        Ok(todo_from_row(&row))
+    }
+
+    async fn transition_todos(
+        &self,
+        user_id: String,
+        filter: TransitionFilter,
+        is_completed: bool,
+    ) -> Result<Vec<Todo>, AppError> {
+        let _timer = QueryTimer::start("transition_todos");
+        let search = filter
+            .search
+            .map(|search| search.trim().to_string())
+            .filter(|search| !search.is_empty());
+
+        // Chỉ dùng điều kiện và tham số của builder, câu UPDATE tự viết phần còn lại
+        let mut query = PaginatedQuery::new("todos", TODO_COLUMNS);
+        let actor = query.bind(user_id.as_str());
+        apply_filter(
+            &mut query,
+            user_id,
+            search.as_deref(),
+            filter.is_completed,
+            true,
+        );
+        let target = query.bind(is_completed);
+        let now = query.bind(Utc::now());
+
+        // Bỏ qua todo đã ở trạng thái đích để không đổi updated_at và đếm đúng số todo thay đổi;
+        // mở lại todo thì xóa ghi chú hoàn thành như update_todo
+        let sql = format!(
+            "UPDATE todos SET is_completed = {target}, \
+                completion_note = CASE WHEN {target} THEN completion_note ELSE NULL END, \
+                updated_at = {now}, updated_by = {actor}, version = version + 1 \
+            {conditions} AND is_completed <> {target} \
+            RETURNING {columns}",
+            target = target,
+            now = now,
+            actor = actor,
+            conditions = query.where_clause(),
+            columns = TODO_COLUMNS
+        );
+
+        let rows = query
+            .bind_to(sqlx::query(&sql))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error transitioning todos: {:?}", e);
+                AppError::internal_server_error("Failed to update todos")
+            })?;
+
+        Ok(rows.iter().map(todo_from_row).collect())
+    }
+
+    async fn delete_todo(
+        &self,
+        user_id: String,
+        todo_uuid: String,
+    ) -> Result<DeleteTodoResponse, AppError> {
+        let _timer = QueryTimer::start("delete_todo");
+        // Xóa mềm: todo nằm trong thùng rác tới khi job dọn dẹp xóa hẳn. Kiểm tra quyền sở hữu
+        // ngay trong câu UPDATE nên hai request xóa đồng thời chỉ một cái khớp dòng
+        let query = "UPDATE todos SET deleted_at = $1 \
+            WHERE uuid = $2 AND owner_id = $3 AND deleted_at IS NULL RETURNING uuid";
+
+        let deleted = sqlx::query(query)
+            .bind(Utc::now())
+            .bind(&todo_uuid)
+            .bind(&user_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error deleting todo: {:?}", e);
+                AppError::internal_server_error("Failed to delete todo")
+            })?;
 
-        if todo_exists.is_none() {
+        // Todo của user khác cũng trả về 404 để không lộ việc nó tồn tại
+        if deleted.is_none() {
             return Err(AppError::not_found(format!(
                 "Todo with id {} not found",
                 todo_uuid
             )));
         }
 
-        let query = "DELETE FROM todos WHERE uuid = $1";
-
-        sqlx::query(query)
-            .bind(&todo_uuid)
-            .execute(&self.pool)
-            .await?;
-
         Ok(DeleteTodoResponse {
             success: true,
             message: "Todo deleted successfully".to_string(),
             todo_id: todo_uuid,
         })
     }
+
+    async fn delete_todos(
+        &self,
+        user_id: String,
+        todo_uuids: Vec<String>,
+    ) -> Result<Vec<String>, AppError> {
+        let _timer = QueryTimer::start("delete_todos");
+        // Kiểm tra quyền sở hữu ngay trong câu UPDATE, không cần truy vấn từng todo
+        let query = "UPDATE todos SET deleted_at = $3 \
+            WHERE owner_id = $1 AND uuid = ANY($2) AND deleted_at IS NULL RETURNING uuid";
+
+        let rows = sqlx::query(query)
+            .bind(&user_id)
+            .bind(&todo_uuids)
+            .bind(Utc::now())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error deleting todos: {:?}", e);
+                AppError::internal_server_error("Failed to delete todos")
+            })?;
+
+        Ok(rows.iter().map(|row| row.get("uuid")).collect())
+    }
+
+    async fn filter_owned(
+        &self,
+        user_id: &str,
+        todo_uuids: &[String],
+    ) -> Result<Vec<String>, AppError> {
+        let _timer = QueryTimer::start("filter_owned");
+        if todo_uuids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Todo trong thùng rác coi như không thuộc về user, giống các thao tác hàng loạt
+        let query = "SELECT uuid FROM todos \
+            WHERE owner_id = $1 AND uuid = ANY($2) AND deleted_at IS NULL";
+
+        let rows = sqlx::query(query)
+            .bind(user_id)
+            .bind(todo_uuids)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error checking todo ownership: {:?}", e);
+                AppError::internal_server_error("Failed to check todo ownership")
+            })?;
+
+        Ok(rows.iter().map(|row| row.get("uuid")).collect())
+    }
+
+    async fn export_todos(
+        &self,
+        user_id: &str,
+        after_uuid: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<ExportedTodo>, AppError> {
+        let _timer = QueryTimer::start("export_todos");
+        // Phân trang keyset theo uuid nên trang sau không bị lệch khi todo bị thêm/xóa giữa chừng
+        let query = format!(
+            "SELECT {}, deleted_at FROM todos \
+            WHERE owner_id = $1 AND ($2::TEXT IS NULL OR uuid > $2) \
+            ORDER BY uuid LIMIT $3",
+            TODO_COLUMNS
+        );
+
+        let rows = sqlx::query(&query)
+            .bind(user_id)
+            .bind(after_uuid)
+            .bind(limit)
+            .fetch_all(&self.read_pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error exporting todos: {:?}", e);
+                AppError::internal_server_error("Failed to export todos")
+            })?;
+
+        Ok(rows
+            .iter()
+            .map(|row| ExportedTodo {
+                todo: TodoResponse::from(todo_from_row(row)),
+                deleted_at: row
+                    .get::<Option<DateTime<Utc>>, _>("deleted_at")
+                    .map(|deleted_at| deleted_at.to_string()),
+            })
+            .collect())
+    }
+
+    async fn get_user_tags(&self, user_id: &str) -> Result<Vec<String>, AppError> {
+        let _timer = QueryTimer::start("get_user_tags");
+        let tags = sqlx::query_scalar(
+            "SELECT DISTINCT tag FROM todos, unnest(tags) AS tag WHERE owner_id = $1 ORDER BY tag",
+        )
+        .bind(user_id)
+        .fetch_all(&self.read_pool)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching tags: {:?}", e);
+            AppError::internal_server_error("Failed to fetch tags")
+        })?;
+
+        Ok(tags)
+    }
+
+    async fn get_trashed_todos(
+        &self,
+        user_id: String,
+        page: i64,
+        page_size: i64,
+    ) -> Result<TrashedTodoList, AppError> {
+        let _timer = QueryTimer::start("get_trashed_todos");
+        // COUNT(*) OVER () trả tổng số cùng với trang, không cần câu đếm riêng
+        let query = format!(
+            "SELECT {}, deleted_at, COUNT(*) OVER () AS total FROM todos \
+            WHERE owner_id = $1 AND deleted_at IS NOT NULL \
+            ORDER BY deleted_at DESC, uuid LIMIT $2 OFFSET $3",
+            TODO_COLUMNS
+        );
+
+        let rows = sqlx::query(&query)
+            .bind(&user_id)
+            .bind(page_size)
+            .bind((page - 1) * page_size)
+            .fetch_all(&self.read_pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error fetching trashed todos: {:?}", e);
+                AppError::internal_server_error("Failed to fetch trash")
+            })?;
+
+        // Trang vượt quá cuối không có dòng nào nên phải đếm lại
+        let total: i64 = match rows.first() {
+            Some(row) => row.get("total"),
+            None if page > 1 => sqlx::query(
+                "SELECT COUNT(*) AS total FROM todos WHERE owner_id = $1 AND deleted_at IS NOT NULL",
+            )
+            .bind(&user_id)
+            .fetch_one(&self.read_pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error counting trashed todos: {:?}", e);
+                AppError::internal_server_error("Failed to fetch trash")
+            })?
+            .get("total"),
+            None => 0,
+        };
+
+        let todos = rows
+            .iter()
+            .map(|row| TrashedTodo {
+                todo: TodoResponse::from(todo_from_row(row)),
+                deleted_at: row.get::<DateTime<Utc>, _>("deleted_at").to_string(),
+            })
+            .collect();
+
+        Ok(TrashedTodoList {
+            todos,
+            total,
+            page,
+            page_size,
+            total_pages: total_pages(total, page_size),
+        })
+    }
+
+    async fn restore_all_todos(&self, user_id: String) -> Result<Vec<Todo>, AppError> {
+        let _timer = QueryTimer::start("restore_all_todos");
+        // Tăng version để bản cache cũ của từng todo không còn được dùng
+        let query = format!(
+            "UPDATE todos SET deleted_at = NULL, updated_at = $2, updated_by = $1, \
+                version = version + 1 \
+            WHERE owner_id = $1 AND deleted_at IS NOT NULL RETURNING {}",
+            TODO_COLUMNS
+        );
+
+        let rows = sqlx::query(&query)
+            .bind(&user_id)
+            .bind(Utc::now())
+            .fetch_all(&self.pool)
+            .await;
+
+        let rows = match rows {
+            Ok(rows) => rows,
+            // Câu lệnh bị hủy nên thùng rác còn nguyên, tìm todo chưa xóa trùng với một todo trong đó
+            Err(e) if is_duplicate_title(&e) => {
+                let trashed_titles: Vec<String> = sqlx::query_scalar(
+                    "SELECT title FROM todos WHERE owner_id = $1 AND deleted_at IS NOT NULL",
+                )
+                .bind(&user_id)
+                .fetch_all(&self.pool)
+                .await
+                .unwrap_or_default();
+                return Err(duplicate_title_conflict(&self.pool, &user_id, &trashed_titles).await);
+            }
+            Err(e) => {
+                eprintln!("Error restoring todos: {:?}", e);
+                return Err(AppError::internal_server_error("Failed to restore todos"));
+            }
+        };
+
+        Ok(rows.iter().map(todo_from_row).collect())
+    }
+
+    async fn empty_trash(&self, user_id: String) -> Result<u64, AppError> {
+        let _timer = QueryTimer::start("empty_trash");
+        let query = "DELETE FROM todos WHERE owner_id = $1 AND deleted_at IS NOT NULL";
+
+        let result = sqlx::query(query)
+            .bind(&user_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error emptying trash: {:?}", e);
+                AppError::internal_server_error("Failed to empty trash")
+            })?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn purge_deleted_todos(
+        &self,
+        deleted_before: chrono::DateTime<Utc>,
+        batch_size: i64,
+    ) -> Result<u64, AppError> {
+        let _timer = QueryTimer::start("purge_deleted_todos");
+        // SKIP LOCKED để không chờ các dòng đang bị request khác giữ khóa
+        let query = "DELETE FROM todos WHERE uuid IN ( \
+                SELECT uuid FROM todos WHERE deleted_at < $1 \
+                ORDER BY deleted_at LIMIT $2 FOR UPDATE SKIP LOCKED)";
+
+        let result = sqlx::query(query)
+            .bind(deleted_before)
+            .bind(batch_size)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Error purging deleted todos: {:?}", e);
+                AppError::internal_server_error("Failed to purge deleted todos")
+            })?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_support::{create_test_user, test_database};
+
+    fn new_todo(title: &str) -> CreateTodoRequest {
+        CreateTodoRequest {
+            title: title.to_string(),
+            description: "description".to_string(),
+            color: None,
+        }
+    }
+
+    fn change(title: Option<&str>, description: Option<&str>) -> UpdateTodoRequest {
+        UpdateTodoRequest {
+            title: title.map(str::to_string),
+            description: description.map(str::to_string),
+            is_completed: None,
+            completion_note: None,
+            color: None,
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn concurrent_updates_to_different_fields_both_persist() {
+        let db = test_database().await;
+        let user = create_test_user(&db).await;
+
+        // Lặp lại vài lần để hai câu UPDATE thực sự chạy chồng lên nhau
+        for round in 0..10 {
+            let todo = db
+                .add_todo(
+                    user.uuid.clone(),
+                    new_todo(&format!("Todo {}", round)),
+                    false,
+                )
+                .await
+                .unwrap();
+            let title = format!("New title {}", round);
+            let description = format!("New description {}", round);
+
+            let (renamed, described) = tokio::join!(
+                db.update_todo(
+                    user.uuid.clone(),
+                    todo.uuid.clone(),
+                    change(Some(&title), None)
+                ),
+                db.update_todo(
+                    user.uuid.clone(),
+                    todo.uuid.clone(),
+                    change(None, Some(&description))
+                ),
+            );
+            assert!(renamed.is_ok() && described.is_ok());
+
+            let stored = db.get_one_todo(todo.uuid.clone()).await.unwrap();
+            assert_eq!(stored.title, title);
+            assert_eq!(stored.description, description);
+            assert_eq!(stored.version, todo.version + 2);
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn batch_with_other_users_ids_only_touches_the_callers_todos() {
+        let db = test_database().await;
+        let user = create_test_user(&db).await;
+        let other = create_test_user(&db).await;
+        let mine = db
+            .add_todo(user.uuid.clone(), new_todo("Mine"), false)
+            .await
+            .unwrap();
+        let trashed = db
+            .add_todo(user.uuid.clone(), new_todo("Trashed"), false)
+            .await
+            .unwrap();
+        db.delete_todo(user.uuid.clone(), trashed.uuid.clone())
+            .await
+            .unwrap();
+        let theirs = db
+            .add_todo(other.uuid.clone(), new_todo("Theirs"), false)
+            .await
+            .unwrap();
+        let batch = vec![
+            theirs.uuid.clone(),
+            mine.uuid.clone(),
+            trashed.uuid.clone(),
+            Uuid::new_v4().to_string(),
+        ];
+
+        let owned = db.filter_owned(&user.uuid, &batch).await.unwrap();
+        assert_eq!(owned, vec![mine.uuid.clone()]);
+
+        // Kể cả khi bỏ qua bước lọc, câu lệnh hàng loạt chỉ đụng tới todo của user
+        let tagged = db
+            .bulk_update_tags(
+                user.uuid.clone(),
+                batch.clone(),
+                vec!["work".to_string()],
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            tagged.iter().map(|todo| &todo.uuid).collect::<Vec<_>>(),
+            vec![&mine.uuid]
+        );
+        let deleted = db.delete_todos(user.uuid.clone(), batch).await.unwrap();
+        assert_eq!(deleted, vec![mine.uuid.clone()]);
+
+        let untouched = db.get_one_todo(theirs.uuid.clone()).await.unwrap();
+        assert!(untouched.tags.is_empty());
+        assert_eq!(untouched.version, theirs.version);
+        assert_eq!(
+            db.filter_owned(&other.uuid, std::slice::from_ref(&theirs.uuid))
+                .await
+                .unwrap(),
+            vec![theirs.uuid]
+        );
+    }
 }
@@ -1,9 +1,61 @@
 use crate::db::database::Database;
+use crate::db::paginated_query::PaginatedQuery;
 use crate::error::user_error::UserError;
-use crate::models::user::{CreateUserRequest, User};
+use crate::models::todo::{SortField, SortOrder};
+use crate::models::user::{
+    BackupCodeSet, CreateUserRequest, TwoFactorDevice, User, UserPreferences,
+};
+use crate::services::metrics_service::QueryTimer;
 use async_trait::async_trait;
 use chrono::Utc;
+use sqlx::postgres::PgRow;
 use sqlx::Row;
+use uuid::Uuid;
+
+const USER_COLUMNS: &str = "uuid, email, name, password, created_at::TEXT as created_at, updated_at::TEXT as updated_at, updated_at as updated_at_utc, two_factor_enabled, two_factor_secret, two_factor_pending_secret, two_factor_pending_since, is_admin, email_changed_at";
+
+const DEVICE_COLUMNS: &str = "uuid, user_id, name, secret, created_at::TEXT as created_at, created_at as created_at_utc, verified_at::TEXT as verified_at";
+
+const BACKUP_CODE_SET_COLUMNS: &str = "uuid, user_id, codes, created_at::TEXT as created_at";
+
+fn device_from_row(row: &PgRow) -> TwoFactorDevice {
+    TwoFactorDevice {
+        uuid: row.get("uuid"),
+        user_id: row.get("user_id"),
+        name: row.get("name"),
+        secret: row.get("secret"),
+        created_at: row.get("created_at"),
+        created_at_utc: row.get("created_at_utc"),
+        verified_at: row.get("verified_at"),
+    }
+}
+
+fn backup_code_set_from_row(row: &PgRow) -> BackupCodeSet {
+    BackupCodeSet {
+        uuid: row.get("uuid"),
+        user_id: row.get("user_id"),
+        codes: row.get("codes"),
+        created_at: row.get("created_at"),
+    }
+}
+
+fn user_from_row(row: &PgRow) -> User {
+    User {
+        uuid: row.get("uuid"),
+        email: row.get("email"),
+        name: row.get("name"),
+        password: row.get("password"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+        updated_at_utc: row.get("updated_at_utc"),
+        two_factor_enabled: row.get("two_factor_enabled"),
+        two_factor_secret: row.get("two_factor_secret"),
+        two_factor_pending_secret: row.get("two_factor_pending_secret"),
+        two_factor_pending_since: row.get("two_factor_pending_since"),
+        is_admin: row.get("is_admin"),
+        email_changed_at: row.get("email_changed_at"),
+    }
+}
 
 #[async_trait]
 pub trait UserData {
@@ -13,30 +65,80 @@ pub trait UserData {
     async fn update_user(&self, user: &User) -> Result<User, UserError>;
     async fn enable_2fa(&self, uuid: &str, secret: &str) -> Result<(), UserError>;
     async fn verify_2fa(&self, uuid: &str) -> Result<(), UserError>;
+    async fn confirm_2fa(
+        &self,
+        uuid: &str,
+        backup_codes: &[String],
+    ) -> Result<BackupCodeSet, UserError>;
+    async fn clear_pending_2fa(&self, uuid: &str) -> Result<(), UserError>;
     async fn disable_2fa(&self, uuid: &str) -> Result<(), UserError>;
+    async fn get_2fa_devices(&self, user_id: &str) -> Result<Vec<TwoFactorDevice>, UserError>;
+    async fn get_2fa_devices_page(
+        &self,
+        user_id: &str,
+        page: i64,
+        page_size: i64,
+    ) -> Result<(Vec<TwoFactorDevice>, i64), UserError>;
+    async fn add_2fa_device(
+        &self,
+        user_id: &str,
+        name: &str,
+        secret: &str,
+    ) -> Result<TwoFactorDevice, UserError>;
+    async fn get_2fa_device(
+        &self,
+        user_id: &str,
+        device_uuid: &str,
+    ) -> Result<TwoFactorDevice, UserError>;
+    async fn verify_2fa_device(&self, user_id: &str, device_uuid: &str) -> Result<(), UserError>;
+    async fn remove_2fa_device(&self, user_id: &str, device_uuid: &str) -> Result<(), UserError>;
+    async fn get_backup_code_sets(&self, user_id: &str) -> Result<Vec<BackupCodeSet>, UserError>;
+    async fn get_backup_code_sets_page(
+        &self,
+        user_id: &str,
+        page: i64,
+        page_size: i64,
+    ) -> Result<(Vec<BackupCodeSet>, i64), UserError>;
+    async fn add_backup_code_set(
+        &self,
+        user_id: &str,
+        codes: &[String],
+        replace_existing: bool,
+    ) -> Result<BackupCodeSet, UserError>;
+    async fn consume_backup_code(&self, user_id: &str, code_hash: &str) -> Result<bool, UserError>;
+    async fn has_backup_code(&self, user_id: &str, code_hash: &str) -> Result<bool, UserError>;
+    async fn revoke_backup_code_set(&self, user_id: &str, set_uuid: &str) -> Result<(), UserError>;
+    async fn get_password_history(
+        &self,
+        user_id: &str,
+        limit: usize,
+    ) -> Result<Vec<String>, UserError>;
+    async fn change_password(
+        &self,
+        user_id: &str,
+        new_hash: &str,
+        keep_history: usize,
+    ) -> Result<(), UserError>;
+    async fn get_user_preferences(&self, user_id: &str) -> Result<UserPreferences, UserError>;
+    async fn update_user_preferences(
+        &self,
+        user_id: &str,
+        preferences: &UserPreferences,
+    ) -> Result<UserPreferences, UserError>;
 }
 
 #[async_trait]
 impl UserData for Database {
     async fn get_user_by_email(&self, email: &str) -> Result<User, UserError> {
-        let query = "SELECT uuid, email, name, password, created_at::TEXT as created_at, updated_at::TEXT as updated_at, two_factor_enabled, two_factor_secret, backup_codes FROM users WHERE email = $1";
+        let _timer = QueryTimer::start("get_user_by_email");
+        let query = format!("SELECT {} FROM users WHERE email = $1", USER_COLUMNS);
 
-        match sqlx::query(query)
+        match sqlx::query(&query)
             .bind(email)
-            .fetch_optional(&self.pool)
+            .fetch_optional(&self.read_pool)
             .await
         {
-            Ok(Some(row)) => Ok(User {
-                uuid: row.get("uuid"),
-                email: row.get("email"),
-                name: row.get("name"),
-                password: row.get("password"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-                two_factor_enabled: row.get("two_factor_enabled"),
-                two_factor_secret: row.get("two_factor_secret"),
-                backup_codes: row.get("backup_codes"),
-            }),
+            Ok(Some(row)) => Ok(user_from_row(&row)),
             Ok(None) => Err(UserError::NoSuchUserFound),
             Err(e) => {
                 eprintln!("Error getting user by email: {:?}", e);
@@ -46,24 +148,15 @@ impl UserData for Database {
     }
 
     async fn get_user_by_uuid(&self, uuid: &str) -> Result<User, UserError> {
-        let query = "SELECT uuid, email, name, password, created_at::TEXT as created_at, updated_at::TEXT as updated_at, two_factor_enabled, two_factor_secret, backup_codes FROM users WHERE uuid = $1";
+        let _timer = QueryTimer::start("get_user_by_uuid");
+        let query = format!("SELECT {} FROM users WHERE uuid = $1", USER_COLUMNS);
 
-        match sqlx::query(query)
+        match sqlx::query(&query)
             .bind(uuid)
-            .fetch_optional(&self.pool)
+            .fetch_optional(&self.read_pool)
             .await
         {
-            Ok(Some(row)) => Ok(User {
-                uuid: row.get("uuid"),
-                email: row.get("email"),
-                name: row.get("name"),
-                password: row.get("password"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-                two_factor_enabled: row.get("two_factor_enabled"),
-                two_factor_secret: row.get("two_factor_secret"),
-                backup_codes: row.get("backup_codes"),
-            }),
+            Ok(Some(row)) => Ok(user_from_row(&row)),
             Ok(None) => Err(UserError::NoSuchUserFound),
             Err(e) => {
                 eprintln!("Error getting user by uuid: {:?}", e);
@@ -73,6 +166,7 @@ impl UserData for Database {
     }
 
     async fn create_user(&self, uuid: &str, user: &CreateUserRequest) -> Result<User, UserError> {
+        let _timer = QueryTimer::start("create_user");
         // Check if user already exists
         let check_query = "SELECT uuid FROM users WHERE email = $1";
 
@@ -89,7 +183,7 @@ impl UserData for Database {
                 let insert_query = "INSERT INTO users (uuid, email, name, password, created_at, updated_at, two_factor_enabled) VALUES ($1, $2, $3, $4, $5, $6, $7)";
 
                 match sqlx::query(insert_query)
-                    .bind(&uuid)
+                    .bind(uuid)
                     .bind(&user.email)
                     .bind(&user.name)
                     .bind(&user.password)
@@ -106,9 +200,13 @@ impl UserData for Database {
                         password: user.password.clone(),
                         created_at: now.to_string(),
                         updated_at: now.to_string(),
+                        updated_at_utc: now,
                         two_factor_enabled: false,
                         two_factor_secret: None,
-                        backup_codes: None,
+                        two_factor_pending_secret: None,
+                        two_factor_pending_since: None,
+                        is_admin: false,
+                        email_changed_at: None,
                     }),
                     Err(e) => {
                         eprintln!("Error adding user: {:?}", e);
@@ -124,30 +222,25 @@ impl UserData for Database {
     }
 
     async fn update_user(&self, user: &User) -> Result<User, UserError> {
-        let query = "UPDATE users SET email = $1, name = $2, password = $3, updated_at = NOW(), two_factor_enabled = $4, two_factor_secret = $5, backup_codes = $6 WHERE uuid = $7 RETURNING uuid, email, name, password, created_at::TEXT as created_at, updated_at::TEXT as updated_at, two_factor_enabled, two_factor_secret, backup_codes";
+        let _timer = QueryTimer::start("update_user");
+        let query = format!(
+            "UPDATE users SET email = $1, name = $2, password = $3, updated_at = NOW(), two_factor_enabled = $4, two_factor_secret = $5, \
+                email_changed_at = CASE WHEN email IS DISTINCT FROM $1 THEN NOW() ELSE email_changed_at END \
+            WHERE uuid = $6 RETURNING {}",
+            USER_COLUMNS
+        );
 
-        match sqlx::query(query)
+        match sqlx::query(&query)
             .bind(&user.email)
             .bind(&user.name)
             .bind(&user.password)
             .bind(user.two_factor_enabled)
             .bind(&user.two_factor_secret)
-            .bind(&user.backup_codes)
             .bind(&user.uuid)
             .fetch_one(&self.pool)
             .await
         {
-            Ok(row) => Ok(User {
-                uuid: row.get("uuid"),
-                email: row.get("email"),
-                name: row.get("name"),
-                password: row.get("password"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-                two_factor_enabled: row.get("two_factor_enabled"),
-                two_factor_secret: row.get("two_factor_secret"),
-                backup_codes: row.get("backup_codes"),
-            }),
+            Ok(row) => Ok(user_from_row(&row)),
             Err(e) => {
                 eprintln!("Error updating user: {:?}", e);
                 Err(UserError::DatabaseError(e.to_string()))
@@ -156,12 +249,12 @@ impl UserData for Database {
     }
 
     async fn enable_2fa(&self, uuid: &str, secret: &str) -> Result<(), UserError> {
+        let _timer = QueryTimer::start("enable_2fa");
         let now = Utc::now();
-        let query = "UPDATE users SET two_factor_secret = $1, two_factor_enabled = $2, updated_at = $3 WHERE uuid = $4";
+        let query = "UPDATE users SET two_factor_pending_secret = $1, two_factor_pending_since = $2, updated_at = $2 WHERE uuid = $3";
 
         match sqlx::query(query)
             .bind(secret)
-            .bind(true)
             .bind(now)
             .bind(uuid)
             .execute(&self.pool)
@@ -176,8 +269,9 @@ impl UserData for Database {
     }
 
     async fn verify_2fa(&self, uuid: &str) -> Result<(), UserError> {
+        let _timer = QueryTimer::start("verify_2fa");
         let now = Utc::now();
-        let query = "UPDATE users SET two_factor_enabled = $1, updated_at = $2 WHERE uuid = $3";
+        let query = "UPDATE users SET two_factor_secret = two_factor_pending_secret, two_factor_pending_secret = NULL, two_factor_pending_since = NULL, two_factor_enabled = $1, updated_at = $2 WHERE uuid = $3 AND two_factor_pending_secret IS NOT NULL";
 
         match sqlx::query(query)
             .bind(true)
@@ -194,12 +288,71 @@ impl UserData for Database {
         }
     }
 
-    async fn disable_2fa(&self, uuid: &str) -> Result<(), UserError> {
+    async fn confirm_2fa(
+        &self,
+        uuid: &str,
+        backup_codes: &[String],
+    ) -> Result<BackupCodeSet, UserError> {
+        let _timer = QueryTimer::start("confirm_2fa");
+        let db_error = |e: sqlx::Error| {
+            eprintln!("Error confirming 2FA: {:?}", e);
+            UserError::DatabaseError(e.to_string())
+        };
+        let set_uuid = Uuid::new_v4().to_string();
         let now = Utc::now();
-        let query = "UPDATE users SET two_factor_secret = NULL, two_factor_enabled = $1, updated_at = $2 WHERE uuid = $3";
+
+        // Kích hoạt secret đang chờ và thay mọi bộ mã backup trong cùng transaction,
+        // để không có lúc 2FA đã bật mà user chưa có mã backup
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+
+        let result = sqlx::query(
+            "UPDATE users SET two_factor_secret = two_factor_pending_secret, \
+                two_factor_pending_secret = NULL, two_factor_pending_since = NULL, \
+                two_factor_enabled = TRUE, updated_at = $1 \
+            WHERE uuid = $2 AND two_factor_pending_secret IS NOT NULL AND NOT two_factor_enabled",
+        )
+        .bind(now)
+        .bind(uuid)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        // Một request khác đã kích hoạt hoặc hủy thiết lập trong lúc này
+        if result.rows_affected() == 0 {
+            return Err(UserError::TwoFactorSetupNotStarted);
+        }
+
+        sqlx::query("DELETE FROM backup_code_sets WHERE user_id = $1")
+            .bind(uuid)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        sqlx::query(
+            "INSERT INTO backup_code_sets (uuid, user_id, codes, created_at) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(&set_uuid)
+        .bind(uuid)
+        .bind(backup_codes)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+        tx.commit().await.map_err(db_error)?;
+
+        Ok(BackupCodeSet {
+            uuid: set_uuid,
+            user_id: uuid.to_string(),
+            codes: backup_codes.to_vec(),
+            created_at: now.to_string(),
+        })
+    }
+
+    async fn clear_pending_2fa(&self, uuid: &str) -> Result<(), UserError> {
+        let _timer = QueryTimer::start("clear_pending_2fa");
+        let now = Utc::now();
+        let query = "UPDATE users SET two_factor_pending_secret = NULL, two_factor_pending_since = NULL, updated_at = $1 WHERE uuid = $2";
 
         match sqlx::query(query)
-            .bind(false)
             .bind(now)
             .bind(uuid)
             .execute(&self.pool)
@@ -207,9 +360,626 @@ impl UserData for Database {
         {
             Ok(_) => Ok(()),
             Err(e) => {
-                eprintln!("Error disabling 2FA: {:?}", e);
-                Err(UserError::NoSuchUserFound)
+                eprintln!("Error clearing pending 2FA: {:?}", e);
+                Err(UserError::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    async fn disable_2fa(&self, uuid: &str) -> Result<(), UserError> {
+        let _timer = QueryTimer::start("disable_2fa");
+        let db_error = |e: sqlx::Error| {
+            eprintln!("Error disabling 2FA: {:?}", e);
+            UserError::DatabaseError(e.to_string())
+        };
+
+        // Mọi thứ liên quan tới 2FA bị xóa cùng lúc, không để lại mã backup hay secret đang chờ
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+
+        let result = sqlx::query(
+            "UPDATE users SET two_factor_secret = NULL, two_factor_enabled = FALSE, \
+                two_factor_pending_secret = NULL, two_factor_pending_since = NULL, updated_at = $1 \
+            WHERE uuid = $2",
+        )
+        .bind(Utc::now())
+        .bind(uuid)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        if result.rows_affected() == 0 {
+            return Err(UserError::NoSuchUserFound);
+        }
+
+        // Additional authenticator devices go away together with 2FA, so do the backup codes
+        sqlx::query("DELETE FROM two_factor_devices WHERE user_id = $1")
+            .bind(uuid)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        sqlx::query("DELETE FROM backup_code_sets WHERE user_id = $1")
+            .bind(uuid)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+
+        tx.commit().await.map_err(db_error)
+    }
+
+    async fn get_2fa_devices(&self, user_id: &str) -> Result<Vec<TwoFactorDevice>, UserError> {
+        let _timer = QueryTimer::start("get_2fa_devices");
+        // Chỉ các thiết bị đã xác minh, thiết bị đang chờ không được dùng để đăng nhập
+        let query = format!("SELECT {} FROM two_factor_devices WHERE user_id = $1 AND verified_at IS NOT NULL ORDER BY created_at", DEVICE_COLUMNS);
+
+        match sqlx::query(&query)
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await
+        {
+            Ok(rows) => Ok(rows.iter().map(device_from_row).collect()),
+            Err(e) => {
+                eprintln!("Error getting 2FA devices: {:?}", e);
+                Err(UserError::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    async fn get_2fa_devices_page(
+        &self,
+        user_id: &str,
+        page: i64,
+        page_size: i64,
+    ) -> Result<(Vec<TwoFactorDevice>, i64), UserError> {
+        let _timer = QueryTimer::start("get_2fa_devices_page");
+        let db_error = |e: sqlx::Error| {
+            eprintln!("Error getting 2FA devices: {:?}", e);
+            UserError::DatabaseError(e.to_string())
+        };
+
+        // Gồm cả thiết bị đang chờ để người dùng còn xác minh hoặc xoá được
+        let mut query = PaginatedQuery::new("two_factor_devices", DEVICE_COLUMNS);
+        let user = query.bind(user_id);
+        query
+            .condition(format!("user_id = {}", user))
+            // uuid giữ thứ tự ổn định giữa các trang khi hai thiết bị thêm cùng lúc
+            .order_by("created_at")
+            .order_by("uuid")
+            .paginate(page, page_size);
+
+        let total = query.fetch_count(&self.pool).await.map_err(db_error)?;
+        let rows = query.fetch_page(&self.pool).await.map_err(db_error)?;
+
+        Ok((rows.iter().map(device_from_row).collect(), total))
+    }
+
+    async fn add_2fa_device(
+        &self,
+        user_id: &str,
+        name: &str,
+        secret: &str,
+    ) -> Result<TwoFactorDevice, UserError> {
+        let _timer = QueryTimer::start("add_2fa_device");
+        let uuid = Uuid::new_v4().to_string();
+        let now = Utc::now();
+        // Thiết bị mới ở trạng thái chờ cho đến khi `verify_2fa_device` xác nhận một mã từ nó
+        let query = "INSERT INTO two_factor_devices (uuid, user_id, name, secret, created_at) VALUES ($1, $2, $3, $4, $5)";
+
+        match sqlx::query(query)
+            .bind(&uuid)
+            .bind(user_id)
+            .bind(name)
+            .bind(secret)
+            .bind(now)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(_) => Ok(TwoFactorDevice {
+                uuid,
+                user_id: user_id.to_string(),
+                name: name.to_string(),
+                secret: secret.to_string(),
+                created_at: now.to_string(),
+                created_at_utc: now,
+                verified_at: None,
+            }),
+            Err(e) => {
+                eprintln!("Error adding 2FA device: {:?}", e);
+                Err(UserError::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    async fn get_2fa_device(
+        &self,
+        user_id: &str,
+        device_uuid: &str,
+    ) -> Result<TwoFactorDevice, UserError> {
+        let _timer = QueryTimer::start("get_2fa_device");
+        let query = format!(
+            "SELECT {} FROM two_factor_devices WHERE uuid = $1 AND user_id = $2",
+            DEVICE_COLUMNS
+        );
+
+        match sqlx::query(&query)
+            .bind(device_uuid)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await
+        {
+            Ok(Some(row)) => Ok(device_from_row(&row)),
+            Ok(None) => Err(UserError::BadRequest("2FA device not found".to_string())),
+            Err(e) => {
+                eprintln!("Error getting 2FA device: {:?}", e);
+                Err(UserError::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    async fn verify_2fa_device(&self, user_id: &str, device_uuid: &str) -> Result<(), UserError> {
+        let _timer = QueryTimer::start("verify_2fa_device");
+        // Kích hoạt một thiết bị đang chờ; thiết bị đã kích hoạt hoặc không tồn tại thì báo lỗi
+        let query = "UPDATE two_factor_devices SET verified_at = $1 WHERE uuid = $2 AND user_id = $3 AND verified_at IS NULL";
+
+        match sqlx::query(query)
+            .bind(Utc::now())
+            .bind(device_uuid)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(result) if result.rows_affected() == 0 => Err(UserError::BadRequest(
+                "2FA device not found or already verified".to_string(),
+            )),
+            Ok(_) => Ok(()),
+            Err(e) => {
+                eprintln!("Error verifying 2FA device: {:?}", e);
+                Err(UserError::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    async fn remove_2fa_device(&self, user_id: &str, device_uuid: &str) -> Result<(), UserError> {
+        let _timer = QueryTimer::start("remove_2fa_device");
+        let query = "DELETE FROM two_factor_devices WHERE uuid = $1 AND user_id = $2";
+
+        match sqlx::query(query)
+            .bind(device_uuid)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(result) if result.rows_affected() == 0 => {
+                Err(UserError::BadRequest("2FA device not found".to_string()))
+            }
+            Ok(_) => Ok(()),
+            Err(e) => {
+                eprintln!("Error removing 2FA device: {:?}", e);
+                Err(UserError::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    async fn get_backup_code_sets(&self, user_id: &str) -> Result<Vec<BackupCodeSet>, UserError> {
+        let _timer = QueryTimer::start("get_backup_code_sets");
+        // Bộ đã dùng hết mã thì coi như không còn hiệu lực
+        let query = format!("SELECT {} FROM backup_code_sets WHERE user_id = $1 AND cardinality(codes) > 0 ORDER BY created_at", BACKUP_CODE_SET_COLUMNS);
+
+        match sqlx::query(&query)
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await
+        {
+            Ok(rows) => Ok(rows.iter().map(backup_code_set_from_row).collect()),
+            Err(e) => {
+                eprintln!("Error getting backup code sets: {:?}", e);
+                Err(UserError::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    async fn get_backup_code_sets_page(
+        &self,
+        user_id: &str,
+        page: i64,
+        page_size: i64,
+    ) -> Result<(Vec<BackupCodeSet>, i64), UserError> {
+        let _timer = QueryTimer::start("get_backup_code_sets_page");
+        let db_error = |e: sqlx::Error| {
+            eprintln!("Error getting backup code sets: {:?}", e);
+            UserError::DatabaseError(e.to_string())
+        };
+
+        // Cùng điều kiện với get_backup_code_sets: bộ đã dùng hết mã không được liệt kê
+        let mut query = PaginatedQuery::new("backup_code_sets", BACKUP_CODE_SET_COLUMNS);
+        let user = query.bind(user_id);
+        query
+            .condition(format!("user_id = {}", user))
+            .condition("cardinality(codes) > 0")
+            .order_by("created_at")
+            .order_by("uuid")
+            .paginate(page, page_size);
+
+        let total = query.fetch_count(&self.pool).await.map_err(db_error)?;
+        let rows = query.fetch_page(&self.pool).await.map_err(db_error)?;
+
+        Ok((rows.iter().map(backup_code_set_from_row).collect(), total))
+    }
+
+    async fn add_backup_code_set(
+        &self,
+        user_id: &str,
+        codes: &[String],
+        replace_existing: bool,
+    ) -> Result<BackupCodeSet, UserError> {
+        let _timer = QueryTimer::start("add_backup_code_set");
+        let db_error = |e: sqlx::Error| {
+            eprintln!("Error adding backup code set: {:?}", e);
+            UserError::DatabaseError(e.to_string())
+        };
+        let uuid = Uuid::new_v4().to_string();
+        let now = Utc::now();
+
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+
+        // Xóa các bộ cũ và thêm bộ mới trong cùng transaction để không có lúc user mất hết mã
+        if replace_existing {
+            sqlx::query("DELETE FROM backup_code_sets WHERE user_id = $1")
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(db_error)?;
+        }
+
+        sqlx::query(
+            "INSERT INTO backup_code_sets (uuid, user_id, codes, created_at) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(&uuid)
+        .bind(user_id)
+        .bind(codes)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+        tx.commit().await.map_err(db_error)?;
+
+        Ok(BackupCodeSet {
+            uuid,
+            user_id: user_id.to_string(),
+            codes: codes.to_vec(),
+            created_at: now.to_string(),
+        })
+    }
+
+    async fn consume_backup_code(&self, user_id: &str, code_hash: &str) -> Result<bool, UserError> {
+        let _timer = QueryTimer::start("consume_backup_code");
+        // Tìm và xóa mã trong một câu lệnh để hai request cùng lúc không dùng được một mã hai lần
+        let query = "UPDATE backup_code_sets SET codes = array_remove(codes, $2) \
+            WHERE id = (SELECT id FROM backup_code_sets WHERE user_id = $1 AND $2 = ANY(codes) LIMIT 1) \
+            RETURNING uuid";
+
+        match sqlx::query(query)
+            .bind(user_id)
+            .bind(code_hash)
+            .fetch_optional(&self.pool)
+            .await
+        {
+            Ok(row) => Ok(row.is_some()),
+            Err(e) => {
+                eprintln!("Error consuming backup code: {:?}", e);
+                Err(UserError::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    async fn has_backup_code(&self, user_id: &str, code_hash: &str) -> Result<bool, UserError> {
+        let _timer = QueryTimer::start("has_backup_code");
+        // Chỉ kiểm tra, không xóa mã; đọc từ pool chính để mã vừa dùng không còn được coi là hợp lệ
+        let query = "SELECT EXISTS(SELECT 1 FROM backup_code_sets WHERE user_id = $1 AND $2 = ANY(codes)) AS found";
+
+        match sqlx::query(query)
+            .bind(user_id)
+            .bind(code_hash)
+            .fetch_one(&self.pool)
+            .await
+        {
+            Ok(row) => Ok(row.get("found")),
+            Err(e) => {
+                eprintln!("Error checking backup code: {:?}", e);
+                Err(UserError::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    async fn revoke_backup_code_set(&self, user_id: &str, set_uuid: &str) -> Result<(), UserError> {
+        let _timer = QueryTimer::start("revoke_backup_code_set");
+        let query = "DELETE FROM backup_code_sets WHERE uuid = $1 AND user_id = $2";
+
+        match sqlx::query(query)
+            .bind(set_uuid)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(result) if result.rows_affected() == 0 => Err(UserError::BadRequest(
+                "Backup code set not found".to_string(),
+            )),
+            Ok(_) => Ok(()),
+            Err(e) => {
+                eprintln!("Error revoking backup code set: {:?}", e);
+                Err(UserError::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    async fn get_password_history(
+        &self,
+        user_id: &str,
+        limit: usize,
+    ) -> Result<Vec<String>, UserError> {
+        let _timer = QueryTimer::start("get_password_history");
+        let query = "SELECT password_hash FROM password_history WHERE user_id = $1 ORDER BY created_at DESC LIMIT $2";
+
+        match sqlx::query(query)
+            .bind(user_id)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await
+        {
+            Ok(rows) => Ok(rows.iter().map(|row| row.get("password_hash")).collect()),
+            Err(e) => {
+                eprintln!("Error getting password history: {:?}", e);
+                Err(UserError::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    async fn change_password(
+        &self,
+        user_id: &str,
+        new_hash: &str,
+        keep_history: usize,
+    ) -> Result<(), UserError> {
+        let _timer = QueryTimer::start("change_password");
+        let user_id = user_id.to_string();
+        let new_hash = new_hash.to_string();
+
+        self.transaction(|tx| {
+            Box::pin(async move {
+                // Lưu hash cũ vào lịch sử trước khi ghi đè, chỉ giữ lại keep_history bản gần nhất
+                if keep_history > 0 {
+                    sqlx::query(
+                        "INSERT INTO password_history (user_id, password_hash, created_at) \
+                         SELECT uuid, password, $2 FROM users WHERE uuid = $1",
+                    )
+                    .bind(&user_id)
+                    .bind(Utc::now())
+                    .execute(&mut *tx)
+                    .await?;
+                }
+
+                sqlx::query(
+                    "DELETE FROM password_history WHERE user_id = $1 AND id NOT IN \
+                     (SELECT id FROM password_history WHERE user_id = $1 ORDER BY created_at DESC LIMIT $2)",
+                )
+                .bind(&user_id)
+                .bind(keep_history as i64)
+                .execute(&mut *tx)
+                .await?;
+
+                sqlx::query("UPDATE users SET password = $1, updated_at = $2 WHERE uuid = $3")
+                    .bind(&new_hash)
+                    .bind(Utc::now())
+                    .bind(&user_id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                Ok(())
+            })
+        })
+        .await
+        .map_err(|e: sqlx::Error| {
+            eprintln!("Error changing password: {:?}", e);
+            UserError::DatabaseError(e.to_string())
+        })
+    }
+
+    async fn get_user_preferences(&self, user_id: &str) -> Result<UserPreferences, UserError> {
+        let _timer = QueryTimer::start("get_user_preferences");
+        let query =
+            "SELECT sort_by, sort_order, page_size FROM user_preferences WHERE user_id = $1";
+
+        match sqlx::query(query)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await
+        {
+            // Giá trị cũ không còn hợp lệ (hoặc relevance, chỉ dùng khi tìm kiếm) coi như chưa đặt
+            Ok(Some(row)) => Ok(UserPreferences {
+                sort_by: row
+                    .get::<Option<String>, _>("sort_by")
+                    .as_deref()
+                    .and_then(SortField::from_name)
+                    .filter(|field| *field != SortField::Relevance),
+                sort_order: row
+                    .get::<Option<String>, _>("sort_order")
+                    .as_deref()
+                    .and_then(SortOrder::from_name),
+                page_size: row.get("page_size"),
+            }),
+            Ok(None) => Ok(UserPreferences::default()),
+            Err(e) => {
+                eprintln!("Error getting user preferences: {:?}", e);
+                Err(UserError::DatabaseError(e.to_string()))
             }
         }
     }
+
+    async fn update_user_preferences(
+        &self,
+        user_id: &str,
+        preferences: &UserPreferences,
+    ) -> Result<UserPreferences, UserError> {
+        let _timer = QueryTimer::start("update_user_preferences");
+        let query = "INSERT INTO user_preferences (user_id, sort_by, sort_order, page_size, updated_at) \
+            VALUES ($1, $2, $3, $4, $5) \
+            ON CONFLICT (user_id) DO UPDATE SET sort_by = EXCLUDED.sort_by, \
+            sort_order = EXCLUDED.sort_order, page_size = EXCLUDED.page_size, updated_at = EXCLUDED.updated_at";
+
+        match sqlx::query(query)
+            .bind(user_id)
+            .bind(preferences.sort_by.map(SortField::column))
+            .bind(preferences.sort_order.map(SortOrder::as_str))
+            .bind(preferences.page_size)
+            .bind(Utc::now())
+            .execute(&self.pool)
+            .await
+        {
+            Ok(_) => self.get_user_preferences(user_id).await,
+            Err(e) => {
+                eprintln!("Error updating user preferences: {:?}", e);
+                Err(UserError::DatabaseError(e.to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_support::{create_test_user, test_database};
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn disable_2fa_clears_secrets_devices_and_backup_codes() {
+        let db = test_database().await;
+        let user = create_test_user(&db).await;
+        db.enable_2fa(&user.uuid, "PENDINGSECRET").await.unwrap();
+        db.confirm_2fa(&user.uuid, &["hash-1".to_string(), "hash-2".to_string()])
+            .await
+            .unwrap();
+        let device = db
+            .add_2fa_device(&user.uuid, "Phone", "DEVICESECRET")
+            .await
+            .unwrap();
+        db.verify_2fa_device(&user.uuid, &device.uuid)
+            .await
+            .unwrap();
+
+        db.disable_2fa(&user.uuid).await.unwrap();
+
+        let user = db.get_user_by_uuid(&user.uuid).await.unwrap();
+        assert!(!user.two_factor_enabled);
+        assert_eq!(user.two_factor_secret, None);
+        assert_eq!(user.two_factor_pending_secret, None);
+        assert_eq!(user.two_factor_pending_since, None);
+        assert!(db
+            .get_backup_code_sets(&user.uuid)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(db.get_2fa_devices(&user.uuid).await.unwrap().is_empty());
+        assert!(!db.has_backup_code(&user.uuid, "hash-1").await.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn disable_2fa_twice_is_a_no_op() {
+        let db = test_database().await;
+        let user = create_test_user(&db).await;
+        db.enable_2fa(&user.uuid, "PENDINGSECRET").await.unwrap();
+        db.confirm_2fa(&user.uuid, &["hash-1".to_string()])
+            .await
+            .unwrap();
+
+        db.disable_2fa(&user.uuid).await.unwrap();
+        db.disable_2fa(&user.uuid).await.unwrap();
+
+        let user = db.get_user_by_uuid(&user.uuid).await.unwrap();
+        assert!(!user.two_factor_enabled);
+        assert_eq!(user.two_factor_secret, None);
+        assert!(db
+            .get_backup_code_sets(&user.uuid)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn change_password_keeps_only_the_latest_old_hashes() {
+        let db = test_database().await;
+        let user = create_test_user(&db).await;
+
+        for hash in ["hash-1", "hash-2", "hash-3"] {
+            db.change_password(&user.uuid, hash, 2).await.unwrap();
+        }
+
+        let stored = db.get_user_by_uuid(&user.uuid).await.unwrap();
+        assert_eq!(stored.password, "hash-3");
+        let history = db.get_password_history(&user.uuid, 10).await.unwrap();
+        assert_eq!(history, vec!["hash-2".to_string(), "hash-1".to_string()]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn added_device_is_pending_until_verified() {
+        let db = test_database().await;
+        let user = create_test_user(&db).await;
+
+        let device = db
+            .add_2fa_device(&user.uuid, "Tablet", "DEVICESECRET")
+            .await
+            .unwrap();
+
+        assert_eq!(device.verified_at, None);
+        assert!(db.get_2fa_devices(&user.uuid).await.unwrap().is_empty());
+        let (listed, total) = db.get_2fa_devices_page(&user.uuid, 1, 10).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(listed[0].verified_at, None);
+
+        db.verify_2fa_device(&user.uuid, &device.uuid)
+            .await
+            .unwrap();
+
+        let active = db.get_2fa_devices(&user.uuid).await.unwrap();
+        assert_eq!(active.len(), 1);
+        assert!(active[0].verified_at.is_some());
+        assert!(matches!(
+            db.verify_2fa_device(&user.uuid, &device.uuid).await,
+            Err(UserError::BadRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn another_users_device_cannot_be_verified() {
+        let db = test_database().await;
+        let owner = create_test_user(&db).await;
+        let other = create_test_user(&db).await;
+        let device = db
+            .add_2fa_device(&owner.uuid, "Tablet", "DEVICESECRET")
+            .await
+            .unwrap();
+
+        assert!(db
+            .verify_2fa_device(&other.uuid, &device.uuid)
+            .await
+            .is_err());
+        assert!(db.get_2fa_device(&other.uuid, &device.uuid).await.is_err());
+        assert_eq!(
+            db.get_2fa_device(&owner.uuid, &device.uuid)
+                .await
+                .unwrap()
+                .verified_at,
+            None
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn disable_2fa_of_unknown_user_fails() {
+        let db = test_database().await;
+        let result = db.disable_2fa(&Uuid::new_v4().to_string()).await;
+        assert!(matches!(result, Err(UserError::NoSuchUserFound)));
+    }
 }
@@ -1,11 +1,115 @@
 use crate::db::redis_client::RedisClient;
-use log::error;
+use futures_util::future::BoxFuture;
+use log::{error, info, warn};
 use sqlx::postgres::PgPoolOptions;
-use sqlx::PgPool;
+use sqlx::{Connection, PgConnection, PgPool};
 use std::env;
+use std::fmt::Display;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Chờ trước lần thử lại đầu tiên, gấp đôi sau mỗi lần thất bại
+const INITIAL_CONNECT_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_CONNECT_BACKOFF: Duration = Duration::from_secs(10);
+/// Thời gian tối đa của một lần thử
+const CONNECT_ATTEMPT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Thao tác Redis lâu hơn ngưỡng này (ms) bị log là chậm khi REDIS_SLOW_OP_THRESHOLD_MS không được đặt
+const DEFAULT_REDIS_SLOW_OP_THRESHOLD_MS: u64 = 100;
+
+/// Giới hạn thử lại khi kết nối Postgres và Redis lúc khởi động, đọc từ
+/// `STARTUP_CONNECT_MAX_ATTEMPTS` và `STARTUP_CONNECT_TIMEOUT_SECONDS`
+///
+/// Trong docker-compose database thường khởi động chậm hơn app một chút.
+struct ConnectRetry {
+    max_attempts: u32,
+    timeout: Duration,
+}
+
+impl ConnectRetry {
+    fn from_env() -> Self {
+        let max_attempts = env::var("STARTUP_CONNECT_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(10u32)
+            .max(1);
+        let timeout_seconds = env::var("STARTUP_CONNECT_TIMEOUT_SECONDS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(60);
+
+        Self {
+            max_attempts,
+            timeout: Duration::from_secs(timeout_seconds),
+        }
+    }
+
+    /// Gọi `connect` đến khi thành công, hết số lần thử hoặc hết thời gian; trả về lỗi cuối cùng
+    ///
+    /// Mỗi lần thử bị cắt sau `CONNECT_ATTEMPT_TIMEOUT` để một host không trả lời không giữ
+    /// khởi động quá lâu.
+    async fn run<T, E, F, Fut>(&self, target: &str, mut connect: F) -> Result<T, String>
+    where
+        E: Display,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let started_at = Instant::now();
+        let mut backoff = INITIAL_CONNECT_BACKOFF;
+        let mut attempt = 1;
+
+        loop {
+            info!(
+                "Connecting to {} (attempt {}/{})",
+                target, attempt, self.max_attempts
+            );
+            let remaining = self.timeout.saturating_sub(started_at.elapsed());
+            let error = match tokio::time::timeout(
+                remaining.clamp(Duration::from_secs(1), CONNECT_ATTEMPT_TIMEOUT),
+                connect(),
+            )
+            .await
+            {
+                Ok(Ok(value)) => return Ok(value),
+                Ok(Err(e)) => e.to_string(),
+                Err(_) => "timed out".to_string(),
+            };
+
+            let remaining = self.timeout.saturating_sub(started_at.elapsed());
+            if attempt >= self.max_attempts || remaining.is_zero() {
+                error!(
+                    "Giving up on {} after {} attempt(s): {}",
+                    target, attempt, error
+                );
+                return Err(error);
+            }
+
+            let delay = backoff.min(remaining);
+            warn!(
+                "Failed to connect to {}: {}; retrying in {:.1}s",
+                target,
+                error,
+                delay.as_secs_f64()
+            );
+            tokio::time::sleep(delay).await;
+            backoff = (backoff * 2).min(MAX_CONNECT_BACKOFF);
+            attempt += 1;
+        }
+    }
+}
+
+/// Mở một kết nối thử trước để lỗi thật (ví dụ connection refused) được ghi log ngay, vì
+/// pool tự thử lại bên trong đến hết `acquire_timeout` rồi chỉ báo hết thời gian
+async fn connect_pool(url: &str) -> Result<PgPool, sqlx::Error> {
+    PgConnection::connect(url).await?.close().await?;
+    PgPoolOptions::new().max_connections(5).connect(url).await
+}
 
 pub struct Database {
     pub pool: PgPool,
+    /// Read-only replica for list and lookup queries, the primary pool when none is configured
+    ///
+    /// Replicas lag behind the primary, so a read right after a write may miss it.
+    pub read_pool: PgPool,
     pub redis_client: RedisClient,
 }
 
@@ -15,18 +119,78 @@ impl Database {
         let redis_url =
             env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
 
-        let pool = PgPoolOptions::new()
-            .max_connections(5)
-            .connect(&database_url)
+        let retry = ConnectRetry::from_env();
+
+        let pool = retry
+            .run("Postgres", || connect_pool(&database_url))
             .await
             .expect("Failed to connect to Postgres");
 
-        let redis_client = RedisClient::new(&redis_url);
+        let read_pool = match env::var("DATABASE_REPLICA_URL") {
+            Ok(replica_url) if !replica_url.trim().is_empty() => {
+                info!("Routing read queries to the database replica");
+                retry
+                    .run("the Postgres replica", || connect_pool(replica_url.trim()))
+                    .await
+                    .expect("Failed to connect to the Postgres replica")
+            }
+            _ => pool.clone(),
+        };
 
-        if let Err(e) = redis_client.check_connection().await {
-            error!("Failed to connect to Redis: {}", e);
+        // Tắt mặc định; bật khi các trang danh sách lớn chiếm nhiều bộ nhớ Redis
+        let compress_min_bytes = env::var("CACHE_COMPRESS_MIN_BYTES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+        // Redis ở xa (dịch vụ managed khác vùng) làm chậm mọi request có cache, log để thấy được
+        let slow_op_threshold_ms = env::var("REDIS_SLOW_OP_THRESHOLD_MS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_REDIS_SLOW_OP_THRESHOLD_MS);
+        let redis_client = RedisClient::new(&redis_url)
+            .with_compression(compress_min_bytes)
+            .with_slow_op_log(slow_op_threshold_ms);
+
+        // Vẫn khởi động khi Redis không lên: lệnh cache tự kết nối lại ở lần gọi sau
+        if let Err(e) = retry.run("Redis", || redis_client.check_connection()).await {
+            error!("Starting without Redis: {}", e);
+        }
+
+        Self {
+            pool,
+            read_pool,
+            redis_client,
         }
+    }
+
+    /// Chạy `f` trong một transaction: commit khi trả về `Ok`, rollback khi trả về `Err`
+    ///
+    /// ```ignore
+    /// db.transaction(|tx| Box::pin(async move {
+    ///     sqlx::query("...").execute(&mut *tx).await?;
+    ///     Ok(())
+    /// })).await?;
+    /// ```
+    pub async fn transaction<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        F: for<'c> FnOnce(&'c mut PgConnection) -> BoxFuture<'c, Result<T, E>> + Send,
+        T: Send,
+        E: From<sqlx::Error> + Send,
+    {
+        let mut tx = self.pool.begin().await?;
 
-        Self { pool, redis_client }
+        match f(&mut tx).await {
+            Ok(value) => {
+                tx.commit().await?;
+                Ok(value)
+            }
+            Err(e) => {
+                // Lỗi gốc quan trọng hơn lỗi rollback, nên chỉ ghi log lỗi rollback
+                if let Err(rollback_error) = tx.rollback().await {
+                    error!("Failed to roll back transaction: {}", rollback_error);
+                }
+                Err(e)
+            }
+        }
     }
 }
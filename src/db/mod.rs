@@ -1,3 +1,6 @@
 pub mod data_trait;
 pub mod database;
+pub mod paginated_query;
 pub mod redis_client;
+#[cfg(test)]
+pub mod test_support;
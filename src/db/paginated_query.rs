@@ -0,0 +1,289 @@
+use chrono::{DateTime, Utc};
+use sqlx::postgres::{PgArguments, PgRow};
+use sqlx::query::Query;
+use sqlx::{PgPool, Postgres, Row};
+
+/// A value bound to a placeholder, kept typed so conditions don't need `::BOOLEAN` style casts
+#[derive(Clone, Debug, PartialEq)]
+pub enum SqlParam {
+    Text(String),
+    Bool(bool),
+    Int(i64),
+    Timestamp(DateTime<Utc>),
+}
+
+impl From<String> for SqlParam {
+    fn from(value: String) -> Self {
+        SqlParam::Text(value)
+    }
+}
+
+impl From<&str> for SqlParam {
+    fn from(value: &str) -> Self {
+        SqlParam::Text(value.to_string())
+    }
+}
+
+impl From<bool> for SqlParam {
+    fn from(value: bool) -> Self {
+        SqlParam::Bool(value)
+    }
+}
+
+impl From<i64> for SqlParam {
+    fn from(value: i64) -> Self {
+        SqlParam::Int(value)
+    }
+}
+
+impl From<DateTime<Utc>> for SqlParam {
+    fn from(value: DateTime<Utc>) -> Self {
+        SqlParam::Timestamp(value)
+    }
+}
+
+/// Builds the count and page queries of a list endpoint from one set of conditions
+///
+/// Values only ever reach the SQL through `bind`, which hands back the placeholder to use,
+/// so callers never count parameter indexes by hand. A client-chosen sort column must come from
+/// a typed enum such as `SortField`, never from the raw parameter.
+#[derive(Clone, Debug)]
+pub struct PaginatedQuery {
+    from: String,
+    columns: Vec<String>,
+    conditions: Vec<String>,
+    params: Vec<SqlParam>,
+    order_by: Vec<String>,
+    page: i64,
+    page_size: i64,
+}
+
+impl PaginatedQuery {
+    pub fn new(from: impl Into<String>, columns: impl Into<String>) -> Self {
+        Self {
+            from: from.into(),
+            columns: vec![columns.into()],
+            conditions: Vec::new(),
+            params: Vec::new(),
+            order_by: Vec::new(),
+            page: 1,
+            page_size: 1,
+        }
+    }
+
+    /// Adds a parameter and returns its placeholder (`$1`, `$2`, ...)
+    pub fn bind(&mut self, value: impl Into<SqlParam>) -> String {
+        self.params.push(value.into());
+        format!("${}", self.params.len())
+    }
+
+    /// Adds a condition ANDed with the others, values in it must come from `bind`
+    pub fn condition(&mut self, condition: impl Into<String>) -> &mut Self {
+        self.conditions.push(condition.into());
+        self
+    }
+
+    /// Adds an extra expression to the page query's select list
+    pub fn select(&mut self, expression: impl Into<String>) -> &mut Self {
+        self.columns.push(expression.into());
+        self
+    }
+
+    /// Orders by a fixed expression written by the caller, never by client input
+    pub fn order_by(&mut self, expression: impl Into<String>) -> &mut Self {
+        self.order_by.push(expression.into());
+        self
+    }
+
+    /// Pages are 1-based, anything below 1 is treated as the first page
+    pub fn paginate(&mut self, page: i64, page_size: i64) -> &mut Self {
+        self.page = page.max(1);
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    pub fn page(&self) -> i64 {
+        self.page
+    }
+
+    pub fn page_size(&self) -> i64 {
+        self.page_size
+    }
+
+    pub fn offset(&self) -> i64 {
+        (self.page - 1) * self.page_size
+    }
+
+    /// ` WHERE ...` for the conditions so far, empty when there are none
+    pub fn where_clause(&self) -> String {
+        if self.conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", self.conditions.join(" AND "))
+        }
+    }
+
+    pub fn count_sql(&self) -> String {
+        format!(
+            "SELECT COUNT(*) AS total FROM {}{}",
+            self.from,
+            self.where_clause()
+        )
+    }
+
+    /// The page query; LIMIT and OFFSET take the two placeholders after the bound params
+    pub fn page_sql(&self) -> String {
+        let mut sql = format!(
+            "SELECT {} FROM {}{}",
+            self.columns.join(", "),
+            self.from,
+            self.where_clause()
+        );
+        if !self.order_by.is_empty() {
+            sql.push_str(&format!(" ORDER BY {}", self.order_by.join(", ")));
+        }
+        sql.push_str(&format!(
+            " LIMIT ${} OFFSET ${}",
+            self.params.len() + 1,
+            self.params.len() + 2
+        ));
+        sql
+    }
+
+    /// Binds every parameter, in placeholder order, to a query built from this builder's SQL
+    pub fn bind_to<'q>(
+        &'q self,
+        mut query: Query<'q, Postgres, PgArguments>,
+    ) -> Query<'q, Postgres, PgArguments> {
+        for param in &self.params {
+            query = match param {
+                SqlParam::Text(value) => query.bind(value),
+                SqlParam::Bool(value) => query.bind(*value),
+                SqlParam::Int(value) => query.bind(*value),
+                SqlParam::Timestamp(value) => query.bind(*value),
+            };
+        }
+        query
+    }
+
+    pub async fn fetch_count(&self, pool: &PgPool) -> Result<i64, sqlx::Error> {
+        let count_sql = self.count_sql();
+        let row = self
+            .bind_to(sqlx::query(&count_sql))
+            .fetch_one(pool)
+            .await?;
+        Ok(row.get("total"))
+    }
+
+    pub async fn fetch_page(&self, pool: &PgPool) -> Result<Vec<PgRow>, sqlx::Error> {
+        let page_sql = self.page_sql();
+        self.bind_to(sqlx::query(&page_sql))
+            .bind(self.page_size)
+            .bind(self.offset())
+            .fetch_all(pool)
+            .await
+    }
+}
+
+pub fn total_pages(total: i64, page_size: i64) -> i64 {
+    if page_size <= 0 {
+        return 0;
+    }
+    (total + page_size - 1) / page_size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bind_numbers_placeholders_in_order() {
+        let mut query = PaginatedQuery::new("todos", "uuid");
+        assert_eq!(query.bind("user"), "$1");
+        assert_eq!(query.bind(true), "$2");
+        assert_eq!(query.bind(5_i64), "$3");
+        assert_eq!(
+            query.params,
+            vec![
+                SqlParam::Text("user".to_string()),
+                SqlParam::Bool(true),
+                SqlParam::Int(5)
+            ]
+        );
+    }
+
+    #[test]
+    fn where_clause_is_empty_without_conditions() {
+        let query = PaginatedQuery::new("todos", "uuid");
+        assert_eq!(query.where_clause(), "");
+        assert_eq!(query.count_sql(), "SELECT COUNT(*) AS total FROM todos");
+    }
+
+    #[test]
+    fn where_clause_ands_conditions() {
+        let mut query = PaginatedQuery::new("todos", "uuid");
+        let owner = query.bind("user");
+        let done = query.bind(false);
+        query
+            .condition(format!("owner_id = {}", owner))
+            .condition(format!("is_completed = {}", done));
+        assert_eq!(
+            query.where_clause(),
+            " WHERE owner_id = $1 AND is_completed = $2"
+        );
+    }
+
+    #[test]
+    fn page_sql_puts_limit_and_offset_after_the_params() {
+        let mut query = PaginatedQuery::new("todos", "uuid");
+        let owner = query.bind("user");
+        query
+            .condition(format!("owner_id = {}", owner))
+            .order_by("created_at DESC")
+            .paginate(3, 20);
+        assert_eq!(
+            query.page_sql(),
+            "SELECT uuid FROM todos WHERE owner_id = $1 ORDER BY created_at DESC LIMIT $2 OFFSET $3"
+        );
+        assert_eq!(query.offset(), 40);
+    }
+
+    #[test]
+    fn page_sql_counts_binds_made_after_the_conditions() {
+        // Như search_todos: tham số chỉ dùng trong câu lấy trang được bind sau khi đếm
+        let mut query = PaginatedQuery::new("todos", "uuid");
+        let owner = query.bind("user");
+        query.condition(format!("owner_id = {}", owner));
+        let count_sql = query.count_sql();
+        assert_eq!(query.bind("options"), "$2");
+        assert_eq!(query.bind("more options"), "$3");
+
+        assert_eq!(
+            count_sql,
+            "SELECT COUNT(*) AS total FROM todos WHERE owner_id = $1"
+        );
+        assert!(query.page_sql().ends_with(" LIMIT $4 OFFSET $5"));
+    }
+
+    #[test]
+    fn paginate_clamps_to_the_first_page() {
+        let mut query = PaginatedQuery::new("todos", "uuid");
+        query.paginate(0, -5);
+        assert_eq!(query.page(), 1);
+        assert_eq!(query.page_size(), 1);
+        assert_eq!(query.offset(), 0);
+    }
+
+    #[test]
+    fn total_pages_rounds_up() {
+        assert_eq!(total_pages(0, 10), 0);
+        assert_eq!(total_pages(10, 10), 1);
+        assert_eq!(total_pages(11, 10), 2);
+    }
+
+    #[test]
+    fn total_pages_is_zero_for_non_positive_sizes() {
+        assert_eq!(total_pages(25, 0), 0);
+        assert_eq!(total_pages(25, -10), 0);
+    }
+}
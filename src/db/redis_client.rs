@@ -1,14 +1,161 @@
 use crate::services::cache_service::CacheService;
+use crate::services::metrics_service::CacheTimer;
 use async_trait::async_trait;
-use log::info;
-use redis::{aio::ConnectionManager, AsyncCommands, Client, RedisError};
+use chrono::Utc;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{error, info, warn};
+use redis::{aio::ConnectionManager, AsyncCommands, Client, RedisError, Script};
 use serde::{de::DeserializeOwned, Serialize};
+use std::io::{Read, Write};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// Only deletes the lock when it still holds our token, so an expired lock
+/// re-acquired by another instance is never released by us
+const RELEASE_LOCK_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+else
+    return 0
+end
+"#;
+
+/// Deletes each token listed in the set, then the set itself. Returns the number of
+/// tokens that still existed.
+const REVOKE_USER_TOKENS_SCRIPT: &str = r#"
+local revoked = 0
+for _, token_id in ipairs(redis.call("SMEMBERS", KEYS[1])) do
+    revoked = revoked + redis.call("DEL", token_id)
+end
+redis.call("DEL", KEYS[1])
+return revoked
+"#;
+
+/// Revokes the tokens in the set with the least time left until only ARGV[1] remain, and
+/// returns their ids. Every refresh token gets the same TTL, so least time left is oldest.
+/// Ids whose token already expired or was used are dropped from the set along the way.
+const EVICT_OLDEST_TOKENS_SCRIPT: &str = r#"
+local tokens = {}
+for _, token_id in ipairs(redis.call("SMEMBERS", KEYS[1])) do
+    local ttl = redis.call("TTL", token_id)
+    if ttl == -2 then
+        redis.call("SREM", KEYS[1], token_id)
+    else
+        table.insert(tokens, {token_id, ttl})
+    end
+end
+table.sort(tokens, function(a, b) return a[2] < b[2] end)
+local evicted = {}
+for i = 1, #tokens - tonumber(ARGV[1]) do
+    redis.call("DEL", tokens[i][1])
+    redis.call("SREM", KEYS[1], tokens[i][1])
+    table.insert(evicted, tokens[i][1])
+end
+return evicted
+"#;
+
+/// Consumes refresh token KEYS[1] and marks it used in KEYS[2] for ARGV[1] seconds in one
+/// step, so of two refreshes racing with the same token only one gets it and the other is
+/// seen as a reuse. ARGV[2] is the prefix of the owner's token set. Returns the owner.
+const CONSUME_REFRESH_TOKEN_SCRIPT: &str = r#"
+local user_id = redis.call("GET", KEYS[1])
+if not user_id then
+    return false
+end
+redis.call("DEL", KEYS[1])
+redis.call("SREM", ARGV[2] .. user_id, KEYS[1])
+redis.call("SET", KEYS[2], user_id, "EX", ARGV[1])
+return user_id
+"#;
+
+fn user_tokens_key(user_id: &str) -> String {
+    format!("user_tokens:{}", user_id)
+}
+
+/// Marks a rotated refresh token so presenting it again is told apart from an unknown token
+fn used_token_key(token_id: &str) -> String {
+    format!("used_refresh:{}", token_id)
+}
+
+/// Last activity of a session, expires once the session has been idle for the timeout
+fn session_activity_key(session_id: &str) -> String {
+    format!("session_activity:{}", session_id)
+}
+
+/// Leading bytes of every gzip stream; JSON never starts with them, so they mark compressed values
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// A cached value as stored: gzipped when the JSON is at least `min_bytes` long, raw otherwise
+fn encode_cached(json: String, min_bytes: Option<usize>) -> Vec<u8> {
+    match min_bytes {
+        Some(min_bytes) if json.len() >= min_bytes => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+            match encoder
+                .write_all(json.as_bytes())
+                .and_then(|_| encoder.finish())
+            {
+                Ok(compressed) => compressed,
+                Err(e) => {
+                    warn!("Failed to compress cached value, storing it raw: {}", e);
+                    json.into_bytes()
+                }
+            }
+        }
+        _ => json.into_bytes(),
+    }
+}
+
+/// The JSON of a stored value, whether or not it was compressed; `None` when unreadable
+fn decode_cached(stored: Vec<u8>) -> Option<String> {
+    if !stored.starts_with(&GZIP_MAGIC) {
+        return String::from_utf8(stored).ok();
+    }
+
+    let mut json = String::new();
+    GzDecoder::new(stored.as_slice())
+        .read_to_string(&mut json)
+        .ok()?;
+    Some(json)
+}
 
 pub struct RedisClient {
     pub client: Client,
     connection_manager: Arc<Mutex<Option<ConnectionManager>>>,
+    /// `set_cached` gzips values at least this long, `None` stores everything raw
+    compress_min_bytes: Option<usize>,
+    /// Operations taking at least this long are logged as slow, `None` logs none
+    slow_op_threshold: Option<Duration>,
+}
+
+/// Times one Redis operation, from getting the connection to the last reply
+///
+/// Adds it to the request's cache time and warns when it reaches the slow threshold.
+struct OpTimer {
+    operation: &'static str,
+    started_at: Instant,
+    slow_threshold: Option<Duration>,
+    _request_timer: Option<CacheTimer>,
+}
+
+impl Drop for OpTimer {
+    fn drop(&mut self) {
+        let Some(threshold) = self.slow_threshold else {
+            return;
+        };
+        let elapsed = self.started_at.elapsed();
+        if elapsed >= threshold {
+            warn!(
+                "Slow Redis operation {} took {} ms (threshold {} ms)",
+                self.operation,
+                elapsed.as_millis(),
+                threshold.as_millis()
+            );
+        }
+    }
 }
 
 impl RedisClient {
@@ -18,7 +165,38 @@ impl RedisClient {
         Self {
             client,
             connection_manager: Arc::new(Mutex::new(None)),
+            compress_min_bytes: None,
+            slow_op_threshold: None,
+        }
+    }
+
+    /// Compresses cached values of at least `min_bytes`, 0 leaves compression off
+    ///
+    /// Reads handle both forms either way, so the threshold can change without flushing Redis.
+    pub fn with_compression(mut self, min_bytes: usize) -> Self {
+        self.compress_min_bytes = (min_bytes > 0).then_some(min_bytes);
+        self
+    }
+
+    /// Warns about operations taking at least `threshold_ms`, 0 leaves the log off
+    pub fn with_slow_op_log(mut self, threshold_ms: u64) -> Self {
+        self.slow_op_threshold = (threshold_ms > 0).then(|| Duration::from_millis(threshold_ms));
+        self
+    }
+
+    /// `None` when neither the slow log nor the request timing needs the operation timed
+    fn time_op(&self, operation: &'static str) -> Option<OpTimer> {
+        let request_timer = CacheTimer::start();
+        if self.slow_op_threshold.is_none() && request_timer.is_none() {
+            return None;
         }
+
+        Some(OpTimer {
+            operation,
+            started_at: Instant::now(),
+            slow_threshold: self.slow_op_threshold,
+            _request_timer: request_timer,
+        })
     }
 
     async fn get_conn(&self) -> Result<ConnectionManager, RedisError> {
@@ -31,73 +209,404 @@ impl RedisClient {
         Ok(manager.as_ref().unwrap().clone())
     }
 
+    /// Stores a refresh token and adds it to the user's token set, see `revoke_all_user_tokens`
     pub async fn store_token_state(
         &self,
         token_id: &str,
         user_id: &str,
         ttl_seconds: u64,
     ) -> Result<(), RedisError> {
+        let _timer = self.time_op("store_token_state");
         let mut conn = self.get_conn().await?;
-        let _: () = redis::cmd("SET")
+        let set_key = user_tokens_key(user_id);
+        // The set lives as long as the newest token in it
+        let _: () = redis::pipe()
+            .atomic()
+            .cmd("SET")
             .arg(token_id)
             .arg(user_id)
             .arg("EX")
             .arg(ttl_seconds)
+            .ignore()
+            .cmd("SADD")
+            .arg(&set_key)
+            .arg(token_id)
+            .ignore()
+            .cmd("EXPIRE")
+            .arg(&set_key)
+            .arg(ttl_seconds)
+            .ignore()
             .query_async(&mut conn)
             .await?;
         Ok(())
     }
 
+    /// Deletes every refresh token issued to a user, returns how many were still valid
+    pub async fn revoke_all_user_tokens(&self, user_id: &str) -> Result<u64, RedisError> {
+        let _timer = self.time_op("revoke_all_user_tokens");
+        let mut conn = self.get_conn().await?;
+
+        Script::new(REVOKE_USER_TOKENS_SCRIPT)
+            .key(user_tokens_key(user_id))
+            .invoke_async(&mut conn)
+            .await
+    }
+
+    /// Revokes a user's oldest refresh tokens so at most `keep` remain, returns the revoked ids
+    pub async fn evict_oldest_user_tokens(
+        &self,
+        user_id: &str,
+        keep: usize,
+    ) -> Result<Vec<String>, RedisError> {
+        let _timer = self.time_op("evict_oldest_user_tokens");
+        let mut conn = self.get_conn().await?;
+
+        Script::new(EVICT_OLDEST_TOKENS_SCRIPT)
+            .key(user_tokens_key(user_id))
+            .arg(keep)
+            .invoke_async(&mut conn)
+            .await
+    }
+
+    /// Id and seconds left of each of a user's refresh tokens, soonest to expire first
+    pub async fn user_sessions(&self, user_id: &str) -> Result<Vec<(String, u64)>, RedisError> {
+        let _timer = self.time_op("user_sessions");
+        let mut conn = self.get_conn().await?;
+
+        let token_ids: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(user_tokens_key(user_id))
+            .query_async(&mut conn)
+            .await?;
+        if token_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut pipe = redis::pipe();
+        for token_id in &token_ids {
+            pipe.cmd("TTL").arg(token_id);
+        }
+        let ttls: Vec<i64> = pipe.query_async(&mut conn).await?;
+
+        // Used or revoked tokens may linger in the set after their key is gone (TTL -2)
+        let mut sessions: Vec<(String, u64)> = token_ids
+            .into_iter()
+            .zip(ttls)
+            .filter_map(|(token_id, ttl)| u64::try_from(ttl).ok().map(|ttl| (token_id, ttl)))
+            .collect();
+        sessions.sort_unstable_by_key(|&(_, ttl)| ttl);
+        Ok(sessions)
+    }
+
+    /// Looks up a token's owner without consuming it
+    pub async fn get_token_state(&self, token_id: &str) -> Result<Option<String>, RedisError> {
+        let _timer = self.time_op("get_token_state");
+        let mut conn = self.get_conn().await?;
+
+        redis::cmd("GET").arg(token_id).query_async(&mut conn).await
+    }
+
+    /// Consumes a refresh token and remembers it as used for `used_ttl_seconds`, see
+    /// `used_token_owner`
     pub async fn validate_and_invalidate_token(
         &self,
         token_id: &str,
+        used_ttl_seconds: u64,
     ) -> Result<Option<String>, RedisError> {
+        let _timer = self.time_op("validate_and_invalidate_token");
         let mut conn = self.get_conn().await?;
 
-        let user_id: Option<String> = redis::cmd("GET")
-            .arg(token_id)
+        Script::new(CONSUME_REFRESH_TOKEN_SCRIPT)
+            .key(token_id)
+            .key(used_token_key(token_id))
+            .arg(used_ttl_seconds.max(1))
+            .arg(user_tokens_key(""))
+            .invoke_async(&mut conn)
+            .await
+    }
+
+    /// Owner of a refresh token that was already rotated, `None` if it never was
+    pub async fn used_token_owner(&self, token_id: &str) -> Result<Option<String>, RedisError> {
+        let _timer = self.time_op("used_token_owner");
+        let mut conn = self.get_conn().await?;
+
+        redis::cmd("GET")
+            .arg(used_token_key(token_id))
+            .query_async(&mut conn)
+            .await
+    }
+
+    /// Records activity on a new session, which then stays alive while `touch_session` finds it
+    pub async fn start_session(
+        &self,
+        session_id: &str,
+        idle_timeout_seconds: u64,
+    ) -> Result<(), RedisError> {
+        let _timer = self.time_op("start_session");
+        let mut conn = self.get_conn().await?;
+
+        redis::cmd("SET")
+            .arg(session_activity_key(session_id))
+            .arg(Utc::now().timestamp())
+            .arg("EX")
+            .arg(idle_timeout_seconds)
+            .query_async(&mut conn)
+            .await
+    }
+
+    /// Moves a session's last activity to now, `false` when it was idle past the timeout
+    /// (or never started) and has to be signed out
+    pub async fn touch_session(
+        &self,
+        session_id: &str,
+        idle_timeout_seconds: u64,
+    ) -> Result<bool, RedisError> {
+        let _timer = self.time_op("touch_session");
+        let mut conn = self.get_conn().await?;
+
+        // XX only updates an existing key, so an expired session is never revived
+        let updated: Option<String> = redis::cmd("SET")
+            .arg(session_activity_key(session_id))
+            .arg(Utc::now().timestamp())
+            .arg("XX")
+            .arg("EX")
+            .arg(idle_timeout_seconds)
             .query_async(&mut conn)
             .await?;
+        Ok(updated.is_some())
+    }
 
-        if user_id.is_some() {
-            let _: () = redis::cmd("DEL")
-                .arg(token_id)
+    /// Counts a hit in a fixed window and returns the number of hits so far
+    pub async fn hit_rate_limit(&self, key: &str, window_seconds: u64) -> Result<u64, RedisError> {
+        let _timer = self.time_op("hit_rate_limit");
+        let mut conn = self.get_conn().await?;
+
+        let count: u64 = redis::cmd("INCR").arg(key).query_async(&mut conn).await?;
+
+        if count == 1 {
+            let _: () = redis::cmd("EXPIRE")
+                .arg(key)
+                .arg(window_seconds)
                 .query_async(&mut conn)
                 .await?;
         }
 
-        Ok(user_id)
+        Ok(count)
+    }
+
+    /// Seconds until a key expires, `None` when it doesn't exist or has no expiry
+    pub async fn ttl(&self, key: &str) -> Result<Option<u64>, RedisError> {
+        let _timer = self.time_op("ttl");
+        let mut conn = self.get_conn().await?;
+
+        let ttl: i64 = redis::cmd("TTL").arg(key).query_async(&mut conn).await?;
+
+        Ok(u64::try_from(ttl).ok())
+    }
+
+    /// Reads a plain string value, `None` when the key is missing
+    pub async fn get_value(&self, key: &str) -> Result<Option<String>, RedisError> {
+        let _timer = self.time_op("get_value");
+        let mut conn = self.get_conn().await?;
+
+        redis::cmd("GET").arg(key).query_async(&mut conn).await
+    }
+
+    /// Stores a plain string value without an expiry
+    pub async fn set_value(&self, key: &str, value: &str) -> Result<(), RedisError> {
+        let _timer = self.time_op("set_value");
+        let mut conn = self.get_conn().await?;
+        let _: () = redis::cmd("SET")
+            .arg(key)
+            .arg(value)
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Stores a plain string value with an expiry unless the key exists, `true` when stored
+    pub async fn set_value_if_absent(
+        &self,
+        key: &str,
+        value: &str,
+        expiry_seconds: u64,
+    ) -> Result<bool, RedisError> {
+        let _timer = self.time_op("set_value_if_absent");
+        let mut conn = self.get_conn().await?;
+
+        let stored: Option<String> = redis::cmd("SET")
+            .arg(key)
+            .arg(value)
+            .arg("NX")
+            .arg("EX")
+            .arg(expiry_seconds)
+            .query_async(&mut conn)
+            .await?;
+        Ok(stored.is_some())
+    }
+
+    /// Raises version pointers to at least the given versions, see `RAISE_VERSIONS_SCRIPT`
+    pub async fn raise_versions(
+        &self,
+        versions: &[(String, i64)],
+        ttl_seconds: u64,
+    ) -> Result<(), RedisError> {
+        if versions.is_empty() {
+            return Ok(());
+        }
+        let _timer = self.time_op("raise_versions");
+        let mut conn = self.get_conn().await?;
+
+        let script = Script::new(RAISE_VERSIONS_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        for (key, version) in versions {
+            invocation.key(key).arg(*version);
+        }
+        let _: i64 = invocation.arg(ttl_seconds).invoke_async(&mut conn).await?;
+        Ok(())
+    }
+
+    /// Appends an entry to a stream, trimming it to roughly `max_len` entries
+    pub async fn append_to_stream(
+        &self,
+        stream: &str,
+        max_len: usize,
+        fields: &[(&str, String)],
+    ) -> Result<String, RedisError> {
+        let _timer = self.time_op("append_to_stream");
+        let mut conn = self.get_conn().await?;
+
+        let mut cmd = redis::cmd("XADD");
+        cmd.arg(stream).arg("MAXLEN").arg("~").arg(max_len).arg("*");
+        for (field, value) in fields {
+            cmd.arg(*field).arg(value);
+        }
+
+        cmd.query_async(&mut conn).await
+    }
+
+    /// Tries to take a lock shared by every instance, `None` when someone else holds it
+    ///
+    /// The lock expires after `ttl` even if the holder dies without releasing it.
+    pub async fn acquire_lock(
+        &self,
+        name: &str,
+        ttl: Duration,
+    ) -> Result<Option<LockGuard>, RedisError> {
+        let _timer = self.time_op("acquire_lock");
+        let mut conn = self.get_conn().await?;
+        let key = format!("lock:{}", name);
+        let token = Uuid::new_v4().to_string();
+
+        let acquired: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(&token)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis().max(1) as u64)
+            .query_async(&mut conn)
+            .await?;
+
+        Ok(acquired.map(|_| LockGuard {
+            conn,
+            key,
+            token: Some(token),
+        }))
     }
 
     pub async fn check_connection(&self) -> Result<(), RedisError> {
+        let _timer = self.time_op("check_connection");
         let mut conn = self.get_conn().await?;
         let _: () = redis::cmd("PING").query_async(&mut conn).await?;
         Ok(())
     }
 }
 
+/// A held distributed lock, released on drop
+pub struct LockGuard {
+    conn: ConnectionManager,
+    key: String,
+    token: Option<String>,
+}
+
+impl LockGuard {
+    /// Releases the lock now instead of waiting for the guard to be dropped
+    pub async fn release(mut self) -> Result<(), RedisError> {
+        match self.token.take() {
+            Some(token) => release_lock(self.conn.clone(), &self.key, &token).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        let Some(token) = self.token.take() else {
+            return;
+        };
+        // Outside a runtime the lock is simply left to expire
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let conn = self.conn.clone();
+        let key = std::mem::take(&mut self.key);
+        handle.spawn(async move {
+            if let Err(e) = release_lock(conn, &key, &token).await {
+                error!("Failed to release lock {}: {}", key, e);
+            }
+        });
+    }
+}
+
+async fn release_lock(
+    mut conn: ConnectionManager,
+    key: &str,
+    token: &str,
+) -> Result<(), RedisError> {
+    let _: i64 = Script::new(RELEASE_LOCK_SCRIPT)
+        .key(key)
+        .arg(token)
+        .invoke_async(&mut conn)
+        .await?;
+    Ok(())
+}
+
+/// Moves each version pointer forward, never back, so a slow reader holding an old
+/// row can't point readers at stale data after an update. ARGV is the versions
+/// (one per key) followed by the TTL.
+const RAISE_VERSIONS_SCRIPT: &str = r#"
+local ttl = ARGV[#ARGV]
+for i, key in ipairs(KEYS) do
+    local current = tonumber(redis.call("GET", key))
+    local version = tonumber(ARGV[i])
+    if not current or current < version then
+        redis.call("SET", key, version, "EX", ttl)
+    end
+end
+return 0
+"#;
+
 #[async_trait]
 impl CacheService for RedisClient {
     async fn get_cached<T>(&self, key: &str) -> Result<Option<T>, RedisError>
     where
         T: DeserializeOwned + Send + Sync,
     {
+        let _timer = self.time_op("get_cached");
         let mut conn = self.get_conn().await?;
-        let data: Option<String> = redis::cmd("GET").arg(key).query_async(&mut conn).await?;
+        let data: Option<Vec<u8>> = redis::cmd("GET").arg(key).query_async(&mut conn).await?;
 
-        match data {
-            Some(cached_data) => match serde_json::from_str(&cached_data) {
-                Ok(parsed) => Ok(Some(parsed)),
-                Err(_) => Ok(None),
-            },
-            None => Ok(None),
-        }
+        // Anything unreadable is treated as a miss and rebuilt from the database
+        Ok(data
+            .and_then(decode_cached)
+            .and_then(|cached_data| serde_json::from_str(&cached_data).ok()))
     }
 
     async fn set_cached<T>(&self, key: &str, value: &T, ttl_seconds: u64) -> Result<(), RedisError>
     where
         T: Serialize + Send + Sync,
     {
+        let _timer = self.time_op("set_cached");
         let mut conn = self.get_conn().await?;
         let serialized = serde_json::to_string(value).map_err(|_| {
             RedisError::from((
@@ -108,7 +617,7 @@ impl CacheService for RedisClient {
 
         let _: () = redis::cmd("SET")
             .arg(key)
-            .arg(serialized)
+            .arg(encode_cached(serialized, self.compress_min_bytes))
             .arg("EX")
             .arg(ttl_seconds)
             .query_async(&mut conn)
@@ -117,6 +626,7 @@ impl CacheService for RedisClient {
     }
 
     async fn delete_cached_by_pattern(&self, pattern: &str) -> Result<u64, RedisError> {
+        let _timer = self.time_op("delete_cached_by_pattern");
         let mut conn = self.get_conn().await?;
 
         let mut cursor = 0;
@@ -154,20 +664,62 @@ impl CacheService for RedisClient {
     }
 
      async fn set_with_expiry(&self, key: &str, value: &str, expiry_seconds: u64) -> Result<(), RedisError> {
+        let _timer = self.time_op("set_with_expiry");
         let mut conn = self.get_conn().await?;
-        conn.set_ex(key, value, expiry_seconds).await?;
+        conn.set_ex::<_, _, ()>(key, value, expiry_seconds).await?;
         Ok(())
     }
 
      async fn get(&self, key: &str) -> Result<String, RedisError> {
+        let _timer = self.time_op("get");
         let mut conn = self.get_conn().await?;
         let value: String = conn.get(key).await?;
         Ok(value)
     }
 
      async fn del(&self, key: &str) -> Result<(), RedisError> {
+        let _timer = self.time_op("del");
         let mut conn = self.get_conn().await?;
-        conn.del(key).await?;
+        conn.del::<_, ()>(key).await?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSON: &str = r#"{"uuid":"abc","title":"Buy milk","tags":["home"]}"#;
+
+    #[test]
+    fn short_value_round_trips_raw() {
+        let stored = encode_cached(JSON.to_string(), Some(JSON.len() + 1));
+        assert_eq!(stored, JSON.as_bytes());
+        assert_eq!(decode_cached(stored).as_deref(), Some(JSON));
+    }
+
+    #[test]
+    fn long_value_round_trips_compressed() {
+        let stored = encode_cached(JSON.to_string(), Some(JSON.len()));
+        assert!(stored.starts_with(&GZIP_MAGIC));
+        assert_eq!(decode_cached(stored).as_deref(), Some(JSON));
+    }
+
+    #[test]
+    fn disabled_compression_stores_raw() {
+        let stored = encode_cached(JSON.to_string(), None);
+        assert_eq!(stored, JSON.as_bytes());
+    }
+
+    #[test]
+    fn legacy_raw_value_still_reads() {
+        // Giá trị được ghi trước khi có nén
+        let stored = JSON.as_bytes().to_vec();
+        assert_eq!(decode_cached(stored).as_deref(), Some(JSON));
+    }
+
+    #[test]
+    fn corrupt_compressed_value_is_unreadable() {
+        assert_eq!(decode_cached(vec![0x1f, 0x8b, 0x00, 0x01]), None);
+    }
+}
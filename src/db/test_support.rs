@@ -0,0 +1,59 @@
+//! Dùng chung cho các test cần Postgres (và Redis)
+//!
+//! Các test này được đánh `#[ignore]` để `cargo test` vẫn chạy được khi không có database;
+//! chạy chúng bằng `TEST_DATABASE_URL=postgres://... cargo test -- --ignored`. Schema lấy từ
+//! setup_db.sql, Redis từ `TEST_REDIS_URL` (mặc định `redis://127.0.0.1:6379`).
+use crate::db::data_trait::user_data_trait::UserData;
+use crate::db::database::Database;
+use crate::db::redis_client::RedisClient;
+use crate::models::user::{CreateUserRequest, User};
+use crate::services::password_service::{hash_password, PasswordAlgorithm};
+use sqlx::postgres::PgPoolOptions;
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+/// Mật khẩu của mọi user tạo bởi `create_test_user`
+pub const TEST_PASSWORD: &str = "correct horse battery";
+
+/// setup_db.sql chỉ chạy một lần cho cả lượt test, các test chạy song song không tạo bảng cùng lúc
+static SCHEMA: OnceCell<()> = OnceCell::const_new();
+
+/// Database nối tới `TEST_DATABASE_URL`, schema đã được tạo
+///
+/// Mỗi test có pool riêng vì pool gắn với runtime của test đã tạo nó.
+pub async fn test_database() -> Database {
+    let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set");
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&url)
+        .await
+        .expect("Failed to connect to the test database");
+
+    SCHEMA
+        .get_or_init(|| async {
+            sqlx::raw_sql(include_str!("../../setup_db.sql"))
+                .execute(&pool)
+                .await
+                .expect("Failed to apply setup_db.sql");
+        })
+        .await;
+
+    let redis_url =
+        std::env::var("TEST_REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+    Database {
+        read_pool: pool.clone(),
+        pool,
+        redis_client: RedisClient::new(&redis_url),
+    }
+}
+
+/// User mới với email ngẫu nhiên để các lượt test không đụng nhau, mật khẩu là `TEST_PASSWORD`
+pub async fn create_test_user(db: &Database) -> User {
+    let uuid = Uuid::new_v4().to_string();
+    let request = CreateUserRequest {
+        email: format!("{}@test.example", uuid),
+        password: hash_password(TEST_PASSWORD, PasswordAlgorithm::Bcrypt { cost: 4 }).unwrap(),
+        name: "Test User".to_string(),
+    };
+    db.create_user(&uuid, &request).await.unwrap()
+}
@@ -1,15 +1,46 @@
 use crate::models::todo::ApiResponse;
 use actix_web::body::BoxBody;
 use actix_web::{
-    http::{header::ContentType, StatusCode},
+    http::{
+        header::{ContentType, RETRY_AFTER},
+        StatusCode,
+    },
     HttpResponse, ResponseError,
 };
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::fmt;
+use utoipa::ToSchema;
+use validator::ValidationErrors;
+
+/// Messages per request field, e.g. `{ "email": ["invalid email"] }`
+pub type FieldErrors = BTreeMap<String, Vec<String>>;
+
+/// `data` of an error envelope, left null when there's nothing beyond the message
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct ErrorData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<FieldErrors>,
+    /// Machine-readable reason for errors a client has to handle specially
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    /// Id of the existing resource a 409 conflicts with, e.g. the todo holding the same title
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub existing_id: Option<String>,
+}
 
 #[derive(Debug)]
 pub struct AppError {
     pub status_code: StatusCode,
     pub message: String,
+    /// Sent as a Retry-After header when set
+    pub retry_after: Option<u64>,
+    /// Sent as `data.errors` when set
+    pub field_errors: Option<FieldErrors>,
+    /// Sent as `data.error_code` when set
+    pub error_code: Option<&'static str>,
+    /// Sent as `data.existing_id` when set
+    pub existing_id: Option<String>,
 }
 
 impl AppError {
@@ -17,9 +48,38 @@ impl AppError {
         AppError {
             status_code,
             message: message.into(),
+            retry_after: None,
+            field_errors: None,
+            error_code: None,
+            existing_id: None,
         }
     }
 
+    pub fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+
+    pub fn with_error_code(mut self, code: &'static str) -> Self {
+        self.error_code = Some(code);
+        self
+    }
+
+    pub fn with_existing_id(mut self, id: impl Into<String>) -> Self {
+        self.existing_id = Some(id.into());
+        self
+    }
+
+    /// A 400 listing what is wrong with each field
+    pub fn validation(errors: FieldErrors) -> Self {
+        let mut error = Self::bad_request(format!(
+            "Validation error: {}",
+            summarize_field_errors(&errors)
+        ));
+        error.field_errors = Some(errors);
+        error
+    }
+
     pub fn internal_server_error(message: impl Into<String>) -> Self {
         Self::new(StatusCode::INTERNAL_SERVER_ERROR, message)
     }
@@ -32,9 +92,17 @@ impl AppError {
         Self::new(StatusCode::UNAUTHORIZED, message)
     }
 
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, message)
+    }
+
     pub fn not_found(message: impl Into<String>) -> Self {
         Self::new(StatusCode::NOT_FOUND, message)
     }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, message)
+    }
 }
 
 impl fmt::Display for AppError {
@@ -49,15 +117,28 @@ impl ResponseError for AppError {
     }
 
     fn error_response(&self) -> HttpResponse<BoxBody> {
-        let error_response = ApiResponse::<()> {
+        let data = ErrorData {
+            errors: self.field_errors.clone(),
+            error_code: self.error_code.map(str::to_string),
+            existing_id: self.existing_id.clone(),
+        };
+        let error_response = ApiResponse {
             success: false,
             message: self.message.clone(),
-            data: None,
+            data: (data.errors.is_some()
+                || data.error_code.is_some()
+                || data.existing_id.is_some())
+            .then_some(data),
         };
 
-        HttpResponse::build(self.status_code)
-            .insert_header(ContentType::json())
-            .json(error_response)
+        let mut response = HttpResponse::build(self.status_code);
+        response.insert_header(ContentType::json());
+
+        if let Some(seconds) = self.retry_after {
+            response.insert_header((RETRY_AFTER, seconds.to_string()));
+        }
+
+        response.json(error_response)
     }
 }
 
@@ -101,3 +182,42 @@ impl From<jsonwebtoken::errors::Error> for AppError {
         Self::unauthorized(format!("JWT error: {}", error))
     }
 }
+
+impl From<ValidationErrors> for AppError {
+    fn from(errors: ValidationErrors) -> Self {
+        Self::validation(field_errors(&errors))
+    }
+}
+
+/// Flattens validator errors into messages per field, falling back to the error code
+pub fn field_errors(errors: &ValidationErrors) -> FieldErrors {
+    errors
+        .field_errors()
+        .into_iter()
+        .map(|(field, errors)| {
+            let messages = errors
+                .iter()
+                .map(|error| match &error.message {
+                    Some(message) => message.to_string(),
+                    None => format!("{} is invalid ({})", field, error.code),
+                })
+                .collect();
+            (field.to_string(), messages)
+        })
+        .collect()
+}
+
+/// A single field's error, for checks made outside of `Validate`
+pub fn field_error(field: &str, message: impl Into<String>) -> FieldErrors {
+    FieldErrors::from([(field.to_string(), vec![message.into()])])
+}
+
+/// Every message in one line for the envelope's `message`; each one names its field already
+pub fn summarize_field_errors(errors: &FieldErrors) -> String {
+    errors
+        .values()
+        .flatten()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
@@ -0,0 +1,40 @@
+use super::app_error::AppError;
+use actix_web::body::BoxBody;
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use derive_more::Display;
+use redis::RedisError;
+
+/// Sent as Retry-After when a request is refused for lack of the cache
+pub const CACHE_RETRY_AFTER_SECONDS: u64 = 5;
+
+/// A cache operation the request can't go on without has failed
+///
+/// Only returned from call sites the cache policy marks as required (see
+/// `cache_service::required`); best-effort sites log the failure and carry on.
+#[derive(Debug, Display)]
+#[display("Service temporarily unavailable, please retry shortly")]
+pub struct CacheUnavailable;
+
+impl From<RedisError> for CacheUnavailable {
+    fn from(_: RedisError) -> Self {
+        CacheUnavailable
+    }
+}
+
+impl From<CacheUnavailable> for AppError {
+    fn from(error: CacheUnavailable) -> Self {
+        AppError::new(StatusCode::SERVICE_UNAVAILABLE, error.to_string())
+            .with_retry_after(CACHE_RETRY_AFTER_SECONDS)
+            .with_error_code("cache_unavailable")
+    }
+}
+
+impl ResponseError for CacheUnavailable {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+
+    fn error_response(&self) -> HttpResponse<BoxBody> {
+        AppError::from(CacheUnavailable).error_response()
+    }
+}
@@ -1,5 +1,5 @@
 pub mod app_error;
-pub mod todo_error;
+pub mod cache_error;
 pub mod user_error;
 
-pub use app_error::AppError;
+pub use app_error::{AppError, FieldErrors};
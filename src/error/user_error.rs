@@ -1,10 +1,9 @@
+use super::app_error::{field_errors, summarize_field_errors, AppError, FieldErrors};
+use super::cache_error::CacheUnavailable;
 use actix_web::body::BoxBody;
-use actix_web::{
-    http::{header::ContentType, StatusCode},
-    HttpResponse, ResponseError,
-};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
 use derive_more::Display;
-use serde_json::json;
+use validator::ValidationErrors;
 
 #[derive(Debug, Display)]
 pub enum UserError {
@@ -16,32 +15,57 @@ pub enum UserError {
     AuthenticationFailure,
     #[display("Username already exists")]
     UserAlreadyExists,
-    #[display("Validation error: {}", _0)]
-    ValidationError(String),
+    #[display("Validation error: {}", summarize_field_errors(_0))]
+    ValidationError(FieldErrors),
     #[display("Invalid refresh token")]
     InvalidRefreshToken,
+    #[display("Refresh token has expired, please log in again")]
+    RefreshTokenExpired,
+    #[display("Refresh token was already used, all sessions have been signed out")]
+    RefreshTokenReused,
+    #[display("Session was idle for too long, please log in again")]
+    SessionIdle,
     #[display("Token creation failed")]
     TokenCreationFailure,
     #[display("Password hashing failed")]
     PasswordHashingFailure,
     #[display("Invalid credentials")]
     InvalidCredentials,
-    #[display("2FA is required")]
-    TwoFactorRequired,
     #[display("2FA is already enabled")]
     TwoFactorAlreadyEnabled,
     #[display("2FA is not enabled")]
     TwoFactorNotEnabled,
     #[display("Invalid 2FA code")]
     InvalidTwoFactorCode,
+    #[display("No pending 2FA setup, please enable 2FA first")]
+    TwoFactorSetupNotStarted,
+    #[display("2FA setup has expired, please enable 2FA again")]
+    TwoFactorSetupExpired,
     #[display("Failed to generate QR code")]
     QRCodeGenerationFailure,
     #[display("Bad request: {}", _0)]
     BadRequest(String),
     #[display("Database error: {}", _0)]
     DatabaseError(String),
+    #[display("You don't have permission to access this user")]
+    Forbidden,
+    #[display("Admin privileges required")]
+    AdminRequired,
+    #[display("Admins must enable 2FA before using admin endpoints")]
+    AdminTwoFactorRequired,
+    #[display("This password was used recently, please choose a different one")]
+    PasswordRecentlyUsed,
     InvalidSession,
     TooManyAttempts,
+    #[display("Too many failed login attempts, retry in {} seconds", _0)]
+    LoginBackoff(u64),
+    #[display(
+        "Email was changed recently, it can be changed again in {} seconds",
+        _0
+    )]
+    EmailChangeCooldown(u64),
+    #[display("{}", _0)]
+    CacheUnavailable(CacheUnavailable),
 }
 
 impl ResponseError for UserError {
@@ -53,30 +77,71 @@ impl ResponseError for UserError {
             UserError::UserAlreadyExists => StatusCode::CONFLICT,
             UserError::ValidationError(_) => StatusCode::BAD_REQUEST,
             UserError::InvalidRefreshToken => StatusCode::UNAUTHORIZED,
+            UserError::RefreshTokenExpired => StatusCode::UNAUTHORIZED,
+            UserError::RefreshTokenReused => StatusCode::UNAUTHORIZED,
+            UserError::SessionIdle => StatusCode::UNAUTHORIZED,
             UserError::TokenCreationFailure => StatusCode::INTERNAL_SERVER_ERROR,
             UserError::PasswordHashingFailure => StatusCode::INTERNAL_SERVER_ERROR,
             UserError::InvalidCredentials => StatusCode::UNAUTHORIZED,
-            UserError::TwoFactorRequired => StatusCode::UNAUTHORIZED,
             UserError::TwoFactorAlreadyEnabled => StatusCode::BAD_REQUEST,
             UserError::TwoFactorNotEnabled => StatusCode::BAD_REQUEST,
             UserError::InvalidTwoFactorCode => StatusCode::UNAUTHORIZED,
+            UserError::TwoFactorSetupNotStarted => StatusCode::BAD_REQUEST,
+            UserError::TwoFactorSetupExpired => StatusCode::BAD_REQUEST,
             UserError::QRCodeGenerationFailure => StatusCode::INTERNAL_SERVER_ERROR,
             UserError::BadRequest(_) => StatusCode::BAD_REQUEST,
             UserError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            UserError::Forbidden => StatusCode::FORBIDDEN,
+            UserError::AdminRequired => StatusCode::FORBIDDEN,
+            UserError::AdminTwoFactorRequired => StatusCode::FORBIDDEN,
+            UserError::PasswordRecentlyUsed => StatusCode::BAD_REQUEST,
             UserError::InvalidSession => StatusCode::UNAUTHORIZED,
             UserError::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
+            UserError::LoginBackoff(_) => StatusCode::TOO_MANY_REQUESTS,
+            UserError::EmailChangeCooldown(_) => StatusCode::TOO_MANY_REQUESTS,
+            UserError::CacheUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
         }
     }
 
+    /// Same envelope as `AppError`, so clients parse one error shape
     fn error_response(&self) -> HttpResponse<BoxBody> {
-        let error_json = json!({
-            "status": "error",
-            "code": self.status_code().as_u16(),
-            "message": self.to_string()
-        });
+        let mut error = AppError::new(self.status_code(), self.to_string());
 
-        HttpResponse::build(self.status_code())
-            .insert_header(ContentType::json())
-            .json(error_json)
+        match self {
+            UserError::ValidationError(errors) => error.field_errors = Some(errors.clone()),
+            // Lets clients tell "enroll in 2FA" apart from a plain 403 and send the admin to setup
+            UserError::AdminTwoFactorRequired => {
+                error = error.with_error_code("admin_2fa_required")
+            }
+            // Expired means log in again; reused means the token may have been stolen
+            UserError::InvalidRefreshToken => {
+                error = error.with_error_code("invalid_refresh_token")
+            }
+            UserError::RefreshTokenExpired => {
+                error = error.with_error_code("refresh_token_expired")
+            }
+            UserError::RefreshTokenReused => error = error.with_error_code("refresh_token_reused"),
+            // Unlike an expired token, this one would still be valid had the user been active
+            UserError::SessionIdle => error = error.with_error_code("session_idle"),
+            UserError::LoginBackoff(seconds) | UserError::EmailChangeCooldown(seconds) => {
+                error = error.with_retry_after(*seconds)
+            }
+            UserError::CacheUnavailable(_) => error = AppError::from(CacheUnavailable),
+            _ => {}
+        }
+
+        error.error_response()
+    }
+}
+
+impl From<ValidationErrors> for UserError {
+    fn from(errors: ValidationErrors) -> Self {
+        UserError::ValidationError(field_errors(&errors))
+    }
+}
+
+impl From<CacheUnavailable> for UserError {
+    fn from(error: CacheUnavailable) -> Self {
+        UserError::CacheUnavailable(error)
     }
 }
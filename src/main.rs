@@ -1,30 +1,54 @@
+mod config;
 mod db;
 mod error;
 mod middleware;
 mod models;
 mod routers;
 mod services;
+mod swagger;
 
 use actix_cors::Cors;
 use actix_web::http::header;
-use actix_web::{middleware::Logger, web::Data, App, HttpServer};
+use actix_web::middleware::{Condition, DefaultHeaders, Logger};
+use actix_web::{web, web::Data, App, HttpServer};
 use actix_web_httpauth::middleware::HttpAuthentication;
+use config::AppConfig;
 use db::database::Database;
 use dotenv::dotenv;
-use env_logger::Env;
 use log::{info, warn};
-use middleware::auth::{validator, TodoOwnershipChecker};
-use routers::{health::health_routes, todo::todo_routes, user::user_routes};
+use middleware::accept::AcceptJson;
+use middleware::auth::validator;
+use middleware::body_logger::BodyLogger;
+use middleware::client_ip::client_ip;
+use middleware::concurrency::UserConcurrencyLimit;
+use middleware::dedup::RequestDedup;
+use middleware::ip_filter::IpAllowlist;
+use middleware::maintenance::MaintenanceGuard;
+use middleware::query_count::QueryCountGuard;
+use middleware::response_format::ResponseFormat;
+use middleware::server_timing::ServerTiming;
+use routers::{
+    admin::admin_routes,
+    bootstrap::bootstrap_routes,
+    fallback::{default_handler, extractor_error},
+    health::health_routes,
+    metrics::metrics_routes,
+    todo::todo_routes,
+    user::user_routes,
+};
+use services::event_service::{EventPublisher, NoopPublisher, RedisStreamPublisher};
+use services::reminder_service::ReminderChannel;
+use services::self_check_service::SelfCheckMode;
+use std::sync::Arc;
+use std::time::Duration;
+use swagger::swagger_routes;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
 
-    // Cấu hình logger
-    env_logger::Builder::from_env(Env::default().default_filter_or("info"))
-        .format_timestamp_millis()
-        .format_module_path(true)
-        .init();
+    // Cấu hình logger, mức log từng module có thể đổi lúc chạy qua /admin/log-level
+    services::log_service::init("info");
 
     info!("Starting application...");
 
@@ -50,17 +74,93 @@ async fn main() -> std::io::Result<()> {
         std::env::set_var("REDIS_URL", "redis://127.0.0.1:6379");
     }
 
+    let config = AppConfig::from_env();
+    if !config.ip_allowlist.is_empty() {
+        info!(
+            "IP allowlist enabled with {} ranges",
+            config.ip_allowlist.len()
+        );
+    }
+    if config.body_log_enabled {
+        if cfg!(debug_assertions) {
+            warn!("Body logging enabled, request/response bodies are logged at debug level (redacted)");
+        } else {
+            warn!("BODY_LOG_ENABLED is ignored in release builds");
+        }
+    }
+    if config.metrics_enabled {
+        services::metrics_service::enable();
+        info!("Metrics enabled at /metrics");
+    }
+    let publisher: Arc<dyn EventPublisher> = match &config.events_stream {
+        Some(stream) => {
+            info!("Publishing domain events to Redis stream {}", stream);
+            let redis_url =
+                std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+            Arc::new(RedisStreamPublisher::new(
+                &redis_url,
+                stream.clone(),
+                config.events_stream_max_len,
+            ))
+        }
+        None => Arc::new(NoopPublisher),
+    };
+    let publisher_data: Data<dyn EventPublisher> = Data::from(publisher.clone());
+    let config_data = Data::new(config);
+
     info!("Initializing database connection...");
     let database = Database::init().await;
     info!("Database connection established successfully");
 
+    if config_data.self_check_mode != SelfCheckMode::Off {
+        if let Err(errors) = services::self_check_service::run_self_check(&database).await {
+            if config_data.self_check_mode == SelfCheckMode::Strict {
+                let summary: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                return Err(std::io::Error::other(format!(
+                    "startup self-check failed: {}",
+                    summary.join("; ")
+                )));
+            }
+        }
+    }
+
     let db_data = Data::new(database);
 
+    if config_data.trash_retention_days > 0 {
+        tokio::spawn(services::purge_service::run_trash_purge(
+            db_data.clone(),
+            config_data.trash_retention_days,
+            Duration::from_secs(config_data.trash_purge_interval_seconds),
+            config_data.trash_purge_batch_size,
+        ));
+    }
+
+    if config_data.reminder_channel != ReminderChannel::Off {
+        if config_data.reminder_channel == ReminderChannel::Events
+            && config_data.events_stream.is_none()
+        {
+            warn!("REMINDER_CHANNEL is events but EVENTS_REDIS_STREAM is unset, reminders are dropped");
+        }
+        tokio::spawn(services::reminder_service::run_reminder_scan(
+            db_data.clone(),
+            publisher,
+            config_data.reminder_channel,
+            Duration::from_secs(config_data.reminder_scan_interval_seconds),
+            config_data.reminder_batch_size,
+        ));
+    }
+
+    // Built once so every worker counts against the same per-user limit
+    let concurrency_limit = UserConcurrencyLimit::new(
+        db_data.clone(),
+        config_data.max_concurrent_requests_per_user,
+    );
+
     let server = HttpServer::new(move || {
         let cors = Cors::default()
             .allowed_origin("http://localhost:8080")
             .allowed_origin("https://todo.quickmem.app")
-            .allow_any_method()
+            .allowed_methods(config_data.cors_allowed_methods.clone())
             .allowed_headers(vec![
                 header::AUTHORIZATION,
                 header::ACCEPT,
@@ -68,30 +168,100 @@ async fn main() -> std::io::Result<()> {
             ])
             .expose_headers(vec![header::AUTHORIZATION])
             .supports_credentials()
-            .max_age(3600);
+            .max_age(config_data.cors_max_age_seconds);
 
         let auth = HttpAuthentication::bearer(validator);
-        let _todo_ownership_checker = TodoOwnershipChecker::new(db_data.clone());
 
         App::new()
             .wrap(cors)
-            .wrap(Logger::new("%a %r %s %b %{Referer}i %{User-Agent}i %T"))
+            .wrap(
+                Logger::new("%{client_ip}xi %r %s %b %{Referer}i %{User-Agent}i %T")
+                    .custom_request_replace("client_ip", {
+                        let trusted_proxies = config_data.trusted_proxies.clone();
+                        move |req| {
+                            client_ip(req.request(), &trusted_proxies)
+                                .map(|ip| ip.to_string())
+                                .unwrap_or_else(|| "-".to_string())
+                        }
+                    }),
+            )
             .app_data(db_data.clone())
+            .app_data(config_data.clone())
+            .app_data(publisher_data.clone())
+            .app_data(web::JsonConfig::default().error_handler(extractor_error))
+            .app_data(web::QueryConfig::default().error_handler(extractor_error))
+            .app_data(web::PathConfig::default().error_handler(extractor_error))
+            .configure(swagger_routes)
+            .configure(|cfg| {
+                if config_data.metrics_enabled {
+                    metrics_routes(cfg);
+                }
+            })
             .service(
                 actix_web::web::scope("/api")
+                    // Every /api response is JSON, so refuse clients that can't take it
+                    .wrap(AcceptJson)
+                    .wrap(Condition::new(
+                        cfg!(debug_assertions),
+                        QueryCountGuard::new(config_data.db_query_warn_threshold),
+                    ))
+                    .wrap(Condition::new(
+                        cfg!(debug_assertions) && config_data.body_log_enabled,
+                        BodyLogger::new(
+                            config_data.body_log_routes.clone(),
+                            config_data.body_log_max_bytes,
+                        ),
+                    ))
+                    .wrap(MaintenanceGuard::new(
+                        db_data.clone(),
+                        config_data.maintenance_mode,
+                        config_data.maintenance_retry_after_seconds,
+                    ))
+                    .wrap(IpAllowlist::new(
+                        config_data.ip_allowlist.clone(),
+                        config_data.trusted_proxies.clone(),
+                    ))
+                    // Outermost so `total` covers the other middlewares too
+                    .wrap(Condition::new(
+                        config_data.server_timing_enabled,
+                        ServerTiming,
+                    ))
                     .configure(health_routes)
                     .service(
-                        actix_web::web::scope("/v1").configure(user_routes).service(
-                            actix_web::web::scope("/todos")
-                                .wrap(auth)
-                                .configure(todo_routes),
-                        ),
+                        actix_web::web::scope("/v1")
+                            // Tokens and user data must not be cached unless a handler opts in
+                            .wrap(DefaultHeaders::new().add((header::CACHE_CONTROL, "no-store")))
+                            .wrap(ResponseFormat::new(config_data.omit_null_fields))
+                            .configure(|cfg| user_routes(cfg, &config_data.features))
+                            .configure(admin_routes)
+                            .configure(bootstrap_routes)
+                            .service(
+                                actix_web::web::scope("/todos")
+                                    // Registered before auth so it runs after it and sees the user id
+                                    .wrap(Condition::new(
+                                        config_data.max_concurrent_requests_per_user > 0,
+                                        concurrency_limit.clone(),
+                                    ))
+                                    // Outside the limit so a duplicate waiting on the first takes no slot
+                                    .wrap(Condition::new(
+                                        config_data.request_dedup_window_seconds.is_some(),
+                                        RequestDedup::new(
+                                            db_data.clone(),
+                                            config_data
+                                                .request_dedup_window_seconds
+                                                .unwrap_or_default(),
+                                        ),
+                                    ))
+                                    .wrap(auth)
+                                    .configure(|cfg| todo_routes(cfg, &config_data.features)),
+                            ),
                     ),
             )
+            .default_service(web::to(default_handler))
     })
     .bind(("0.0.0.0", 8080))?;
 
     info!("Server started at http://127.0.0.1:8080");
-    info!("Swagger UI available at http://127.0.0.1:8080/swagger-ui/");
+    info!("OpenAPI spec available at http://127.0.0.1:8080/api-docs/openapi.json");
     server.run().await
 }
@@ -0,0 +1,75 @@
+use crate::error::AppError;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{Accept, Header, Quality};
+use actix_web::http::StatusCode;
+use actix_web::Error as ActixError;
+use futures_util::future::LocalBoxFuture;
+use std::future::{ready, Ready};
+
+/// Rejects with 406 requests whose Accept header rules out JSON, the only type we serve
+///
+/// A missing or unparsable header is treated as `*/*`.
+pub struct AcceptJson;
+
+/// Whether any media range with a non-zero q-factor covers `application/json`
+fn accepts_json(accept: &Accept) -> bool {
+    accept.is_empty()
+        || accept.iter().any(|range| {
+            range.quality > Quality::ZERO
+                && matches!(
+                    (range.item.type_().as_str(), range.item.subtype().as_str()),
+                    ("*", "*") | ("application", "*") | ("application", "json")
+                )
+        })
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AcceptJson
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Transform = AcceptJsonMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AcceptJsonMiddleware { service }))
+    }
+}
+
+pub struct AcceptJsonMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for AcceptJsonMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if let Ok(accept) = Accept::parse(req.request()) {
+            if !accepts_json(&accept) {
+                return Box::pin(async move {
+                    Err(AppError::new(
+                        StatusCode::NOT_ACCEPTABLE,
+                        "Only application/json responses are available",
+                    )
+                    .into())
+                });
+            }
+        }
+
+        let fut = self.service.call(req);
+        Box::pin(fut)
+    }
+}
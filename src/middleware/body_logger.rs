@@ -0,0 +1,209 @@
+use actix_web::body::{to_bytes, BoxBody, MessageBody};
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::error::PayloadError;
+use actix_web::http::header::{HeaderMap, CONTENT_ENCODING, CONTENT_TYPE};
+use actix_web::web::{Bytes, BytesMut};
+use actix_web::{Error as ActixError, HttpMessage};
+use futures_util::future::LocalBoxFuture;
+use futures_util::{stream, Stream, StreamExt};
+use log::debug;
+use serde_json::Value;
+use std::future::{ready, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// Key fragments whose values are never logged, matched case-insensitively anywhere in the key
+///
+/// Covers password/current_password/new_password, access/refresh/mfa tokens, TOTP secrets
+/// (also pending and per device), backup codes, one-time codes and session ids.
+const REDACTED_KEY_FRAGMENTS: &[&str] = &[
+    "password",
+    "token",
+    "secret",
+    "backup_code",
+    "otp",
+    "session",
+    "authorization",
+    "qr_code",
+];
+
+/// Keys redacted only on an exact match, as a fragment they'd hide too much (e.g. `error_code`)
+const REDACTED_KEYS: &[&str] = &["code"];
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Logs JSON request and response bodies of selected routes at debug level, with credentials
+/// redacted by key name
+///
+/// Development aid only: main.rs registers it in debug builds when `BODY_LOG_ENABLED` is set.
+/// Bodies that aren't JSON, or are compressed, are logged by size only, never raw.
+pub struct BodyLogger {
+    /// Path prefixes to log, empty logs every route
+    routes: Rc<Vec<String>>,
+    /// Longer logged bodies are cut to this many bytes
+    max_bytes: usize,
+}
+
+impl BodyLogger {
+    pub fn new(routes: Vec<String>, max_bytes: usize) -> Self {
+        BodyLogger {
+            routes: Rc::new(routes),
+            max_bytes,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for BodyLogger
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = ActixError;
+    type Transform = BodyLoggerMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(BodyLoggerMiddleware {
+            service: Rc::new(service),
+            routes: self.routes.clone(),
+            max_bytes: self.max_bytes,
+        }))
+    }
+}
+
+pub struct BodyLoggerMiddleware<S> {
+    service: Rc<S>,
+    routes: Rc<Vec<String>>,
+    max_bytes: usize,
+}
+
+impl<S, B> Service<ServiceRequest> for BodyLoggerMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let max_bytes = self.max_bytes;
+        let selected = self.routes.is_empty()
+            || self
+                .routes
+                .iter()
+                .any(|prefix| req.path().starts_with(prefix.as_str()));
+
+        Box::pin(async move {
+            if !selected {
+                return Ok(service.call(req).await?.map_into_boxed_body());
+            }
+
+            let method = req.method().clone();
+            let path = req.path().to_string();
+
+            // Read the whole body, then hand the handler a payload replaying the same bytes
+            let mut payload = req.take_payload();
+            let mut body = BytesMut::new();
+            while let Some(chunk) = payload.next().await {
+                body.extend_from_slice(&chunk?);
+            }
+            let body = body.freeze();
+            debug!(
+                "{} {} request: {}",
+                method,
+                path,
+                describe_body(req.headers(), &body, max_bytes)
+            );
+            req.set_payload(replay_payload(body));
+
+            let res = service.call(req).await?;
+            let (req, res) = res.into_parts();
+            let (res, body) = res.into_parts();
+            let body = to_bytes(body)
+                .await
+                .map_err(|e| actix_web::error::ErrorInternalServerError(e.into()))?;
+            debug!(
+                "{} {} response {}: {}",
+                method,
+                path,
+                res.status().as_u16(),
+                describe_body(res.headers(), &body, max_bytes)
+            );
+
+            Ok(ServiceResponse::new(req, res.set_body(BoxBody::new(body))))
+        })
+    }
+}
+
+fn replay_payload(body: Bytes) -> Payload {
+    let stream: Pin<Box<dyn Stream<Item = Result<Bytes, PayloadError>>>> =
+        Box::pin(stream::once(async move { Ok(body) }));
+    Payload::from(stream)
+}
+
+/// The redacted JSON, or just the size when the body can't be shown safely
+fn describe_body(headers: &HeaderMap, body: &[u8], max_bytes: usize) -> String {
+    if body.is_empty() {
+        return "<empty>".to_string();
+    }
+    if let Some(encoding) = headers
+        .get(CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+    {
+        return format!("<{} bytes, {} encoded>", body.len(), encoding);
+    }
+
+    // Anything that doesn't parse as JSON could hold credentials in a shape we can't redact
+    let Ok(mut json) = serde_json::from_slice::<Value>(body) else {
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("unknown type");
+        return format!("<{} bytes, {}>", body.len(), content_type);
+    };
+
+    redact(&mut json);
+    let mut logged = json.to_string();
+    if logged.len() > max_bytes {
+        let mut end = max_bytes;
+        while !logged.is_char_boundary(end) {
+            end -= 1;
+        }
+        logged.truncate(end);
+        logged.push_str("...");
+    }
+    logged
+}
+
+fn is_sensitive(key: &str) -> bool {
+    let key = key.to_lowercase();
+    REDACTED_KEYS.contains(&key.as_str())
+        || REDACTED_KEY_FRAGMENTS
+            .iter()
+            .any(|fragment| key.contains(fragment))
+}
+
+/// Replaces the value of every sensitive key at any depth, whatever its type
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                if is_sensitive(key) {
+                    *field = Value::String(REDACTED.to_string());
+                } else {
+                    redact(field);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
@@ -0,0 +1,84 @@
+use actix_web::HttpRequest;
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+fn is_trusted(ip: &IpAddr, trusted_proxies: &[IpNet]) -> bool {
+    trusted_proxies.iter().any(|net| net.contains(ip))
+}
+
+/// Parses a single node from `Forwarded: for=...`, e.g. `"[2001:db8::1]:4711"` or `192.0.2.60:80`
+fn parse_forwarded_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok();
+    }
+
+    node.parse()
+        .ok()
+        .or_else(|| node.rsplit_once(':')?.0.parse().ok())
+}
+
+/// Collects the proxy chain, left (client) to right (nearest proxy)
+fn forwarded_chain(req: &HttpRequest) -> Option<Vec<Option<IpAddr>>> {
+    let headers = req.headers();
+
+    if let Some(forwarded) = headers.get("Forwarded").and_then(|v| v.to_str().ok()) {
+        let chain = forwarded
+            .split(',')
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (key, value) = pair.split_once('=')?;
+                    key.trim()
+                        .eq_ignore_ascii_case("for")
+                        .then(|| parse_forwarded_node(value))
+                })
+            })
+            .collect();
+        return Some(chain);
+    }
+
+    headers
+        .get("X-Forwarded-For")
+        .and_then(|v| v.to_str().ok())
+        .map(|value| {
+            value
+                .split(',')
+                .map(|hop| hop.trim().parse::<IpAddr>().ok())
+                .collect()
+        })
+}
+
+/// Resolves the real client IP for a request
+///
+/// `Forwarded` / `X-Forwarded-For` are only honored when the immediate peer
+/// is a trusted proxy. The chain is walked right to left, skipping our own
+/// proxies, so a client can't spoof its address by prepending entries.
+pub fn client_ip(req: &HttpRequest, trusted_proxies: &[IpNet]) -> Option<IpAddr> {
+    let peer_ip = req.peer_addr()?.ip();
+
+    if !is_trusted(&peer_ip, trusted_proxies) {
+        return Some(peer_ip);
+    }
+
+    let chain = match forwarded_chain(req) {
+        Some(chain) => chain,
+        None => return Some(peer_ip),
+    };
+
+    let mut client = peer_ip;
+    for hop in chain.into_iter().rev() {
+        match hop {
+            Some(ip) => {
+                client = ip;
+                if !is_trusted(&ip, trusted_proxies) {
+                    break;
+                }
+            }
+            // Obfuscated or malformed entries stop the walk at the last known hop
+            None => break,
+        }
+    }
+
+    Some(client)
+}
@@ -0,0 +1,146 @@
+use crate::db::data_trait::user_data_trait::UserData;
+use crate::db::database::Database;
+use crate::error::AppError;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::StatusCode;
+use actix_web::web::Data;
+use actix_web::{Error as ActixError, HttpMessage};
+use futures_util::future::LocalBoxFuture;
+use std::collections::HashMap;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Rejects a user's request with 429 while they already have `limit` requests in flight
+///
+/// Must run after authentication so the user id is in the request extensions. Admins are
+/// exempt. The count is per instance, each worker of this process shares it.
+#[derive(Clone)]
+pub struct UserConcurrencyLimit {
+    db: Data<Database>,
+    limit: usize,
+    in_flight: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+}
+
+impl UserConcurrencyLimit {
+    pub fn new(db: Data<Database>, limit: usize) -> Self {
+        UserConcurrencyLimit {
+            db,
+            limit,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn try_acquire(&self, user_id: String) -> InFlight {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        let permit = in_flight
+            .entry(user_id.clone())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.limit)))
+            .clone()
+            .try_acquire_owned()
+            .ok();
+
+        InFlight {
+            in_flight: self.in_flight.clone(),
+            user_id,
+            permit,
+        }
+    }
+}
+
+/// A request counted against its user, released on drop so cancelled requests free it too
+struct InFlight {
+    in_flight: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+    user_id: String,
+    /// `None` when the user was over the limit
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for InFlight {
+    /// Forgets the user once nothing of theirs is in flight, so the map doesn't grow forever
+    fn drop(&mut self) {
+        self.permit.take();
+
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        // Semaphores are only cloned under this lock, so a count of one means the map is
+        // the last holder and no request can be about to take a permit from it
+        if in_flight
+            .get(&self.user_id)
+            .is_some_and(|semaphore| Arc::strong_count(semaphore) == 1)
+        {
+            in_flight.remove(&self.user_id);
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for UserConcurrencyLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Transform = UserConcurrencyLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(UserConcurrencyLimitMiddleware {
+            service: Rc::new(service),
+            limiter: self.clone(),
+        }))
+    }
+}
+
+pub struct UserConcurrencyLimitMiddleware<S> {
+    service: Rc<S>,
+    limiter: UserConcurrencyLimit,
+}
+
+impl<S, B> Service<ServiceRequest> for UserConcurrencyLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let limiter = self.limiter.clone();
+
+        Box::pin(async move {
+            let Some(user_id) = req.extensions().get::<String>().cloned() else {
+                return service.call(req).await;
+            };
+
+            let in_flight = limiter.try_acquire(user_id.clone());
+            // Only look the user up when they're over the limit, the common path stays DB-free
+            if in_flight.permit.is_none() {
+                let is_admin = limiter
+                    .db
+                    .get_user_by_uuid(&user_id)
+                    .await
+                    .is_ok_and(|user| user.is_admin);
+                if !is_admin {
+                    return Err(AppError::new(
+                        StatusCode::TOO_MANY_REQUESTS,
+                        "Too many concurrent requests, wait for one to finish",
+                    )
+                    .with_retry_after(1)
+                    .into());
+                }
+            }
+
+            let result = service.call(req).await;
+            drop(in_flight);
+            result
+        })
+    }
+}
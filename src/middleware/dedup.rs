@@ -0,0 +1,260 @@
+use crate::db::database::Database;
+use crate::error::AppError;
+use crate::services::cache_service::{best_effort, CacheService};
+use actix_web::body::{to_bytes, BoxBody, MessageBody};
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::error::PayloadError;
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::http::{Method, StatusCode};
+use actix_web::web::{Bytes, BytesMut, Data};
+use actix_web::{Error as ActixError, HttpMessage, HttpResponse};
+use futures_util::future::LocalBoxFuture;
+use futures_util::{stream, Stream, StreamExt};
+use log::info;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::future::{ready, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Held by the key while the first of the identical requests is still running
+const PENDING: &str = "pending";
+
+/// How often a duplicate checks whether the first request has finished
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Set on responses replayed from the first of the identical requests
+const DEDUPLICATED_HEADER: &str = "X-Deduplicated";
+
+/// Most of a body read to hash it: actix's default JSON limit, the largest body any todo
+/// endpoint accepts, so anything bigger is refused before it is buffered whole
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// The first request's response, as kept for its duplicates
+#[derive(Serialize, Deserialize)]
+struct StoredResponse {
+    status: u16,
+    content_type: Option<String>,
+    body: String,
+}
+
+/// Collapses identical todo writes from the same user within `window_seconds`, such as the
+/// two requests of a double-click: the first one runs, the others get its response
+///
+/// Requests are identical when method, path, query and body all match. Only successful
+/// responses are kept, so a failed first attempt can be retried at once. Must run after
+/// authentication so the user id is in the request extensions. Without Redis every request
+/// simply runs. Bodies over `MAX_BODY_BYTES` get a 413.
+#[derive(Clone)]
+pub struct RequestDedup {
+    db: Data<Database>,
+    window_seconds: u64,
+}
+
+impl RequestDedup {
+    pub fn new(db: Data<Database>, window_seconds: u64) -> Self {
+        RequestDedup { db, window_seconds }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestDedup
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = ActixError;
+    type Transform = RequestDedupMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestDedupMiddleware {
+            service: Rc::new(service),
+            dedup: self.clone(),
+        }))
+    }
+}
+
+pub struct RequestDedupMiddleware<S> {
+    service: Rc<S>,
+    dedup: RequestDedup,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestDedupMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let db = self.dedup.db.clone();
+        let window_seconds = self.dedup.window_seconds;
+
+        Box::pin(async move {
+            let is_write = matches!(*req.method(), Method::POST | Method::PUT | Method::PATCH);
+            let user_id = req.extensions().get::<String>().cloned();
+            let Some(user_id) = user_id.filter(|_| is_write) else {
+                return Ok(service.call(req).await?.map_into_boxed_body());
+            };
+
+            // Read the whole body to hash it, then hand the handler a payload replaying it
+            let mut payload = req.take_payload();
+            let mut body = BytesMut::new();
+            while let Some(chunk) = payload.next().await {
+                let chunk = chunk?;
+                if body.len() + chunk.len() > MAX_BODY_BYTES {
+                    return Err(AppError::new(
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                        format!("Request body is larger than {} bytes", MAX_BODY_BYTES),
+                    )
+                    .into());
+                }
+                body.extend_from_slice(&chunk);
+            }
+            let body = body.freeze();
+            let key = dedup_key(&user_id, &req, &body);
+            req.set_payload(replay_payload(body));
+
+            let claimed = best_effort(
+                db.redis_client
+                    .set_value_if_absent(&key, PENDING, window_seconds)
+                    .await,
+                "dedup claim",
+            );
+            match claimed {
+                // Without Redis nothing can be deduplicated, the request just runs
+                None => Ok(service.call(req).await?.map_into_boxed_body()),
+                Some(true) => run_first(&db, &key, window_seconds, service.call(req).await).await,
+                Some(false) => match wait_for_first(&db, &key, window_seconds).await? {
+                    Some(stored) => {
+                        info!(
+                            "Replayed a duplicate {} {} of user {}",
+                            req.method(),
+                            req.path(),
+                            user_id
+                        );
+                        Ok(req.into_response(replay_response(stored)))
+                    }
+                    // The first attempt failed, so this one is no duplicate of a success
+                    None => Ok(service.call(req).await?.map_into_boxed_body()),
+                },
+            }
+        })
+    }
+}
+
+/// Scoped to the user in clear so one user's keys can be told apart, the rest is hashed
+fn dedup_key(user_id: &str, req: &ServiceRequest, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(req.method().as_str());
+    hasher.update(b"\0");
+    hasher.update(req.uri().path());
+    hasher.update(b"?");
+    hasher.update(req.query_string());
+    hasher.update(b"\0");
+    hasher.update(body);
+    format!("dedup:{}:{}", user_id, hex::encode(hasher.finalize()))
+}
+
+fn replay_payload(body: Bytes) -> Payload {
+    let stream: Pin<Box<dyn Stream<Item = Result<Bytes, PayloadError>>>> =
+        Box::pin(stream::once(async move { Ok(body) }));
+    Payload::from(stream)
+}
+
+/// Keeps a successful response for the duplicates, otherwise frees the key for a retry
+async fn run_first<B: MessageBody + 'static>(
+    db: &Database,
+    key: &str,
+    window_seconds: u64,
+    res: Result<ServiceResponse<B>, ActixError>,
+) -> Result<ServiceResponse<BoxBody>, ActixError> {
+    let res = match res {
+        Ok(res) if res.status().is_success() => res,
+        other => {
+            best_effort(db.redis_client.del(key).await, "dedup release");
+            return other.map(ServiceResponse::map_into_boxed_body);
+        }
+    };
+
+    let (req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let body = to_bytes(body)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e.into()))?;
+
+    let stored = String::from_utf8(body.to_vec())
+        .ok()
+        .map(|text| StoredResponse {
+            status: res.status().as_u16(),
+            content_type: res
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            body: text,
+        });
+    match stored.and_then(|stored| serde_json::to_string(&stored).ok()) {
+        Some(stored) => {
+            best_effort(
+                db.redis_client
+                    .set_with_expiry(key, &stored, window_seconds)
+                    .await,
+                "dedup store",
+            );
+        }
+        // A body we can't replay as text, duplicates will run on their own
+        None => {
+            best_effort(db.redis_client.del(key).await, "dedup release");
+        }
+    }
+
+    Ok(ServiceResponse::new(req, res.set_body(BoxBody::new(body))))
+}
+
+/// The first request's response once it finishes, `None` when it failed or Redis is gone
+///
+/// Gives up with a 409 if the first request is still running when the window ends.
+async fn wait_for_first(
+    db: &Database,
+    key: &str,
+    window_seconds: u64,
+) -> Result<Option<StoredResponse>, ActixError> {
+    let deadline = Instant::now() + Duration::from_secs(window_seconds);
+    loop {
+        let value = best_effort(db.redis_client.get_value(key).await, "dedup read").flatten();
+        match value.as_deref() {
+            Some(PENDING) if Instant::now() < deadline => tokio::time::sleep(POLL_INTERVAL).await,
+            Some(PENDING) => {
+                return Err(AppError::new(
+                    StatusCode::CONFLICT,
+                    "An identical request is still being processed",
+                )
+                .with_retry_after(1)
+                .into())
+            }
+            Some(stored) => return Ok(serde_json::from_str(stored).ok()),
+            None => return Ok(None),
+        }
+    }
+}
+
+fn replay_response(stored: StoredResponse) -> HttpResponse {
+    let status = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    let mut res = HttpResponse::build(status);
+    if let Some(content_type) = stored.content_type {
+        res.insert_header((CONTENT_TYPE, content_type));
+    }
+    res.insert_header((DEDUPLICATED_HEADER, "true"))
+        .body(stored.body)
+}
@@ -0,0 +1,81 @@
+use crate::error::AppError;
+use crate::middleware::client_ip::client_ip;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::Error as ActixError;
+use futures_util::future::LocalBoxFuture;
+use ipnet::IpNet;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+/// Rejects requests whose client IP is outside the configured CIDR ranges
+pub struct IpAllowlist {
+    allowlist: Rc<Vec<IpNet>>,
+    trusted_proxies: Rc<Vec<IpNet>>,
+}
+
+impl IpAllowlist {
+    pub fn new(allowlist: Vec<IpNet>, trusted_proxies: Vec<IpNet>) -> Self {
+        IpAllowlist {
+            allowlist: Rc::new(allowlist),
+            trusted_proxies: Rc::new(trusted_proxies),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for IpAllowlist
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Transform = IpAllowlistMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(IpAllowlistMiddleware {
+            service,
+            allowlist: self.allowlist.clone(),
+            trusted_proxies: self.trusted_proxies.clone(),
+        }))
+    }
+}
+
+pub struct IpAllowlistMiddleware<S> {
+    service: S,
+    allowlist: Rc<Vec<IpNet>>,
+    trusted_proxies: Rc<Vec<IpNet>>,
+}
+
+impl<S, B> Service<ServiceRequest> for IpAllowlistMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // An empty allowlist means the filter is disabled
+        if !self.allowlist.is_empty() {
+            let allowed = client_ip(req.request(), &self.trusted_proxies)
+                .map(|ip| self.allowlist.iter().any(|net| net.contains(&ip)))
+                .unwrap_or(false);
+
+            if !allowed {
+                return Box::pin(async move {
+                    Err(AppError::forbidden("Access from this IP address is not allowed").into())
+                });
+            }
+        }
+
+        let fut = self.service.call(req);
+        Box::pin(fut)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::db::database::Database;
+use crate::error::AppError;
+use crate::services::maintenance_service::{self, MaintenanceMode};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{Method, StatusCode};
+use actix_web::web::Data;
+use actix_web::Error as ActixError;
+use futures_util::future::LocalBoxFuture;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+/// Answers 503 while maintenance mode is on: `readonly` blocks writes, `full` blocks everything
+///
+/// Health checks and the admin endpoints stay reachable so maintenance can be switched off.
+pub struct MaintenanceGuard {
+    db: Data<Database>,
+    default_mode: MaintenanceMode,
+    retry_after_seconds: u64,
+}
+
+impl MaintenanceGuard {
+    pub fn new(
+        db: Data<Database>,
+        default_mode: MaintenanceMode,
+        retry_after_seconds: u64,
+    ) -> Self {
+        MaintenanceGuard {
+            db,
+            default_mode,
+            retry_after_seconds,
+        }
+    }
+}
+
+fn is_exempt(path: &str) -> bool {
+    path == "/api/health" || path.starts_with("/api/v1/admin/")
+}
+
+fn is_read_only(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+impl<S, B> Transform<S, ServiceRequest> for MaintenanceGuard
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Transform = MaintenanceGuardMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(MaintenanceGuardMiddleware {
+            service: Rc::new(service),
+            db: self.db.clone(),
+            default_mode: self.default_mode,
+            retry_after_seconds: self.retry_after_seconds,
+        }))
+    }
+}
+
+pub struct MaintenanceGuardMiddleware<S> {
+    service: Rc<S>,
+    db: Data<Database>,
+    default_mode: MaintenanceMode,
+    retry_after_seconds: u64,
+}
+
+impl<S, B> Service<ServiceRequest> for MaintenanceGuardMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let db = self.db.clone();
+        let default_mode = self.default_mode;
+        let retry_after_seconds = self.retry_after_seconds;
+
+        Box::pin(async move {
+            if !is_exempt(req.path()) {
+                let mode = maintenance_service::current_mode(&db.redis_client, default_mode).await;
+                let blocked = match mode {
+                    MaintenanceMode::Off => false,
+                    MaintenanceMode::Readonly => !is_read_only(req.method()),
+                    MaintenanceMode::Full => true,
+                };
+
+                if blocked {
+                    let message = match mode {
+                        MaintenanceMode::Readonly => {
+                            "The API is read-only during maintenance, please retry later"
+                        }
+                        _ => "The API is down for maintenance, please retry later",
+                    };
+                    return Err(AppError::new(StatusCode::SERVICE_UNAVAILABLE, message)
+                        .with_retry_after(retry_after_seconds)
+                        .into());
+                }
+            }
+
+            service.call(req).await
+        })
+    }
+}
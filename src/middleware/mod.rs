@@ -1,2 +1,11 @@
+pub mod accept;
 pub mod auth;
-pub mod logger;
+pub mod body_logger;
+pub mod client_ip;
+pub mod concurrency;
+pub mod dedup;
+pub mod ip_filter;
+pub mod maintenance;
+pub mod query_count;
+pub mod response_format;
+pub mod server_timing;
@@ -0,0 +1,88 @@
+use crate::services::metrics_service::count_queries;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::Error as ActixError;
+use futures_util::future::LocalBoxFuture;
+use log::warn;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+/// Response header carrying the number of DB operations the request ran
+pub const QUERY_COUNT_HEADER: &str = "x-db-query-count";
+
+/// Counts the DB operations each request runs and warns past `threshold`, to catch N+1 patterns
+///
+/// Development aid only: main.rs registers it in debug builds, and the count itself is
+/// compiled out of release builds.
+pub struct QueryCountGuard {
+    threshold: u32,
+}
+
+impl QueryCountGuard {
+    pub fn new(threshold: u32) -> Self {
+        QueryCountGuard { threshold }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for QueryCountGuard
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Transform = QueryCountGuardMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(QueryCountGuardMiddleware {
+            service: Rc::new(service),
+            threshold: self.threshold,
+        }))
+    }
+}
+
+pub struct QueryCountGuardMiddleware<S> {
+    service: Rc<S>,
+    threshold: u32,
+}
+
+impl<S, B> Service<ServiceRequest> for QueryCountGuardMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let threshold = self.threshold;
+        let method = req.method().clone();
+        let path = req.path().to_string();
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let (result, queries) = count_queries(fut).await;
+            let mut res = result?;
+
+            if queries > threshold {
+                warn!(
+                    "{} {} ran {} DB operations (threshold {}), possible N+1",
+                    method, path, queries, threshold
+                );
+            }
+            res.headers_mut().insert(
+                HeaderName::from_static(QUERY_COUNT_HEADER),
+                HeaderValue::from(queries),
+            );
+
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,167 @@
+use actix_web::body::{to_bytes, BoxBody, EitherBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderValue, CONTENT_DISPOSITION, VARY};
+use actix_web::http::Method;
+use actix_web::Error as ActixError;
+use futures_util::future::LocalBoxFuture;
+use serde_json::Value;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+/// Request header a client sets to `bare` to get reads without the response envelope
+pub const RESPONSE_FORMAT_HEADER: &str = "X-Response-Format";
+
+/// Request header a client sets to `true`/`false` to drop or keep null fields in responses
+pub const OMIT_NULLS_HEADER: &str = "X-Omit-Nulls";
+
+/// Unwraps `{success, message, data}` to just `data` on successful GETs that ask for it,
+/// and drops null fields from successful responses when asked to
+///
+/// Errors and writes keep the envelope so clients can still read the message.
+pub struct ResponseFormat {
+    /// Whether nulls are dropped when the client doesn't send `X-Omit-Nulls`
+    omit_nulls_by_default: bool,
+}
+
+impl ResponseFormat {
+    pub fn new(omit_nulls_by_default: bool) -> Self {
+        ResponseFormat {
+            omit_nulls_by_default,
+        }
+    }
+}
+
+fn wants_bare(req: &ServiceRequest) -> bool {
+    req.method() == Method::GET
+        && req
+            .headers()
+            .get(RESPONSE_FORMAT_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("bare"))
+}
+
+fn wants_omit_nulls(req: &ServiceRequest, default: bool) -> bool {
+    req.headers()
+        .get(OMIT_NULLS_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().to_lowercase().parse().ok())
+        .unwrap_or(default)
+}
+
+/// Takes the `data` payload out when the body is an envelope, `None` otherwise
+fn unwrap_envelope(body: &mut Value) -> Option<Value> {
+    match body {
+        Value::Object(envelope) if envelope.contains_key("success") => envelope.remove("data"),
+        _ => None,
+    }
+}
+
+/// The rewritten body, `None` when it isn't JSON or nothing needed to change
+fn reshape(body: &[u8], bare: bool, omit_nulls: bool) -> Option<Vec<u8>> {
+    let mut json = serde_json::from_slice::<Value>(body).ok()?;
+    let mut changed = false;
+
+    if bare {
+        if let Some(data) = unwrap_envelope(&mut json) {
+            json = data;
+            changed = true;
+        }
+    }
+    if omit_nulls {
+        strip_nulls(&mut json);
+        changed = true;
+    }
+
+    if !changed {
+        return None;
+    }
+    serde_json::to_vec(&json).ok()
+}
+
+/// Removes null object fields at every depth; nulls inside arrays keep their position
+fn strip_nulls(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            fields.retain(|_, field| !field.is_null());
+            fields.values_mut().for_each(strip_nulls);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_nulls),
+        _ => {}
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ResponseFormat
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B, BoxBody>>;
+    type Error = ActixError;
+    type Transform = ResponseFormatMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ResponseFormatMiddleware {
+            service: Rc::new(service),
+            omit_nulls_by_default: self.omit_nulls_by_default,
+        }))
+    }
+}
+
+pub struct ResponseFormatMiddleware<S> {
+    service: Rc<S>,
+    omit_nulls_by_default: bool,
+}
+
+impl<S, B> Service<ServiceRequest> for ResponseFormatMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B, BoxBody>>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let is_get = req.method() == Method::GET;
+        let bare = wants_bare(&req);
+        let omit_nulls = wants_omit_nulls(&req, self.omit_nulls_by_default);
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let mut res = fut.await?;
+
+            // Caches keyed only on the URL must not mix the two shapes
+            if is_get {
+                res.headers_mut()
+                    .append(VARY, HeaderValue::from_static(RESPONSE_FORMAT_HEADER));
+                res.headers_mut()
+                    .append(VARY, HeaderValue::from_static(OMIT_NULLS_HEADER));
+            }
+
+            // Downloads such as the data export are streamed, buffering them here would undo that
+            let is_download = res.headers().contains_key(CONTENT_DISPOSITION);
+            if !(bare || omit_nulls) || !res.status().is_success() || is_download {
+                return Ok(res.map_into_left_body());
+            }
+
+            let (req, res) = res.into_parts();
+            let (res, body) = res.into_parts();
+            let body = to_bytes(body)
+                .await
+                .map_err(|e| actix_web::error::ErrorInternalServerError(e.into()))?;
+
+            let body = match reshape(&body, bare, omit_nulls) {
+                Some(reshaped) => BoxBody::new(reshaped),
+                None => BoxBody::new(body),
+            };
+
+            Ok(ServiceResponse::new(req, res.set_body(body)).map_into_right_body())
+        })
+    }
+}
@@ -0,0 +1,82 @@
+use crate::services::metrics_service::time_request;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::Error as ActixError;
+use futures_util::future::LocalBoxFuture;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Response header devtools show as the request's timing breakdown
+pub const SERVER_TIMING_HEADER: &str = "server-timing";
+
+/// Adds a `Server-Timing` header with the time each request spent in the database, in Redis
+/// and in total, e.g. `db;dur=4.1, cache;dur=0.7, total;dur=9.3`
+///
+/// Built from the same timers as the /metrics histograms. It reveals internal timings, so
+/// main.rs only registers it when `SERVER_TIMING_ENABLED` is set.
+pub struct ServerTiming;
+
+impl<S, B> Transform<S, ServiceRequest> for ServerTiming
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Transform = ServerTimingMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ServerTimingMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct ServerTimingMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ServerTimingMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let started_at = Instant::now();
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let (result, timings) = time_request(fut).await;
+            let mut res = result?;
+
+            let value = format!(
+                "db;dur={}, cache;dur={}, total;dur={}",
+                millis(timings.db),
+                millis(timings.cache),
+                millis(started_at.elapsed())
+            );
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                res.headers_mut()
+                    .insert(HeaderName::from_static(SERVER_TIMING_HEADER), value);
+            }
+
+            Ok(res)
+        })
+    }
+}
+
+/// Milliseconds with one decimal, the precision devtools display
+fn millis(duration: Duration) -> String {
+    format!("{:.1}", duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,27 @@
+use crate::services::maintenance_service::MaintenanceMode;
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use utoipa::ToSchema;
+use validator::{Validate, ValidationError};
+
+/// Log level override for one module (e.g. `sqlx`), applied without a restart
+#[derive(Validate, Deserialize, Serialize, ToSchema, Debug)]
+pub struct LogLevelRequest {
+    #[validate(length(min = 1, max = 255, message = "module required"))]
+    pub module: String,
+    #[validate(custom(function = "validate_log_level"))]
+    pub level: String,
+}
+
+fn validate_log_level(level: &str) -> Result<(), ValidationError> {
+    LevelFilter::from_str(level).map(|_| ()).map_err(|_| {
+        ValidationError::new("level")
+            .with_message("must be one of off, error, warn, info, debug, trace".into())
+    })
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct MaintenanceModeRequest {
+    pub mode: MaintenanceMode,
+}
@@ -1,38 +1,151 @@
-use crate::models::todo::{DeleteTodoResponse, TodoResponse, TodoResponseList};
-use crate::routers::health::HealthResponse;
+use crate::models::todo::{
+    Agenda, BulkDeleteResponse, BulkTagResponse, CreatedTodo, DeleteTodoResponse,
+    EmptyTrashResponse, GroupedTodos, ImportResponse, PaginationLimits, RestoreAllResponse,
+    TodoCalendar, TodoCount, TodoResponse, TodoResponseList, TodoSearchResponse, TodoSuggestions,
+    TransitionResponse, TrashedTodoList,
+};
+use crate::routers::health::{HealthResponse, SchemaCheckResponse};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct ApiResponseTodoResponse {
     pub success: bool,
     pub message: String,
     pub data: Option<TodoResponse>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponseCreatedTodo {
+    pub success: bool,
+    pub message: String,
+    pub data: Option<CreatedTodo>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct ApiResponseTodoResponseList {
     pub success: bool,
     pub message: String,
     pub data: Option<TodoResponseList>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponsePaginationLimits {
+    pub success: bool,
+    pub message: String,
+    pub data: Option<PaginationLimits>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponseTodoSearchResponse {
+    pub success: bool,
+    pub message: String,
+    pub data: Option<TodoSearchResponse>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponseTodoCount {
+    pub success: bool,
+    pub message: String,
+    pub data: Option<TodoCount>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponseGroupedTodos {
+    pub success: bool,
+    pub message: String,
+    pub data: Option<GroupedTodos>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponseAgenda {
+    pub success: bool,
+    pub message: String,
+    pub data: Option<Agenda>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponseTodoCalendar {
+    pub success: bool,
+    pub message: String,
+    /// Todos keyed by due day, e.g. `{ "2024-05-03": [...] }`
+    #[schema(value_type = Option<std::collections::BTreeMap<String, Vec<TodoResponse>>>)]
+    pub data: Option<TodoCalendar>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponseImportResponse {
+    pub success: bool,
+    pub message: String,
+    pub data: Option<ImportResponse>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponseTodoSuggestions {
+    pub success: bool,
+    pub message: String,
+    pub data: Option<TodoSuggestions>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponseBulkTagResponse {
+    pub success: bool,
+    pub message: String,
+    pub data: Option<BulkTagResponse>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponseTrashedTodoList {
+    pub success: bool,
+    pub message: String,
+    pub data: Option<TrashedTodoList>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponseRestoreAllResponse {
+    pub success: bool,
+    pub message: String,
+    pub data: Option<RestoreAllResponse>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponseEmptyTrashResponse {
+    pub success: bool,
+    pub message: String,
+    pub data: Option<EmptyTrashResponse>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponseBulkDeleteResponse {
+    pub success: bool,
+    pub message: String,
+    pub data: Option<BulkDeleteResponse>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponseTransitionResponse {
+    pub success: bool,
+    pub message: String,
+    pub data: Option<TransitionResponse>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct ApiResponseDeleteTodoResponse {
     pub success: bool,
     pub message: String,
     pub data: Option<DeleteTodoResponse>,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct ApiResponseHealthResponse {
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponseSchemaCheckResponse {
     pub success: bool,
     pub message: String,
-    pub data: Option<HealthResponse>,
+    pub data: Option<SchemaCheckResponse>,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct ApiResponseEmpty {
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiResponseHealthResponse {
     pub success: bool,
     pub message: String,
-    pub data: Option<()>,
+    pub data: Option<HealthResponse>,
 }
@@ -0,0 +1,23 @@
+use crate::models::todo::{PaginationLimits, TodoResponseList};
+use crate::models::user::{UserPreferences, UserResponseWithoutPassword};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Todo totals shown on the home screen, snoozed todos included
+#[derive(Deserialize, Serialize, ToSchema, Clone, Debug)]
+pub struct TodoStats {
+    pub total: i64,
+    pub open: i64,
+    pub completed: i64,
+}
+
+/// What a client loads on launch, in one response instead of one call each
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct Bootstrap {
+    pub profile: UserResponseWithoutPassword,
+    pub preferences: UserPreferences,
+    /// First page of todos in the user's default order, as `GET /todos` without parameters
+    pub todos: TodoResponseList,
+    pub stats: TodoStats,
+    pub limits: PaginationLimits,
+}
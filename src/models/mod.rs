@@ -1,3 +1,5 @@
+pub mod admin;
+pub mod bootstrap;
 pub mod todo;
 pub mod user;
 pub mod app;
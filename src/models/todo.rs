@@ -1,17 +1,86 @@
+use crate::routers::extract::{FromQueryValues, QueryValues};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct CreateTodoRequest {
     pub title: String,
     pub description: String,
+    /// `#rrggbb` or a palette name (`TODO_COLOR_PALETTE`), null or omitted for no color
+    #[serde(default)]
+    pub color: Option<String>,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CreateTodoQuery {
+    /// Look for existing todos with a very similar title and list them in `possible_duplicates`
+    pub check_duplicates: Option<bool>,
+}
+
+/// An existing todo whose title closely resembles the one just created
+#[derive(Deserialize, Serialize, ToSchema, Clone)]
+pub struct PossibleDuplicate {
+    pub uuid: String,
+    pub title: String,
+    pub is_completed: bool,
+    /// Trigram similarity of the two titles, from 0 to 1
+    pub similarity: f32,
+}
+
+/// The created todo, plus similar existing ones when `check_duplicates=true` was passed
+#[derive(Deserialize, Serialize, ToSchema, Clone)]
+pub struct CreatedTodo {
+    #[serde(flatten)]
+    pub todo: TodoResponse,
+    /// Most similar first; null unless duplicates were checked
+    pub possible_duplicates: Option<Vec<PossibleDuplicate>>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Default)]
 pub struct UpdateTodoRequest {
     pub title: Option<String>,
     pub description: Option<String>,
     pub is_completed: Option<bool>,
+    /// Why the todo was completed; an empty string clears it
+    pub completion_note: Option<String>,
+    /// `#rrggbb` or a palette name; an empty string clears it, null or omitted keeps it
+    pub color: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct SnoozeTodoRequest {
+    /// Hidden from lists until this time, must be in the future
+    pub until: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct SetReminderRequest {
+    /// When the reminder fires, must be in the future
+    pub remind_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MovePosition {
+    Top,
+    Bottom,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct MoveTodoRequest {
+    /// Where the todo goes in `sort_by=position` order
+    pub position: MovePosition,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct CompleteTodoRequest {
+    /// How the todo was resolved
+    #[serde(default)]
+    pub note: Option<String>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -24,7 +93,7 @@ pub struct GetTodoURL {
     pub uuid: String,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema, Clone)]
 pub struct TodoResponse {
     pub uuid: String,
     pub title: String,
@@ -33,23 +102,164 @@ pub struct TodoResponse {
     pub user_id: String,
     pub created_at: String,
     pub updated_at: String,
+    pub tags: Vec<String>,
+    pub is_pinned: bool,
+    /// Why the todo was completed, cleared when it's reopened
+    #[serde(default)]
+    pub completion_note: Option<String>,
+    /// Bumped on every change, cached copies are keyed by it
+    #[serde(default)]
+    pub version: i64,
+    /// Hidden from lists until then unless `include_snoozed` is set
+    #[serde(default)]
+    pub snoozed_until: Option<String>,
+    /// When a reminder is sent for this todo
+    #[serde(default)]
+    pub remind_at: Option<String>,
+    /// Manual order, lowest first when listing with `sort_by=position`
+    #[serde(default)]
+    pub position: i64,
+    /// Lowercase `#rrggbb` or palette name, null when the todo has no color
+    #[serde(default)]
+    pub color: Option<String>,
+    /// Id of the last user to change the todo, the owner until someone else does
+    #[serde(default)]
+    pub updated_by: String,
+    /// Name of `updated_by`, only present when requested with `include_owner`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_by_name: Option<String>,
+    /// Owner details, only present when requested with `include_owner`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_name: Option<String>,
+    /// Full-text search relevance, only present when a search is active
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relevance: Option<f32>,
+    /// True when a list cut `description` short because of `truncate_description`
+    #[serde(default)]
+    pub description_truncated: bool,
 }
 
-#[derive(Deserialize, Serialize)]
+impl TodoResponse {
+    /// Cuts the description to at most `max_chars` characters, never inside a character
+    pub fn truncate_description(&mut self, max_chars: usize) {
+        if let Some((end, _)) = self.description.char_indices().nth(max_chars) {
+            self.description.truncate(end);
+            self.description_truncated = true;
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct TodoResponseList {
     pub todos: Vec<TodoResponse>,
     pub total: i64,
     pub page: i64,
     pub page_size: i64,
     pub total_pages: i64,
+    /// Largest `page_size` the server accepts
+    #[serde(default)]
+    pub max_page_size: i64,
+}
+
+/// Paging limits, so clients can size their pages without guessing
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct PaginationLimits {
+    pub default_page_size: i64,
+    pub max_page_size: i64,
+}
+
+/// What a todo list may be sorted by; `relevance` only applies while searching
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    CreatedAt,
+    UpdatedAt,
+    Title,
+    IsCompleted,
+    Position,
+    Relevance,
+}
+
+impl SortField {
+    pub const ALL: [SortField; 6] = [
+        SortField::CreatedAt,
+        SortField::UpdatedAt,
+        SortField::Title,
+        SortField::IsCompleted,
+        SortField::Position,
+        SortField::Relevance,
+    ];
+
+    /// The expression ordered by, also the name clients send; `relevance` is a computed column
+    pub fn column(self) -> &'static str {
+        match self {
+            SortField::CreatedAt => "created_at",
+            SortField::UpdatedAt => "updated_at",
+            SortField::Title => "title",
+            SortField::IsCompleted => "is_completed",
+            SortField::Position => "position",
+            SortField::Relevance => "relevance",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|field| field.column() == name)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    pub const ALL: [SortOrder; 2] = [SortOrder::Asc, SortOrder::Desc];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|order| order.as_str() == name)
+    }
+
+    pub fn sql(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
+impl std::fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct PaginationParams {
     pub page: Option<i64>,
     pub page_size: Option<i64>,
 }
 
+impl FromQueryValues for PaginationParams {
+    fn from_query_values(values: &mut QueryValues) -> Self {
+        Self {
+            page: values.int("page"),
+            page_size: values.int("page_size"),
+        }
+    }
+}
+
 impl Default for PaginationParams {
     fn default() -> Self {
         Self {
@@ -59,12 +269,24 @@ impl Default for PaginationParams {
     }
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct TodoFilter {
     pub search: Option<String>,
     pub is_completed: Option<bool>,
-    pub sort_by: Option<String>,
-    pub sort_order: Option<String>,
+    /// Unknown values are rejected, not replaced by the default
+    pub sort_by: Option<SortField>,
+    pub sort_order: Option<SortOrder>,
+    /// Include owner email and name, and the name behind `updated_by`, for views listing
+    /// other users' todos
+    pub include_owner: Option<bool>,
+    /// List pinned todos before the rest (default true); `false` sorts purely by `sort_by`
+    pub pinned_first: Option<bool>,
+    /// Also list snoozed todos, which are hidden until their snooze ends by default
+    pub include_snoozed: Option<bool>,
+    /// Cut each description to this many characters for previews, the single-todo GET
+    /// always returns the full text
+    pub truncate_description: Option<i64>,
 }
 
 impl Default for TodoFilter {
@@ -72,13 +294,151 @@ impl Default for TodoFilter {
         Self {
             search: None,
             is_completed: None,
-            sort_by: Some("created_at".to_string()),
-            sort_order: Some("desc".to_string()),
+            sort_by: Some(SortField::CreatedAt),
+            sort_order: Some(SortOrder::Desc),
+            include_owner: None,
+            pinned_first: None,
+            include_snoozed: None,
+            truncate_description: None,
+        }
+    }
+}
+
+impl FromQueryValues for TodoFilter {
+    fn from_query_values(values: &mut QueryValues) -> Self {
+        Self {
+            search: values.string("search"),
+            is_completed: values.bool("is_completed"),
+            sort_by: values.choice("sort_by", &SortField::ALL.map(SortField::column)),
+            sort_order: values.choice("sort_order", &SortOrder::ALL.map(SortOrder::as_str)),
+            include_owner: values.bool("include_owner"),
+            pinned_first: values.bool("pinned_first"),
+            include_snoozed: values.bool("include_snoozed"),
+            truncate_description: values.int("truncate_description"),
         }
     }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+pub const DEFAULT_SUGGEST_LIMIT: i64 = 10;
+pub const MAX_SUGGEST_LIMIT: i64 = 20;
+/// Most similar todos listed in `possible_duplicates`
+pub const MAX_POSSIBLE_DUPLICATES: i64 = 5;
+
+#[derive(Deserialize, Serialize, Clone, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SuggestQuery {
+    /// Text typed so far
+    pub q: String,
+    /// Maximum number of suggestions (default 10, max 20)
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct TodoSuggestions {
+    pub suggestions: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    /// Search terms, same syntax as the list `search` filter
+    pub q: String,
+    pub page: Option<i64>,
+    pub page_size: Option<i64>,
+}
+
+/// A search match; `<mark>` wraps matched terms and everything else is HTML-escaped
+#[derive(Deserialize, Serialize, ToSchema, Clone)]
+pub struct TodoSearchResult {
+    #[serde(flatten)]
+    pub todo: TodoResponse,
+    pub title_highlight: String,
+    /// Best matching fragments of the description
+    pub snippet: String,
+    pub score: f32,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct TodoSearchResponse {
+    pub results: Vec<TodoSearchResult>,
+    pub total: i64,
+    pub page: i64,
+    pub page_size: i64,
+    pub total_pages: i64,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct TodoCount {
+    pub count: i64,
+}
+
+pub const DEFAULT_GROUP_SIZE: i64 = 20;
+
+#[derive(Deserialize, Serialize, Clone, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GroupedQuery {
+    /// Maximum todos returned per group (default 20)
+    pub page_size: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Default)]
+pub struct TodoGroup {
+    /// Pinned first, then most recently updated
+    pub todos: Vec<TodoResponse>,
+    /// Todos in the group, including those beyond `page_size`
+    pub total: i64,
+}
+
+/// Open todos split by whether their reminder time has passed, snoozed todos are left out
+#[derive(Deserialize, Serialize, ToSchema, Default)]
+pub struct GroupedTodos {
+    pub pending: TodoGroup,
+    pub completed: TodoGroup,
+    /// Open todos whose `remind_at` is in the past
+    pub overdue: TodoGroup,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AgendaQuery {
+    /// IANA time zone the days are counted in, e.g. `Asia/Ho_Chi_Minh` (default UTC)
+    pub tz: Option<String>,
+    /// Maximum todos returned per bucket (default 20)
+    pub page_size: Option<i64>,
+}
+
+/// Open todos bucketed by the local day of their `remind_at`, the todo's due date
+///
+/// Every bucket is sorted by due date, earliest first; `total` counts the whole bucket.
+#[derive(Deserialize, Serialize, ToSchema, Default)]
+pub struct Agenda {
+    /// The time zone the buckets were computed in
+    pub tz: String,
+    /// Due on a day before today
+    pub overdue: TodoGroup,
+    pub today: TodoGroup,
+    pub tomorrow: TodoGroup,
+    /// Due after tomorrow, up to the end of this week (Sunday)
+    pub this_week: TodoGroup,
+    pub later: TodoGroup,
+    /// No reminder set, pinned first then newest
+    pub no_due_date: TodoGroup,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CalendarQuery {
+    /// Month to show as `YYYY-MM`, e.g. `2024-05`
+    pub month: String,
+    /// IANA time zone the days are counted in, e.g. `Asia/Ho_Chi_Minh` (default UTC)
+    pub tz: Option<String>,
+}
+
+/// Todos due in a month keyed by local day (`YYYY-MM-DD`), earliest due first within a day;
+/// days without todos are left out
+pub type TodoCalendar = BTreeMap<String, Vec<TodoResponse>>;
+
+#[derive(Deserialize, Serialize, Debug, Default)]
 pub struct TodoQueryParams {
     #[serde(flatten)]
     pub pagination: PaginationParams,
@@ -86,11 +446,33 @@ pub struct TodoQueryParams {
     pub filter: TodoFilter,
 }
 
-impl Default for TodoQueryParams {
-    fn default() -> Self {
+impl FromQueryValues for TodoQueryParams {
+    fn from_query_values(values: &mut QueryValues) -> Self {
         Self {
-            pagination: PaginationParams::default(),
-            filter: TodoFilter::default(),
+            pagination: PaginationParams::from_query_values(values),
+            filter: TodoFilter::from_query_values(values),
+        }
+    }
+}
+
+impl TodoQueryParams {
+    /// A list request with no query parameters at all, i.e. the default first page
+    pub fn unfiltered() -> Self {
+        Self {
+            pagination: PaginationParams {
+                page: None,
+                page_size: None,
+            },
+            filter: TodoFilter {
+                search: None,
+                is_completed: None,
+                sort_by: None,
+                sort_order: None,
+                include_owner: None,
+                pinned_first: None,
+                include_snoozed: None,
+                truncate_description: None,
+            },
         }
     }
 }
@@ -99,32 +481,209 @@ impl std::fmt::Display for TodoQueryParams {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "page={};page_size={};search={};is_completed={};sort_by={};sort_order={}",
+            "page={};page_size={};search={};is_completed={};sort_by={};sort_order={};include_owner={};pinned_first={};include_snoozed={};truncate_description={}",
             self.pagination.page.unwrap_or(1),
             self.pagination.page_size.unwrap_or(10),
             self.filter.search.as_deref().unwrap_or(""),
             self.filter.is_completed.unwrap_or(false),
-            self.filter.sort_by.as_deref().unwrap_or("created_at"),
-            self.filter.sort_order.as_deref().unwrap_or("desc")
+            self.filter
+                .sort_by
+                .map(SortField::column)
+                .unwrap_or_default(),
+            self.filter.sort_order.unwrap_or(SortOrder::Desc),
+            self.filter.include_owner.unwrap_or(false),
+            self.filter.pinned_first.unwrap_or(true),
+            self.filter.include_snoozed.unwrap_or(false),
+            self.filter.truncate_description.unwrap_or(0)
         )
     }
 }
 
-#[derive(Deserialize, Serialize)]
+pub const MAX_BULK_TAGS: u64 = 20;
+pub const MAX_TAG_LENGTH: usize = 32;
+
+#[derive(Validate, Deserialize, Serialize, ToSchema)]
+pub struct BulkTagRequest {
+    /// At most MAX_BATCH_SIZE ids
+    #[validate(length(min = 1, message = "no todos"))]
+    pub todo_uuids: Vec<String>,
+    #[serde(default)]
+    #[validate(length(max = MAX_BULK_TAGS, message = "too many tags to add"))]
+    pub add: Vec<String>,
+    /// Removal wins when a tag is in both lists
+    #[serde(default)]
+    #[validate(length(max = MAX_BULK_TAGS, message = "too many tags to remove"))]
+    pub remove: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct BatchItemResult {
+    /// Position of the item in the request
+    pub index: usize,
+    pub todo_uuid: String,
+    pub success: bool,
+    /// Machine readable failure reason, e.g. `not_found`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The todo after the change, for operations that return one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<TodoResponse>,
+}
+
+impl BatchItemResult {
+    pub fn succeeded(index: usize, todo_uuid: String, data: Option<TodoResponse>) -> Self {
+        Self {
+            index,
+            todo_uuid,
+            success: true,
+            error_code: None,
+            error: None,
+            data,
+        }
+    }
+
+    /// Todos the caller can't see are reported the same way as missing ones
+    pub fn not_found(index: usize, todo_uuid: String) -> Self {
+        Self {
+            index,
+            todo_uuid,
+            success: false,
+            error_code: Some("not_found".to_string()),
+            error: Some("Todo not found".to_string()),
+            data: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct BulkTagResponse {
+    pub updated: usize,
+    pub results: Vec<BatchItemResult>,
+}
+
+/// Which todos a transition applies to; same meaning as the list filters, empty matches all
+#[derive(Deserialize, Serialize, ToSchema, Clone, Debug, Default)]
+pub struct TransitionFilter {
+    pub search: Option<String>,
+    pub is_completed: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct TransitionSet {
+    pub is_completed: bool,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct TransitionRequest {
+    #[serde(default)]
+    pub filter: TransitionFilter,
+    pub set: TransitionSet,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct TransitionResponse {
+    /// Todos whose state actually changed, ones already in the target state aren't counted
+    pub affected: usize,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BulkDeleteQuery {
+    /// Comma separated todo ids
+    pub ids: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    /// Body format, only `csv` is supported
+    pub format: String,
+}
+
+/// A validated row, ready to insert
+pub struct ImportedTodo {
+    pub title: String,
+    pub description: String,
+    pub is_completed: bool,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct ImportRowError {
+    /// Line in the file, the header is line 1
+    pub line: usize,
+    pub error: String,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct ImportResponse {
+    pub imported: usize,
+    /// Every invalid row; nothing is imported unless this is empty
+    pub errors: Vec<ImportRowError>,
+    pub todos: Vec<TodoResponse>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct TrashedTodo {
+    #[serde(flatten)]
+    pub todo: TodoResponse,
+    pub deleted_at: String,
+}
+
+/// A todo in a data export, with `deleted_at` set when it is in the trash
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct ExportedTodo {
+    #[serde(flatten)]
+    pub todo: TodoResponse,
+    pub deleted_at: Option<String>,
+}
+
+/// The user's trash, most recently deleted first
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct TrashedTodoList {
+    pub todos: Vec<TrashedTodo>,
+    pub total: i64,
+    pub page: i64,
+    pub page_size: i64,
+    pub total_pages: i64,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct RestoreAllResponse {
+    pub restored: usize,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct EmptyTrashResponse {
+    /// Todos deleted for good
+    pub purged: u64,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct BulkDeleteResponse {
+    pub requested: usize,
+    /// Lower than `requested` when some ids don't exist or belong to someone else
+    pub deleted: usize,
+    pub deleted_ids: Vec<String>,
+    pub results: Vec<BatchItemResult>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct DeleteTodoResponse {
     pub success: bool,
     pub message: String,
     pub todo_id: String,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct ApiResponse<T> {
     pub success: bool,
     pub message: String,
     pub data: Option<T>,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct Todo {
     pub uuid: String,
     pub title: String,
@@ -133,6 +692,16 @@ pub struct Todo {
     pub owner_id: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub tags: Vec<String>,
+    pub is_pinned: bool,
+    pub completion_note: Option<String>,
+    pub version: i64,
+    pub snoozed_until: Option<DateTime<Utc>>,
+    pub remind_at: Option<DateTime<Utc>>,
+    pub position: i64,
+    pub color: Option<String>,
+    /// Last user to change the todo, `None` when only its owner ever did
+    pub updated_by: Option<String>,
 }
 
 impl Todo {
@@ -153,12 +722,22 @@ impl Todo {
             owner_id,
             created_at,
             updated_at,
+            tags: Vec::new(),
+            is_pinned: false,
+            completion_note: None,
+            version: 1,
+            snoozed_until: None,
+            remind_at: None,
+            position: 0,
+            color: None,
+            updated_by: None,
         }
     }
 }
 
 impl From<Todo> for TodoResponse {
     fn from(todo: Todo) -> Self {
+        let updated_by = todo.updated_by.unwrap_or_else(|| todo.owner_id.clone());
         TodoResponse {
             uuid: todo.uuid,
             title: todo.title,
@@ -167,6 +746,20 @@ impl From<Todo> for TodoResponse {
             user_id: todo.owner_id,
             created_at: todo.created_at.to_string(),
             updated_at: todo.updated_at.to_string(),
+            tags: todo.tags,
+            is_pinned: todo.is_pinned,
+            completion_note: todo.completion_note,
+            version: todo.version,
+            snoozed_until: todo.snoozed_until.map(|until| until.to_string()),
+            remind_at: todo.remind_at.map(|remind_at| remind_at.to_string()),
+            position: todo.position,
+            color: todo.color,
+            updated_by,
+            updated_by_name: None,
+            owner_email: None,
+            owner_name: None,
+            relevance: None,
+            description_truncated: false,
         }
     }
 }
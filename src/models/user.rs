@@ -1,7 +1,10 @@
+use crate::models::todo::{ExportedTodo, SortField, SortOrder};
+use crate::services::two_factor_service::{MAX_BACKUP_CODES_COUNT, MIN_BACKUP_CODES_COUNT};
 use serde::{Deserialize, Serialize};
-use validator::Validate;
+use utoipa::ToSchema;
+use validator::{Validate, ValidationError};
 
-#[derive(Validate, Deserialize, Serialize)]
+#[derive(Validate, Deserialize, Serialize, ToSchema)]
 pub struct CreateUserRequest {
     #[validate(email, length(min = 6, message = "email required"))]
     pub email: String,
@@ -11,7 +14,7 @@ pub struct CreateUserRequest {
     pub name: String,
 }
 
-#[derive(Validate, Deserialize, Serialize)]
+#[derive(Validate, Deserialize, Serialize, ToSchema)]
 pub struct LoginRequest {
     #[validate(email, length(min = 6, message = "email required"))]
     pub email: String,
@@ -20,39 +23,33 @@ pub struct LoginRequest {
     pub totp_code: Option<String>,
 }
 
-#[derive(Validate, Deserialize, Serialize)]
+#[derive(Validate, Deserialize, Serialize, ToSchema)]
 pub struct RefreshTokenRequest {
     #[validate(length(min = 1, message = "refresh token required"))]
     pub refresh_token: String,
 }
 
-#[derive(Validate, Deserialize, Serialize)]
-pub struct UpdateUserRequest {
-    #[validate(email, length(min = 6, message = "email required"))]
-    pub email: String,
-}
-
-#[derive(Validate, Deserialize, Serialize)]
-pub struct UpdateUserURL {
-    pub uuid: String,
-}
-
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct UserResponse {
     pub user: UserResponseWithoutPassword,
     pub access_token: String,
     pub refresh_token: String,
     pub token_type: String,
+    /// Admin without 2FA while `REQUIRE_ADMIN_2FA` is on; admin endpoints refuse them until they enroll
+    pub two_factor_setup_required: bool,
+    /// Ids of the oldest sessions signed out because the account hit `MAX_SESSIONS_PER_USER`
+    #[serde(default)]
+    pub evicted_sessions: Vec<String>,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct TokenResponse {
     pub access_token: String,
     pub refresh_token: String,
     pub token_type: String,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct UserResponseWithoutPassword {
     pub uuid: String,
     pub email: String,
@@ -62,7 +59,7 @@ pub struct UserResponseWithoutPassword {
     pub two_factor_enabled: bool,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, ToSchema)]
 pub struct User {
     pub uuid: String,
     pub email: String,
@@ -70,9 +67,16 @@ pub struct User {
     pub password: String,
     pub created_at: String,
     pub updated_at: String,
+    /// `updated_at` as read from the database, for ETag and Last-Modified
+    #[serde(skip)]
+    pub updated_at_utc: chrono::DateTime<chrono::Utc>,
     pub two_factor_enabled: bool,
     pub two_factor_secret: Option<String>,
-    pub backup_codes: Option<Vec<String>>,
+    pub two_factor_pending_secret: Option<String>,
+    pub two_factor_pending_since: Option<chrono::DateTime<chrono::Utc>>,
+    pub is_admin: bool,
+    /// Last email change, used for the change cooldown
+    pub email_changed_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl User {
@@ -90,9 +94,13 @@ impl User {
             password: String::new(),
             created_at: created_at.to_string(),
             updated_at: updated_at.to_string(),
+            updated_at_utc: updated_at.and_utc(),
             two_factor_enabled: false,
             two_factor_secret: None,
-            backup_codes: None,
+            two_factor_pending_secret: None,
+            two_factor_pending_since: None,
+            is_admin: false,
+            email_changed_at: None,
         }
     }
 }
@@ -110,32 +118,144 @@ impl From<User> for UserResponseWithoutPassword {
     }
 }
 
-#[derive(Validate, Deserialize, Serialize)]
+#[derive(Validate, Deserialize, Serialize, ToSchema)]
 pub struct Enable2FARequest {
     #[validate(length(min = 6, message = "password required"))]
     pub password: String,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct Enable2FAResponse {
     pub secret: String,
     pub qr_code: String,
     pub message: String,
 }
 
-#[derive(Validate, Deserialize, Serialize)]
+#[derive(Validate, Deserialize, Serialize, ToSchema)]
+pub struct Regenerate2FAQrRequest {
+    #[validate(length(min = 6, message = "password required"))]
+    pub password: String,
+    /// Current TOTP code, or a backup code when `factor` is `backup_code`
+    #[validate(length(min = 6, max = 32, message = "code required"))]
+    pub code: String,
+    /// Defaults to `totp`
+    pub factor: Option<String>,
+    /// Also return the raw secret for manual entry
+    #[serde(default)]
+    pub reveal_secret: bool,
+}
+
+/// QR for the existing secret, so a new device can be enrolled without disabling 2FA
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct Regenerate2FAQrResponse {
+    pub otpauth_url: String,
+    pub qr_code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    pub message: String,
+}
+
+#[derive(Validate, Deserialize, Serialize, ToSchema)]
 pub struct Verify2FARequest {
     #[validate(length(min = 6, message = "code required"))]
     pub code: String,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Validate, Deserialize, Serialize, ToSchema)]
+pub struct Confirm2FARequest {
+    #[validate(length(min = 6, message = "password required"))]
+    pub password: String,
+    #[validate(length(min = 6, message = "code required"))]
+    pub code: String,
+}
+
+/// 2FA is active; the backup codes are shown only this once
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct Confirm2FAResponse {
+    pub success: bool,
+    pub backup_codes: Vec<String>,
+    /// Id of the backup code set, used to revoke it later
+    pub generation: String,
+    pub message: String,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct Verify2FAResponse {
     pub success: bool,
     pub message: String,
 }
 
-#[derive(Validate, Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone, ToSchema)]
+pub struct TwoFactorDevice {
+    pub uuid: String,
+    pub user_id: String,
+    pub name: String,
+    pub secret: String,
+    pub created_at: String,
+    /// `created_at` as read from the database, for the pending setup window
+    #[serde(skip)]
+    pub created_at_utc: chrono::DateTime<chrono::Utc>,
+    /// None while the device is pending, i.e. no code from it has been confirmed yet
+    pub verified_at: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct TwoFactorDeviceResponse {
+    pub uuid: String,
+    pub name: String,
+    pub created_at: String,
+    /// Pending devices aren't accepted at login until verified
+    pub verified: bool,
+}
+
+impl From<TwoFactorDevice> for TwoFactorDeviceResponse {
+    fn from(device: TwoFactorDevice) -> Self {
+        TwoFactorDeviceResponse {
+            uuid: device.uuid,
+            name: device.name,
+            created_at: device.created_at,
+            verified: device.verified_at.is_some(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct TwoFactorDevicesResponse {
+    pub has_primary_device: bool,
+    /// One page of the extra devices, oldest first, pending ones included
+    pub devices: Vec<TwoFactorDeviceResponse>,
+    pub total: i64,
+    pub page: i64,
+    pub page_size: i64,
+    pub total_pages: i64,
+}
+
+#[derive(Validate, Deserialize, Serialize, ToSchema)]
+pub struct AddTwoFactorDeviceRequest {
+    #[validate(length(min = 6, message = "password required"))]
+    pub password: String,
+    #[validate(length(min = 1, max = 50, message = "device name must be 1-50 characters"))]
+    pub name: String,
+    /// Current code from a device that is already active
+    #[validate(length(min = 6, message = "code required"))]
+    pub code: String,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct AddTwoFactorDeviceResponse {
+    pub device: TwoFactorDeviceResponse,
+    pub secret: String,
+    pub qr_code: String,
+    pub message: String,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct Test2FAResponse {
+    pub valid: bool,
+    pub message: String,
+}
+
+#[derive(Validate, Deserialize, Serialize, ToSchema)]
 pub struct Disable2FARequest {
     #[validate(length(min = 6, message = "password required"))]
     pub password: String,
@@ -143,41 +263,225 @@ pub struct Disable2FARequest {
     pub code: String,
 }
 
-#[derive(Deserialize, Serialize)]
+fn validate_backup_codes_count(count: usize) -> Result<(), ValidationError> {
+    if (MIN_BACKUP_CODES_COUNT..=MAX_BACKUP_CODES_COUNT).contains(&count) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("range").with_message(
+            format!(
+                "count must be between {} and {}",
+                MIN_BACKUP_CODES_COUNT, MAX_BACKUP_CODES_COUNT
+            )
+            .into(),
+        ))
+    }
+}
+
+#[derive(Validate, Deserialize, Serialize, ToSchema)]
+pub struct GenerateBackupCodesRequest {
+    #[validate(length(min = 6, message = "code required"))]
+    pub code: String,
+    #[validate(custom(function = "validate_backup_codes_count"))]
+    pub count: Option<usize>,
+    /// Keep the existing sets valid instead of replacing them
+    #[serde(default)]
+    pub append: bool,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct GenerateBackupCodesResponse {
     pub backup_codes: Vec<String>,
+    /// Id of the new set, used to revoke it later
+    pub generation: String,
     pub message: String,
 }
 
-#[derive(Validate, Deserialize, Serialize)]
+/// One generation of backup codes, codes are stored hashed
+#[derive(Deserialize, Serialize, Clone, ToSchema)]
+pub struct BackupCodeSet {
+    pub uuid: String,
+    pub user_id: String,
+    pub codes: Vec<String>,
+    pub created_at: String,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct BackupCodeSetResponse {
+    pub generation: String,
+    /// Unused codes left in this set
+    pub remaining: usize,
+    pub created_at: String,
+}
+
+impl From<BackupCodeSet> for BackupCodeSetResponse {
+    fn from(set: BackupCodeSet) -> Self {
+        BackupCodeSetResponse {
+            generation: set.uuid,
+            remaining: set.codes.len(),
+            created_at: set.created_at,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct BackupCodeSetsResponse {
+    /// One page of the sets with codes left, oldest first
+    pub sets: Vec<BackupCodeSetResponse>,
+    pub total: i64,
+    pub page: i64,
+    pub page_size: i64,
+    pub total_pages: i64,
+}
+
+#[derive(Validate, Deserialize, Serialize, ToSchema)]
 pub struct VerifyBackupCodeRequest {
-    pub backup_code: String,
+    #[validate(length(min = 1, max = 32, message = "backup code required"))]
+    pub code: String,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Validate, Deserialize, Serialize, ToSchema)]
 pub struct UseBackupCodeForLoginRequest {
+    #[validate(email, length(min = 6, message = "email required"))]
     pub email: String,
+    #[validate(length(min = 6, message = "password required"))]
     pub password: String,
+    #[validate(length(min = 1, max = 32, message = "backup code required"))]
     pub backup_code: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct TwoFactorChallengeResponse {
     pub user_id: String,
+    /// Kept for older clients, same value as `mfa_token`
     pub session_id: String,
+    /// Short-lived token exchanged on `/verify-otp` instead of resending the password
+    pub mfa_token: String,
+    /// Factors the client may prompt for, e.g. `totp`, `backup_code`
+    pub available_factors: Vec<String>,
+    pub expires_in: u64,
     pub message: String,
 }
 
-#[derive(Deserialize, Validate)]
+#[derive(Deserialize, Validate, ToSchema)]
 pub struct VerifyOtpRequest {
-    #[validate(length(min = 6, max = 6))]
+    /// TOTP code, or a backup code when `factor` is `backup_code`
+    #[validate(length(min = 6, max = 32))]
     pub otp: String,
+    #[serde(alias = "mfa_token")]
     pub session_id: String,
+    /// Defaults to `totp`
+    pub factor: Option<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 #[serde(untagged)]
 pub enum LoginResponse {
     FullLogin(UserResponse),
     TwoFactorChallenge(TwoFactorChallengeResponse),
-}
\ No newline at end of file
+}
+
+fn validate_sort_by(sort_by: &SortField) -> Result<(), ValidationError> {
+    if *sort_by == SortField::Relevance {
+        Err(ValidationError::new("sort_by")
+            .with_message("relevance only applies to searches".into()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Defaults applied to todo lists when the request leaves them out; `null` means built-in default
+#[derive(Validate, Deserialize, Serialize, ToSchema, Clone, Debug, Default)]
+pub struct UserPreferences {
+    #[validate(custom(function = "validate_sort_by"))]
+    pub sort_by: Option<SortField>,
+    pub sort_order: Option<SortOrder>,
+    /// Between 1 and MAX_PAGE_SIZE
+    pub page_size: Option<i64>,
+}
+
+/// A signed-in session, i.e. a refresh token that hasn't been used or revoked
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct ExportedSession {
+    pub expires_at: String,
+}
+
+/// A signed-in session as listed to its user
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct SessionResponse {
+    /// Id of the session's refresh token, as reported in `evicted_sessions`
+    pub id: String,
+    pub expires_at: String,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct SessionList {
+    /// One page of the sessions, most recently signed in or refreshed first
+    pub sessions: Vec<SessionResponse>,
+    pub total: i64,
+    pub page: i64,
+    pub page_size: i64,
+    pub total_pages: i64,
+}
+
+/// Everything stored about a user, for a data-portability download
+///
+/// Passwords, 2FA secrets and backup codes are left out; `todos` includes deleted ones.
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct UserExport {
+    pub exported_at: String,
+    pub profile: UserResponseWithoutPassword,
+    pub preferences: UserPreferences,
+    pub two_factor_devices: Vec<TwoFactorDeviceResponse>,
+    pub sessions: Vec<ExportedSession>,
+    /// Every tag used on the user's todos, sorted
+    pub tags: Vec<String>,
+    /// Has to stay the last field: the export is streamed by serializing the rest with no
+    /// todos, then writing the todos into the array page by page
+    pub todos: Vec<ExportedTodo>,
+}
+
+/// What the 2FA settings screen needs to render the real state
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct TwoFactorStatusResponse {
+    pub totp_enabled: bool,
+    /// Email codes aren't supported yet, always false
+    pub email_otp_enabled: bool,
+    pub backup_codes_remaining: usize,
+    /// Extra authenticator devices registered on top of the primary one
+    pub trusted_devices_count: usize,
+}
+
+#[derive(Validate, Deserialize, Serialize, ToSchema)]
+pub struct VerifyPasswordRequest {
+    #[validate(length(min = 1, message = "password required"))]
+    pub password: String,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct VerifyPasswordResponse {
+    pub valid: bool,
+}
+
+#[derive(Validate, Deserialize, Serialize, ToSchema)]
+pub struct ChangeEmailRequest {
+    #[validate(length(min = 6, message = "password required"))]
+    pub password: String,
+    #[validate(email, length(min = 6, message = "email required"))]
+    pub new_email: String,
+}
+
+#[derive(Validate, Deserialize, Serialize, ToSchema)]
+pub struct ChangePasswordRequest {
+    #[validate(length(min = 6, message = "current password required"))]
+    pub current_password: String,
+    #[validate(length(min = 6, message = "new password must be at least 6 characters"))]
+    pub new_password: String,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct ChangePasswordResponse {
+    pub success: bool,
+    pub message: String,
+    /// Whether every refresh token was revoked; access tokens still run out their hour
+    pub sessions_revoked: bool,
+}
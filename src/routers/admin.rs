@@ -0,0 +1,139 @@
+use crate::config::AppConfig;
+use crate::db::data_trait::user_data_trait::UserData;
+use crate::db::database::Database;
+use crate::error::user_error::UserError;
+use crate::middleware::auth::validator;
+use crate::models::admin::{LogLevelRequest, MaintenanceModeRequest};
+use crate::services::cache_service::required;
+use crate::services::{log_service, maintenance_service};
+use actix_web::web::{Data, Json};
+use actix_web::{get, put, HttpMessage, HttpRequest};
+use actix_web_httpauth::middleware::HttpAuthentication;
+use log::{info, LevelFilter};
+use std::str::FromStr;
+use utoipa::OpenApi;
+use validator::Validate;
+
+pub fn admin_routes(cfg: &mut actix_web::web::ServiceConfig) {
+    cfg.service(set_log_level)
+        .service(get_maintenance_mode)
+        .service(set_maintenance_mode);
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(set_log_level, get_maintenance_mode, set_maintenance_mode))]
+pub struct AdminApi;
+
+/// Loads the caller and rejects anyone without the admin flag
+///
+/// With `REQUIRE_ADMIN_2FA` an admin also needs 2FA enabled.
+pub(crate) async fn ensure_admin(
+    req: &HttpRequest,
+    db: &Database,
+    config: &AppConfig,
+) -> Result<String, UserError> {
+    let user_id = req
+        .extensions()
+        .get::<String>()
+        .cloned()
+        .ok_or(UserError::AuthenticationFailure)?;
+
+    let user = db.get_user_by_uuid(&user_id).await?;
+    if !user.is_admin {
+        return Err(UserError::AdminRequired);
+    }
+    if config.require_admin_2fa && !user.two_factor_enabled {
+        return Err(UserError::AdminTwoFactorRequired);
+    }
+
+    Ok(user_id)
+}
+
+#[utoipa::path(
+    tag = "admin",
+    request_body = LogLevelRequest,
+    responses(
+        (status = 200, description = "Level applied", body = LogLevelRequest),
+        (status = 400, description = "Unknown log level"),
+        (status = 403, description = "Caller is not an admin, or an admin without required 2FA")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[put("/admin/log-level", wrap = "HttpAuthentication::bearer(validator)")]
+pub async fn set_log_level(
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    body: Json<LogLevelRequest>,
+) -> Result<Json<LogLevelRequest>, UserError> {
+    let admin_id = ensure_admin(&req, &db, &config).await?;
+
+    body.validate()?;
+
+    let module = body.module.trim().to_string();
+    let level = LevelFilter::from_str(&body.level)
+        .map_err(|_| UserError::BadRequest(format!("unknown log level: {}", body.level)))?;
+
+    log_service::set_module_level(&module, level);
+    info!(
+        "Log level for {} set to {} by admin {}",
+        module, level, admin_id
+    );
+
+    Ok(Json(LogLevelRequest {
+        module,
+        level: level.to_string().to_lowercase(),
+    }))
+}
+
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "Current maintenance mode", body = MaintenanceModeRequest),
+        (status = 403, description = "Caller is not an admin, or an admin without required 2FA")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[get("/admin/maintenance", wrap = "HttpAuthentication::bearer(validator)")]
+pub async fn get_maintenance_mode(
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+) -> Result<Json<MaintenanceModeRequest>, UserError> {
+    ensure_admin(&req, &db, &config).await?;
+
+    let mode = maintenance_service::current_mode(&db.redis_client, config.maintenance_mode).await;
+
+    Ok(Json(MaintenanceModeRequest { mode }))
+}
+
+#[utoipa::path(
+    tag = "admin",
+    request_body = MaintenanceModeRequest,
+    responses(
+        (status = 200, description = "Mode applied on every instance within a few seconds", body = MaintenanceModeRequest),
+        (status = 403, description = "Caller is not an admin, or an admin without required 2FA")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[put("/admin/maintenance", wrap = "HttpAuthentication::bearer(validator)")]
+pub async fn set_maintenance_mode(
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    body: Json<MaintenanceModeRequest>,
+) -> Result<Json<MaintenanceModeRequest>, UserError> {
+    let admin_id = ensure_admin(&req, &db, &config).await?;
+
+    required(
+        maintenance_service::set_mode(&db.redis_client, body.mode).await,
+        "maintenance mode write",
+    )?;
+    info!(
+        "Maintenance mode set to {} by admin {}",
+        body.mode.as_str(),
+        admin_id
+    );
+
+    Ok(Json(MaintenanceModeRequest { mode: body.mode }))
+}
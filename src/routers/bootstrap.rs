@@ -0,0 +1,124 @@
+use crate::config::AppConfig;
+use crate::db::data_trait::todo_data_trait::TodoData;
+use crate::db::data_trait::user_data_trait::UserData;
+use crate::db::database::Database;
+use crate::error::user_error::UserError;
+use crate::error::AppError;
+use crate::middleware::auth::validator;
+use crate::models::bootstrap::{Bootstrap, TodoStats};
+use crate::models::todo::{PaginationLimits, TodoFilter, TodoQueryParams};
+use crate::models::user::UserResponseWithoutPassword;
+use crate::services::cache_service::{best_effort, CacheService};
+use actix_web::web::{Data, Json};
+use actix_web::{get, HttpMessage, HttpRequest};
+use actix_web_httpauth::middleware::HttpAuthentication;
+use futures_util::try_join;
+use utoipa::OpenApi;
+
+/// Short on purpose: todo and preference changes drop the entry, profile changes don't
+const BOOTSTRAP_CACHE_TTL: u64 = 30;
+
+pub fn bootstrap_routes(cfg: &mut actix_web::web::ServiceConfig) {
+    cfg.service(get_bootstrap);
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(get_bootstrap))]
+pub struct BootstrapApi;
+
+/// Under the user's `todos:` prefix so every todo write and preference update invalidates it
+fn bootstrap_cache_key(user_id: &str) -> String {
+    format!("todos:user:{}:bootstrap", user_id)
+}
+
+fn todo_error(error: AppError) -> UserError {
+    UserError::DatabaseError(error.message)
+}
+
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 200, description = "Profile, preferences, first page of todos, todo totals and paging limits", body = Bootstrap),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[get("/bootstrap", wrap = "HttpAuthentication::bearer(validator)")]
+pub async fn get_bootstrap(
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+) -> Result<Json<Bootstrap>, UserError> {
+    let user_id = req
+        .extensions()
+        .get::<String>()
+        .cloned()
+        .ok_or(UserError::AuthenticationFailure)?;
+
+    let cache_key = bootstrap_cache_key(&user_id);
+    if let Some(cached) = best_effort(
+        db.redis_client.get_cached::<Bootstrap>(&cache_key).await,
+        &format!("read of {}", cache_key),
+    )
+    .flatten()
+    {
+        return Ok(Json(cached));
+    }
+
+    // The same reads the separate endpoints make, run side by side
+    let first_page = TodoQueryParams::unfiltered();
+    let count_filter = |is_completed| TodoFilter {
+        is_completed: Some(is_completed),
+        include_snoozed: Some(true),
+        ..TodoFilter::default()
+    };
+    let (user, preferences, todos, open, completed) = try_join!(
+        db.get_user_by_uuid(&user_id),
+        db.get_user_preferences(&user_id),
+        async {
+            Database::get_all_todos(
+                &db,
+                user_id.clone(),
+                first_page.pagination,
+                first_page.filter,
+                &config.limits,
+            )
+            .await
+            .map_err(todo_error)
+        },
+        async {
+            Database::count_todos(&db, user_id.clone(), count_filter(false))
+                .await
+                .map_err(todo_error)
+        },
+        async {
+            Database::count_todos(&db, user_id.clone(), count_filter(true))
+                .await
+                .map_err(todo_error)
+        },
+    )?;
+
+    let bootstrap = Bootstrap {
+        profile: UserResponseWithoutPassword::from(user),
+        preferences,
+        todos,
+        stats: TodoStats {
+            total: open + completed,
+            open,
+            completed,
+        },
+        limits: PaginationLimits {
+            default_page_size: config.limits.default_page_size,
+            max_page_size: config.limits.max_page_size,
+        },
+    };
+
+    best_effort(
+        db.redis_client
+            .set_cached(&cache_key, &bootstrap, BOOTSTRAP_CACHE_TTL)
+            .await,
+        &format!("write of bootstrap for user {}", user_id),
+    );
+
+    Ok(Json(bootstrap))
+}
@@ -0,0 +1,221 @@
+use crate::error::{AppError, FieldErrors};
+use actix_web::dev::Payload;
+use actix_web::web::Query;
+use actix_web::{FromRequest, HttpRequest};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::{ready, Ready};
+use std::ops::Deref;
+
+/// Raw query parameters, read one field at a time so each bad value gets its own message
+pub struct QueryValues {
+    values: HashMap<String, String>,
+    errors: FieldErrors,
+}
+
+impl QueryValues {
+    pub fn parse(query: &str) -> Result<Self, AppError> {
+        let values = Query::<HashMap<String, String>>::from_query(query)
+            .map_err(|_| AppError::bad_request("malformed query string"))?
+            .into_inner();
+
+        Ok(QueryValues {
+            values,
+            errors: FieldErrors::new(),
+        })
+    }
+
+    pub fn string(&mut self, key: &str) -> Option<String> {
+        self.values.remove(key)
+    }
+
+    /// An empty value counts as absent
+    pub fn int(&mut self, key: &str) -> Option<i64> {
+        let value = self.values.remove(key)?;
+        let value = value.trim();
+        if value.is_empty() {
+            return None;
+        }
+
+        match value.parse() {
+            Ok(parsed) => Some(parsed),
+            Err(_) => {
+                self.error(key, format!("{} must be an integer", key));
+                None
+            }
+        }
+    }
+
+    /// A unit enum read through its serde names in any case, naming the `allowed` ones on
+    /// failure; an empty value counts as absent
+    pub fn choice<T: DeserializeOwned>(&mut self, key: &str, allowed: &[&str]) -> Option<T> {
+        let value = self.values.remove(key)?;
+        let value = value.trim().to_lowercase();
+        if value.is_empty() {
+            return None;
+        }
+
+        match serde_json::from_value(Value::String(value)) {
+            Ok(parsed) => Some(parsed),
+            Err(_) => {
+                self.error(
+                    key,
+                    format!("{} must be one of: {}", key, allowed.join(", ")),
+                );
+                None
+            }
+        }
+    }
+
+    /// Accepts `true`/`false`, `1`/`0` and `yes`/`no` in any case; an empty value counts as absent
+    pub fn bool(&mut self, key: &str) -> Option<bool> {
+        let value = self.values.remove(key)?;
+        match value.trim().to_lowercase().as_str() {
+            "" => None,
+            "true" | "1" | "yes" => Some(true),
+            "false" | "0" | "no" => Some(false),
+            _ => {
+                self.error(key, format!("{} must be true/false", key));
+                None
+            }
+        }
+    }
+
+    fn error(&mut self, key: &str, message: String) {
+        self.errors
+            .entry(key.to_string())
+            .or_default()
+            .push(message);
+    }
+
+    /// Every field error at once, so clients can fix them in one go
+    fn finish(self) -> Result<(), AppError> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::validation(self.errors))
+        }
+    }
+}
+
+/// Query parameter structs readable through `LenientQuery`
+pub trait FromQueryValues: Sized {
+    fn from_query_values(values: &mut QueryValues) -> Self;
+}
+
+/// Like `Query<T>`, but malformed values are rejected field by field in the error envelope
+pub struct LenientQuery<T>(pub T);
+
+impl<T> LenientQuery<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for LenientQuery<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: FromQueryValues> FromRequest for LenientQuery<T> {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            QueryValues::parse(req.query_string()).and_then(|mut values| {
+                let parsed = T::from_query_values(&mut values);
+                values.finish().map(|_| LenientQuery(parsed))
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::todo::{SortField, TodoQueryParams};
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    fn extract(query: &str) -> Result<TodoQueryParams, AppError> {
+        let req = TestRequest::with_uri(&format!("/todos?{}", query)).to_http_request();
+        LenientQuery::<TodoQueryParams>::from_request(&req, &mut Payload::None)
+            .into_inner()
+            .map(LenientQuery::into_inner)
+    }
+
+    fn field_errors(query: &str) -> FieldErrors {
+        let err = extract(query).expect_err("query should be rejected");
+        assert_eq!(err.status_code, StatusCode::BAD_REQUEST);
+        err.field_errors.expect("rejection should name the fields")
+    }
+
+    #[test]
+    fn misspelled_sort_column_is_rejected_naming_the_allowed_ones() {
+        let errors = field_errors("sort_by=titel");
+
+        assert_eq!(
+            errors["sort_by"],
+            vec!["sort_by must be one of: created_at, updated_at, title, is_completed, position, relevance"]
+        );
+    }
+
+    #[test]
+    fn sort_column_is_read_in_any_case() {
+        let params = extract("sort_by=Title").unwrap();
+
+        assert_eq!(params.filter.sort_by, Some(SortField::Title));
+    }
+
+    #[test]
+    fn malformed_bool_is_rejected_by_field() {
+        let errors = field_errors("is_completed=maybe");
+
+        assert_eq!(
+            errors["is_completed"],
+            vec!["is_completed must be true/false"]
+        );
+    }
+
+    #[test]
+    fn malformed_int_is_rejected_by_field() {
+        let errors = field_errors("page=two");
+
+        assert_eq!(errors["page"], vec!["page must be an integer"]);
+    }
+
+    #[test]
+    fn malformed_sort_order_is_rejected_naming_the_allowed_ones() {
+        let errors = field_errors("sort_order=up");
+
+        assert_eq!(
+            errors["sort_order"],
+            vec!["sort_order must be one of: asc, desc"]
+        );
+    }
+
+    #[test]
+    fn every_malformed_field_is_reported_at_once() {
+        let errors = field_errors("is_completed=maybe&page_size=lots&sort_order=up&search=ok");
+
+        assert_eq!(
+            errors.keys().collect::<Vec<_>>(),
+            vec!["is_completed", "page_size", "sort_order"]
+        );
+    }
+
+    #[test]
+    fn lenient_bools_and_empty_values_are_accepted() {
+        let params = extract("is_completed=YES&pinned_first=0&include_owner=1&page=").unwrap();
+
+        assert_eq!(params.filter.is_completed, Some(true));
+        assert_eq!(params.filter.pinned_first, Some(false));
+        assert_eq!(params.filter.include_owner, Some(true));
+        assert_eq!(params.pagination.page, None);
+    }
+}
@@ -0,0 +1,29 @@
+use crate::error::AppError;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, Resource, ResponseError};
+
+/// App-wide default service, so unmatched requests get the usual error envelope
+///
+/// A path that exists for other methods is answered with 405 instead of 404.
+pub async fn default_handler(req: HttpRequest) -> Result<HttpResponse, AppError> {
+    if req.resource_map().has_resource(req.path()) {
+        Err(AppError::new(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "Method Not Allowed",
+        ))
+    } else {
+        Err(AppError::not_found("Not Found"))
+    }
+}
+
+/// Stands in for a route switched off by a feature flag
+///
+/// Registered at the route's own path so a broader pattern like `/{uuid}` can't pick it up.
+pub fn disabled_route(path: &str) -> Resource {
+    web::resource(path).to(|| async { Err::<HttpResponse, _>(AppError::not_found("Not Found")) })
+}
+
+/// Rejections from the JSON, query and path extractors, wrapped in the error envelope
+pub fn extractor_error<E: ResponseError>(err: E, _req: &HttpRequest) -> actix_web::Error {
+    AppError::new(err.status_code(), err.to_string()).into()
+}
@@ -1,25 +1,80 @@
+use crate::config::AppConfig;
 use crate::db::database::Database;
-use actix_web::{get, web, HttpResponse};
+use crate::error::user_error::UserError;
+use crate::middleware::auth::validator;
+use crate::models::app::{ApiResponseHealthResponse, ApiResponseSchemaCheckResponse};
+use crate::routers::admin::ensure_admin;
+use crate::services::self_check_service::{schema_drift, TableSchemaDiff};
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use actix_web_httpauth::middleware::HttpAuthentication;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::time::{SystemTime, UNIX_EPOCH};
-use crate::models::app::ApiResponseHealthResponse;
+use utoipa::{OpenApi, ToSchema};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct HealthResponse {
     pub status: String,
-    pub version: String,
     pub timestamp: u64,
-    pub database: String,
-    pub redis: String,
+    /// Detailed fields, omitted unless HEALTH_TOKEN is unset or sent in `X-Health-Token`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redis: Option<String>,
+}
+
+/// Header carrying the shared secret that unlocks the detailed health fields
+pub const HEALTH_TOKEN_HEADER: &str = "X-Health-Token";
+
+/// Actual columns compared against the ones the queries need
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SchemaCheckResponse {
+    /// `pass`, or `fail` when a column is missing; extra columns alone don't fail the check
+    pub status: String,
+    pub tables: Vec<TableSchemaDiff>,
 }
 
 pub fn health_routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(health);
+    cfg.service(health).service(schema_health);
 }
 
+#[derive(OpenApi)]
+#[openapi(paths(health, schema_health))]
+pub struct HealthApi;
 
+#[utoipa::path(
+    tag = "health",
+    params(("X-Health-Token" = Option<String>, Header, description = "Required for version and dependency status when HEALTH_TOKEN is set")),
+    responses((status = 200, description = "Service health", body = ApiResponseHealthResponse))
+)]
 #[get("/health")]
-async fn health(db: web::Data<Database>) -> HttpResponse {
+async fn health(
+    req: HttpRequest,
+    db: web::Data<Database>,
+    config: web::Data<AppConfig>,
+) -> HttpResponse {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    // Without the token this is only a liveness probe, so don't touch the dependencies
+    if !is_authorized(&req, config.health_token.as_deref()) {
+        return HttpResponse::Ok().json(ApiResponseHealthResponse {
+            success: true,
+            message: "Health check successful".to_string(),
+            data: Some(HealthResponse {
+                status: "ok".to_string(),
+                timestamp,
+                version: None,
+                database: None,
+                redis: None,
+            }),
+        });
+    }
+
     let db_status = match sqlx::query("SELECT 1").fetch_one(&db.pool).await {
         Ok(_) => "connected",
         Err(_) => "disconnected",
@@ -30,19 +85,14 @@ async fn health(db: web::Data<Database>) -> HttpResponse {
         Err(_) => "disconnected",
     };
 
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
-
     let version = env!("CARGO_PKG_VERSION", "0.1.0");
 
     let health_data = HealthResponse {
         status: "ok".to_string(),
-        version: version.to_string(),
         timestamp,
-        database: db_status.to_string(),
-        redis: redis_status.to_string(),
+        version: Some(version.to_string()),
+        database: Some(db_status.to_string()),
+        redis: Some(redis_status.to_string()),
     };
 
     let response = ApiResponseHealthResponse {
@@ -53,3 +103,60 @@ async fn health(db: web::Data<Database>) -> HttpResponse {
 
     HttpResponse::Ok().json(response)
 }
+
+/// Detailed health is open when no token is configured, as before HEALTH_TOKEN existed
+fn is_authorized(req: &HttpRequest, expected: Option<&str>) -> bool {
+    let Some(expected) = expected else {
+        return true;
+    };
+
+    // Comparing digests keeps the comparison time independent of where the tokens differ
+    req.headers()
+        .get(HEALTH_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|provided| {
+            Sha256::digest(provided.as_bytes()) == Sha256::digest(expected.as_bytes())
+        })
+}
+
+#[utoipa::path(
+    tag = "health",
+    responses(
+        (status = 200, description = "Every column the queries need exists", body = ApiResponseSchemaCheckResponse),
+        (status = 403, description = "Caller is not an admin, or an admin without required 2FA"),
+        (status = 503, description = "Columns are missing, the database needs a migration", body = ApiResponseSchemaCheckResponse)
+    ),
+    security(("bearer_auth" = []))
+)]
+#[get("/health/schema", wrap = "HttpAuthentication::bearer(validator)")]
+async fn schema_health(
+    req: HttpRequest,
+    db: web::Data<Database>,
+    config: web::Data<AppConfig>,
+) -> Result<HttpResponse, UserError> {
+    ensure_admin(&req, &db, &config).await?;
+
+    let tables = schema_drift(&db)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+    let passed = tables.iter().all(|table| table.missing.is_empty());
+
+    let response = ApiResponseSchemaCheckResponse {
+        success: passed,
+        message: if passed {
+            "Schema matches the application".to_string()
+        } else {
+            "Schema drift detected: missing columns".to_string()
+        },
+        data: Some(SchemaCheckResponse {
+            status: if passed { "pass" } else { "fail" }.to_string(),
+            tables,
+        }),
+    };
+
+    Ok(if passed {
+        HttpResponse::Ok().json(response)
+    } else {
+        HttpResponse::ServiceUnavailable().json(response)
+    })
+}
@@ -0,0 +1,14 @@
+use crate::services::metrics_service;
+use actix_web::{get, web, HttpResponse};
+
+/// Only registered when METRICS_ENABLED is set; scrape it from inside the network
+pub fn metrics_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(metrics);
+}
+
+#[get("/metrics")]
+async fn metrics() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics_service::render())
+}
@@ -1,3 +1,8 @@
+pub mod admin;
+pub mod bootstrap;
+pub mod extract;
+pub mod fallback;
 pub mod health;
+pub mod metrics;
 pub mod todo;
 pub mod user;
@@ -1,74 +1,285 @@
+use crate::config::{features, AppConfig, Features};
 use crate::db::data_trait::todo_data_trait::TodoData;
 use crate::db::database::Database;
+use crate::error::app_error::field_error;
 use crate::error::AppError;
 use crate::models::app::{
-    ApiResponseDeleteTodoResponse, ApiResponseTodoResponse, ApiResponseTodoResponseList,
+    ApiResponseAgenda, ApiResponseBulkDeleteResponse, ApiResponseBulkTagResponse,
+    ApiResponseCreatedTodo, ApiResponseDeleteTodoResponse, ApiResponseEmptyTrashResponse,
+    ApiResponseGroupedTodos, ApiResponseImportResponse, ApiResponsePaginationLimits,
+    ApiResponseRestoreAllResponse, ApiResponseTodoCalendar, ApiResponseTodoCount,
+    ApiResponseTodoResponse, ApiResponseTodoResponseList, ApiResponseTodoSearchResponse,
+    ApiResponseTodoSuggestions, ApiResponseTransitionResponse, ApiResponseTrashedTodoList,
 };
 use crate::models::todo::{
-    CreateTodoRequest, GetTodoURL, TodoQueryParams, TodoResponse, TodoResponseList,
-    UpdateTodoRequest, UpdateTodoURL,
+    Agenda, AgendaQuery, BatchItemResult, BulkDeleteQuery, BulkDeleteResponse, BulkTagRequest,
+    BulkTagResponse, CalendarQuery, CompleteTodoRequest, CreateTodoQuery, CreateTodoRequest,
+    CreatedTodo, EmptyTrashResponse, GetTodoURL, GroupedQuery, GroupedTodos, ImportQuery,
+    ImportResponse, ImportRowError, ImportedTodo, MoveTodoRequest, PaginationLimits,
+    PaginationParams, RestoreAllResponse, SearchQuery, SetReminderRequest, SnoozeTodoRequest,
+    SuggestQuery, TodoCalendar, TodoCount, TodoFilter, TodoQueryParams, TodoResponse,
+    TodoResponseList, TodoSearchResponse, TodoSuggestions, TransitionRequest, TransitionResponse,
+    UpdateTodoRequest, UpdateTodoURL, DEFAULT_GROUP_SIZE, DEFAULT_SUGGEST_LIMIT,
+    MAX_POSSIBLE_DUPLICATES, MAX_SUGGEST_LIMIT, MAX_TAG_LENGTH,
 };
-use crate::services::cache_service::CacheService;
+use crate::routers::extract::LenientQuery;
+use crate::routers::fallback::disabled_route;
+use crate::services::cache_service::{best_effort, CacheService};
+use crate::services::csv_service::{parse_csv, CsvRecord};
+use crate::services::event_service::{publish_event, DomainEvent, EventPublisher};
+use actix_web::http::header::{HeaderName, CACHE_CONTROL};
 use actix_web::http::StatusCode;
 use actix_web::web::{Data, Json, Path, Query};
-use actix_web::{delete, get, patch, post, HttpMessage, HttpRequest};
+use actix_web::{
+    delete, get, patch, post, CustomizeResponder, HttpMessage, HttpRequest, Responder,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use log::warn;
+use std::collections::{HashMap, HashSet};
+use utoipa::OpenApi;
+use validator::Validate;
 
 const CACHE_TTL: u64 = 300; // 5 minutes
+const SUGGEST_CACHE_TTL: u64 = 30; // called on every keystroke, keep it short
+const COUNT_CACHE_TTL: u64 = 30; // badges are polled often
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    get_todos,
+    get_limits,
+    count_todos,
+    grouped_todos,
+    get_agenda,
+    get_calendar,
+    suggest_todos,
+    search_todos,
+    get_trash,
+    restore_all_todos,
+    empty_trash,
+    get_todo,
+    create_todo,
+    import_todos,
+    update_todo,
+    complete_todo,
+    pin_todo,
+    unpin_todo,
+    move_todo,
+    snooze_todo,
+    unsnooze_todo,
+    set_reminder,
+    clear_reminder,
+    bulk_update_tags,
+    transition_todos,
+    delete_todo,
+    delete_todos
+))]
+pub struct TodoApi;
+
+/// Authenticated reads may be cached by the browser, never by shared caches
+fn private_cache_control(config: &AppConfig) -> (HeaderName, String) {
+    (
+        CACHE_CONTROL,
+        format!("private, max-age={}", config.cache_max_age_seconds),
+    )
+}
+
+/// Trims and lowercases tag names, rejecting anything but letters, digits, `-` and `_`
+fn normalize_tags(tags: &[String]) -> Result<Vec<String>, AppError> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        let valid = !tag.is_empty()
+            && tag.chars().count() <= MAX_TAG_LENGTH
+            && tag
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(AppError::bad_request(format!(
+                "invalid tag name: '{}' (1-{} letters, digits, '-' or '_')",
+                tag, MAX_TAG_LENGTH
+            )));
+        }
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    Ok(normalized)
+}
+
+/// Lists, counts and searches; single todos are versioned so changes never need to delete them
+fn collections_cache_pattern(user_id: &str) -> String {
+    format!("todos:user:{}:[^i]*", user_id)
+}
+
+fn item_version_key(user_id: &str, todo_uuid: &str) -> String {
+    format!("todos:user:{}:item:{}:version", user_id, todo_uuid)
+}
+
+fn item_cache_key(user_id: &str, todo_uuid: &str, version: i64) -> String {
+    format!("todos:user:{}:item:{}:v{}", user_id, todo_uuid, version)
+}
 
-pub fn todo_routes(cfg: &mut actix_web::web::ServiceConfig) {
+/// Drops cached entries of the user matching `pattern`, true when it worked
+///
+/// Best effort: the write already happened in the database; a failure leaves stale entries
+/// until their TTL runs out.
+async fn invalidate_todo_cache(db: &Database, user_id: &str, pattern: &str) -> bool {
+    best_effort(
+        db.redis_client.delete_cached_by_pattern(pattern).await,
+        &format!("invalidation for user {}", user_id),
+    )
+    .is_some()
+}
+
+/// Points readers at the given versions; entries cached under older versions are never read again
+async fn raise_item_versions(db: &Database, user_id: &str, todos: &[(&str, i64)]) {
+    let versions: Vec<(String, i64)> = todos
+        .iter()
+        .map(|(todo_uuid, version)| (item_version_key(user_id, todo_uuid), *version))
+        .collect();
+
+    best_effort(
+        db.redis_client.raise_versions(&versions, CACHE_TTL).await,
+        &format!("version update for user {}", user_id),
+    );
+}
+
+/// Caches a todo under its own version, then moves the version pointer to it
+async fn cache_todo_version(db: &Database, user_id: &str, todo: &TodoResponse) {
+    let cache_key = item_cache_key(user_id, &todo.uuid, todo.version);
+    let cached = best_effort(
+        db.redis_client
+            .set_cached(&cache_key, todo, CACHE_TTL)
+            .await,
+        &format!("write of todo {} for user {}", todo.uuid, user_id),
+    );
+    if cached.is_none() {
+        return;
+    }
+    raise_item_versions(db, user_id, &[(&todo.uuid, todo.version)]).await;
+}
+
+/// `207 Multi-Status` when any item of a batch failed, `200` when all of them succeeded
+fn batch_status(results: &[BatchItemResult]) -> StatusCode {
+    if results.iter().all(|result| result.success) {
+        StatusCode::OK
+    } else {
+        StatusCode::MULTI_STATUS
+    }
+}
+
+fn get_user_id(req: &HttpRequest) -> Result<String, AppError> {
+    req.extensions()
+        .get::<String>()
+        .cloned()
+        .ok_or_else(|| AppError::new(StatusCode::UNAUTHORIZED, "User ID not found in request"))
+}
+
+pub fn todo_routes(cfg: &mut actix_web::web::ServiceConfig, features: &Features) {
     cfg.service(get_todos);
+    // Must be registered before `/{uuid}` so "limits", "count", "grouped", "agenda", "calendar",
+    // "suggest", "search" and "trash" aren't taken as todo ids
+    cfg.service(get_limits);
+    cfg.service(count_todos);
+    cfg.service(grouped_todos);
+    cfg.service(get_agenda);
+    cfg.service(get_calendar);
+    cfg.service(suggest_todos);
+    if features.is_enabled(features::TODO_SEARCH) {
+        cfg.service(search_todos);
+    } else {
+        cfg.service(disabled_route("/search"));
+    }
+    cfg.service(get_trash);
+    cfg.service(restore_all_todos);
+    cfg.service(empty_trash);
     cfg.service(get_todo);
     cfg.service(create_todo);
+    if features.is_enabled(features::TODO_IMPORT) {
+        cfg.service(import_todos);
+    } else {
+        cfg.service(disabled_route("/import"));
+    }
     cfg.service(update_todo);
+    cfg.service(complete_todo);
+    cfg.service(pin_todo);
+    cfg.service(unpin_todo);
+    cfg.service(move_todo);
+    cfg.service(snooze_todo);
+    cfg.service(unsnooze_todo);
+    cfg.service(set_reminder);
+    cfg.service(clear_reminder);
+    cfg.service(bulk_update_tags);
+    cfg.service(transition_todos);
     cfg.service(delete_todo);
+    cfg.service(delete_todos);
 }
 
+#[utoipa::path(
+    tag = "todos",
+    params(PaginationParams, TodoFilter),
+    responses(
+        (status = 200, description = "Paginated todos", body = ApiResponseTodoResponseList),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer_auth" = []))
+)]
 #[get("")]
 async fn get_todos(
     req: HttpRequest,
     db: Data<Database>,
-    query_params: Query<TodoQueryParams>,
-) -> Result<Json<ApiResponseTodoResponseList>, AppError> {
-    let extensions = req.extensions();
-    let user_id = extensions
-        .get::<String>()
-        .ok_or_else(|| AppError::new(StatusCode::UNAUTHORIZED, "User ID not found in request"))?;
+    config: Data<AppConfig>,
+    query_params: LenientQuery<TodoQueryParams>,
+) -> Result<CustomizeResponder<Json<ApiResponseTodoResponseList>>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    config
+        .limits
+        .check_page_size(query_params.pagination.page_size)?;
+    if let Some(search) = &query_params.filter.search {
+        config.limits.check_search(search)?;
+    }
 
     // Clone query_params before consuming it
     let query_params_inner = query_params.into_inner();
-    let cache_key = format!(
-        "todos:user:{}:list:{}",
-        user_id,
-        query_params_inner.to_string()
-    );
+    let cache_key = format!("todos:user:{}:list:{}", user_id, query_params_inner);
 
     // Try to get from cache first
-    if let Ok(Some(cached_data)) = db
-        .redis_client
-        .get_cached::<TodoResponseList>(&cache_key)
-        .await
+    if let Some(cached_data) = best_effort(
+        db.redis_client
+            .get_cached::<TodoResponseList>(&cache_key)
+            .await,
+        &format!("read of {}", cache_key),
+    )
+    .flatten()
     {
         return Ok(Json(ApiResponseTodoResponseList {
             success: true,
             message: "Todos retrieved successfully".to_string(),
             data: Some(cached_data),
-        }));
+        })
+        .customize()
+        .insert_header(private_cache_control(&config)));
     }
 
     // If not in cache, get from database
     let todos = Database::get_all_todos(
         &db,
-        user_id.to_string(),
+        user_id.clone(),
         query_params_inner.pagination,
         query_params_inner.filter,
+        &config.limits,
     )
     .await?;
 
     // Store in cache
-    if let Ok(_) = db
-        .redis_client
-        .set_cached(&cache_key, &todos, CACHE_TTL)
-        .await
+    if best_effort(
+        db.redis_client
+            .set_cached(&cache_key, &todos, CACHE_TTL)
+            .await,
+        &format!("write of todos list for user {}", user_id),
+    )
+    .is_some()
     {
         log::info!("Successfully cached todos list for user {}", user_id);
     }
@@ -77,124 +288,802 @@ async fn get_todos(
         success: true,
         message: "Todos retrieved successfully".to_string(),
         data: Some(todos),
-    }))
+    })
+    .customize()
+    .insert_header(private_cache_control(&config)))
 }
 
+#[utoipa::path(
+    tag = "todos",
+    responses(
+        (status = 200, description = "Default and largest page_size accepted by list endpoints", body = ApiResponsePaginationLimits),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[get("/limits")]
+async fn get_limits(
+    config: Data<AppConfig>,
+) -> CustomizeResponder<Json<ApiResponsePaginationLimits>> {
+    Json(ApiResponsePaginationLimits {
+        success: true,
+        message: "Limits retrieved successfully".to_string(),
+        data: Some(PaginationLimits {
+            default_page_size: config.limits.default_page_size,
+            max_page_size: config.limits.max_page_size,
+        }),
+    })
+    .customize()
+    .insert_header(private_cache_control(&config))
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(TodoFilter),
+    responses(
+        (status = 200, description = "Number of matching todos", body = ApiResponseTodoCount),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[get("/count")]
+async fn count_todos(
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    filter: LenientQuery<TodoFilter>,
+) -> Result<CustomizeResponder<Json<ApiResponseTodoCount>>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    if let Some(search) = &filter.search {
+        config.limits.check_search(search)?;
+    }
+
+    let filter = filter.into_inner();
+    let cache_key = format!(
+        "todos:user:{}:count:search={};is_completed={:?};include_snoozed={}",
+        user_id,
+        filter.search.as_deref().unwrap_or(""),
+        filter.is_completed,
+        filter.include_snoozed.unwrap_or(false)
+    );
+
+    let count = match best_effort(
+        db.redis_client.get_cached::<i64>(&cache_key).await,
+        &format!("read of {}", cache_key),
+    )
+    .flatten()
+    {
+        Some(cached) => cached,
+        None => {
+            let count = Database::count_todos(&db, user_id.clone(), filter).await?;
+            best_effort(
+                db.redis_client
+                    .set_cached(&cache_key, &count, COUNT_CACHE_TTL)
+                    .await,
+                &format!("write of todo count for user {}", user_id),
+            );
+            count
+        }
+    };
+
+    Ok(Json(ApiResponseTodoCount {
+        success: true,
+        message: "Todo count retrieved successfully".to_string(),
+        data: Some(TodoCount { count }),
+    })
+    .customize()
+    .insert_header((
+        CACHE_CONTROL,
+        format!("private, max-age={}", COUNT_CACHE_TTL),
+    )))
+}
+
+/// Accepts IANA names such as `Europe/Berlin` or `Etc/GMT+7`; Postgres reports unknown ones
+fn check_time_zone(tz: &str) -> Result<(), AppError> {
+    let valid = !tz.is_empty()
+        && tz.len() <= 64
+        && tz
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '+'));
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::validation(field_error(
+            "tz",
+            format!("invalid time zone: {}", tz),
+        )))
+    }
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(AgendaQuery),
+    responses(
+        (status = 200, description = "Open todos bucketed by due day, with a total per bucket", body = ApiResponseAgenda),
+        (status = 400, description = "Unknown time zone or page_size out of range"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[get("/agenda")]
+async fn get_agenda(
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    query: Query<AgendaQuery>,
+) -> Result<CustomizeResponder<Json<ApiResponseAgenda>>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    config.limits.check_page_size(query.page_size)?;
+    let page_size = query.page_size.unwrap_or(DEFAULT_GROUP_SIZE);
+    let tz = query.tz.clone().unwrap_or_else(|| "UTC".to_string());
+    check_time_zone(&tz)?;
+
+    // Todos move to the next bucket when the day changes without any write, like /grouped
+    let cache_key = format!("todos:user:{}:agenda:{}:{}", user_id, tz, page_size);
+    let agenda = match best_effort(
+        db.redis_client.get_cached::<Agenda>(&cache_key).await,
+        &format!("read of {}", cache_key),
+    )
+    .flatten()
+    {
+        Some(cached) => cached,
+        None => {
+            let agenda = Database::get_agenda(&db, user_id.clone(), tz, page_size).await?;
+            best_effort(
+                db.redis_client
+                    .set_cached(&cache_key, &agenda, COUNT_CACHE_TTL)
+                    .await,
+                &format!("write of agenda for user {}", user_id),
+            );
+            agenda
+        }
+    };
+
+    Ok(Json(ApiResponseAgenda {
+        success: true,
+        message: "Agenda retrieved successfully".to_string(),
+        data: Some(agenda),
+    })
+    .customize()
+    .insert_header((
+        CACHE_CONTROL,
+        format!("private, max-age={}", COUNT_CACHE_TTL),
+    )))
+}
+
+/// First day of a `YYYY-MM` month
+fn parse_month(month: &str) -> Result<NaiveDate, AppError> {
+    NaiveDate::parse_from_str(&format!("{}-01", month.trim()), "%Y-%m-%d").map_err(|_| {
+        AppError::validation(field_error(
+            "month",
+            format!("invalid month: {}, expected YYYY-MM", month),
+        ))
+    })
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(CalendarQuery),
+    responses(
+        (status = 200, description = "Todos due in the month keyed by local day, days without todos left out", body = ApiResponseTodoCalendar),
+        (status = 400, description = "Invalid month or unknown time zone"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[get("/calendar")]
+async fn get_calendar(
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    query: Query<CalendarQuery>,
+) -> Result<CustomizeResponder<Json<ApiResponseTodoCalendar>>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    let month_start = parse_month(&query.month)?;
+    let tz = query.tz.clone().unwrap_or_else(|| "UTC".to_string());
+    check_time_zone(&tz)?;
+
+    // Unlike the agenda nothing here depends on today, so entries only change with a write
+    let cache_key = format!(
+        "todos:user:{}:calendar:{}:{}",
+        user_id,
+        month_start.format("%Y-%m"),
+        tz
+    );
+    let calendar = match best_effort(
+        db.redis_client.get_cached::<TodoCalendar>(&cache_key).await,
+        &format!("read of {}", cache_key),
+    )
+    .flatten()
+    {
+        Some(cached) => cached,
+        None => {
+            let calendar = Database::get_calendar(&db, user_id.clone(), month_start, tz).await?;
+            best_effort(
+                db.redis_client
+                    .set_cached(&cache_key, &calendar, CACHE_TTL)
+                    .await,
+                &format!("write of calendar for user {}", user_id),
+            );
+            calendar
+        }
+    };
+
+    Ok(Json(ApiResponseTodoCalendar {
+        success: true,
+        message: "Calendar retrieved successfully".to_string(),
+        data: Some(calendar),
+    })
+    .customize()
+    .insert_header(private_cache_control(&config)))
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(GroupedQuery),
+    responses(
+        (status = 200, description = "Pending, completed and overdue todos in one call", body = ApiResponseGroupedTodos),
+        (status = 400, description = "page_size out of range"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[get("/grouped")]
+async fn grouped_todos(
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    query: Query<GroupedQuery>,
+) -> Result<CustomizeResponder<Json<ApiResponseGroupedTodos>>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    config.limits.check_page_size(query.page_size)?;
+    let page_size = query.page_size.unwrap_or(DEFAULT_GROUP_SIZE);
+
+    // Todos become overdue as time passes without any write, so keep this short-lived
+    let cache_key = format!("todos:user:{}:grouped:{}", user_id, page_size);
+    let grouped = match best_effort(
+        db.redis_client.get_cached::<GroupedTodos>(&cache_key).await,
+        &format!("read of {}", cache_key),
+    )
+    .flatten()
+    {
+        Some(cached) => cached,
+        None => {
+            let grouped = Database::get_grouped_todos(&db, user_id.clone(), page_size).await?;
+            best_effort(
+                db.redis_client
+                    .set_cached(&cache_key, &grouped, COUNT_CACHE_TTL)
+                    .await,
+                &format!("write of grouped todos for user {}", user_id),
+            );
+            grouped
+        }
+    };
+
+    Ok(Json(ApiResponseGroupedTodos {
+        success: true,
+        message: "Grouped todos retrieved successfully".to_string(),
+        data: Some(grouped),
+    })
+    .customize()
+    .insert_header((
+        CACHE_CONTROL,
+        format!("private, max-age={}", COUNT_CACHE_TTL),
+    )))
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(SuggestQuery),
+    responses(
+        (status = 200, description = "Distinct matching titles", body = ApiResponseTodoSuggestions),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[get("/suggest")]
+async fn suggest_todos(
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    query: Query<SuggestQuery>,
+) -> Result<CustomizeResponder<Json<ApiResponseTodoSuggestions>>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    config.limits.check_search(&query.q)?;
+
+    let prefix = query.q.trim().to_lowercase();
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SUGGEST_LIMIT)
+        .clamp(1, MAX_SUGGEST_LIMIT);
+    let cache_header = (
+        CACHE_CONTROL,
+        format!("private, max-age={}", SUGGEST_CACHE_TTL),
+    );
+
+    if prefix.is_empty() {
+        return Ok(Json(ApiResponseTodoSuggestions {
+            success: true,
+            message: "Suggestions retrieved successfully".to_string(),
+            data: Some(TodoSuggestions {
+                suggestions: Vec::new(),
+            }),
+        })
+        .customize()
+        .insert_header(cache_header));
+    }
+
+    let cache_key = format!("todos:user:{}:suggest:{}:{}", user_id, limit, prefix);
+
+    let suggestions = match best_effort(
+        db.redis_client.get_cached::<Vec<String>>(&cache_key).await,
+        &format!("read of {}", cache_key),
+    )
+    .flatten()
+    {
+        Some(cached) => cached,
+        None => {
+            let suggestions =
+                Database::suggest_todo_titles(&db, user_id.clone(), prefix, limit).await?;
+            best_effort(
+                db.redis_client
+                    .set_cached(&cache_key, &suggestions, SUGGEST_CACHE_TTL)
+                    .await,
+                &format!("write of suggestions for user {}", user_id),
+            );
+            suggestions
+        }
+    };
+
+    Ok(Json(ApiResponseTodoSuggestions {
+        success: true,
+        message: "Suggestions retrieved successfully".to_string(),
+        data: Some(TodoSuggestions { suggestions }),
+    })
+    .customize()
+    .insert_header(cache_header))
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Matches ranked by relevance, with highlighted fragments", body = ApiResponseTodoSearchResponse),
+        (status = 400, description = "Empty search query")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[get("/search")]
+async fn search_todos(
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    query: Query<SearchQuery>,
+) -> Result<CustomizeResponder<Json<ApiResponseTodoSearchResponse>>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    let search = query.q.trim().to_string();
+    if search.is_empty() {
+        return Err(AppError::bad_request("search query must not be empty"));
+    }
+    config.limits.check_search(&search)?;
+    config.limits.check_page_size(query.page_size)?;
+    let page = query.page.unwrap_or(1).max(1);
+    let page_size = config.limits.page_size(query.page_size);
+
+    let cache_key = format!(
+        "todos:user:{}:search:page={};page_size={};q={}",
+        user_id, page, page_size, search
+    );
+
+    let results = match best_effort(
+        db.redis_client
+            .get_cached::<TodoSearchResponse>(&cache_key)
+            .await,
+        &format!("read of {}", cache_key),
+    )
+    .flatten()
+    {
+        Some(cached) => cached,
+        None => {
+            let results =
+                Database::search_todos(&db, user_id.clone(), search, page, page_size).await?;
+            best_effort(
+                db.redis_client
+                    .set_cached(&cache_key, &results, CACHE_TTL)
+                    .await,
+                &format!("write of todo search for user {}", user_id),
+            );
+            results
+        }
+    };
+
+    Ok(Json(ApiResponseTodoSearchResponse {
+        success: true,
+        message: "Search results retrieved successfully".to_string(),
+        data: Some(results),
+    })
+    .customize()
+    .insert_header(private_cache_control(&config)))
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(("uuid" = String, Path, description = "Todo id")),
+    responses(
+        (status = 200, description = "Todo details", body = ApiResponseTodoResponse),
+        (status = 404, description = "Todo not found")
+    ),
+    security(("bearer_auth" = []))
+)]
 #[get("/{uuid}")]
 async fn get_todo(
     get_todo_url: Path<GetTodoURL>,
     req: HttpRequest,
     db: Data<Database>,
-) -> Result<Json<ApiResponseTodoResponse>, AppError> {
-    let extensions = req.extensions();
-    let user_id = extensions
-        .get::<String>()
-        .ok_or_else(|| AppError::new(StatusCode::UNAUTHORIZED, "User ID not found in request"))?;
+    config: Data<AppConfig>,
+) -> Result<CustomizeResponder<Json<ApiResponseTodoResponse>>, AppError> {
+    let user_id = get_user_id(&req)?;
 
-    let cache_key = format!("todos:user:{}:item:{}", user_id, get_todo_url.uuid);
+    // The cached copy is only used when it matches the latest known version
+    let version_key = item_version_key(&user_id, &get_todo_url.uuid);
+    let cached_version = best_effort(
+        db.redis_client.get_value(&version_key).await,
+        &format!("read of {}", version_key),
+    )
+    .flatten()
+    .and_then(|version| version.parse::<i64>().ok());
 
-    // Try to get from cache first
-    if let Ok(Some(cached_data)) = db.redis_client.get_cached::<TodoResponse>(&cache_key).await {
-        return Ok(Json(ApiResponseTodoResponse {
-            success: true,
-            message: "Todo retrieved successfully".to_string(),
-            data: Some(cached_data),
-        }));
+    if let Some(version) = cached_version {
+        let cache_key = item_cache_key(&user_id, &get_todo_url.uuid, version);
+        if let Some(cached_data) = best_effort(
+            db.redis_client.get_cached::<TodoResponse>(&cache_key).await,
+            &format!("read of {}", cache_key),
+        )
+        .flatten()
+        {
+            return Ok(Json(ApiResponseTodoResponse {
+                success: true,
+                message: "Todo retrieved successfully".to_string(),
+                data: Some(cached_data),
+            })
+            .customize()
+            .insert_header(private_cache_control(&config)));
+        }
     }
 
     // If not in cache, get from database
     let todo = Database::get_one_todo(&db, get_todo_url.uuid.clone()).await?;
-
-    // Store in cache
-    if let Ok(_) = db
-        .redis_client
-        .set_cached(&cache_key, &todo, CACHE_TTL)
-        .await
-    {
-        log::info!("Successfully cached todo for user {}", user_id);
+    // Someone else's todo is answered like a missing one and never cached under this user
+    if todo.user_id != user_id {
+        return Err(AppError::not_found("Todo not found"));
     }
 
+    cache_todo_version(&db, &user_id, &todo).await;
+
     Ok(Json(ApiResponseTodoResponse {
         success: true,
         message: "Todo retrieved successfully".to_string(),
         data: Some(todo),
-    }))
+    })
+    .customize()
+    .insert_header(private_cache_control(&config)))
 }
 
+#[utoipa::path(
+    tag = "todos",
+    request_body = CreateTodoRequest,
+    params(CreateTodoQuery),
+    responses(
+        (status = 200, description = "Todo created, with `possible_duplicates` when `check_duplicates=true`", body = ApiResponseCreatedTodo),
+        (status = 400, description = "Title or description too long, or color invalid"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 409, description = "`UNIQUE_TODO_TITLES` is on and a todo with this title exists, its id is in `data.existing_id`")
+    ),
+    security(("bearer_auth" = []))
+)]
 #[post("")]
 async fn create_todo(
     body: Json<CreateTodoRequest>,
+    query: Query<CreateTodoQuery>,
     req: HttpRequest,
     db: Data<Database>,
-) -> Result<Json<ApiResponseTodoResponse>, AppError> {
-    let extensions = req.extensions();
-    let user_id = extensions
-        .get::<String>()
-        .ok_or_else(|| AppError::new(StatusCode::UNAUTHORIZED, "User ID not found in request"))?;
+    config: Data<AppConfig>,
+    publisher: Data<dyn EventPublisher>,
+) -> Result<Json<ApiResponseCreatedTodo>, AppError> {
+    let user_id = get_user_id(&req)?;
 
-    let todo = Database::add_todo(&db, user_id.to_string(), body.into_inner()).await?;
+    config.limits.check_title(&body.title)?;
+    config.limits.check_description(&body.description)?;
+
+    let mut new_todo = body.into_inner();
+    new_todo.color = new_todo
+        .color
+        .map(|color| config.check_todo_color(&color))
+        .transpose()?;
+
+    let todo =
+        Database::add_todo(&db, user_id.clone(), new_todo, config.unique_todo_titles).await?;
 
     // Invalidate user's todos list cache
-    let cache_pattern = format!("todos:user:{}:*", user_id);
-    if let Err(e) = db
-        .redis_client
-        .delete_cached_by_pattern(&cache_pattern)
+    invalidate_todo_cache(&db, &user_id, &collections_cache_pattern(&user_id)).await;
+
+    publish_event(
+        publisher.get_ref(),
+        DomainEvent::TodoCreated { todo: todo.clone() },
+    )
+    .await;
+
+    // Only a hint, so a failed lookup never fails the create
+    let possible_duplicates = if query.check_duplicates.unwrap_or(false) {
+        match Database::find_similar_titles(
+            &db,
+            &user_id,
+            &todo.title,
+            &todo.uuid,
+            MAX_POSSIBLE_DUPLICATES,
+        )
         .await
-    {
-        log::error!(
-            "Failed to invalidate todos cache for user {}: {:?}",
-            user_id,
-            e
-        );
+        {
+            Ok(duplicates) => Some(duplicates),
+            Err(e) => {
+                warn!("Duplicate check failed for todo {}: {}", todo.uuid, e);
+                None
+            }
+        }
     } else {
-        log::info!("Successfully invalidated todos cache for user {}", user_id);
-    }
+        None
+    };
 
-    Ok(Json(ApiResponseTodoResponse {
+    Ok(Json(ApiResponseCreatedTodo {
         success: true,
         message: "Todo created successfully".to_string(),
-        data: Some(todo),
+        data: Some(CreatedTodo {
+            todo,
+            possible_duplicates,
+        }),
     }))
 }
 
+/// Turns CSV records into todos, the first record being the header
+///
+/// Columns are matched by name so they may come in any order; only `title` is required.
+/// Every bad row is reported, not just the first one.
+fn parse_import_rows(
+    records: Vec<CsvRecord>,
+    config: &AppConfig,
+) -> Result<Vec<ImportedTodo>, Vec<ImportRowError>> {
+    let mut records = records.into_iter();
+    let Some(header) = records.next() else {
+        return Err(vec![ImportRowError {
+            line: 1,
+            error: "missing header row".to_string(),
+        }]);
+    };
+
+    let column = |name: &str| {
+        header
+            .fields
+            .iter()
+            .position(|field| field.trim().eq_ignore_ascii_case(name))
+    };
+    let Some(title_column) = column("title") else {
+        return Err(vec![ImportRowError {
+            line: header.line,
+            error: "header has no title column".to_string(),
+        }]);
+    };
+    let description_column = column("description");
+    let completed_column = column("is_completed");
+
+    let mut todos = Vec::new();
+    let mut errors = Vec::new();
+    for record in records {
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|index| record.fields.get(index))
+                .map(String::as_str)
+                .unwrap_or("")
+        };
+        let title = field(Some(title_column)).trim().to_string();
+        let description = field(description_column).to_string();
+
+        let row = if record.fields.len() > header.fields.len() {
+            Err(AppError::bad_request(format!(
+                "expected at most {} fields, found {}",
+                header.fields.len(),
+                record.fields.len()
+            )))
+        } else if title.is_empty() {
+            Err(AppError::bad_request("title is required"))
+        } else {
+            config
+                .limits
+                .check_title(&title)
+                .and_then(|_| config.limits.check_description(&description))
+                .and_then(
+                    |_| match field(completed_column).trim().to_lowercase().as_str() {
+                        "" | "false" | "0" | "no" => Ok(false),
+                        "true" | "1" | "yes" => Ok(true),
+                        _ => Err(AppError::bad_request("is_completed must be true/false")),
+                    },
+                )
+        };
+
+        match row {
+            Ok(is_completed) => todos.push(ImportedTodo {
+                title,
+                description,
+                is_completed,
+            }),
+            Err(e) => errors.push(ImportRowError {
+                line: record.line,
+                error: e.message,
+            }),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(todos)
+    } else {
+        Err(errors)
+    }
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(ImportQuery),
+    request_body(content = String, content_type = "text/csv", description = "Header row followed by `title,description,is_completed` rows"),
+    responses(
+        (status = 200, description = "Every row imported", body = ApiResponseImportResponse),
+        (status = 400, description = "Malformed file or invalid rows, nothing imported", body = ApiResponseImportResponse),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 409, description = "`UNIQUE_TODO_TITLES` is on and a row repeats a title, nothing imported; the existing todo's id, if any, is in `data.existing_id`")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post("/import")]
+async fn import_todos(
+    query: Query<ImportQuery>,
+    body: String,
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    publisher: Data<dyn EventPublisher>,
+) -> Result<CustomizeResponder<Json<ApiResponseImportResponse>>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    if query.format != "csv" {
+        return Err(AppError::bad_request(format!(
+            "unsupported import format: {}; allowed: csv",
+            query.format
+        )));
+    }
+
+    let rejected = |errors: Vec<ImportRowError>| {
+        Json(ApiResponseImportResponse {
+            success: false,
+            message: "Import rejected, nothing was imported".to_string(),
+            data: Some(ImportResponse {
+                imported: 0,
+                errors,
+                todos: Vec::new(),
+            }),
+        })
+        .customize()
+        .with_status(StatusCode::BAD_REQUEST)
+    };
+
+    let records = match parse_csv(&body) {
+        Ok(records) => records,
+        Err(e) => {
+            return Ok(rejected(vec![ImportRowError {
+                line: e.line,
+                error: e.message.to_string(),
+            }]))
+        }
+    };
+    // The header doesn't count towards the cap
+    config
+        .limits
+        .check_import_rows(records.len().saturating_sub(1))?;
+
+    let rows = match parse_import_rows(records, &config) {
+        Ok(rows) => rows,
+        Err(errors) => return Ok(rejected(errors)),
+    };
+
+    let todos = if rows.is_empty() {
+        Vec::new()
+    } else {
+        Database::import_todos(&db, user_id.clone(), rows).await?
+    };
+
+    if !todos.is_empty() {
+        invalidate_todo_cache(&db, &user_id, &collections_cache_pattern(&user_id)).await;
+    }
+
+    let todos: Vec<TodoResponse> = todos.into_iter().map(TodoResponse::from).collect();
+    for todo in &todos {
+        publish_event(
+            publisher.get_ref(),
+            DomainEvent::TodoCreated { todo: todo.clone() },
+        )
+        .await;
+    }
+
+    Ok(Json(ApiResponseImportResponse {
+        success: true,
+        message: format!("Imported {} todos", todos.len()),
+        data: Some(ImportResponse {
+            imported: todos.len(),
+            errors: Vec::new(),
+            todos,
+        }),
+    })
+    .customize())
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(("uuid" = String, Path, description = "Todo id")),
+    request_body = UpdateTodoRequest,
+    responses(
+        (status = 200, description = "Todo updated", body = ApiResponseTodoResponse),
+        (status = 400, description = "Title or description too long, or color invalid"),
+        (status = 404, description = "Todo not found"),
+        (status = 409, description = "`UNIQUE_TODO_TITLES` is on and another todo has the new title, its id is in `data.existing_id`")
+    ),
+    security(("bearer_auth" = []))
+)]
 #[patch("/{uuid}")]
 async fn update_todo(
     update_todo_url: Path<UpdateTodoURL>,
     body: Json<UpdateTodoRequest>,
     req: HttpRequest,
     db: Data<Database>,
+    config: Data<AppConfig>,
+    publisher: Data<dyn EventPublisher>,
 ) -> Result<Json<ApiResponseTodoResponse>, AppError> {
-    let extensions = req.extensions();
-    let user_id = extensions
-        .get::<String>()
-        .ok_or_else(|| AppError::new(StatusCode::UNAUTHORIZED, "User ID not found in request"))?;
+    let user_id = get_user_id(&req)?;
 
-    let todo = Database::update_todo(
-        &db,
-        update_todo_url.uuid.clone(),
-        body.title.clone(),
-        body.description.clone(),
-        body.is_completed,
-    )
-    .await?;
+    if let Some(title) = &body.title {
+        config.limits.check_title(title)?;
+    }
+    if let Some(description) = &body.description {
+        config.limits.check_description(description)?;
+    }
+    if let Some(note) = &body.completion_note {
+        config.limits.check_completion_note(note)?;
+    }
+
+    let mut changes = body.into_inner();
+    changes.color = changes
+        .color
+        .map(|color| config.check_todo_color(&color))
+        .transpose()?;
+
+    let todo =
+        Database::update_todo(&db, user_id.clone(), update_todo_url.uuid.clone(), changes).await?;
 
     let todo_response = TodoResponse::from(todo);
 
-    // Invalidate both specific todo and list caches for the user
-    let cache_pattern = format!("todos:user:{}:*", user_id);
-    if let Err(e) = db
-        .redis_client
-        .delete_cached_by_pattern(&cache_pattern)
-        .await
-    {
-        log::error!(
-            "Failed to invalidate todos cache for user {}: {:?}",
-            user_id,
-            e
-        );
-    } else {
-        log::info!("Successfully invalidated todos cache for user {}", user_id);
-    }
+    // The new version supersedes the cached item; lists still have to be rebuilt
+    cache_todo_version(&db, &user_id, &todo_response).await;
+    invalidate_todo_cache(&db, &user_id, &collections_cache_pattern(&user_id)).await;
+
+    publish_event(
+        publisher.get_ref(),
+        DomainEvent::TodoUpdated {
+            todo: todo_response.clone(),
+        },
+    )
+    .await;
 
     Ok(Json(ApiResponseTodoResponse {
         success: true,
@@ -203,34 +1092,617 @@ async fn update_todo(
     }))
 }
 
+#[utoipa::path(
+    tag = "todos",
+    params(("uuid" = String, Path, description = "Todo id")),
+    request_body = CompleteTodoRequest,
+    responses(
+        (status = 200, description = "Todo completed", body = ApiResponseTodoResponse),
+        (status = 400, description = "Note too long"),
+        (status = 404, description = "Todo not found")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post("/{uuid}/complete")]
+async fn complete_todo(
+    path: Path<GetTodoURL>,
+    body: Json<CompleteTodoRequest>,
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    publisher: Data<dyn EventPublisher>,
+) -> Result<Json<ApiResponseTodoResponse>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    let note = body.into_inner().note;
+    if let Some(note) = &note {
+        config.limits.check_completion_note(note)?;
+    }
+
+    let todo = TodoResponse::from(
+        Database::update_todo(
+            &db,
+            user_id.clone(),
+            path.into_inner().uuid,
+            UpdateTodoRequest {
+                is_completed: Some(true),
+                completion_note: note,
+                ..Default::default()
+            },
+        )
+        .await?,
+    );
+
+    cache_todo_version(&db, &user_id, &todo).await;
+    invalidate_todo_cache(&db, &user_id, &collections_cache_pattern(&user_id)).await;
+
+    publish_event(
+        publisher.get_ref(),
+        DomainEvent::TodoUpdated { todo: todo.clone() },
+    )
+    .await;
+
+    Ok(Json(ApiResponseTodoResponse {
+        success: true,
+        message: "Todo completed successfully".to_string(),
+        data: Some(todo),
+    }))
+}
+
+async fn set_pinned(
+    uuid: String,
+    is_pinned: bool,
+    req: HttpRequest,
+    db: Data<Database>,
+    publisher: Data<dyn EventPublisher>,
+) -> Result<Json<ApiResponseTodoResponse>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    let todo =
+        TodoResponse::from(Database::set_todo_pinned(&db, user_id.clone(), uuid, is_pinned).await?);
+
+    // Pinning reorders every cached list page
+    cache_todo_version(&db, &user_id, &todo).await;
+    invalidate_todo_cache(&db, &user_id, &collections_cache_pattern(&user_id)).await;
+
+    publish_event(
+        publisher.get_ref(),
+        DomainEvent::TodoUpdated { todo: todo.clone() },
+    )
+    .await;
+
+    Ok(Json(ApiResponseTodoResponse {
+        success: true,
+        message: if is_pinned {
+            "Todo pinned successfully".to_string()
+        } else {
+            "Todo unpinned successfully".to_string()
+        },
+        data: Some(todo),
+    }))
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(("uuid" = String, Path, description = "Todo id")),
+    responses(
+        (status = 200, description = "Todo pinned", body = ApiResponseTodoResponse),
+        (status = 404, description = "Todo not found")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post("/{uuid}/pin")]
+async fn pin_todo(
+    path: Path<GetTodoURL>,
+    req: HttpRequest,
+    db: Data<Database>,
+    publisher: Data<dyn EventPublisher>,
+) -> Result<Json<ApiResponseTodoResponse>, AppError> {
+    set_pinned(path.into_inner().uuid, true, req, db, publisher).await
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(("uuid" = String, Path, description = "Todo id")),
+    responses(
+        (status = 200, description = "Todo unpinned", body = ApiResponseTodoResponse),
+        (status = 404, description = "Todo not found")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post("/{uuid}/unpin")]
+async fn unpin_todo(
+    path: Path<GetTodoURL>,
+    req: HttpRequest,
+    db: Data<Database>,
+    publisher: Data<dyn EventPublisher>,
+) -> Result<Json<ApiResponseTodoResponse>, AppError> {
+    set_pinned(path.into_inner().uuid, false, req, db, publisher).await
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(("uuid" = String, Path, description = "Todo id")),
+    request_body = MoveTodoRequest,
+    responses(
+        (status = 200, description = "Todo moved before or after all the user's other todos", body = ApiResponseTodoResponse),
+        (status = 404, description = "Todo not found")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post("/{uuid}/move")]
+async fn move_todo(
+    path: Path<GetTodoURL>,
+    body: Json<MoveTodoRequest>,
+    req: HttpRequest,
+    db: Data<Database>,
+    publisher: Data<dyn EventPublisher>,
+) -> Result<Json<ApiResponseTodoResponse>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    let todo = TodoResponse::from(
+        Database::move_todo(&db, user_id.clone(), path.into_inner().uuid, body.position).await?,
+    );
+
+    // Moving reorders every cached list page sorted by position
+    cache_todo_version(&db, &user_id, &todo).await;
+    invalidate_todo_cache(&db, &user_id, &collections_cache_pattern(&user_id)).await;
+
+    publish_event(
+        publisher.get_ref(),
+        DomainEvent::TodoUpdated { todo: todo.clone() },
+    )
+    .await;
+
+    Ok(Json(ApiResponseTodoResponse {
+        success: true,
+        message: "Todo moved successfully".to_string(),
+        data: Some(todo),
+    }))
+}
+
+async fn set_snoozed(
+    uuid: String,
+    snoozed_until: Option<DateTime<Utc>>,
+    req: HttpRequest,
+    db: Data<Database>,
+    publisher: Data<dyn EventPublisher>,
+) -> Result<Json<ApiResponseTodoResponse>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    let todo = TodoResponse::from(
+        Database::set_todo_snoozed(&db, user_id.clone(), uuid, snoozed_until).await?,
+    );
+
+    // Snoozed todos drop out of every cached list and count
+    cache_todo_version(&db, &user_id, &todo).await;
+    invalidate_todo_cache(&db, &user_id, &collections_cache_pattern(&user_id)).await;
+
+    publish_event(
+        publisher.get_ref(),
+        DomainEvent::TodoUpdated { todo: todo.clone() },
+    )
+    .await;
+
+    Ok(Json(ApiResponseTodoResponse {
+        success: true,
+        message: if snoozed_until.is_some() {
+            "Todo snoozed successfully".to_string()
+        } else {
+            "Todo unsnoozed successfully".to_string()
+        },
+        data: Some(todo),
+    }))
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(("uuid" = String, Path, description = "Todo id")),
+    request_body = SnoozeTodoRequest,
+    responses(
+        (status = 200, description = "Todo hidden from lists until `until`", body = ApiResponseTodoResponse),
+        (status = 400, description = "`until` is not in the future"),
+        (status = 404, description = "Todo not found")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post("/{uuid}/snooze")]
+async fn snooze_todo(
+    path: Path<GetTodoURL>,
+    body: Json<SnoozeTodoRequest>,
+    req: HttpRequest,
+    db: Data<Database>,
+    publisher: Data<dyn EventPublisher>,
+) -> Result<Json<ApiResponseTodoResponse>, AppError> {
+    if body.until <= Utc::now() {
+        return Err(AppError::bad_request("until must be in the future"));
+    }
+
+    set_snoozed(path.into_inner().uuid, Some(body.until), req, db, publisher).await
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(("uuid" = String, Path, description = "Todo id")),
+    responses(
+        (status = 200, description = "Todo listed again", body = ApiResponseTodoResponse),
+        (status = 404, description = "Todo not found")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post("/{uuid}/unsnooze")]
+async fn unsnooze_todo(
+    path: Path<GetTodoURL>,
+    req: HttpRequest,
+    db: Data<Database>,
+    publisher: Data<dyn EventPublisher>,
+) -> Result<Json<ApiResponseTodoResponse>, AppError> {
+    set_snoozed(path.into_inner().uuid, None, req, db, publisher).await
+}
+
+async fn update_reminder(
+    uuid: String,
+    remind_at: Option<DateTime<Utc>>,
+    req: HttpRequest,
+    db: Data<Database>,
+    publisher: Data<dyn EventPublisher>,
+) -> Result<Json<ApiResponseTodoResponse>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    let todo = TodoResponse::from(
+        Database::set_todo_reminder(&db, user_id.clone(), uuid, remind_at).await?,
+    );
+
+    cache_todo_version(&db, &user_id, &todo).await;
+    invalidate_todo_cache(&db, &user_id, &collections_cache_pattern(&user_id)).await;
+
+    publish_event(
+        publisher.get_ref(),
+        DomainEvent::TodoUpdated { todo: todo.clone() },
+    )
+    .await;
+
+    Ok(Json(ApiResponseTodoResponse {
+        success: true,
+        message: if remind_at.is_some() {
+            "Reminder set successfully".to_string()
+        } else {
+            "Reminder cleared successfully".to_string()
+        },
+        data: Some(todo),
+    }))
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(("uuid" = String, Path, description = "Todo id")),
+    request_body = SetReminderRequest,
+    responses(
+        (status = 200, description = "Reminder scheduled, replacing any previous one", body = ApiResponseTodoResponse),
+        (status = 400, description = "`remind_at` is not in the future"),
+        (status = 404, description = "Todo not found")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post("/{uuid}/reminder")]
+async fn set_reminder(
+    path: Path<GetTodoURL>,
+    body: Json<SetReminderRequest>,
+    req: HttpRequest,
+    db: Data<Database>,
+    publisher: Data<dyn EventPublisher>,
+) -> Result<Json<ApiResponseTodoResponse>, AppError> {
+    if body.remind_at <= Utc::now() {
+        return Err(AppError::bad_request("remind_at must be in the future"));
+    }
+
+    update_reminder(
+        path.into_inner().uuid,
+        Some(body.remind_at),
+        req,
+        db,
+        publisher,
+    )
+    .await
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(("uuid" = String, Path, description = "Todo id")),
+    responses(
+        (status = 200, description = "Reminder cancelled", body = ApiResponseTodoResponse),
+        (status = 404, description = "Todo not found")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post("/{uuid}/reminder/clear")]
+async fn clear_reminder(
+    path: Path<GetTodoURL>,
+    req: HttpRequest,
+    db: Data<Database>,
+    publisher: Data<dyn EventPublisher>,
+) -> Result<Json<ApiResponseTodoResponse>, AppError> {
+    update_reminder(path.into_inner().uuid, None, req, db, publisher).await
+}
+
+#[utoipa::path(
+    tag = "todos",
+    request_body = BulkTagRequest,
+    responses(
+        (status = 200, description = "Tags updated on every todo", body = ApiResponseBulkTagResponse),
+        (status = 207, description = "Some todos were not found, see each result's `success` and `error_code`", body = ApiResponseBulkTagResponse),
+        (status = 400, description = "Invalid tag name or batch too large")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post("/tags/bulk")]
+async fn bulk_update_tags(
+    body: Json<BulkTagRequest>,
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+) -> Result<CustomizeResponder<Json<ApiResponseBulkTagResponse>>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    body.validate()?;
+    config.limits.check_batch_size(body.todo_uuids.len())?;
+
+    let body = body.into_inner();
+    let add = normalize_tags(&body.add)?;
+    let remove = normalize_tags(&body.remove)?;
+
+    // Repeated ids are only reported once, at their first position
+    let mut seen = HashSet::new();
+    let requested: Vec<(usize, String)> = body
+        .todo_uuids
+        .into_iter()
+        .enumerate()
+        .filter(|(_, uuid)| seen.insert(uuid.clone()))
+        .collect();
+    let todo_uuids: Vec<String> = requested.iter().map(|(_, uuid)| uuid.clone()).collect();
+
+    // Ids the user doesn't own are skipped up front, a batch of only those writes nothing
+    let owned = Database::filter_owned(&db, &user_id, &todo_uuids).await?;
+    let updated_todos = if owned.is_empty() {
+        Vec::new()
+    } else {
+        Database::bulk_update_tags(&db, user_id.clone(), owned, add, remove).await?
+    };
+    let versions: Vec<(&str, i64)> = updated_todos
+        .iter()
+        .map(|todo| (todo.uuid.as_str(), todo.version))
+        .collect();
+    raise_item_versions(&db, &user_id, &versions).await;
+
+    let mut updated: HashMap<String, TodoResponse> = updated_todos
+        .into_iter()
+        .map(|todo| (todo.uuid.clone(), TodoResponse::from(todo)))
+        .collect();
+    let updated_count = updated.len();
+
+    // Todos not owned by the user are reported as not found rather than failing the batch
+    let results: Vec<BatchItemResult> = requested
+        .into_iter()
+        .map(|(index, todo_uuid)| match updated.remove(&todo_uuid) {
+            Some(todo) => BatchItemResult::succeeded(index, todo_uuid, Some(todo)),
+            None => BatchItemResult::not_found(index, todo_uuid),
+        })
+        .collect();
+
+    if updated_count > 0 {
+        invalidate_todo_cache(&db, &user_id, &collections_cache_pattern(&user_id)).await;
+    }
+
+    let status = batch_status(&results);
+    Ok(Json(ApiResponseBulkTagResponse {
+        success: true,
+        message: if status == StatusCode::OK {
+            "Tags updated successfully".to_string()
+        } else {
+            "Tags updated on some todos".to_string()
+        },
+        data: Some(BulkTagResponse {
+            updated: updated_count,
+            results,
+        }),
+    })
+    .customize()
+    .with_status(status))
+}
+
+#[utoipa::path(
+    tag = "todos",
+    request_body = TransitionRequest,
+    responses(
+        (status = 200, description = "Number of todos whose state changed", body = ApiResponseTransitionResponse),
+        (status = 400, description = "Search query too long")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post("/transition")]
+async fn transition_todos(
+    body: Json<TransitionRequest>,
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    publisher: Data<dyn EventPublisher>,
+) -> Result<Json<ApiResponseTransitionResponse>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    let body = body.into_inner();
+    if let Some(search) = &body.filter.search {
+        config.limits.check_search(search)?;
+    }
+
+    let todos =
+        Database::transition_todos(&db, user_id.clone(), body.filter, body.set.is_completed)
+            .await?;
+
+    if !todos.is_empty() {
+        let versions: Vec<(&str, i64)> = todos
+            .iter()
+            .map(|todo| (todo.uuid.as_str(), todo.version))
+            .collect();
+        raise_item_versions(&db, &user_id, &versions).await;
+
+        invalidate_todo_cache(&db, &user_id, &collections_cache_pattern(&user_id)).await;
+    }
+
+    let affected = todos.len();
+    for todo in todos {
+        publish_event(
+            publisher.get_ref(),
+            DomainEvent::TodoUpdated {
+                todo: TodoResponse::from(todo),
+            },
+        )
+        .await;
+    }
+
+    Ok(Json(ApiResponseTransitionResponse {
+        success: true,
+        message: "Todos updated successfully".to_string(),
+        data: Some(TransitionResponse { affected }),
+    }))
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(PaginationParams),
+    responses(
+        (status = 200, description = "Deleted todos, most recently deleted first", body = ApiResponseTrashedTodoList),
+        (status = 400, description = "Page size too large"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[get("/trash")]
+async fn get_trash(
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    pagination: LenientQuery<PaginationParams>,
+) -> Result<Json<ApiResponseTrashedTodoList>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    config.limits.check_page_size(pagination.page_size)?;
+    let page = pagination.page.unwrap_or(1).max(1);
+    let page_size = config.limits.page_size(pagination.page_size);
+
+    let trash = Database::get_trashed_todos(&db, user_id, page, page_size).await?;
+
+    Ok(Json(ApiResponseTrashedTodoList {
+        success: true,
+        message: "Trash retrieved successfully".to_string(),
+        data: Some(trash),
+    }))
+}
+
+/// Trash changes can touch any cached item or list, so everything of the user goes
+async fn invalidate_user_cache(db: &Database, user_id: &str) {
+    invalidate_todo_cache(db, user_id, &format!("todos:user:{}:*", user_id)).await;
+}
+
+#[utoipa::path(
+    tag = "todos",
+    responses(
+        (status = 200, description = "Every todo in the trash restored", body = ApiResponseRestoreAllResponse),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 409, description = "`UNIQUE_TODO_TITLES` is on and a trashed todo's title is taken, nothing restored; the existing todo's id, if any, is in `data.existing_id`")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post("/trash/restore-all")]
+async fn restore_all_todos(
+    req: HttpRequest,
+    db: Data<Database>,
+    publisher: Data<dyn EventPublisher>,
+) -> Result<Json<ApiResponseRestoreAllResponse>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    let restored = Database::restore_all_todos(&db, user_id.clone()).await?;
+
+    if !restored.is_empty() {
+        invalidate_user_cache(&db, &user_id).await;
+    }
+
+    let count = restored.len();
+    for todo in restored {
+        publish_event(
+            publisher.get_ref(),
+            DomainEvent::TodoUpdated {
+                todo: TodoResponse::from(todo),
+            },
+        )
+        .await;
+    }
+
+    Ok(Json(ApiResponseRestoreAllResponse {
+        success: true,
+        message: format!("Restored {} todos", count),
+        data: Some(RestoreAllResponse { restored: count }),
+    }))
+}
+
+#[utoipa::path(
+    tag = "todos",
+    responses(
+        (status = 200, description = "Trash emptied, its todos are deleted for good", body = ApiResponseEmptyTrashResponse),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[delete("/trash")]
+async fn empty_trash(
+    req: HttpRequest,
+    db: Data<Database>,
+) -> Result<Json<ApiResponseEmptyTrashResponse>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    let purged = Database::empty_trash(&db, user_id.clone()).await?;
+
+    if purged > 0 {
+        invalidate_user_cache(&db, &user_id).await;
+    }
+
+    Ok(Json(ApiResponseEmptyTrashResponse {
+        success: true,
+        message: format!("Deleted {} todos for good", purged),
+        data: Some(EmptyTrashResponse { purged }),
+    }))
+}
+
+#[utoipa::path(
+    tag = "todos",
+    params(("uuid" = String, Path, description = "Todo id")),
+    responses(
+        (status = 200, description = "Todo moved to the trash", body = ApiResponseDeleteTodoResponse),
+        (status = 404, description = "Todo not found")
+    ),
+    security(("bearer_auth" = []))
+)]
 #[delete("/{uuid}")]
 async fn delete_todo(
     todo_url: Path<GetTodoURL>,
     req: HttpRequest,
     db: Data<Database>,
+    publisher: Data<dyn EventPublisher>,
 ) -> Result<Json<ApiResponseDeleteTodoResponse>, AppError> {
-    let extensions = req.extensions();
-    let user_id = extensions
-        .get::<String>()
-        .ok_or_else(|| AppError::new(StatusCode::UNAUTHORIZED, "User ID not found in request"))?;
+    let user_id = get_user_id(&req)?;
 
-    let response = Database::delete_todo(&db, todo_url.uuid.clone()).await?;
+    let response = Database::delete_todo(&db, user_id.clone(), todo_url.uuid.clone()).await?;
 
     // Clear cache for the user
-    let cache_pattern = format!("todos:user:{}:*", user_id);
-    if let Err(e) = db
-        .redis_client
-        .delete_cached_by_pattern(&cache_pattern)
-        .await
-    {
-        log::error!(
-            "Failed to invalidate todos cache for user {}: {:?}",
+    invalidate_todo_cache(&db, &user_id, &format!("todos:user:{}:*", user_id)).await;
+
+    publish_event(
+        publisher.get_ref(),
+        DomainEvent::TodoDeleted {
+            todo_id: response.todo_id.clone(),
             user_id,
-            e
-        );
-    } else {
-        log::info!("Successfully invalidated todos cache for user {}", user_id);
-    }
+        },
+    )
+    .await;
 
     Ok(Json(ApiResponseDeleteTodoResponse {
         success: true,
@@ -238,3 +1710,221 @@ async fn delete_todo(
         data: Some(response),
     }))
 }
+
+#[utoipa::path(
+    tag = "todos",
+    params(BulkDeleteQuery),
+    responses(
+        (status = 200, description = "Every todo was deleted", body = ApiResponseBulkDeleteResponse),
+        (status = 207, description = "Some todos were not found, see each result's `success` and `error_code`", body = ApiResponseBulkDeleteResponse),
+        (status = 400, description = "No ids or too many ids")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[delete("")]
+async fn delete_todos(
+    query: Query<BulkDeleteQuery>,
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    publisher: Data<dyn EventPublisher>,
+) -> Result<CustomizeResponder<Json<ApiResponseBulkDeleteResponse>>, AppError> {
+    let user_id = get_user_id(&req)?;
+
+    // Indexes count every comma separated entry, repeated ids are reported at their first one
+    let mut seen = HashSet::new();
+    let requested_ids: Vec<(usize, String)> = query
+        .ids
+        .split(',')
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, id)| !id.is_empty() && seen.insert(id.to_string()))
+        .map(|(index, id)| (index, id.to_string()))
+        .collect();
+
+    if requested_ids.is_empty() {
+        return Err(AppError::bad_request("ids must list at least one todo id"));
+    }
+    config.limits.check_batch_size(requested_ids.len())?;
+
+    let requested = requested_ids.len();
+    let todo_uuids: Vec<String> = requested_ids.iter().map(|(_, id)| id.clone()).collect();
+    let owned = Database::filter_owned(&db, &user_id, &todo_uuids).await?;
+    let deleted_ids = if owned.is_empty() {
+        Vec::new()
+    } else {
+        Database::delete_todos(&db, user_id.clone(), owned).await?
+    };
+
+    let deleted: HashSet<&String> = deleted_ids.iter().collect();
+    let results: Vec<BatchItemResult> = requested_ids
+        .into_iter()
+        .map(|(index, todo_uuid)| {
+            if deleted.contains(&todo_uuid) {
+                BatchItemResult::succeeded(index, todo_uuid, None)
+            } else {
+                BatchItemResult::not_found(index, todo_uuid)
+            }
+        })
+        .collect();
+
+    if !deleted_ids.is_empty() {
+        invalidate_todo_cache(&db, &user_id, &format!("todos:user:{}:*", user_id)).await;
+    }
+
+    for todo_id in &deleted_ids {
+        publish_event(
+            publisher.get_ref(),
+            DomainEvent::TodoDeleted {
+                todo_id: todo_id.clone(),
+                user_id: user_id.clone(),
+            },
+        )
+        .await;
+    }
+
+    let status = batch_status(&results);
+    Ok(Json(ApiResponseBulkDeleteResponse {
+        success: true,
+        message: if status == StatusCode::OK {
+            "Todos deleted successfully".to_string()
+        } else {
+            "Some todos were deleted".to_string()
+        },
+        data: Some(BulkDeleteResponse {
+            requested,
+            deleted: deleted_ids.len(),
+            deleted_ids,
+            results,
+        }),
+    })
+    .customize()
+    .with_status(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_support::{create_test_user, test_database};
+    use crate::middleware::auth::validator;
+    use crate::models::user::User;
+    use crate::services::event_service::NoopPublisher;
+    use crate::services::token_service::generate_jwt_token;
+    use actix_web::{test, web, App};
+    use actix_web_httpauth::middleware::HttpAuthentication;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    /// The todo routes behind authentication, wired the way main.rs does it
+    macro_rules! test_app {
+        ($db:expr) => {
+            test::init_service(
+                App::new()
+                    .app_data($db.clone())
+                    .app_data(Data::new(AppConfig::from_env()))
+                    .app_data(Data::from(
+                        Arc::new(NoopPublisher) as Arc<dyn EventPublisher>
+                    ))
+                    .service(
+                        web::scope("/todos")
+                            .wrap(HttpAuthentication::bearer(validator))
+                            .configure(|cfg| todo_routes(cfg, &Features::from_env())),
+                    ),
+            )
+            .await
+        };
+    }
+
+    fn bearer(user: &User) -> (&'static str, String) {
+        let token = generate_jwt_token(&user.uuid, "access", 1, None, None).unwrap();
+        ("Authorization", format!("Bearer {}", token))
+    }
+
+    fn new_todo(title: &str) -> CreateTodoRequest {
+        CreateTodoRequest {
+            title: title.to_string(),
+            description: "description".to_string(),
+            color: None,
+        }
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL and Redis"]
+    async fn read_after_update_is_fresh_without_deleting_the_cached_item() {
+        let db = Data::new(test_database().await);
+        let user = create_test_user(&db).await;
+        let todo = db
+            .add_todo(user.uuid.clone(), new_todo("Before"), false)
+            .await
+            .unwrap();
+        let app = test_app!(db);
+        let get = || {
+            test::TestRequest::get()
+                .uri(&format!("/todos/{}", todo.uuid))
+                .insert_header(bearer(&user))
+                .to_request()
+        };
+
+        let body: serde_json::Value = test::call_and_read_body_json(&app, get()).await;
+        assert_eq!(body["data"]["title"], "Before");
+        let stale_key = item_cache_key(&user.uuid, &todo.uuid, todo.version);
+        assert!(db
+            .redis_client
+            .get_cached::<TodoResponse>(&stale_key)
+            .await
+            .unwrap()
+            .is_some());
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::patch()
+                .uri(&format!("/todos/{}", todo.uuid))
+                .insert_header(bearer(&user))
+                .set_json(json!({ "title": "After" }))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // The old copy is still cached: the read below is fresh because of the version, not
+        // because a pattern delete (SCAN) found and removed it
+        assert!(db
+            .redis_client
+            .get_cached::<TodoResponse>(&stale_key)
+            .await
+            .unwrap()
+            .is_some());
+        let body: serde_json::Value = test::call_and_read_body_json(&app, get()).await;
+        assert_eq!(body["data"]["title"], "After");
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL and Redis"]
+    async fn another_users_todo_is_not_found_and_not_cached() {
+        let db = Data::new(test_database().await);
+        let owner = create_test_user(&db).await;
+        let intruder = create_test_user(&db).await;
+        let todo = db
+            .add_todo(owner.uuid.clone(), new_todo("Private"), false)
+            .await
+            .unwrap();
+        let app = test_app!(db);
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri(&format!("/todos/{}", todo.uuid))
+                .insert_header(bearer(&intruder))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            db.redis_client
+                .get_value(&item_version_key(&intruder.uuid, &todo.uuid))
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -1,36 +1,138 @@
+use crate::config::{features, AppConfig, Features};
+use crate::db::data_trait::todo_data_trait::TodoData;
 use crate::db::data_trait::user_data_trait::UserData;
 use crate::db::database::Database;
+use crate::db::paginated_query::total_pages;
 use crate::db::redis_client::RedisClient;
+use crate::error::app_error::field_error;
 use crate::error::user_error::UserError;
-use crate::models::user::{CreateUserRequest, Disable2FARequest, Enable2FARequest, Enable2FAResponse, GenerateBackupCodesResponse, LoginRequest, LoginResponse, RefreshTokenRequest, TokenResponse, TwoFactorChallengeResponse, UpdateUserRequest, UpdateUserURL, UseBackupCodeForLoginRequest, User, UserResponse, Verify2FARequest, Verify2FAResponse, VerifyOtpRequest};
-use crate::services::token_service::generate_jwt_token;
+use crate::middleware::auth::validator;
+use crate::middleware::client_ip::client_ip;
+use crate::models::todo::PaginationParams;
+use crate::models::user::{
+    AddTwoFactorDeviceRequest, AddTwoFactorDeviceResponse, BackupCodeSetsResponse,
+    ChangeEmailRequest, ChangePasswordRequest, ChangePasswordResponse, Confirm2FARequest,
+    Confirm2FAResponse, CreateUserRequest, Disable2FARequest, Enable2FARequest, Enable2FAResponse,
+    ExportedSession, GenerateBackupCodesRequest, GenerateBackupCodesResponse, LoginRequest,
+    LoginResponse, RefreshTokenRequest, Regenerate2FAQrRequest, Regenerate2FAQrResponse,
+    SessionList, SessionResponse, Test2FAResponse, TokenResponse, TwoFactorChallengeResponse,
+    TwoFactorDeviceResponse, TwoFactorDevicesResponse, TwoFactorStatusResponse,
+    UseBackupCodeForLoginRequest, User, UserExport, UserPreferences, UserResponse,
+    UserResponseWithoutPassword, Verify2FARequest, Verify2FAResponse, VerifyBackupCodeRequest,
+    VerifyOtpRequest, VerifyPasswordRequest, VerifyPasswordResponse,
+};
+use crate::routers::extract::LenientQuery;
+use crate::routers::fallback::disabled_route;
+use crate::services::cache_service::{best_effort, required, CacheService};
+use crate::services::event_service::{publish_event, DomainEvent, EventPublisher};
+use crate::services::export_service::export_stream;
+use crate::services::password_service::{self, PasswordAlgorithm};
+use crate::services::token_service::{decode_jwt_token, generate_jwt_token};
 use crate::services::two_factor_service;
+use actix_web::http::header::{
+    ContentDisposition, DispositionParam, DispositionType, ETag, EntityTag, IfModifiedSince,
+    IfNoneMatch, LastModified, CACHE_CONTROL,
+};
 use actix_web::{
-    patch, post,
+    delete, get, post, put,
     web::{Data, Json, Path},
+    HttpMessage, HttpRequest, HttpResponse,
 };
-use bcrypt::{hash, verify};
-use chrono::Utc;
-use jsonwebtoken::{decode, DecodingKey, Validation};
+use actix_web_httpauth::middleware::HttpAuthentication;
+use chrono::{SubsecRound, Utc};
+use jsonwebtoken::errors::ErrorKind;
 use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
 use tokio;
+use utoipa::OpenApi;
 use uuid::Uuid;
 use validator::Validate;
-use crate::services::cache_service::CacheService;
 
-pub fn user_routes(cfg: &mut actix_web::web::ServiceConfig) {
-    cfg.service(register)
-        .service(login)
+pub fn user_routes(cfg: &mut actix_web::web::ServiceConfig, features: &Features) {
+    if features.is_enabled(features::REGISTRATION) {
+        cfg.service(register);
+    } else {
+        cfg.service(disabled_route("/register"));
+    }
+    cfg.service(login)
         .service(verify_otp)
         .service(refresh_token_endpoint)
         .service(enable_2fa)
         .service(disable_2fa)
+        .service(regenerate_2fa_qr)
         .service(verify_2fa)
+        .service(confirm_2fa)
+        .service(test_2fa)
+        .service(list_2fa_devices)
+        .service(add_2fa_device)
+        .service(verify_2fa_device)
+        .service(remove_2fa_device)
         .service(generate_backup_codes)
-        .service(login_with_backup_code);
+        .service(list_backup_code_sets)
+        .service(list_sessions)
+        .service(verify_backup_code)
+        .service(revoke_backup_code_set)
+        .service(login_with_backup_code)
+        .service(get_two_factor_status)
+        .service(get_profile)
+        .service(get_preferences)
+        .service(update_preferences)
+        .service(change_password)
+        .service(verify_password)
+        .service(change_email)
+        .service(export_user_data);
 }
 
-const HASH_COST: u32 = 8;
+#[derive(OpenApi)]
+#[openapi(paths(
+    register,
+    login,
+    verify_otp,
+    refresh_token_endpoint,
+    enable_2fa,
+    disable_2fa,
+    regenerate_2fa_qr,
+    verify_2fa,
+    confirm_2fa,
+    test_2fa,
+    list_2fa_devices,
+    add_2fa_device,
+    verify_2fa_device,
+    remove_2fa_device,
+    generate_backup_codes,
+    list_backup_code_sets,
+    list_sessions,
+    verify_backup_code,
+    revoke_backup_code_set,
+    login_with_backup_code,
+    get_two_factor_status,
+    get_profile,
+    get_preferences,
+    update_preferences,
+    change_password,
+    verify_password,
+    change_email,
+    export_user_data
+))]
+pub struct UserApi;
+
+const TOTP_TEST_MAX_ATTEMPTS: u64 = 5;
+const TOTP_TEST_WINDOW_SECONDS: u64 = 60;
+const BACKUP_CODE_VERIFY_MAX_ATTEMPTS: u64 = 5;
+const BACKUP_CODE_VERIFY_WINDOW_SECONDS: u64 = 60 * 60;
+const LOGIN_MAX_ATTEMPTS_PER_IP: u64 = 20;
+const VERIFY_PASSWORD_MAX_ATTEMPTS: u64 = 10;
+const VERIFY_PASSWORD_WINDOW_SECONDS: u64 = 60;
+const EXPORT_MAX_PER_WINDOW: u64 = 3;
+const EXPORT_WINDOW_SECONDS: u64 = 60 * 60;
+const LOGIN_WINDOW_SECONDS: u64 = 60;
+const LOGIN_BACKOFF_FREE_ATTEMPTS: u64 = 3;
+const LOGIN_BACKOFF_BASE_SECONDS: u64 = 1;
+const LOGIN_BACKOFF_MAX_SECONDS: u64 = 15 * 60;
+const LOGIN_FAILURES_TTL_SECONDS: u64 = 24 * 60 * 60;
+const MFA_SESSION_TTL_SECONDS: u64 = 1800; // 30 minutes
+const FACTOR_TOTP: &str = "totp";
+const FACTOR_BACKUP_CODE: &str = "backup_code";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -38,23 +140,39 @@ pub struct Claims {
     pub exp: usize,
     pub token_type: String,
     pub user_id: Option<String>,
+    /// Session of an access token, the id of the refresh token issued with it
+    pub sid: Option<String>,
 }
 
+struct TokenPair {
+    access_token: String,
+    refresh_token: String,
+    /// Ids of the refresh tokens signed out to make room for this one
+    evicted_sessions: Vec<String>,
+}
+
+/// Issues an access and a refresh token; with a session cap configured the user's oldest
+/// refresh tokens are revoked first so at most that many remain, this one included
+///
+/// The refresh token's id doubles as the session id carried by the access token.
 async fn generate_token_pair(
     user_id: &str,
     redis_client: &RedisClient,
-) -> Result<(String, String), UserError> {
+    config: &AppConfig,
+) -> Result<TokenPair, UserError> {
     let token_id = Uuid::new_v4().to_string();
+    let max_sessions = config.max_sessions_per_user;
 
-    let user_id_clone = user_id.to_string();
-
-    let access_token_future =
-        tokio::spawn(async move { generate_jwt_token(&user_id_clone, "access", 1, None) });
+    let access_token_future = tokio::spawn({
+        let token_id = token_id.clone();
+        let user_id = user_id.to_string();
+        async move { generate_jwt_token(&user_id, "access", 1, None, Some(&token_id)) }
+    });
 
     let refresh_token_future = tokio::spawn({
         let token_id = token_id.clone();
         let user_id = user_id.to_string();
-        async move { generate_jwt_token(&token_id, "refresh", 24 * 7, Some(&user_id)) }
+        async move { generate_jwt_token(&token_id, "refresh", 24 * 7, Some(&user_id), None) }
     });
 
     let access_token = access_token_future
@@ -65,29 +183,64 @@ async fn generate_token_pair(
         .await
         .map_err(|_| UserError::TokenCreationFailure)??;
 
-    redis_client
-        .store_token_state(&token_id, user_id, 7 * 24 * 60 * 60)
-        .await
-        .map_err(|e| {
-            eprintln!("Redis error: {:?}", e);
-            UserError::TokenCreationFailure
-        })?;
+    // A soft cap: if eviction fails the login still goes through with one session too many
+    let evicted_sessions = if max_sessions > 0 {
+        best_effort(
+            redis_client
+                .evict_oldest_user_tokens(user_id, max_sessions - 1)
+                .await,
+            "session cap eviction",
+        )
+        .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    if !evicted_sessions.is_empty() {
+        log::info!(
+            "Signed out {} oldest session(s) of user {} to stay within {}",
+            evicted_sessions.len(),
+            user_id,
+            max_sessions
+        );
+    }
+
+    // A refresh token Redis doesn't know about could never be used
+    required(
+        redis_client
+            .store_token_state(&token_id, user_id, 7 * 24 * 60 * 60)
+            .await,
+        "refresh token write",
+    )?;
+    // Without it the validator would take the new session for an idle one
+    if let Some(idle_timeout) = config.idle_session_timeout_seconds {
+        required(
+            redis_client.start_session(&token_id, idle_timeout).await,
+            "session activity write",
+        )?;
+    }
 
-    Ok((access_token, refresh_token))
+    Ok(TokenPair {
+        access_token,
+        refresh_token,
+        evicted_sessions,
+    })
 }
 
+/// Validates a refresh token and returns its user id, its id (the session id) and whether it
+/// was consumed
+///
+/// With a rotation window configured, tokens further than the window from expiry are
+/// left in place so the caller can hand the same refresh token back. With an idle timeout
+/// configured, a session idle past it can't be refreshed.
 async fn validate_refresh_token(
     token: &str,
     redis_client: &RedisClient,
-) -> Result<String, UserError> {
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "secret_key".into());
-
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
-    )
-    .map_err(|_| UserError::InvalidRefreshToken)?;
+    config: &AppConfig,
+) -> Result<(String, String, bool), UserError> {
+    let token_data = decode_jwt_token::<Claims>(token).map_err(|e| match e.kind() {
+        ErrorKind::ExpiredSignature => UserError::RefreshTokenExpired,
+        _ => UserError::InvalidRefreshToken,
+    })?;
 
     if token_data.claims.token_type != "refresh" {
         return Err(UserError::InvalidRefreshToken);
@@ -99,41 +252,93 @@ async fn validate_refresh_token(
         .user_id
         .ok_or(UserError::InvalidRefreshToken)?;
 
-    match redis_client.validate_and_invalidate_token(&token_id).await {
-        Ok(Some(stored_user_id)) => {
+    let remaining_seconds = token_data
+        .claims
+        .exp
+        .saturating_sub(Utc::now().timestamp() as usize) as u64;
+    let rotate = config
+        .refresh_rotation_window_seconds
+        .is_none_or(|window| remaining_seconds <= window);
+
+    // The used marker only has to outlive the token itself, after that it fails as expired
+    let stored = if rotate {
+        redis_client
+            .validate_and_invalidate_token(&token_id, remaining_seconds)
+            .await
+    } else {
+        redis_client.get_token_state(&token_id).await
+    };
+
+    // Without Redis a revoked token can't be told from a live one
+    match required(stored, "refresh token read")? {
+        Some(stored_user_id) => {
             if stored_user_id != user_id {
                 return Err(UserError::InvalidRefreshToken);
             }
-            Ok(user_id)
+            // Refreshing counts as activity, but can't bring an idle session back
+            if let Some(idle_timeout) = config.idle_session_timeout_seconds {
+                let active = required(
+                    redis_client.touch_session(&token_id, idle_timeout).await,
+                    "session activity update",
+                )?;
+                if !active {
+                    return Err(UserError::SessionIdle);
+                }
+            }
+            Ok((user_id, token_id, rotate))
         }
-        Ok(None) => Err(UserError::InvalidRefreshToken),
-        Err(e) => {
-            eprintln!("Redis error: {:?}", e);
-            Err(UserError::AuthenticationFailure)
+        None => {
+            let used_by = required(
+                redis_client.used_token_owner(&token_id).await,
+                "refresh token reuse check",
+            )?;
+            if used_by.as_deref() != Some(user_id.as_str()) {
+                return Err(UserError::InvalidRefreshToken);
+            }
+
+            // A rotated token coming back means it leaked: whoever holds its successor may be
+            // the attacker, so every session of the account is signed out
+            let revoked = best_effort(
+                redis_client.revoke_all_user_tokens(&user_id).await,
+                "session revocation after refresh token reuse",
+            );
+            log::warn!(
+                "Refresh token {} of user {} was reused, revoked {} session(s)",
+                token_id,
+                user_id,
+                revoked.unwrap_or(0)
+            );
+            Err(UserError::RefreshTokenReused)
         }
     }
 }
 
+#[utoipa::path(
+    tag = "users",
+    request_body = CreateUserRequest,
+    responses(
+        (status = 200, description = "User registered", body = UserResponse),
+        (status = 409, description = "Email already registered")
+    )
+)]
 #[post("/register")]
 pub async fn register(
     body: Json<CreateUserRequest>,
     db: Data<Database>,
+    config: Data<AppConfig>,
+    publisher: Data<dyn EventPublisher>,
 ) -> Result<Json<UserResponse>, UserError> {
     // Validate request
-    body.validate()
-        .map_err(|e| UserError::ValidationError(e.to_string()))?;
+    body.validate()?;
 
     // Check if user already exists
     let existing_user_result = db.get_user_by_email(&body.email).await;
-    if let Ok(_) = existing_user_result {
+    if existing_user_result.is_ok() {
         return Err(UserError::UserAlreadyExists);
     }
 
-    // Hash password with lower cost
-    let hashed_password = hash(&body.password, HASH_COST).map_err(|e| {
-        eprintln!("Password hashing error: {:?}", e);
-        UserError::PasswordHashingFailure
-    })?;
+    let hashed_password =
+        password_service::hash_password(&body.password, config.password_algorithm)?;
 
     // Create new user
     let new_uuid = Uuid::new_v4().to_string();
@@ -146,9 +351,17 @@ pub async fn register(
     // Save user to database
     db.create_user(&new_uuid, &user).await?;
 
+    publish_event(
+        publisher.get_ref(),
+        DomainEvent::UserRegistered {
+            user_id: new_uuid.clone(),
+            email: body.email.clone(),
+        },
+    )
+    .await;
+
     // Generate token pair
-    let (access_token, refresh_token_str) =
-        generate_token_pair(&new_uuid, &db.redis_client).await?;
+    let tokens = generate_token_pair(&new_uuid, &db.redis_client, &config).await?;
 
     let new_user = User::new(
         new_uuid.clone(),
@@ -160,42 +373,263 @@ pub async fn register(
 
     let user_response = UserResponse {
         user: new_user.into(),
-        access_token,
-        refresh_token: refresh_token_str,
+        access_token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
         token_type: "Bearer".to_string(),
+        two_factor_setup_required: false,
+        evicted_sessions: tokens.evicted_sessions,
     };
     Ok(Json(user_response))
 }
 
+/// Second factors the user can complete a 2FA login with
+async fn available_factors(db: &Database, user: &User) -> Result<Vec<String>, UserError> {
+    let mut factors = vec![FACTOR_TOTP.to_string()];
+    if !db.get_backup_code_sets(&user.uuid).await?.is_empty() {
+        factors.push(FACTOR_BACKUP_CODE.to_string());
+    }
+    Ok(factors)
+}
+
+/// Checks a backup code against every active set and removes it on success so it can't be reused
+async fn consume_backup_code(db: &Database, user: &User, code: &str) -> Result<bool, UserError> {
+    let Some(code) = two_factor_service::normalize_backup_code(code) else {
+        return Ok(false);
+    };
+
+    db.consume_backup_code(&user.uuid, &two_factor_service::hash_backup_code(&code))
+        .await
+}
+
+/// Checks a code against the user's pending secret, which only becomes active once the user
+/// proves they can produce codes
+async fn check_pending_totp_code(
+    db: &Database,
+    user: &User,
+    code: &str,
+    config: &AppConfig,
+) -> Result<(), UserError> {
+    let secret = match &user.two_factor_pending_secret {
+        Some(secret) => secret,
+        None => return Err(UserError::TwoFactorSetupNotStarted),
+    };
+
+    // An abandoned setup must not leave a dangling secret behind
+    if two_factor_service::is_pending_setup_expired(user.two_factor_pending_since) {
+        db.clear_pending_2fa(&user.uuid).await?;
+        return Err(UserError::TwoFactorSetupExpired);
+    }
+
+    let is_valid = two_factor_service::verify_totp(secret, code, config.totp_digits)
+        .map_err(|_| UserError::InvalidTwoFactorCode)?;
+
+    if !is_valid {
+        return Err(UserError::InvalidTwoFactorCode);
+    }
+
+    Ok(())
+}
+
+fn login_backoff_keys(email: &str) -> (String, String) {
+    let email = email.trim().to_lowercase();
+    (
+        format!("login_failures:{}", email),
+        format!("login_lock:{}", email),
+    )
+}
+
+/// Remaining wait before this account may try again, if it is backed off
+async fn login_backoff_remaining(db: &Database, email: &str) -> Result<Option<u64>, UserError> {
+    let (_, lock_key) = login_backoff_keys(email);
+
+    Ok(required(
+        db.redis_client.ttl(&lock_key).await,
+        "login backoff read",
+    )?)
+}
+
+/// Counts a failed login; past the free attempts each failure doubles the lockout
+async fn record_login_failure(db: &Database, email: &str) {
+    let (failures_key, lock_key) = login_backoff_keys(email);
+
+    // The failed login is answered either way; only the next lockout is lost
+    let Some(failures) = best_effort(
+        db.redis_client
+            .hit_rate_limit(&failures_key, LOGIN_FAILURES_TTL_SECONDS)
+            .await,
+        "login failure count",
+    ) else {
+        return;
+    };
+
+    if failures < LOGIN_BACKOFF_FREE_ATTEMPTS {
+        return;
+    }
+
+    let exponent = (failures - LOGIN_BACKOFF_FREE_ATTEMPTS).min(32) as u32;
+    let wait = LOGIN_BACKOFF_BASE_SECONDS
+        .saturating_mul(2u64.saturating_pow(exponent))
+        .min(LOGIN_BACKOFF_MAX_SECONDS);
+
+    best_effort(
+        db.redis_client.set_with_expiry(&lock_key, "1", wait).await,
+        "login lock write",
+    );
+}
+
+async fn reset_login_failures(db: &Database, email: &str) {
+    let (failures_key, lock_key) = login_backoff_keys(email);
+
+    for key in [failures_key, lock_key] {
+        best_effort(db.redis_client.del(&key).await, "login failure reset");
+    }
+}
+
+/// User id the bearer token was issued to, for `/users/me` routes
+fn current_user_id(req: &HttpRequest) -> Result<String, UserError> {
+    req.extensions()
+        .get::<String>()
+        .cloned()
+        .ok_or(UserError::AuthenticationFailure)
+}
+
+/// Đảm bảo người dùng trong token chính là người dùng trên URL
+fn ensure_same_user(req: &HttpRequest, uuid: &str) -> Result<(), UserError> {
+    match req.extensions().get::<String>() {
+        Some(user_id) if user_id == uuid => Ok(()),
+        Some(_) => Err(UserError::Forbidden),
+        None => Err(UserError::AuthenticationFailure),
+    }
+}
+
+/// Page and page size of a per-user list, under the same limits as todo lists
+fn list_page(config: &AppConfig, pagination: &PaginationParams) -> Result<(i64, i64), UserError> {
+    config
+        .limits
+        .check_page_size(pagination.page_size)
+        .map_err(|e| UserError::ValidationError(field_error("page_size", e.message)))?;
+    Ok((
+        pagination.page.unwrap_or(1).max(1),
+        config.limits.page_size(pagination.page_size),
+    ))
+}
+
+/// Băm lại mật khẩu bằng thuật toán hiện tại; lỗi chỉ được ghi log, không chặn đăng nhập
+async fn upgrade_password_hash(
+    db: &Database,
+    user: &User,
+    password: &str,
+    algorithm: PasswordAlgorithm,
+) {
+    let hashed = match password_service::hash_password(password, algorithm) {
+        Ok(hashed) => hashed,
+        Err(e) => {
+            log::warn!("Failed to rehash password for user {}: {}", user.uuid, e);
+            return;
+        }
+    };
+
+    let mut updated_user = user.clone();
+    updated_user.password = hashed;
+    if let Err(e) = db.update_user(&updated_user).await {
+        log::warn!(
+            "Failed to store rehashed password for user {}: {}",
+            user.uuid,
+            e
+        );
+    }
+}
+
+/// Rejects anything that can't be a TOTP code before any HMAC work is spent on it
+fn check_totp_format(code: &str, config: &AppConfig) -> Result<(), UserError> {
+    if two_factor_service::is_well_formed_code(code, config.totp_digits) {
+        Ok(())
+    } else {
+        Err(UserError::BadRequest(format!(
+            "2FA code must be exactly {} digits",
+            config.totp_digits
+        )))
+    }
+}
+
+/// Lấy tất cả secret TOTP đang hoạt động: thiết bị chính và các thiết bị bổ sung
+async fn active_totp_secrets(db: &Database, user: &User) -> Result<Vec<String>, UserError> {
+    let mut secrets: Vec<String> = user.two_factor_secret.iter().cloned().collect();
+    secrets.extend(
+        db.get_2fa_devices(&user.uuid)
+            .await?
+            .into_iter()
+            .map(|device| device.secret),
+    );
+    Ok(secrets)
+}
+
+#[utoipa::path(
+    tag = "users",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Tokens, or a 2FA challenge when 2FA is enabled", body = LoginResponse),
+        (status = 401, description = "Invalid credentials"),
+        (status = 429, description = "Too many attempts")
+    )
+)]
 #[post("/login")]
 pub async fn login(
     body: Json<LoginRequest>,
+    req: HttpRequest,
     db: Data<Database>,
+    config: Data<AppConfig>,
 ) -> Result<Json<LoginResponse>, UserError> {
     // Validate request
-    body.validate()
-        .map_err(|e| UserError::ValidationError(e.to_string()))?;
+    body.validate()?;
+
+    // Limit per real client, not per load balancer
+    if let Some(ip) = client_ip(&req, &config.trusted_proxies) {
+        let attempts = required(
+            db.redis_client
+                .hit_rate_limit(&format!("login_ip:{}", ip), LOGIN_WINDOW_SECONDS)
+                .await,
+            "login rate limit",
+        )?;
+
+        if attempts > LOGIN_MAX_ATTEMPTS_PER_IP {
+            return Err(UserError::TooManyAttempts);
+        }
+    }
+
+    if let Some(remaining) = login_backoff_remaining(&db, &body.email).await? {
+        return Err(UserError::LoginBackoff(remaining));
+    }
 
+    // Unknown emails are backed off too so they can't be told apart from wrong passwords
     let user = match db.get_user_by_email(&body.email).await {
         Ok(user) => user,
-        Err(UserError::NoSuchUserFound) => return Err(UserError::InvalidCredentials),
+        Err(UserError::NoSuchUserFound) => {
+            record_login_failure(&db, &body.email).await;
+            return Err(UserError::InvalidCredentials);
+        }
         Err(e) => return Err(e),
     };
 
-    let password_matches = verify(&body.password, &user.password).map_err(|e| {
-        eprintln!("Password verification error: {:?}", e);
-        UserError::AuthenticationFailure
-    })?;
+    let password_matches = password_service::verify_password(&body.password, &user.password)?;
 
     if !password_matches {
+        record_login_failure(&db, &body.email).await;
         return Err(UserError::InvalidCredentials);
     }
 
+    reset_login_failures(&db, &body.email).await;
+
+    // Upgrade the stored hash (algorithm or bcrypt cost) while the plaintext is at hand
+    if password_service::needs_rehash(&user.password, config.password_algorithm) {
+        upgrade_password_hash(&db, &user, &body.password, config.password_algorithm).await;
+    }
+
     if user.two_factor_enabled {
         // Create a new session ID
         let session_id = Uuid::new_v4().to_string();
 
-        // Store session in Redis (30 minutes expiry)
+        // Store session in Redis, the session id doubles as the mfa_token
         let session_data = serde_json::json!({
             "user_id": user.uuid,
             "email": user.email,
@@ -204,45 +638,76 @@ pub async fn login(
             "created_at": Utc::now().timestamp()
         });
 
-        // Store session data in Redis with 30 minute expiry
-        db.redis_client.set_with_expiry(
-            &format!("2fa_session:{}", session_id),
-            &session_data.to_string(),
-            1800 // 30 minutes in seconds
-        ).await.map_err(|_| UserError::TokenCreationFailure)?;
+        required(
+            db.redis_client
+                .set_with_expiry(
+                    &format!("2fa_session:{}", session_id),
+                    &session_data.to_string(),
+                    MFA_SESSION_TTL_SECONDS,
+                )
+                .await,
+            "2FA session write",
+        )?;
 
         // Return 2FA challenge
-        return Ok(Json(LoginResponse::TwoFactorChallenge(TwoFactorChallengeResponse {
-            user_id: user.uuid,
-            session_id,
-            message: "Please enter your authentication code".to_string(),
-        })));
+        return Ok(Json(LoginResponse::TwoFactorChallenge(
+            TwoFactorChallengeResponse {
+                available_factors: available_factors(&db, &user).await?,
+                user_id: user.uuid,
+                mfa_token: session_id.clone(),
+                session_id,
+                expires_in: MFA_SESSION_TTL_SECONDS,
+                message: "Please enter your authentication code".to_string(),
+            },
+        )));
     }
 
-    let (access_token, refresh_token_str) =
-        generate_token_pair(&user.uuid, &db.redis_client).await?;
+    let tokens = generate_token_pair(&user.uuid, &db.redis_client, &config).await?;
+
+    // 2FA is off past the challenge above; the login still succeeds so the admin can enroll
+    let two_factor_setup_required = config.require_admin_2fa && user.is_admin;
 
     let user_response = UserResponse {
         user: user.into(),
-        access_token,
-        refresh_token: refresh_token_str,
+        access_token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
         token_type: "Bearer".to_string(),
+        two_factor_setup_required,
+        evicted_sessions: tokens.evicted_sessions,
     };
 
     Ok(Json(LoginResponse::FullLogin(user_response)))
 }
 
+#[utoipa::path(
+    tag = "users",
+    request_body = RefreshTokenRequest,
+    responses(
+        (status = 200, description = "New token pair", body = TokenResponse),
+        (status = 401, description = "Expired, invalid or reused refresh token, or an idle session, told apart by data.error_code")
+    )
+)]
 #[post("/refresh")]
 pub async fn refresh_token_endpoint(
     db: Data<Database>,
+    config: Data<AppConfig>,
     body: Json<RefreshTokenRequest>,
 ) -> Result<Json<TokenResponse>, UserError> {
-    let user_id = validate_refresh_token(&body.refresh_token, &db.redis_client).await?;
+    let (user_id, session_id, rotate) =
+        validate_refresh_token(&body.refresh_token, &db.redis_client, &config).await?;
 
     let user = db.get_user_by_uuid(&user_id).await?;
 
-    let (access_token, refresh_token_str) =
-        generate_token_pair(&user.uuid, &db.redis_client).await?;
+    // Outside the rotation window only the access token is renewed
+    let (access_token, refresh_token_str) = if rotate {
+        let tokens = generate_token_pair(&user.uuid, &db.redis_client, &config).await?;
+        (tokens.access_token, tokens.refresh_token)
+    } else {
+        (
+            generate_jwt_token(&user.uuid, "access", 1, None, Some(&session_id))?,
+            body.refresh_token.clone(),
+        )
+    };
 
     Ok(Json(TokenResponse {
         access_token,
@@ -251,37 +716,27 @@ pub async fn refresh_token_endpoint(
     }))
 }
 
-#[patch("/users/{uuid}")]
-pub async fn update_user(
-    update_user_url: Path<UpdateUserURL>,
-    body: Json<UpdateUserRequest>,
-    db: Data<Database>,
-) -> Result<Json<User>, UserError> {
-    // Validate request
-    body.validate()
-        .map_err(|e| UserError::ValidationError(e.to_string()))?;
-
-    let user = db.get_user_by_uuid(&update_user_url.uuid).await?;
-
-    let mut updated_user = user.clone();
-    updated_user.email = body.email.clone();
-
-    let result = db.update_user(&updated_user).await?;
-
-    Ok(Json(result))
-}
-
+#[utoipa::path(
+    tag = "2fa",
+    params(("uuid" = String, Path, description = "User id")),
+    request_body = Enable2FARequest,
+    responses(
+        (status = 200, description = "Pending secret and QR code", body = Enable2FAResponse),
+        (status = 401, description = "Invalid credentials")
+    )
+)]
 #[post("/users/{uuid}/enable-2fa")]
 pub async fn enable_2fa(
     uuid: Path<String>,
     body: Json<Enable2FARequest>,
     db: Data<Database>,
+    config: Data<AppConfig>,
 ) -> Result<Json<Enable2FAResponse>, UserError> {
     let user_id = uuid.into_inner();
 
     let user = db.get_user_by_uuid(&user_id).await?;
 
-    if !verify(&body.password, &user.password).map_err(|_| UserError::AuthenticationFailure)? {
+    if !password_service::verify_password(&body.password, &user.password)? {
         return Err(UserError::InvalidCredentials);
     }
 
@@ -291,12 +746,17 @@ pub async fn enable_2fa(
 
     let secret = two_factor_service::generate_secret();
 
-    let app_name = "Todo App";
-    let totp_url = two_factor_service::generate_totp_url(&secret, &user.email, app_name);
+    let totp_url = two_factor_service::generate_totp_url(
+        &secret,
+        &user.email,
+        &config.totp_issuer,
+        config.totp_digits,
+    );
 
     let qr_code = two_factor_service::generate_qr_code(&totp_url)
         .map_err(|_| UserError::QRCodeGenerationFailure)?;
 
+    // Store as pending; calling enable again restarts enrollment with a fresh secret
     db.enable_2fa(&user_id, &secret).await?;
 
     let response = Enable2FAResponse {
@@ -309,33 +769,47 @@ pub async fn enable_2fa(
     Ok(Json(response))
 }
 
+#[utoipa::path(
+    tag = "2fa",
+    params(("uuid" = String, Path, description = "User id")),
+    request_body = Disable2FARequest,
+    responses(
+        (status = 200, description = "2FA disabled, or already was", body = Verify2FAResponse),
+        (status = 401, description = "Invalid credentials or code")
+    )
+)]
 #[post("/users/{uuid}/disable-2fa")]
 pub async fn disable_2fa(
     uuid: Path<String>,
     body: Json<Disable2FARequest>,
     db: Data<Database>,
+    config: Data<AppConfig>,
 ) -> Result<Json<Verify2FAResponse>, UserError> {
     let user_id = uuid.into_inner();
 
     let user = db.get_user_by_uuid(&user_id).await?;
 
-    if !verify(&body.password, &user.password).map_err(|_| UserError::AuthenticationFailure)? {
+    if !password_service::verify_password(&body.password, &user.password)? {
         return Err(UserError::InvalidCredentials);
     }
 
+    // Already off: still clear any leftover pending secret or backup codes, then succeed
     if !user.two_factor_enabled {
-        return Err(UserError::TwoFactorNotEnabled);
+        db.disable_2fa(&user_id).await?;
+        return Ok(Json(Verify2FAResponse {
+            success: true,
+            message: "2FA đã được tắt từ trước.".to_string(),
+        }));
     }
 
-    let secret = match &user.two_factor_secret {
-        Some(secret) => secret,
-        None => return Err(UserError::TwoFactorNotEnabled),
-    };
+    check_totp_format(&body.code, &config)?;
 
-    let is_valid = two_factor_service::verify_totp(secret, &body.code)
-        .map_err(|_| UserError::InvalidTwoFactorCode)?;
+    let secrets = active_totp_secrets(&db, &user).await?;
+    if secrets.is_empty() {
+        return Err(UserError::TwoFactorNotEnabled);
+    }
 
-    if !is_valid {
+    if !two_factor_service::verify_totp_any(&secrets, &body.code, config.totp_digits) {
         return Err(UserError::InvalidTwoFactorCode);
     }
 
@@ -349,27 +823,113 @@ pub async fn disable_2fa(
     Ok(Json(response))
 }
 
-#[post("/users/{uuid}/verify-2fa")]
-pub async fn verify_2fa(
+#[utoipa::path(
+    tag = "2fa",
+    params(("uuid" = String, Path, description = "User id")),
+    request_body = Regenerate2FAQrRequest,
+    responses(
+        (status = 200, description = "QR code for the current secret", body = Regenerate2FAQrResponse),
+        (status = 400, description = "2FA is not enabled or unknown factor"),
+        (status = 401, description = "Invalid password or code"),
+        (status = 429, description = "Account backed off after failed logins")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post("/users/{uuid}/2fa/qr", wrap = "HttpAuthentication::bearer(validator)")]
+pub async fn regenerate_2fa_qr(
     uuid: Path<String>,
-    body: Json<Verify2FARequest>,
+    body: Json<Regenerate2FAQrRequest>,
+    req: HttpRequest,
     db: Data<Database>,
-) -> Result<Json<Verify2FAResponse>, UserError> {
+    config: Data<AppConfig>,
+) -> Result<Json<Regenerate2FAQrResponse>, UserError> {
+    body.validate()?;
     let user_id = uuid.into_inner();
+    ensure_same_user(&req, &user_id)?;
 
     let user = db.get_user_by_uuid(&user_id).await?;
 
-    let secret = match &user.two_factor_secret {
-        Some(secret) => secret,
-        None => return Err(UserError::TwoFactorNotEnabled),
-    };
+    // The secret it hands out beats 2FA, so it takes both factors and shares the login backoff
+    if let Some(remaining) = login_backoff_remaining(&db, &user.email).await? {
+        return Err(UserError::LoginBackoff(remaining));
+    }
+    if !password_service::verify_password(&body.password, &user.password)? {
+        record_login_failure(&db, &user.email).await;
+        return Err(UserError::InvalidCredentials);
+    }
 
-    let is_valid = two_factor_service::verify_totp(secret, &body.code)
-        .map_err(|_| UserError::InvalidTwoFactorCode)?;
+    // Dùng lại secret hiện có để các thiết bị đã quét trước đó vẫn hoạt động
+    let secret = match (user.two_factor_enabled, user.two_factor_secret.clone()) {
+        (true, Some(secret)) => secret,
+        _ => return Err(UserError::TwoFactorNotEnabled),
+    };
 
+    let factor = body.factor.as_deref().unwrap_or(FACTOR_TOTP);
+    let is_valid = match factor {
+        FACTOR_TOTP => {
+            check_totp_format(&body.code, &config)?;
+            let secrets = active_totp_secrets(&db, &user).await?;
+            two_factor_service::verify_totp_any(&secrets, &body.code, config.totp_digits)
+        }
+        FACTOR_BACKUP_CODE => consume_backup_code(&db, &user, &body.code).await?,
+        _ => {
+            return Err(UserError::BadRequest(format!(
+                "Unknown factor: {}; allowed: {}, {}",
+                factor, FACTOR_TOTP, FACTOR_BACKUP_CODE
+            )))
+        }
+    };
     if !is_valid {
+        record_login_failure(&db, &user.email).await;
         return Err(UserError::InvalidTwoFactorCode);
     }
+    reset_login_failures(&db, &user.email).await;
+
+    let otpauth_url = two_factor_service::generate_totp_url(
+        &secret,
+        &user.email,
+        &config.totp_issuer,
+        config.totp_digits,
+    );
+
+    let qr_code = two_factor_service::generate_qr_code(&otpauth_url)
+        .map_err(|_| UserError::QRCodeGenerationFailure)?;
+
+    Ok(Json(Regenerate2FAQrResponse {
+        otpauth_url,
+        qr_code,
+        secret: body.reveal_secret.then_some(secret),
+        message: "Quét mã QR để thêm thiết bị, mã 2FA hiện tại vẫn hoạt động.".to_string(),
+    }))
+}
+
+#[utoipa::path(
+    tag = "2fa",
+    params(("uuid" = String, Path, description = "User id")),
+    request_body = Verify2FARequest,
+    responses(
+        (status = 200, description = "2FA activated", body = Verify2FAResponse),
+        (status = 400, description = "No pending or expired setup"),
+        (status = 401, description = "Invalid code")
+    )
+)]
+#[post("/users/{uuid}/verify-2fa")]
+pub async fn verify_2fa(
+    uuid: Path<String>,
+    body: Json<Verify2FARequest>,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+) -> Result<Json<Verify2FAResponse>, UserError> {
+    let user_id = uuid.into_inner();
+    check_totp_format(&body.code, &config)?;
+
+    let user = db.get_user_by_uuid(&user_id).await?;
+
+    if user.two_factor_enabled {
+        return Err(UserError::TwoFactorAlreadyEnabled);
+    }
+
+    check_pending_totp_code(&db, &user, &body.code, &config).await?;
 
     db.verify_2fa(&user_id).await?;
 
@@ -381,12 +941,345 @@ pub async fn verify_2fa(
     Ok(Json(response))
 }
 
+#[utoipa::path(
+    tag = "2fa",
+    params(("uuid" = String, Path, description = "User id")),
+    request_body = Confirm2FARequest,
+    responses(
+        (status = 200, description = "2FA activated, with the first backup codes", body = Confirm2FAResponse),
+        (status = 400, description = "2FA already enabled, or no pending or expired setup"),
+        (status = 401, description = "Invalid password or code"),
+        (status = 429, description = "Account backed off after failed logins")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post(
+    "/users/{uuid}/2fa/confirm",
+    wrap = "HttpAuthentication::bearer(validator)"
+)]
+pub async fn confirm_2fa(
+    uuid: Path<String>,
+    body: Json<Confirm2FARequest>,
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+) -> Result<Json<Confirm2FAResponse>, UserError> {
+    body.validate()?;
+    let user_id = uuid.into_inner();
+    ensure_same_user(&req, &user_id)?;
+    check_totp_format(&body.code, &config)?;
+
+    let user = db.get_user_by_uuid(&user_id).await?;
+
+    // Shares the login backoff, so a stolen access token can't guess the password here instead
+    if let Some(remaining) = login_backoff_remaining(&db, &user.email).await? {
+        return Err(UserError::LoginBackoff(remaining));
+    }
+    if !password_service::verify_password(&body.password, &user.password)? {
+        record_login_failure(&db, &user.email).await;
+        return Err(UserError::InvalidCredentials);
+    }
+    reset_login_failures(&db, &user.email).await;
+
+    if user.two_factor_enabled {
+        return Err(UserError::TwoFactorAlreadyEnabled);
+    }
+
+    check_pending_totp_code(&db, &user, &body.code, &config).await?;
+
+    let (plain_codes, hashed_codes) = two_factor_service::generate_backup_codes(None);
+    let set = db.confirm_2fa(&user_id, &hashed_codes).await?;
+
+    Ok(Json(Confirm2FAResponse {
+        success: true,
+        backup_codes: plain_codes
+            .iter()
+            .map(|code| two_factor_service::format_backup_code(code))
+            .collect(),
+        generation: set.uuid,
+        message: "2FA is active. Save these backup codes now, they won't be shown again."
+            .to_string(),
+    }))
+}
+
+#[utoipa::path(
+    tag = "2fa",
+    params(("uuid" = String, Path, description = "User id")),
+    request_body = Verify2FARequest,
+    responses(
+        (status = 200, description = "Whether the code matches", body = Test2FAResponse),
+        (status = 429, description = "Too many attempts")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post(
+    "/users/{uuid}/2fa/test",
+    wrap = "HttpAuthentication::bearer(validator)"
+)]
+pub async fn test_2fa(
+    uuid: Path<String>,
+    body: Json<Verify2FARequest>,
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+) -> Result<Json<Test2FAResponse>, UserError> {
+    let user_id = uuid.into_inner();
+    ensure_same_user(&req, &user_id)?;
+    check_totp_format(&body.code, &config)?;
+
+    let attempts = required(
+        db.redis_client
+            .hit_rate_limit(&format!("2fa_test:{}", user_id), TOTP_TEST_WINDOW_SECONDS)
+            .await,
+        "2FA test rate limit",
+    )?;
+
+    if attempts > TOTP_TEST_MAX_ATTEMPTS {
+        return Err(UserError::TooManyAttempts);
+    }
+
+    let user = db.get_user_by_uuid(&user_id).await?;
+
+    if !user.two_factor_enabled {
+        return Err(UserError::TwoFactorNotEnabled);
+    }
+
+    let secrets = active_totp_secrets(&db, &user).await?;
+    if secrets.is_empty() {
+        return Err(UserError::TwoFactorNotEnabled);
+    }
+
+    // Only checks the code, nothing is written back
+    let valid = two_factor_service::verify_totp_any(&secrets, &body.code, config.totp_digits);
+
+    let message = if valid {
+        "Authenticator is in sync"
+    } else {
+        "Code does not match, check your authenticator"
+    };
+
+    Ok(Json(Test2FAResponse {
+        valid,
+        message: message.to_string(),
+    }))
+}
+
+#[utoipa::path(
+    tag = "2fa",
+    params(("uuid" = String, Path, description = "User id"), PaginationParams),
+    responses(
+        (status = 200, description = "One page of enrolled devices", body = TwoFactorDevicesResponse),
+        (status = 400, description = "Page size too large")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[get(
+    "/users/{uuid}/2fa/devices",
+    wrap = "HttpAuthentication::bearer(validator)"
+)]
+pub async fn list_2fa_devices(
+    uuid: Path<String>,
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    pagination: LenientQuery<PaginationParams>,
+) -> Result<Json<TwoFactorDevicesResponse>, UserError> {
+    let user_id = uuid.into_inner();
+    ensure_same_user(&req, &user_id)?;
+    let (page, page_size) = list_page(&config, &pagination)?;
+
+    let user = db.get_user_by_uuid(&user_id).await?;
+    let (devices, total) = db.get_2fa_devices_page(&user_id, page, page_size).await?;
+
+    Ok(Json(TwoFactorDevicesResponse {
+        has_primary_device: user.two_factor_enabled && user.two_factor_secret.is_some(),
+        devices: devices
+            .into_iter()
+            .map(TwoFactorDeviceResponse::from)
+            .collect(),
+        total,
+        page,
+        page_size,
+        total_pages: total_pages(total, page_size),
+    }))
+}
+
+#[utoipa::path(
+    tag = "2fa",
+    params(("uuid" = String, Path, description = "User id")),
+    request_body = AddTwoFactorDeviceRequest,
+    responses(
+        (status = 200, description = "Device added, pending until verified", body = AddTwoFactorDeviceResponse),
+        (status = 400, description = "2FA is not enabled or the code is malformed"),
+        (status = 401, description = "Invalid password or code")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post(
+    "/users/{uuid}/2fa/devices",
+    wrap = "HttpAuthentication::bearer(validator)"
+)]
+pub async fn add_2fa_device(
+    uuid: Path<String>,
+    body: Json<AddTwoFactorDeviceRequest>,
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+) -> Result<Json<AddTwoFactorDeviceResponse>, UserError> {
+    // Validate request
+    body.validate()?;
+    check_totp_format(&body.code, &config)?;
+
+    let user_id = uuid.into_inner();
+    ensure_same_user(&req, &user_id)?;
+
+    let user = db.get_user_by_uuid(&user_id).await?;
+
+    if !password_service::verify_password(&body.password, &user.password)? {
+        return Err(UserError::InvalidCredentials);
+    }
+
+    // Extra devices only make sense on top of an active 2FA setup
+    if !user.two_factor_enabled {
+        return Err(UserError::TwoFactorNotEnabled);
+    }
+
+    // A stolen password alone must not be enough to enroll the attacker's authenticator
+    let secrets = active_totp_secrets(&db, &user).await?;
+    if !two_factor_service::verify_totp_any(&secrets, &body.code, config.totp_digits) {
+        return Err(UserError::InvalidTwoFactorCode);
+    }
+
+    let secret = two_factor_service::generate_secret();
+
+    let totp_url = two_factor_service::generate_totp_url(
+        &secret,
+        &user.email,
+        &config.totp_issuer,
+        config.totp_digits,
+    );
+
+    let qr_code = two_factor_service::generate_qr_code(&totp_url)
+        .map_err(|_| UserError::QRCodeGenerationFailure)?;
+
+    let device = db.add_2fa_device(&user_id, &body.name, &secret).await?;
+
+    Ok(Json(AddTwoFactorDeviceResponse {
+        device: device.into(),
+        secret,
+        qr_code,
+        message: "Device added. Scan the QR code with your authenticator app, then verify a code from it to activate it.".to_string(),
+    }))
+}
+
+#[utoipa::path(
+    tag = "2fa",
+    params(
+        ("uuid" = String, Path, description = "User id"),
+        ("device_uuid" = String, Path, description = "Device id")
+    ),
+    request_body = Verify2FARequest,
+    responses(
+        (status = 200, description = "Device activated", body = Verify2FAResponse),
+        (status = 400, description = "Device not found, already verified or its setup expired"),
+        (status = 401, description = "Invalid code")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post(
+    "/users/{uuid}/2fa/devices/{device_uuid}/verify",
+    wrap = "HttpAuthentication::bearer(validator)"
+)]
+pub async fn verify_2fa_device(
+    path: Path<(String, String)>,
+    body: Json<Verify2FARequest>,
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+) -> Result<Json<Verify2FAResponse>, UserError> {
+    let (user_id, device_uuid) = path.into_inner();
+    ensure_same_user(&req, &user_id)?;
+    check_totp_format(&body.code, &config)?;
+
+    let device = db.get_2fa_device(&user_id, &device_uuid).await?;
+    if device.verified_at.is_some() {
+        return Err(UserError::BadRequest(
+            "2FA device already verified".to_string(),
+        ));
+    }
+
+    // Like the primary setup, a device never confirmed in time is dropped rather than kept around
+    if two_factor_service::is_pending_setup_expired(Some(device.created_at_utc)) {
+        db.remove_2fa_device(&user_id, &device_uuid).await?;
+        return Err(UserError::TwoFactorSetupExpired);
+    }
+
+    let is_valid = two_factor_service::verify_totp(&device.secret, &body.code, config.totp_digits)
+        .map_err(|_| UserError::InvalidTwoFactorCode)?;
+    if !is_valid {
+        return Err(UserError::InvalidTwoFactorCode);
+    }
+
+    db.verify_2fa_device(&user_id, &device_uuid).await?;
+
+    Ok(Json(Verify2FAResponse {
+        success: true,
+        message: "2FA device verified".to_string(),
+    }))
+}
+
+#[utoipa::path(
+    tag = "2fa",
+    params(
+        ("uuid" = String, Path, description = "User id"),
+        ("device_uuid" = String, Path, description = "Device id")
+    ),
+    responses(
+        (status = 200, description = "Device removed", body = Verify2FAResponse),
+        (status = 400, description = "Device not found")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[delete(
+    "/users/{uuid}/2fa/devices/{device_uuid}",
+    wrap = "HttpAuthentication::bearer(validator)"
+)]
+pub async fn remove_2fa_device(
+    path: Path<(String, String)>,
+    req: HttpRequest,
+    db: Data<Database>,
+) -> Result<Json<Verify2FAResponse>, UserError> {
+    let (user_id, device_uuid) = path.into_inner();
+    ensure_same_user(&req, &user_id)?;
+
+    db.remove_2fa_device(&user_id, &device_uuid).await?;
+
+    Ok(Json(Verify2FAResponse {
+        success: true,
+        message: "2FA device removed".to_string(),
+    }))
+}
+
+#[utoipa::path(
+    tag = "2fa",
+    params(("uuid" = String, Path, description = "User id")),
+    request_body = GenerateBackupCodesRequest,
+    responses(
+        (status = 200, description = "New backup codes", body = GenerateBackupCodesResponse),
+        (status = 400, description = "Invalid code or count")
+    )
+)]
 #[post("/users/{uuid}/2fa/backup-codes")]
 pub async fn generate_backup_codes(
     uuid: Path<String>,
-    body: Json<Verify2FARequest>,
+    body: Json<GenerateBackupCodesRequest>,
     db: Data<Database>,
+    config: Data<AppConfig>,
 ) -> Result<Json<GenerateBackupCodesResponse>, UserError> {
+    // Validate request
+    body.validate()?;
+    check_totp_format(&body.code, &config)?;
+
     let user = db.get_user_by_uuid(&uuid).await?;
 
     if !user.two_factor_enabled {
@@ -395,55 +1288,254 @@ pub async fn generate_backup_codes(
         ));
     }
 
-    if user.two_factor_secret.is_none() {
+    let secrets = active_totp_secrets(&db, &user).await?;
+    if secrets.is_empty() {
         return Err(UserError::BadRequest("2FA secret not found".to_string()));
     }
 
-    let secret = user.two_factor_secret.as_ref().unwrap();
-    let is_valid = match two_factor_service::verify_totp(secret, &body.code) {
-        Ok(valid) => valid,
-        Err(e) => {
-            return Err(UserError::BadRequest(format!(
-                "Error verifying TOTP: {}",
-                e
-            )))
-        }
-    };
-
-    if !is_valid {
+    if !two_factor_service::verify_totp_any(&secrets, &body.code, config.totp_digits) {
         return Err(UserError::BadRequest("Invalid 2FA code".to_string()));
     }
 
-    // Invalidate previous backup codes
-    let mut updated_user = user.clone();
-    updated_user.backup_codes = None;
-    db.update_user(&updated_user).await?;
-
     // Generate new backup codes
-    let (plain_codes, hashed_codes) = two_factor_service::generate_backup_codes(None);
+    let (plain_codes, hashed_codes) = two_factor_service::generate_backup_codes(body.count);
 
     let formatted_codes: Vec<String> = plain_codes
         .iter()
         .map(|code| two_factor_service::format_backup_code(code))
         .collect();
 
-    updated_user.backup_codes = Some(hashed_codes);
-    db.update_user(&updated_user).await?;
+    // Unless appending, the new set replaces (and invalidates) every previous one
+    let set = db
+        .add_backup_code_set(&user.uuid, &hashed_codes, !body.append)
+        .await?;
 
     Ok(Json(GenerateBackupCodesResponse {
         backup_codes: formatted_codes,
+        generation: set.uuid,
         message: "Backup codes generated successfully".to_string(),
     }))
 }
 
+#[utoipa::path(
+    tag = "2fa",
+    params(("uuid" = String, Path, description = "User id"), PaginationParams),
+    responses(
+        (status = 200, description = "One page of backup code sets with codes left", body = BackupCodeSetsResponse),
+        (status = 400, description = "Page size too large")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[get(
+    "/users/{uuid}/2fa/backup-codes",
+    wrap = "HttpAuthentication::bearer(validator)"
+)]
+pub async fn list_backup_code_sets(
+    uuid: Path<String>,
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    pagination: LenientQuery<PaginationParams>,
+) -> Result<Json<BackupCodeSetsResponse>, UserError> {
+    let user_id = uuid.into_inner();
+    ensure_same_user(&req, &user_id)?;
+    let (page, page_size) = list_page(&config, &pagination)?;
+
+    let (sets, total) = db
+        .get_backup_code_sets_page(&user_id, page, page_size)
+        .await?;
+
+    Ok(Json(BackupCodeSetsResponse {
+        sets: sets.into_iter().map(Into::into).collect(),
+        total,
+        page,
+        page_size,
+        total_pages: total_pages(total, page_size),
+    }))
+}
+
+#[utoipa::path(
+    tag = "users",
+    params(("uuid" = String, Path, description = "User id"), PaginationParams),
+    responses(
+        (status = 200, description = "One page of signed-in sessions", body = SessionList),
+        (status = 400, description = "Page size too large"),
+        (status = 503, description = "Redis unavailable")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[get(
+    "/users/{uuid}/sessions",
+    wrap = "HttpAuthentication::bearer(validator)"
+)]
+pub async fn list_sessions(
+    uuid: Path<String>,
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    pagination: LenientQuery<PaginationParams>,
+) -> Result<Json<SessionList>, UserError> {
+    let user_id = uuid.into_inner();
+    ensure_same_user(&req, &user_id)?;
+    let (page, page_size) = list_page(&config, &pagination)?;
+
+    // Sessions only live in Redis, which holds at most a few per device, so they're paged here
+    let sessions = required(
+        db.redis_client.user_sessions(&user_id).await,
+        "session listing",
+    )?;
+    let total = sessions.len() as i64;
+    let now = Utc::now();
+    let sessions = sessions
+        .into_iter()
+        .rev()
+        .skip(((page - 1) * page_size) as usize)
+        .take(page_size as usize)
+        .map(|(id, ttl)| SessionResponse {
+            id,
+            expires_at: (now + chrono::Duration::seconds(ttl as i64)).to_rfc3339(),
+        })
+        .collect();
+
+    Ok(Json(SessionList {
+        sessions,
+        total,
+        page,
+        page_size,
+        total_pages: total_pages(total, page_size),
+    }))
+}
+
+#[utoipa::path(
+    tag = "2fa",
+    params(("uuid" = String, Path, description = "User id")),
+    request_body = VerifyBackupCodeRequest,
+    responses(
+        (status = 200, description = "Whether the code is valid and unused", body = Test2FAResponse),
+        (status = 400, description = "Invalid backup code format"),
+        (status = 429, description = "Too many attempts, or the account is backed off after failed logins")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post(
+    "/users/{uuid}/2fa/backup-codes/verify",
+    wrap = "HttpAuthentication::bearer(validator)"
+)]
+pub async fn verify_backup_code(
+    uuid: Path<String>,
+    body: Json<VerifyBackupCodeRequest>,
+    req: HttpRequest,
+    db: Data<Database>,
+) -> Result<Json<Test2FAResponse>, UserError> {
+    body.validate()?;
+    let user_id = uuid.into_inner();
+    ensure_same_user(&req, &user_id)?;
+
+    // Counted before the format check so malformed guesses use up attempts too
+    let attempts = required(
+        db.redis_client
+            .hit_rate_limit(
+                &format!("backup_code_verify:{}", user_id),
+                BACKUP_CODE_VERIFY_WINDOW_SECONDS,
+            )
+            .await,
+        "backup code verification rate limit",
+    )?;
+
+    if attempts > BACKUP_CODE_VERIFY_MAX_ATTEMPTS {
+        return Err(UserError::TooManyAttempts);
+    }
+
+    let user = db.get_user_by_uuid(&user_id).await?;
+
+    // Shares the login backoff, so a stolen access token can't turn this into a code oracle
+    if let Some(remaining) = login_backoff_remaining(&db, &user.email).await? {
+        return Err(UserError::LoginBackoff(remaining));
+    }
+
+    if !user.two_factor_enabled {
+        return Err(UserError::TwoFactorNotEnabled);
+    }
+
+    let backup_code = two_factor_service::normalize_backup_code(&body.code)
+        .ok_or_else(|| UserError::BadRequest("Invalid backup code format".to_string()))?;
+
+    // Only checks the code, it stays usable for login
+    let valid = db
+        .has_backup_code(
+            &user.uuid,
+            &two_factor_service::hash_backup_code(&backup_code),
+        )
+        .await?;
+
+    let message = if valid {
+        "Backup code is valid and unused"
+    } else {
+        record_login_failure(&db, &user.email).await;
+        "Backup code is not valid or was already used"
+    };
+
+    Ok(Json(Test2FAResponse {
+        valid,
+        message: message.to_string(),
+    }))
+}
+
+#[utoipa::path(
+    tag = "2fa",
+    params(
+        ("uuid" = String, Path, description = "User id"),
+        ("generation" = String, Path, description = "Backup code set id")
+    ),
+    responses(
+        (status = 200, description = "Backup code set revoked", body = Verify2FAResponse),
+        (status = 400, description = "Backup code set not found")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[delete(
+    "/users/{uuid}/2fa/backup-codes/{generation}",
+    wrap = "HttpAuthentication::bearer(validator)"
+)]
+pub async fn revoke_backup_code_set(
+    path: Path<(String, String)>,
+    req: HttpRequest,
+    db: Data<Database>,
+) -> Result<Json<Verify2FAResponse>, UserError> {
+    let (user_id, generation) = path.into_inner();
+    ensure_same_user(&req, &user_id)?;
+
+    db.revoke_backup_code_set(&user_id, &generation).await?;
+
+    Ok(Json(Verify2FAResponse {
+        success: true,
+        message: "Backup code set revoked".to_string(),
+    }))
+}
+
+#[utoipa::path(
+    tag = "users",
+    request_body = UseBackupCodeForLoginRequest,
+    responses(
+        (status = 200, description = "Tokens", body = LoginResponse),
+        (status = 400, description = "Invalid credentials or backup code")
+    )
+)]
 #[post("/login/backup")]
 pub async fn login_with_backup_code(
     body: Json<UseBackupCodeForLoginRequest>,
     db: Data<Database>,
+    config: Data<AppConfig>,
 ) -> Result<Json<LoginResponse>, UserError> {
+    body.validate()?;
+
+    // Reject malformed codes before any database work
+    let backup_code = two_factor_service::normalize_backup_code(&body.backup_code)
+        .ok_or_else(|| UserError::BadRequest("Invalid backup code format".to_string()))?;
+
     let user = db.get_user_by_email(&body.email).await?;
 
-    let is_valid = verify(&body.password, &user.password)
+    let is_valid = password_service::verify_password(&body.password, &user.password)
         .map_err(|_| UserError::BadRequest("Invalid email or password".to_string()))?;
 
     if !is_valid {
@@ -458,55 +1550,57 @@ pub async fn login_with_backup_code(
         ));
     }
 
-    if user.backup_codes.is_none() || user.backup_codes.as_ref().unwrap().is_empty() {
-        return Err(UserError::BadRequest(
-            "No backup codes available".to_string(),
-        ));
-    }
-
-    let backup_code = body.backup_code.replace("-", "");
-    let backup_codes = user.backup_codes.as_ref().unwrap();
-    let code_index = two_factor_service::verify_backup_code(&backup_code, backup_codes);
-
-    if let Some(index) = code_index {
-        let mut updated_user = user.clone();
-        let mut updated_codes = backup_codes.clone();
-        updated_codes.remove(index);
-        updated_user.backup_codes = Some(updated_codes);
-        db.update_user(&updated_user).await?;
-
-        let (access_token, refresh_token) =
-            generate_token_pair(&user.uuid, &db.redis_client).await?;
+    let code_hash = two_factor_service::hash_backup_code(&backup_code);
+    if db.consume_backup_code(&user.uuid, &code_hash).await? {
+        let tokens = generate_token_pair(&user.uuid, &db.redis_client, &config).await?;
 
         let user_response = UserResponse {
             user: user.into(),
-            access_token,
-            refresh_token,
+            access_token: tokens.access_token,
+            refresh_token: tokens.refresh_token,
             token_type: "Bearer".to_string(),
+            two_factor_setup_required: false,
+            evicted_sessions: tokens.evicted_sessions,
         };
 
         Ok(Json(LoginResponse::FullLogin(user_response)))
+    } else if db.get_backup_code_sets(&user.uuid).await?.is_empty() {
+        Err(UserError::BadRequest(
+            "No backup codes available".to_string(),
+        ))
     } else {
         Err(UserError::BadRequest("Invalid backup code".to_string()))
     }
 }
 
+#[utoipa::path(
+    tag = "users",
+    request_body = VerifyOtpRequest,
+    responses(
+        (status = 200, description = "Tokens", body = LoginResponse),
+        (status = 401, description = "Invalid code or session"),
+        (status = 429, description = "Too many attempts")
+    )
+)]
 #[post("/verify-otp")]
 pub async fn verify_otp(
     body: Json<VerifyOtpRequest>,
     db: Data<Database>,
+    config: Data<AppConfig>,
 ) -> Result<Json<LoginResponse>, UserError> {
     // Validate request
-    body.validate()
-        .map_err(|e| UserError::ValidationError(e.to_string()))?;
+    body.validate()?;
 
     // Get session data from Redis
     let session_key = format!("2fa_session:{}", body.session_id);
-    let session_data = db.redis_client.get(&session_key).await
-        .map_err(|_| UserError::InvalidSession)?;
+    let session_data = required(
+        db.redis_client.get_value(&session_key).await,
+        "2FA session read",
+    )?
+    .ok_or(UserError::InvalidSession)?;
 
-    let session: serde_json::Value = serde_json::from_str(&session_data)
-        .map_err(|_| UserError::InvalidSession)?;
+    let session: serde_json::Value =
+        serde_json::from_str(&session_data).map_err(|_| UserError::InvalidSession)?;
 
     // Check if session is locked due to too many attempts
     let attempts = session["attempts"].as_i64().unwrap_or(0);
@@ -517,17 +1611,33 @@ pub async fn verify_otp(
     }
 
     // Get user from session
-    let user_id = session["user_id"].as_str()
+    let user_id = session["user_id"]
+        .as_str()
         .ok_or(UserError::InvalidSession)?;
 
     let user = db.get_user_by_uuid(user_id).await?;
 
-    // Verify OTP
-    let secret = user.two_factor_secret.as_ref()
-        .ok_or(UserError::TwoFactorNotEnabled)?;
+    let factor = body.factor.as_deref().unwrap_or(FACTOR_TOTP);
+    let is_valid = match factor {
+        FACTOR_TOTP => {
+            check_totp_format(&body.otp, &config)?;
 
-    let is_valid = two_factor_service::verify_totp(secret, &body.otp)
-        .map_err(|_| UserError::InvalidTwoFactorCode)?;
+            // Verify OTP against every enrolled device
+            let secrets = active_totp_secrets(&db, &user).await?;
+            if secrets.is_empty() {
+                return Err(UserError::TwoFactorNotEnabled);
+            }
+
+            two_factor_service::verify_totp_any(&secrets, &body.otp, config.totp_digits)
+        }
+        FACTOR_BACKUP_CODE => consume_backup_code(&db, &user, &body.otp).await?,
+        _ => {
+            return Err(UserError::BadRequest(format!(
+                "Unknown factor: {}; allowed: {}, {}",
+                factor, FACTOR_TOTP, FACTOR_BACKUP_CODE
+            )))
+        }
+    };
 
     if !is_valid {
         // Increment attempt counter
@@ -539,29 +1649,881 @@ pub async fn verify_otp(
             "created_at": session["created_at"]
         });
 
-        db.redis_client.set_with_expiry(
-            &session_key,
-            &updated_session.to_string(),
-            1800 // Keep the same expiry
-        ).await.map_err(|_| UserError::TokenCreationFailure)?;
+        // Losing the attempt count would lift the attempt limit
+        required(
+            db.redis_client
+                .set_with_expiry(
+                    &session_key,
+                    &updated_session.to_string(),
+                    MFA_SESSION_TTL_SECONDS,
+                )
+                .await,
+            "2FA session write",
+        )?;
 
         return Err(UserError::InvalidTwoFactorCode);
     }
 
     // OTP is valid, delete session
-    db.redis_client.del(&session_key).await
-        .map_err(|_| UserError::TokenCreationFailure)?;
+    // A session left behind could be replayed for another token pair
+    required(
+        db.redis_client.del(&session_key).await,
+        "2FA session delete",
+    )?;
 
     // Generate token pair
-    let (access_token, refresh_token_str) =
-        generate_token_pair(&user.uuid, &db.redis_client).await?;
+    let tokens = generate_token_pair(&user.uuid, &db.redis_client, &config).await?;
 
     let user_response = UserResponse {
         user: user.into(),
-        access_token,
-        refresh_token: refresh_token_str,
+        access_token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
         token_type: "Bearer".to_string(),
+        two_factor_setup_required: false,
+        evicted_sessions: tokens.evicted_sessions,
     };
 
     Ok(Json(LoginResponse::FullLogin(user_response)))
 }
+
+#[utoipa::path(
+    tag = "2fa",
+    responses((status = 200, description = "Configured second factors", body = TwoFactorStatusResponse)),
+    security(("bearer_auth" = []))
+)]
+#[get("/users/me/2fa/status", wrap = "HttpAuthentication::bearer(validator)")]
+pub async fn get_two_factor_status(
+    req: HttpRequest,
+    db: Data<Database>,
+) -> Result<Json<TwoFactorStatusResponse>, UserError> {
+    let user_id = current_user_id(&req)?;
+
+    let user = db.get_user_by_uuid(&user_id).await?;
+    let devices = db.get_2fa_devices(&user_id).await?;
+    let backup_code_sets = db.get_backup_code_sets(&user_id).await?;
+
+    Ok(Json(TwoFactorStatusResponse {
+        totp_enabled: user.two_factor_enabled,
+        email_otp_enabled: false,
+        backup_codes_remaining: backup_code_sets.iter().map(|set| set.codes.len()).sum(),
+        trusted_devices_count: devices.len(),
+    }))
+}
+
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 200, description = "Profile of the signed-in user", body = UserResponseWithoutPassword),
+        (status = 304, description = "Unchanged since the sent If-None-Match or If-Modified-Since")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[get("/users/me", wrap = "HttpAuthentication::bearer(validator)")]
+pub async fn get_profile(req: HttpRequest, db: Data<Database>) -> Result<HttpResponse, UserError> {
+    let user_id = current_user_id(&req)?;
+    let user = db.get_user_by_uuid(&user_id).await?;
+
+    // Every profile change (2FA toggled, email or password changed) bumps updated_at
+    let updated_at = user.updated_at_utc;
+    let etag = EntityTag::new_strong(format!("{:x}", updated_at.timestamp_micros()));
+    // HTTP dates have whole seconds, a change within the same second still changes the ETag
+    let last_modified = SystemTime::from(updated_at.trunc_subsecs(0));
+
+    // If-Modified-Since only counts when the client sent no ETag (RFC 9110 13.1.3)
+    let not_modified = match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        None => req
+            .get_header::<IfModifiedSince>()
+            .is_some_and(|since| SystemTime::from(since.0) >= last_modified),
+    };
+
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header(ETag(etag))
+        .insert_header(LastModified(last_modified.into()))
+        // Clients may keep the profile but must revalidate it before each use
+        .insert_header((CACHE_CONTROL, "private, no-cache"));
+
+    if not_modified {
+        Ok(response.finish())
+    } else {
+        Ok(response.json(UserResponseWithoutPassword::from(user)))
+    }
+}
+
+#[utoipa::path(
+    tag = "users",
+    responses((status = 200, description = "Todo list defaults", body = UserPreferences)),
+    security(("bearer_auth" = []))
+)]
+#[get(
+    "/users/me/preferences",
+    wrap = "HttpAuthentication::bearer(validator)"
+)]
+pub async fn get_preferences(
+    req: HttpRequest,
+    db: Data<Database>,
+) -> Result<Json<UserPreferences>, UserError> {
+    let user_id = current_user_id(&req)?;
+
+    Ok(Json(db.get_user_preferences(&user_id).await?))
+}
+
+#[utoipa::path(
+    tag = "users",
+    request_body = UserPreferences,
+    responses(
+        (status = 200, description = "Saved preferences", body = UserPreferences),
+        (status = 400, description = "Unknown sort column or invalid page size")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[put(
+    "/users/me/preferences",
+    wrap = "HttpAuthentication::bearer(validator)"
+)]
+pub async fn update_preferences(
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    body: Json<UserPreferences>,
+) -> Result<Json<UserPreferences>, UserError> {
+    let user_id = current_user_id(&req)?;
+
+    body.validate()?;
+    config
+        .limits
+        .check_page_size(body.page_size)
+        .map_err(|e| UserError::ValidationError(field_error("page_size", e.message)))?;
+
+    let preferences = db.update_user_preferences(&user_id, &body).await?;
+
+    // Cached lists were built with the old defaults
+    best_effort(
+        db.redis_client
+            .delete_cached_by_pattern(&format!("todos:user:{}:*", user_id))
+            .await,
+        &format!("invalidation for user {}", user_id),
+    );
+
+    Ok(Json(preferences))
+}
+
+#[utoipa::path(
+    tag = "users",
+    request_body = ChangePasswordRequest,
+    responses(
+        (status = 200, description = "Password changed and every session signed out, including this one", body = ChangePasswordResponse),
+        (status = 400, description = "Password used recently"),
+        (status = 401, description = "Current password is wrong")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post("/users/me/password", wrap = "HttpAuthentication::bearer(validator)")]
+pub async fn change_password(
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    body: Json<ChangePasswordRequest>,
+) -> Result<Json<ChangePasswordResponse>, UserError> {
+    let user_id = current_user_id(&req)?;
+
+    body.validate()?;
+
+    let user = db.get_user_by_uuid(&user_id).await?;
+
+    if !password_service::verify_password(&body.current_password, &user.password)? {
+        return Err(UserError::InvalidCredentials);
+    }
+
+    // The current password counts as the most recent entry of the history
+    if config.password_history_size > 0 {
+        let mut recent = vec![user.password.clone()];
+        recent.extend(
+            db.get_password_history(&user_id, config.password_history_size - 1)
+                .await?,
+        );
+        for hashed in &recent {
+            if password_service::verify_password(&body.new_password, hashed)? {
+                return Err(UserError::PasswordRecentlyUsed);
+            }
+        }
+    }
+
+    let new_hash = password_service::hash_password(&body.new_password, config.password_algorithm)?;
+    db.change_password(
+        &user_id,
+        &new_hash,
+        config.password_history_size.saturating_sub(1),
+    )
+    .await?;
+
+    // A stolen refresh token must stop working; the password is changed either way
+    let sessions_revoked = best_effort(
+        db.redis_client.revoke_all_user_tokens(&user_id).await,
+        &format!("session revocation for user {}", user_id),
+    )
+    .is_some();
+
+    Ok(Json(ChangePasswordResponse {
+        success: true,
+        message: "Password changed successfully".to_string(),
+        sessions_revoked,
+    }))
+}
+
+#[utoipa::path(
+    tag = "users",
+    request_body = VerifyPasswordRequest,
+    responses(
+        (status = 200, description = "Whether the password is the current one", body = VerifyPasswordResponse),
+        (status = 429, description = "Too many attempts, or failed attempts backed off, see Retry-After")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post(
+    "/users/me/verify-password",
+    wrap = "HttpAuthentication::bearer(validator)"
+)]
+pub async fn verify_password(
+    req: HttpRequest,
+    db: Data<Database>,
+    body: Json<VerifyPasswordRequest>,
+) -> Result<Json<VerifyPasswordResponse>, UserError> {
+    let user_id = current_user_id(&req)?;
+
+    body.validate()?;
+
+    let attempts = required(
+        db.redis_client
+            .hit_rate_limit(
+                &format!("verify_password:{}", user_id),
+                VERIFY_PASSWORD_WINDOW_SECONDS,
+            )
+            .await,
+        "password verification rate limit",
+    )?;
+
+    if attempts > VERIFY_PASSWORD_MAX_ATTEMPTS {
+        return Err(UserError::TooManyAttempts);
+    }
+
+    let user = db.get_user_by_uuid(&user_id).await?;
+
+    // Shares the login backoff, so a stolen access token can't guess the password here instead
+    if let Some(remaining) = login_backoff_remaining(&db, &user.email).await? {
+        return Err(UserError::LoginBackoff(remaining));
+    }
+
+    // Only checks the password; unlike login the hash is never upgraded here
+    let valid = password_service::verify_password(&body.password, &user.password)?;
+
+    if valid {
+        reset_login_failures(&db, &user.email).await;
+    } else {
+        record_login_failure(&db, &user.email).await;
+    }
+
+    Ok(Json(VerifyPasswordResponse { valid }))
+}
+
+#[utoipa::path(
+    tag = "users",
+    request_body = ChangeEmailRequest,
+    responses(
+        (status = 200, description = "Updated account", body = UserResponseWithoutPassword),
+        (status = 401, description = "Password is wrong"),
+        (status = 409, description = "Email already in use"),
+        (status = 429, description = "Email was changed too recently, see Retry-After")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[post("/users/me/email", wrap = "HttpAuthentication::bearer(validator)")]
+pub async fn change_email(
+    req: HttpRequest,
+    db: Data<Database>,
+    config: Data<AppConfig>,
+    body: Json<ChangeEmailRequest>,
+) -> Result<Json<UserResponseWithoutPassword>, UserError> {
+    let user_id = current_user_id(&req)?;
+
+    body.validate()?;
+
+    let user = db.get_user_by_uuid(&user_id).await?;
+
+    if !password_service::verify_password(&body.password, &user.password)? {
+        return Err(UserError::InvalidCredentials);
+    }
+
+    let new_email = body.new_email.trim().to_string();
+    if new_email.eq_ignore_ascii_case(&user.email) {
+        return Ok(Json(user.into()));
+    }
+
+    // Rapid email changes are a common account takeover pattern, so space them out
+    if let Some(changed_at) = user.email_changed_at {
+        let elapsed = (Utc::now() - changed_at).num_seconds().max(0) as u64;
+        if elapsed < config.email_change_cooldown_seconds {
+            return Err(UserError::EmailChangeCooldown(
+                config.email_change_cooldown_seconds - elapsed,
+            ));
+        }
+    }
+
+    match db.get_user_by_email(&new_email).await {
+        Ok(_) => return Err(UserError::UserAlreadyExists),
+        Err(UserError::NoSuchUserFound) => {}
+        Err(e) => return Err(e),
+    }
+
+    let mut updated_user = user;
+    updated_user.email = new_email;
+    let updated_user = db.update_user(&updated_user).await?;
+
+    Ok(Json(updated_user.into()))
+}
+
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 200, description = "Everything stored about the signed-in user, as a JSON download", body = UserExport),
+        (status = 429, description = "Too many exports in the last hour")
+    ),
+    security(("bearer_auth" = []))
+)]
+#[get("/users/me/export", wrap = "HttpAuthentication::bearer(validator)")]
+pub async fn export_user_data(
+    req: HttpRequest,
+    db: Data<Database>,
+) -> Result<HttpResponse, UserError> {
+    let user_id = current_user_id(&req)?;
+
+    // Reads every todo of the user, so keep it to an occasional download
+    let exports = required(
+        db.redis_client
+            .hit_rate_limit(&format!("export:{}", user_id), EXPORT_WINDOW_SECONDS)
+            .await,
+        "export rate limit",
+    )?;
+    if exports > EXPORT_MAX_PER_WINDOW {
+        return Err(UserError::TooManyAttempts);
+    }
+
+    let user = db.get_user_by_uuid(&user_id).await?;
+    let preferences = db.get_user_preferences(&user_id).await?;
+    let devices = db.get_2fa_devices(&user_id).await?;
+    let tags = db
+        .get_user_tags(&user_id)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.message))?;
+    let now = Utc::now();
+    let sessions = required(
+        db.redis_client.user_sessions(&user_id).await,
+        "session listing",
+    )?
+    .into_iter()
+    .map(|(_, ttl)| ExportedSession {
+        expires_at: (now + chrono::Duration::seconds(ttl as i64)).to_rfc3339(),
+    })
+    .collect();
+
+    let export = UserExport {
+        exported_at: now.to_rfc3339(),
+        profile: UserResponseWithoutPassword::from(user),
+        preferences,
+        two_factor_devices: devices
+            .into_iter()
+            .map(TwoFactorDeviceResponse::from)
+            .collect(),
+        sessions,
+        tags,
+        todos: Vec::new(),
+    };
+    // Todos are read page by page while the response is sent, never all at once
+    let body = export_stream(db.clone(), user_id, &export)
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("todo-export.json".to_string())],
+        })
+        .streaming(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_support::{create_test_user, test_database, TEST_PASSWORD};
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use serde_json::json;
+
+    /// Every user route, wired the way main.rs does it
+    macro_rules! test_app {
+        ($db:expr, $config:expr) => {
+            test::init_service(
+                App::new()
+                    .app_data(Data::new($db))
+                    .app_data(Data::new($config))
+                    .configure(|cfg| user_routes(cfg, &Features::from_env())),
+            )
+            .await
+        };
+    }
+
+    fn test_config() -> AppConfig {
+        AppConfig {
+            password_algorithm: PasswordAlgorithm::Bcrypt { cost: 4 },
+            ..AppConfig::from_env()
+        }
+    }
+
+    fn access_token(user: &User) -> String {
+        generate_jwt_token(&user.uuid, "access", 1, None, None).unwrap()
+    }
+
+    fn change_password_request(user: &User, current: &str, new: &str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/users/me/password")
+            .insert_header(("Authorization", format!("Bearer {}", access_token(user))))
+            .set_json(json!({ "current_password": current, "new_password": new }))
+    }
+
+    fn change_email_request(user: &User, new_email: &str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/users/me/email")
+            .insert_header(("Authorization", format!("Bearer {}", access_token(user))))
+            .set_json(json!({ "password": TEST_PASSWORD, "new_email": new_email }))
+    }
+
+    /// The code an authenticator app shows right now for `secret`
+    fn current_code(secret: &str, digits: usize) -> String {
+        let secret = data_encoding::BASE32_NOPAD
+            .decode(secret.as_bytes())
+            .unwrap();
+        totp_rs::TOTP::new(totp_rs::Algorithm::SHA1, digits, 1, 30, secret)
+            .unwrap()
+            .generate_current()
+            .unwrap()
+    }
+
+    /// A user whose primary authenticator is set up, with its secret
+    async fn create_2fa_user(db: &Database) -> (User, String) {
+        let user = create_test_user(db).await;
+        let secret = two_factor_service::generate_secret();
+        db.enable_2fa(&user.uuid, &secret).await.unwrap();
+        db.verify_2fa(&user.uuid).await.unwrap();
+        (user, secret)
+    }
+
+    /// A database handle whose sessions render timestamps as text like
+    /// `01/05/2024 15:50:30.123456 IST` instead of the ISO form
+    async fn database_with_sql_date_style() -> Database {
+        // sqlx asks for ISO dates in UTC when connecting, a later SET still overrides that
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .max_connections(1)
+            .after_connect(|conn, _| {
+                Box::pin(async move {
+                    sqlx::Executor::execute(
+                        conn,
+                        "SET TIME ZONE 'Asia/Kolkata'; SET DateStyle = 'SQL, DMY'",
+                    )
+                    .await
+                    .map(|_| ())
+                })
+            })
+            .connect(&std::env::var("TEST_DATABASE_URL").unwrap())
+            .await
+            .unwrap();
+        Database {
+            read_pool: pool.clone(),
+            pool,
+            redis_client: test_database().await.redis_client,
+        }
+    }
+
+    fn add_device_request(user: &User, code: &str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri(&format!("/users/{}/2fa/devices", user.uuid))
+            .insert_header(("Authorization", format!("Bearer {}", access_token(user))))
+            .set_json(json!({ "password": TEST_PASSWORD, "name": "Tablet", "code": code }))
+    }
+
+    fn verify_device_request(user: &User, device_uuid: &str, code: &str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri(&format!(
+                "/users/{}/2fa/devices/{}/verify",
+                user.uuid, device_uuid
+            ))
+            .insert_header(("Authorization", format!("Bearer {}", access_token(user))))
+            .set_json(json!({ "code": code }))
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL and Redis"]
+    async fn refresh_token_from_before_a_password_change_is_rejected() {
+        let db = test_database().await;
+        let config = test_config();
+        let user = create_test_user(&db).await;
+        let tokens = generate_token_pair(&user.uuid, &db.redis_client, &config)
+            .await
+            .unwrap();
+        let app = test_app!(db, config);
+
+        let res = test::call_service(
+            &app,
+            change_password_request(&user, TEST_PASSWORD, "a brand new password").to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["sessions_revoked"], true);
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/refresh")
+                .set_json(json!({ "refresh_token": tokens.refresh_token }))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL and Redis"]
+    async fn concurrent_refreshes_with_one_token_let_only_one_through() {
+        let db = test_database().await;
+        let config = AppConfig {
+            refresh_rotation_window_seconds: None,
+            ..test_config()
+        };
+        let user = create_test_user(&db).await;
+        let tokens = generate_token_pair(&user.uuid, &db.redis_client, &config)
+            .await
+            .unwrap();
+        let app = test_app!(db, config);
+        let refresh = || {
+            test::TestRequest::post()
+                .uri("/refresh")
+                .set_json(json!({ "refresh_token": tokens.refresh_token }))
+                .to_request()
+        };
+
+        let (first, second) = futures_util::future::join(
+            test::call_service(&app, refresh()),
+            test::call_service(&app, refresh()),
+        )
+        .await;
+
+        let statuses = [first.status(), second.status()];
+        assert!(
+            statuses.contains(&StatusCode::OK) && statuses.contains(&StatusCode::UNAUTHORIZED),
+            "expected one success and one rejection, got {:?}",
+            statuses
+        );
+        let rejected = if first.status() == StatusCode::OK {
+            second
+        } else {
+            first
+        };
+        let body: serde_json::Value = test::read_body_json(rejected).await;
+        assert_eq!(body["data"]["error_code"], "refresh_token_reused");
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL and Redis"]
+    async fn changing_back_to_a_previous_password_is_rejected() {
+        let db = test_database().await;
+        let config = AppConfig {
+            password_history_size: 3,
+            ..test_config()
+        };
+        let user = create_test_user(&db).await;
+        let app = test_app!(db, config);
+
+        let res = test::call_service(
+            &app,
+            change_password_request(&user, TEST_PASSWORD, "second password").to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = test::call_service(
+            &app,
+            change_password_request(&user, "second password", TEST_PASSWORD).to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        // The rejected change left the password as it was
+        let res = test::call_service(
+            &app,
+            change_password_request(&user, "second password", "third password").to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn second_email_change_within_the_cooldown_is_rejected() {
+        let db = test_database().await;
+        let config = AppConfig {
+            email_change_cooldown_seconds: 3600,
+            ..test_config()
+        };
+        let user = create_test_user(&db).await;
+        let app = test_app!(db, config);
+        let first_email = format!("first-{}", user.email);
+
+        let res =
+            test::call_service(&app, change_email_request(&user, &first_email).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = test::call_service(
+            &app,
+            change_email_request(&user, &format!("second-{}", user.email)).to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = res
+            .headers()
+            .get("Retry-After")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(retry_after > 0 && retry_after <= 3600);
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/users/me")
+                .insert_header(("Authorization", format!("Bearer {}", access_token(&user))))
+                .to_request(),
+        )
+        .await;
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["email"], first_email);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn adding_a_device_needs_a_code_from_an_active_one() {
+        let db = test_database().await;
+        let config = test_config();
+        let (user, _) = create_2fa_user(&db).await;
+        let digits = config.totp_digits;
+        let app = test_app!(db, config);
+        // The app owns the first handle, this one reads what the requests stored
+        let db = test_database().await;
+
+        // A code from some other authenticator proves nothing
+        let stranger_code = current_code(&two_factor_service::generate_secret(), digits);
+        let res =
+            test::call_service(&app, add_device_request(&user, &stranger_code).to_request()).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let (_, total) = db.get_2fa_devices_page(&user.uuid, 1, 10).await.unwrap();
+        assert_eq!(total, 0);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL and Redis"]
+    async fn pending_device_verifies_whatever_the_session_date_style() {
+        let config = test_config();
+        let (user, primary_secret) = create_2fa_user(&test_database().await).await;
+        let digits = config.totp_digits;
+        let app = test_app!(database_with_sql_date_style().await, config);
+
+        let res = test::call_service(
+            &app,
+            add_device_request(&user, &current_code(&primary_secret, digits)).to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(res).await;
+        let device_uuid = body["device"]["uuid"].as_str().unwrap().to_string();
+        let device_secret = body["secret"].as_str().unwrap().to_string();
+
+        // A freshly added device is inside its setup window, however `created_at` renders
+        let res = test::call_service(
+            &app,
+            verify_device_request(&user, &device_uuid, &current_code(&device_secret, digits))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let active = test_database()
+            .await
+            .get_2fa_devices(&user.uuid)
+            .await
+            .unwrap();
+        assert_eq!(active.len(), 1);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn disabling_2fa_again_needs_no_code() {
+        let db = test_database().await;
+        let config = test_config();
+        let (user, secret) = create_2fa_user(&db).await;
+        let digits = config.totp_digits;
+        let app = test_app!(db, config);
+        let disable = |code: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/users/{}/disable-2fa", user.uuid))
+                .set_json(json!({ "password": TEST_PASSWORD, "code": code }))
+                .to_request()
+        };
+
+        let res = test::call_service(&app, disable(&current_code(&secret, digits))).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // The authenticator is gone by now, so a retry can't carry a current code
+        let res = test::call_service(&app, disable("")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn added_device_is_only_active_after_a_code_from_it() {
+        let db = test_database().await;
+        let config = test_config();
+        let (user, primary_secret) = create_2fa_user(&db).await;
+        let digits = config.totp_digits;
+        let app = test_app!(db, config);
+        // The app owns the first handle, this one reads what the requests stored
+        let db = test_database().await;
+
+        let res = test::call_service(
+            &app,
+            add_device_request(&user, &current_code(&primary_secret, digits)).to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(res).await;
+        let device_uuid = body["device"]["uuid"].as_str().unwrap().to_string();
+        let device_secret = body["secret"].as_str().unwrap().to_string();
+        assert_eq!(body["device"]["verified"], false);
+        assert!(db.get_2fa_devices(&user.uuid).await.unwrap().is_empty());
+
+        // The primary authenticator's code doesn't activate the new device
+        let res = test::call_service(
+            &app,
+            verify_device_request(&user, &device_uuid, &current_code(&primary_secret, digits))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert!(db.get_2fa_devices(&user.uuid).await.unwrap().is_empty());
+
+        let res = test::call_service(
+            &app,
+            verify_device_request(&user, &device_uuid, &current_code(&device_secret, digits))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let active = db.get_2fa_devices(&user.uuid).await.unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].uuid, device_uuid);
+
+        let res = test::call_service(
+            &app,
+            verify_device_request(&user, &device_uuid, &current_code(&device_secret, digits))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL and Redis"]
+    async fn qr_of_the_current_secret_needs_the_users_token_and_a_current_code() {
+        let db = test_database().await;
+        let config = test_config();
+        let (user, secret) = create_2fa_user(&db).await;
+        let other = create_test_user(&db).await;
+        let digits = config.totp_digits;
+        let app = test_app!(db, config);
+        let qr_request = |code: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/users/{}/2fa/qr", user.uuid))
+                .set_json(json!({ "password": TEST_PASSWORD, "code": code, "reveal_secret": true }))
+        };
+
+        // The password alone was once enough to read the secret
+        let res = test::call_service(
+            &app,
+            qr_request(&current_code(&secret, digits)).to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = test::call_service(
+            &app,
+            qr_request(&current_code(&secret, digits))
+                .insert_header(("Authorization", format!("Bearer {}", access_token(&other))))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let stranger_code = current_code(&two_factor_service::generate_secret(), digits);
+        let res = test::call_service(
+            &app,
+            qr_request(&stranger_code)
+                .insert_header(("Authorization", format!("Bearer {}", access_token(&user))))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = test::call_service(
+            &app,
+            qr_request(&current_code(&secret, digits))
+                .insert_header(("Authorization", format!("Bearer {}", access_token(&user))))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["secret"], secret);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn profile_etag_holds_whatever_the_session_date_style() {
+        let user = create_test_user(&test_database().await).await;
+        let app = test_app!(database_with_sql_date_style().await, test_config());
+        let profile = || {
+            test::TestRequest::get()
+                .uri("/users/me")
+                .insert_header(("Authorization", format!("Bearer {}", access_token(&user))))
+        };
+
+        let res = test::call_service(&app, profile().to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let etag = res.headers().get("ETag").unwrap().clone();
+        assert!(res.headers().contains_key("Last-Modified"));
+
+        let res = test::call_service(
+            &app,
+            profile()
+                .insert_header(("If-None-Match", etag))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    }
+}
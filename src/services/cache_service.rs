@@ -1,3 +1,4 @@
+use crate::error::cache_error::CacheUnavailable;
 use async_trait::async_trait;
 use redis::RedisError;
 use serde::{de::DeserializeOwned, Serialize};
@@ -22,3 +23,32 @@ pub trait CacheService {
     async fn get(&self, key: &str) -> Result<String, redis::RedisError>;
     async fn del(&self, key: &str) -> Result<(), redis::RedisError>;
 }
+
+// Cache policy: every Redis call site goes through one of the two helpers below, so whether a
+// failure reaches the client is decided where the call is made.
+//
+// - best effort: the cache only mirrors or speeds up the database (todo lists, counts, item
+//   versions, invalidation). A failure is logged and the request goes on against the database;
+//   whatever is left stale expires with its TTL.
+// - required: the data lives only in Redis (refresh tokens, MFA sessions, rate limits, login
+//   backoff), so going on without it would skip a security check or lose state. The request
+//   fails with 503 and Retry-After.
+
+/// Logs a failed best-effort cache operation and carries on without its result
+pub fn best_effort<T>(result: Result<T, RedisError>, operation: &str) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            log::warn!("Cache {} failed, continuing without it: {:?}", operation, e);
+            None
+        }
+    }
+}
+
+/// Turns a failed required cache operation into a 503 for the client
+pub fn required<T>(result: Result<T, RedisError>, operation: &str) -> Result<T, CacheUnavailable> {
+    result.map_err(|e| {
+        log::error!("Cache {} failed, refusing the request: {:?}", operation, e);
+        CacheUnavailable
+    })
+}
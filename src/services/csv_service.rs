@@ -0,0 +1,181 @@
+/// Lỗi cú pháp CSV, `line` tính từ 1
+#[derive(Debug, PartialEq, Eq)]
+pub struct CsvError {
+    pub line: usize,
+    pub message: &'static str,
+}
+
+/// Một dòng dữ liệu kèm số dòng bắt đầu của nó trong file, để báo lỗi đúng chỗ
+pub struct CsvRecord {
+    pub line: usize,
+    pub fields: Vec<String>,
+}
+
+/// Tách CSV theo RFC 4180: trường có thể nằm trong `"..."` để chứa dấu phẩy, xuống dòng
+/// và `""` (một dấu nháy); chấp nhận cả `\n` lẫn `\r\n`, bỏ qua BOM và dòng trống
+pub fn parse_csv(input: &str) -> Result<Vec<CsvRecord>, CsvError> {
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    // Trường đã mở ngoặc thì không được có ký tự nào sau dấu nháy đóng ngoài dấu phân cách
+    let mut after_quotes = false;
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => {
+                    in_quotes = false;
+                    after_quotes = true;
+                }
+                '\n' => {
+                    line += 1;
+                    field.push(c);
+                }
+                _ => field.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            ',' => {
+                fields.push(std::mem::take(&mut field));
+                after_quotes = false;
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                fields.push(std::mem::take(&mut field));
+                push_record(&mut records, record_line, std::mem::take(&mut fields));
+                after_quotes = false;
+                line += 1;
+                record_line = line;
+            }
+            '"' if field.is_empty() && !after_quotes => in_quotes = true,
+            _ if after_quotes => {
+                return Err(CsvError {
+                    line,
+                    message: "unexpected character after a closing quote",
+                });
+            }
+            '"' => {
+                return Err(CsvError {
+                    line,
+                    message: "quote inside an unquoted field",
+                });
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if in_quotes {
+        return Err(CsvError {
+            line: record_line,
+            message: "unterminated quoted field",
+        });
+    }
+    if !field.is_empty() || !fields.is_empty() || after_quotes {
+        fields.push(field);
+        push_record(&mut records, record_line, fields);
+    }
+
+    Ok(records)
+}
+
+fn push_record(records: &mut Vec<CsvRecord>, line: usize, fields: Vec<String>) {
+    // Dòng trống (thường là dòng cuối file) không phải là một bản ghi
+    if fields.len() == 1 && fields[0].is_empty() {
+        return;
+    }
+    records.push(CsvRecord { line, fields });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(input: &str) -> Vec<Vec<String>> {
+        parse_csv(input)
+            .unwrap()
+            .into_iter()
+            .map(|record| record.fields)
+            .collect()
+    }
+
+    #[test]
+    fn quoted_field_keeps_commas() {
+        assert_eq!(
+            rows("title,tags\n\"a, b\",c\n"),
+            vec![vec!["title", "tags"], vec!["a, b", "c"]]
+        );
+    }
+
+    #[test]
+    fn doubled_quote_is_one_quote() {
+        assert_eq!(
+            rows("\"say \"\"hi\"\"\",\"\"\"\"\n"),
+            vec![vec!["say \"hi\"", "\""]]
+        );
+    }
+
+    #[test]
+    fn quoted_field_keeps_newlines_and_records_start_line() {
+        let records = parse_csv("title,description\n\"a\",\"line 1\nline 2\"\nb,c\n").unwrap();
+
+        assert_eq!(records[1].fields, vec!["a", "line 1\nline 2"]);
+        assert_eq!(
+            records.iter().map(|record| record.line).collect::<Vec<_>>(),
+            vec![1, 2, 4]
+        );
+    }
+
+    #[test]
+    fn crlf_line_endings_and_bom_are_accepted() {
+        assert_eq!(
+            rows("\u{feff}title,done\r\n\"a\",true\r\nb,false"),
+            vec![vec!["title", "done"], vec!["a", "true"], vec!["b", "false"]]
+        );
+    }
+
+    #[test]
+    fn blank_lines_are_skipped_but_empty_fields_kept() {
+        assert_eq!(rows("a,\n\n,\"\"\n\n"), vec![vec!["a", ""], vec!["", ""]]);
+    }
+
+    #[test]
+    fn unterminated_quote_is_reported_at_its_record() {
+        let err = parse_csv("title\nok\n\"never closed\nmore\n")
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err,
+            CsvError {
+                line: 3,
+                message: "unterminated quoted field"
+            }
+        );
+    }
+
+    #[test]
+    fn stray_quotes_are_rejected() {
+        assert_eq!(
+            parse_csv("a\"b\n").err().unwrap().message,
+            "quote inside an unquoted field"
+        );
+        assert_eq!(
+            parse_csv("ok\n\"a\"b\n").err().unwrap(),
+            CsvError {
+                line: 2,
+                message: "unexpected character after a closing quote"
+            }
+        );
+    }
+}
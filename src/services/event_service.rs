@@ -0,0 +1,88 @@
+use crate::db::redis_client::RedisClient;
+use crate::models::todo::TodoResponse;
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::Serialize;
+use std::error::Error;
+
+pub type PublishError = Box<dyn Error + Send + Sync>;
+
+/// Sự kiện nghiệp vụ gửi cho các service khác sau khi thay đổi thành công
+#[derive(Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DomainEvent {
+    TodoCreated { todo: TodoResponse },
+    TodoUpdated { todo: TodoResponse },
+    TodoDeleted { todo_id: String, user_id: String },
+    TodoReminderDue { todo: TodoResponse },
+    UserRegistered { user_id: String, email: String },
+}
+
+impl DomainEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DomainEvent::TodoCreated { .. } => "todo_created",
+            DomainEvent::TodoUpdated { .. } => "todo_updated",
+            DomainEvent::TodoDeleted { .. } => "todo_deleted",
+            DomainEvent::TodoReminderDue { .. } => "todo_reminder_due",
+            DomainEvent::UserRegistered { .. } => "user_registered",
+        }
+    }
+}
+
+/// Nơi nhận sự kiện; thêm Kafka/NATS bằng cách implement trait này
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    async fn publish(&self, event: &DomainEvent) -> Result<(), PublishError>;
+}
+
+/// Gửi sự kiện và chỉ ghi log khi lỗi, request không bị thất bại vì broker
+pub async fn publish_event(publisher: &dyn EventPublisher, event: DomainEvent) {
+    if let Err(e) = publisher.publish(&event).await {
+        log::error!("Failed to publish {} event: {:?}", event.name(), e);
+    }
+}
+
+/// Mặc định khi không cấu hình broker
+pub struct NoopPublisher;
+
+#[async_trait]
+impl EventPublisher for NoopPublisher {
+    async fn publish(&self, _event: &DomainEvent) -> Result<(), PublishError> {
+        Ok(())
+    }
+}
+
+/// Ghi sự kiện vào một Redis stream bằng XADD, giới hạn xấp xỉ `max_len` entry
+pub struct RedisStreamPublisher {
+    redis_client: RedisClient,
+    stream: String,
+    max_len: usize,
+}
+
+impl RedisStreamPublisher {
+    pub fn new(redis_url: &str, stream: String, max_len: usize) -> Self {
+        Self {
+            redis_client: RedisClient::new(redis_url),
+            stream,
+            max_len,
+        }
+    }
+}
+
+#[async_trait]
+impl EventPublisher for RedisStreamPublisher {
+    async fn publish(&self, event: &DomainEvent) -> Result<(), PublishError> {
+        let payload = serde_json::to_string(event)?;
+        let fields = [
+            ("type", event.name().to_string()),
+            ("occurred_at", Utc::now().to_rfc3339()),
+            ("payload", payload),
+        ];
+
+        self.redis_client
+            .append_to_stream(&self.stream, self.max_len, &fields)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use crate::db::data_trait::todo_data_trait::TodoData;
+use crate::db::database::Database;
+use crate::models::user::UserExport;
+use actix_web::web::{Bytes, Data};
+use futures_util::{stream, Stream};
+use log::error;
+
+/// Số todo đọc mỗi lần, giữ bộ nhớ cố định dù user có bao nhiêu todo
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+enum ExportPhase {
+    /// Mọi phần của bản xuất trừ todo, mở sẵn mảng `todos`
+    Header(Bytes),
+    /// Đọc trang todo sau `after_uuid`; `first` cho biết chưa có todo nào được ghi
+    Todos {
+        after_uuid: Option<String>,
+        first: bool,
+    },
+    Done,
+}
+
+/// Bản xuất dưới dạng một tài liệu JSON được gửi dần: phần đầu đã có sẵn trong `export`,
+/// todo được đọc và ghi từng trang vào mảng `todos`
+///
+/// Lỗi ở giữa chừng chỉ có thể cắt ngang kết nối vì status 200 đã được gửi; client nhận một
+/// tài liệu JSON không hoàn chỉnh nên biết bản xuất bị lỗi.
+pub fn export_stream(
+    db: Data<Database>,
+    user_id: String,
+    export: &UserExport,
+) -> Result<impl Stream<Item = Result<Bytes, actix_web::Error>>, serde_json::Error> {
+    // `todos` là trường cuối và đang rỗng nên JSON kết thúc bằng `[]}`, bỏ hai ký tự đó để mở lại mảng
+    let mut header = serde_json::to_vec(export)?;
+    header.truncate(header.len() - 2);
+
+    Ok(stream::unfold(
+        ExportPhase::Header(Bytes::from(header)),
+        move |phase| {
+            let db = db.clone();
+            let user_id = user_id.clone();
+            async move {
+                match phase {
+                    ExportPhase::Header(header) => Some((
+                        Ok(header),
+                        ExportPhase::Todos {
+                            after_uuid: None,
+                            first: true,
+                        },
+                    )),
+                    ExportPhase::Todos { after_uuid, first } => {
+                        let todos = match db
+                            .export_todos(&user_id, after_uuid.as_deref(), EXPORT_PAGE_SIZE)
+                            .await
+                        {
+                            Ok(todos) => todos,
+                            Err(e) => {
+                                error!("Export of user {} failed: {}", user_id, e);
+                                return Some((Err(e.into()), ExportPhase::Done));
+                            }
+                        };
+
+                        let mut chunk = Vec::new();
+                        for (index, todo) in todos.iter().enumerate() {
+                            if !first || index > 0 {
+                                chunk.push(b',');
+                            }
+                            if let Err(e) = serde_json::to_writer(&mut chunk, todo) {
+                                error!("Export of user {} failed: {}", user_id, e);
+                                return Some((
+                                    Err(actix_web::error::ErrorInternalServerError(e)),
+                                    ExportPhase::Done,
+                                ));
+                            }
+                        }
+
+                        // Trang chưa đầy là trang cuối, đóng mảng và tài liệu luôn
+                        if (todos.len() as i64) < EXPORT_PAGE_SIZE {
+                            chunk.extend_from_slice(b"]}");
+                            return Some((Ok(Bytes::from(chunk)), ExportPhase::Done));
+                        }
+
+                        let after_uuid = todos.last().map(|todo| todo.todo.uuid.clone());
+                        Some((
+                            Ok(Bytes::from(chunk)),
+                            ExportPhase::Todos {
+                                after_uuid,
+                                first: false,
+                            },
+                        ))
+                    }
+                    ExportPhase::Done => None,
+                }
+            }
+        },
+    ))
+}
@@ -0,0 +1,90 @@
+use env_logger::{Builder, Env, Logger};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+/// Mức log theo module được đặt lúc chạy, ưu tiên hơn RUST_LOG
+static MODULE_LEVELS: RwLock<BTreeMap<String, LevelFilter>> = RwLock::new(BTreeMap::new());
+
+/// Logger bọc env_logger để có thể đổi mức log từng module mà không cần khởi động lại
+struct RuntimeLogger {
+    /// Bộ lọc đọc từ RUST_LOG lúc khởi động
+    startup_filter: Logger,
+    /// Ghi mọi record được chuyển tới, việc lọc do `enabled` đảm nhận
+    writer: Logger,
+}
+
+impl RuntimeLogger {
+    /// Override của module dài nhất khớp với target, giống cách RUST_LOG chọn directive
+    fn override_for(target: &str) -> Option<LevelFilter> {
+        let levels = MODULE_LEVELS.read().ok()?;
+
+        levels
+            .iter()
+            .filter(|(module, _)| {
+                target == module.as_str()
+                    || target
+                        .strip_prefix(module.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+    }
+}
+
+impl Log for RuntimeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        match Self::override_for(metadata.target()) {
+            Some(level) => metadata.level() <= level,
+            None => self.startup_filter.enabled(metadata),
+        }
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.writer.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.writer.flush();
+    }
+}
+
+fn configure_format(builder: &mut Builder) -> &mut Builder {
+    builder.format_timestamp_millis().format_module_path(true)
+}
+
+/// Khởi tạo logger toàn cục, mặc định ở mức `default_filter` khi RUST_LOG không được đặt
+pub fn init(default_filter: &str) {
+    let startup_filter = configure_format(&mut Builder::from_env(
+        Env::default().default_filter_or(default_filter),
+    ))
+    .build();
+    let writer = configure_format(&mut Builder::new())
+        .filter_level(LevelFilter::Trace)
+        .build();
+
+    let max_level = startup_filter.filter();
+    log::set_boxed_logger(Box::new(RuntimeLogger {
+        startup_filter,
+        writer,
+    }))
+    .expect("Logger already initialized");
+    log::set_max_level(max_level);
+}
+
+/// Đặt mức log cho một module (vd. `sqlx`), áp dụng ngay cho mọi thread
+pub fn set_module_level(module: &str, level: LevelFilter) {
+    let mut levels = match MODULE_LEVELS.write() {
+        Ok(levels) => levels,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    levels.insert(module.to_string(), level);
+
+    // `log` bỏ qua macro sớm theo max level, nên phải nâng nó lên khi override chi tiết hơn
+    let max_override = levels.values().copied().max().unwrap_or(LevelFilter::Off);
+    if max_override > log::max_level() {
+        log::set_max_level(max_override);
+    }
+}
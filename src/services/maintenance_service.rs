@@ -0,0 +1,88 @@
+use crate::db::redis_client::RedisClient;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// Key Redis lưu chế độ bảo trì, dùng chung cho mọi instance
+pub const MAINTENANCE_MODE_KEY: &str = "maintenance_mode";
+
+/// Thời gian giữ chế độ đã đọc trong bộ nhớ để không phải gọi Redis ở mỗi request
+const MODE_CACHE_TTL: Duration = Duration::from_secs(5);
+
+static CACHED_MODE: RwLock<Option<(Instant, MaintenanceMode)>> = RwLock::new(None);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MaintenanceMode {
+    Off,
+    /// Chỉ cho phép GET/HEAD/OPTIONS
+    Readonly,
+    /// Từ chối mọi request trừ health check
+    Full,
+}
+
+impl MaintenanceMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MaintenanceMode::Off => "off",
+            MaintenanceMode::Readonly => "readonly",
+            MaintenanceMode::Full => "full",
+        }
+    }
+}
+
+impl FromStr for MaintenanceMode {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "off" => Ok(MaintenanceMode::Off),
+            "readonly" => Ok(MaintenanceMode::Readonly),
+            "full" => Ok(MaintenanceMode::Full),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Chế độ hiện tại: key Redis nếu có, nếu không thì giá trị MAINTENANCE_MODE lúc khởi động
+///
+/// Khi Redis lỗi thì dùng giá trị mặc định để sự cố Redis không khóa luôn API.
+pub async fn current_mode(redis: &RedisClient, default: MaintenanceMode) -> MaintenanceMode {
+    if let Ok(cached) = CACHED_MODE.read() {
+        if let Some((fetched_at, mode)) = *cached {
+            if fetched_at.elapsed() < MODE_CACHE_TTL {
+                return mode;
+            }
+        }
+    }
+
+    let mode = match redis.get_value(MAINTENANCE_MODE_KEY).await {
+        Ok(Some(value)) => value.parse().unwrap_or(default),
+        Ok(None) => default,
+        Err(e) => {
+            log::error!("Failed to read maintenance mode: {:?}", e);
+            default
+        }
+    };
+
+    remember(mode);
+    mode
+}
+
+/// Lưu chế độ vào Redis để mọi instance áp dụng trong vòng vài giây
+///
+/// "off" cũng được lưu để ghi đè cả MAINTENANCE_MODE đặt lúc khởi động.
+pub async fn set_mode(redis: &RedisClient, mode: MaintenanceMode) -> Result<(), redis::RedisError> {
+    redis.set_value(MAINTENANCE_MODE_KEY, mode.as_str()).await?;
+
+    remember(mode);
+    Ok(())
+}
+
+fn remember(mode: MaintenanceMode) {
+    if let Ok(mut cached) = CACHED_MODE.write() {
+        *cached = Some((Instant::now(), mode));
+    }
+}
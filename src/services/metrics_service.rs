@@ -0,0 +1,240 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Upper bounds (giây) của các bucket, theo mặc định của Prometheus
+const DURATION_BUCKETS: [f64; 11] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+tokio::task_local! {
+    /// Số thao tác DB đã chạy trong request hiện tại, chỉ có trong scope của `count_queries`
+    static REQUEST_QUERIES: Cell<u32>;
+    /// Thời gian request hiện tại đã dành cho DB và cache, chỉ có trong scope của `time_request`
+    static REQUEST_TIMINGS: Cell<RequestTimings>;
+}
+static DB_QUERY_DURATIONS: OnceLock<Mutex<BTreeMap<&'static str, Histogram>>> = OnceLock::new();
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; DURATION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(index) = DURATION_BUCKETS.iter().position(|&bound| seconds <= bound) {
+            self.buckets[index] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// Thời gian một request đã dành cho DB và cache
+///
+/// Thao tác lồng nhau (một method của data trait gọi method khác) chỉ tính một lần, từ lúc
+/// thao tác ngoài cùng bắt đầu đến khi nó kết thúc.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RequestTimings {
+    pub db: Duration,
+    pub cache: Duration,
+    db_depth: u32,
+    cache_depth: u32,
+}
+
+#[derive(Clone, Copy)]
+enum TimingKind {
+    Db,
+    Cache,
+}
+
+impl RequestTimings {
+    fn depth(&mut self, kind: TimingKind) -> &mut u32 {
+        match kind {
+            TimingKind::Db => &mut self.db_depth,
+            TimingKind::Cache => &mut self.cache_depth,
+        }
+    }
+
+    fn total(&mut self, kind: TimingKind) -> &mut Duration {
+        match kind {
+            TimingKind::Db => &mut self.db,
+            TimingKind::Cache => &mut self.cache,
+        }
+    }
+}
+
+/// Đánh dấu một thao tác bắt đầu; `false` khi không có request nào đang được đo
+fn enter_timing(kind: TimingKind) -> bool {
+    REQUEST_TIMINGS
+        .try_with(|timings| {
+            let mut value = timings.get();
+            *value.depth(kind) += 1;
+            timings.set(value);
+        })
+        .is_ok()
+}
+
+/// Đánh dấu một thao tác kết thúc, cộng thời gian khi đó là thao tác ngoài cùng
+fn leave_timing(kind: TimingKind, elapsed: Duration) {
+    let _ = REQUEST_TIMINGS.try_with(|timings| {
+        let mut value = timings.get();
+        let depth = value.depth(kind);
+        *depth = depth.saturating_sub(1);
+        if *depth == 0 {
+            *value.total(kind) += elapsed;
+        }
+        timings.set(value);
+    });
+}
+
+/// Bật thu thập metrics, gọi một lần lúc khởi động
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Đo thời gian một thao tác DB, ghi lại khi timer bị drop
+///
+/// Trả về `None` khi metrics tắt và request không được đo nên không tốn gì ngoài một lần
+/// đọc atomic và task-local.
+pub struct QueryTimer {
+    operation: &'static str,
+    started_at: Instant,
+    /// Thời gian cũng được cộng vào `RequestTimings` của request
+    timed: bool,
+}
+
+impl QueryTimer {
+    pub fn start(operation: &'static str) -> Option<Self> {
+        // Mọi method của data trait đều gọi qua đây nên đây là chỗ đếm thao tác DB của request
+        if cfg!(debug_assertions) {
+            let _ = REQUEST_QUERIES.try_with(|count| count.set(count.get() + 1));
+        }
+
+        let timed = enter_timing(TimingKind::Db);
+        if !ENABLED.load(Ordering::Relaxed) && !timed {
+            return None;
+        }
+
+        Some(Self {
+            operation,
+            started_at: Instant::now(),
+            timed,
+        })
+    }
+}
+
+impl Drop for QueryTimer {
+    fn drop(&mut self) {
+        let elapsed = self.started_at.elapsed();
+        if self.timed {
+            leave_timing(TimingKind::Db, elapsed);
+        }
+        if !ENABLED.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let seconds = elapsed.as_secs_f64();
+        let histograms = DB_QUERY_DURATIONS.get_or_init(Default::default);
+
+        if let Ok(mut histograms) = histograms.lock() {
+            histograms
+                .entry(self.operation)
+                .or_default()
+                .observe(seconds);
+        }
+    }
+}
+
+/// Đo thời gian một thao tác Redis cho `RequestTimings`, `None` khi request không được đo
+pub struct CacheTimer {
+    started_at: Instant,
+}
+
+impl CacheTimer {
+    pub fn start() -> Option<Self> {
+        enter_timing(TimingKind::Cache).then(|| Self {
+            started_at: Instant::now(),
+        })
+    }
+}
+
+impl Drop for CacheTimer {
+    fn drop(&mut self) {
+        leave_timing(TimingKind::Cache, self.started_at.elapsed());
+    }
+}
+
+/// Chạy `future` và trả về kèm thời gian nó đã dành cho DB và cache
+pub async fn time_request<F: Future>(future: F) -> (F::Output, RequestTimings) {
+    REQUEST_TIMINGS
+        .scope(Cell::new(RequestTimings::default()), async {
+            let output = future.await;
+            (output, REQUEST_TIMINGS.with(Cell::get))
+        })
+        .await
+}
+
+/// Chạy `future` và trả về kèm số thao tác DB nó đã thực hiện (chỉ đếm ở bản debug)
+pub async fn count_queries<F: Future>(future: F) -> (F::Output, u32) {
+    REQUEST_QUERIES
+        .scope(Cell::new(0), async {
+            let output = future.await;
+            (output, REQUEST_QUERIES.with(Cell::get))
+        })
+        .await
+}
+
+/// Xuất các histogram theo Prometheus text exposition format
+pub fn render() -> String {
+    let mut output = String::new();
+    let _ = writeln!(
+        output,
+        "# HELP db_query_duration_seconds Time spent in database operations"
+    );
+    let _ = writeln!(output, "# TYPE db_query_duration_seconds histogram");
+
+    let Some(histograms) = DB_QUERY_DURATIONS.get() else {
+        return output;
+    };
+    let Ok(histograms) = histograms.lock() else {
+        return output;
+    };
+
+    for (operation, histogram) in histograms.iter() {
+        let mut cumulative = 0;
+        for (bound, count) in DURATION_BUCKETS.iter().zip(histogram.buckets.iter()) {
+            cumulative += count;
+            let _ = writeln!(
+                output,
+                "db_query_duration_seconds_bucket{{operation=\"{}\",le=\"{}\"}} {}",
+                operation, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            output,
+            "db_query_duration_seconds_bucket{{operation=\"{}\",le=\"+Inf\"}} {}",
+            operation, histogram.count
+        );
+        let _ = writeln!(
+            output,
+            "db_query_duration_seconds_sum{{operation=\"{}\"}} {}",
+            operation, histogram.sum
+        );
+        let _ = writeln!(
+            output,
+            "db_query_duration_seconds_count{{operation=\"{}\"}} {}",
+            operation, histogram.count
+        );
+    }
+
+    output
+}
@@ -1,3 +1,13 @@
 pub mod cache_service;
+pub mod csv_service;
+pub mod event_service;
+pub mod export_service;
+pub mod log_service;
+pub mod maintenance_service;
+pub mod metrics_service;
+pub mod password_service;
+pub mod purge_service;
+pub mod reminder_service;
+pub mod self_check_service;
 pub mod token_service;
 pub mod two_factor_service;
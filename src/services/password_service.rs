@@ -0,0 +1,89 @@
+use crate::error::user_error::UserError;
+use argon2::password_hash::{
+    rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+};
+use argon2::Argon2;
+
+pub const DEFAULT_BCRYPT_COST: u32 = 8;
+
+/// Thuật toán dùng để băm mật khẩu mới
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PasswordAlgorithm {
+    Bcrypt { cost: u32 },
+    Argon2,
+}
+
+impl PasswordAlgorithm {
+    pub fn from_name(name: &str, bcrypt_cost: u32) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "bcrypt" => Some(PasswordAlgorithm::Bcrypt { cost: bcrypt_cost }),
+            "argon2" | "argon2id" => Some(PasswordAlgorithm::Argon2),
+            _ => None,
+        }
+    }
+
+    /// Nhận diện thuật toán từ chuỗi hash đã lưu (PHC string hoặc bcrypt)
+    ///
+    /// Hash bcrypt không đọc được cost được coi là cost 0 để luôn được băm lại
+    fn of_hash(hashed: &str) -> Self {
+        if hashed.starts_with("$argon2") {
+            PasswordAlgorithm::Argon2
+        } else {
+            let cost = hashed
+                .parse::<bcrypt::HashParts>()
+                .map(|parts| parts.get_cost())
+                .unwrap_or(0);
+            PasswordAlgorithm::Bcrypt { cost }
+        }
+    }
+}
+
+/// Băm mật khẩu bằng thuật toán đã cấu hình
+pub fn hash_password(password: &str, algorithm: PasswordAlgorithm) -> Result<String, UserError> {
+    match algorithm {
+        PasswordAlgorithm::Bcrypt { cost } => bcrypt::hash(password, cost).map_err(|e| {
+            eprintln!("Password hashing error: {:?}", e);
+            UserError::PasswordHashingFailure
+        }),
+        PasswordAlgorithm::Argon2 => {
+            let salt = SaltString::generate(&mut OsRng);
+            Argon2::default()
+                .hash_password(password.as_bytes(), &salt)
+                .map(|hash| hash.to_string())
+                .map_err(|e| {
+                    eprintln!("Password hashing error: {:?}", e);
+                    UserError::PasswordHashingFailure
+                })
+        }
+    }
+}
+
+/// Xác thực mật khẩu, tự chọn thuật toán theo hash đã lưu
+pub fn verify_password(password: &str, hashed: &str) -> Result<bool, UserError> {
+    match PasswordAlgorithm::of_hash(hashed) {
+        PasswordAlgorithm::Bcrypt { .. } => bcrypt::verify(password, hashed).map_err(|e| {
+            eprintln!("Password verification error: {:?}", e);
+            UserError::AuthenticationFailure
+        }),
+        PasswordAlgorithm::Argon2 => {
+            let parsed = PasswordHash::new(hashed).map_err(|e| {
+                eprintln!("Password verification error: {:?}", e);
+                UserError::AuthenticationFailure
+            })?;
+            Ok(Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok())
+        }
+    }
+}
+
+/// Hash đã lưu có cần băm lại hay không: khác thuật toán, hoặc bcrypt với cost thấp hơn cấu hình
+pub fn needs_rehash(hashed: &str, algorithm: PasswordAlgorithm) -> bool {
+    match (PasswordAlgorithm::of_hash(hashed), algorithm) {
+        (
+            PasswordAlgorithm::Bcrypt { cost: stored },
+            PasswordAlgorithm::Bcrypt { cost: target },
+        ) => stored < target,
+        (stored, target) => stored != target,
+    }
+}
@@ -0,0 +1,73 @@
+use crate::db::data_trait::todo_data_trait::TodoData;
+use crate::db::database::Database;
+use actix_web::web::Data;
+use chrono::Utc;
+use log::{debug, error, info};
+use std::time::Duration;
+
+/// Nghỉ giữa các batch để job không tranh chấp với request thật
+const PAUSE_BETWEEN_BATCHES: Duration = Duration::from_millis(200);
+const PURGE_LOCK_NAME: &str = "trash_purge";
+
+/// Định kỳ xóa hẳn các todo đã nằm trong thùng rác lâu hơn `retention_days`
+pub async fn run_trash_purge(
+    db: Data<Database>,
+    retention_days: u64,
+    interval: Duration,
+    batch_size: i64,
+) {
+    info!(
+        "Trash purge job started: retention {} days, every {}s",
+        retention_days,
+        interval.as_secs()
+    );
+
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+
+        // Chỉ một instance chạy mỗi lượt; lock hết hạn sau một chu kỳ nếu instance giữ nó chết
+        let lock = match db
+            .redis_client
+            .acquire_lock(PURGE_LOCK_NAME, interval)
+            .await
+        {
+            Ok(Some(lock)) => lock,
+            Ok(None) => {
+                debug!("Trash purge skipped, another instance holds the lock");
+                continue;
+            }
+            Err(e) => {
+                error!("Trash purge skipped, failed to acquire lock: {}", e);
+                continue;
+            }
+        };
+
+        let deleted_before = Utc::now() - chrono::Duration::days(retention_days as i64);
+        let mut purged = 0;
+
+        loop {
+            match db.purge_deleted_todos(deleted_before, batch_size).await {
+                Ok(count) => {
+                    purged += count;
+                    if count < batch_size as u64 {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!("Trash purge failed: {}", e);
+                    break;
+                }
+            }
+            tokio::time::sleep(PAUSE_BETWEEN_BATCHES).await;
+        }
+
+        if purged > 0 {
+            info!("Purged {} todos deleted before {}", purged, deleted_before);
+        }
+
+        if let Err(e) = lock.release().await {
+            error!("Failed to release trash purge lock: {}", e);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use crate::db::data_trait::todo_data_trait::TodoData;
+use crate::db::database::Database;
+use crate::models::todo::TodoResponse;
+use crate::services::event_service::{publish_event, DomainEvent, EventPublisher};
+use actix_web::web::Data;
+use log::{debug, error, info};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+const REMINDER_LOCK_NAME: &str = "reminder_scan";
+
+/// Nơi gửi nhắc việc khi tới `remind_at`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReminderChannel {
+    /// Không chạy job nhắc việc
+    Off,
+    /// Chỉ ghi log, hữu ích khi thử cấu hình
+    Log,
+    /// Gửi sự kiện `todo_reminder_due` qua event publisher
+    Events,
+}
+
+impl ReminderChannel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReminderChannel::Off => "off",
+            ReminderChannel::Log => "log",
+            ReminderChannel::Events => "events",
+        }
+    }
+}
+
+impl FromStr for ReminderChannel {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "off" => Ok(ReminderChannel::Off),
+            "log" => Ok(ReminderChannel::Log),
+            "events" => Ok(ReminderChannel::Events),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Định kỳ tìm các todo tới hạn nhắc và gửi nhắc qua `channel`, mỗi todo đúng một lần
+pub async fn run_reminder_scan(
+    db: Data<Database>,
+    publisher: Arc<dyn EventPublisher>,
+    channel: ReminderChannel,
+    interval: Duration,
+    batch_size: i64,
+) {
+    info!(
+        "Reminder job started: channel {}, every {}s",
+        channel.as_str(),
+        interval.as_secs()
+    );
+
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+
+        // Giống job dọn thùng rác, chỉ một instance quét mỗi lượt
+        let lock = match db
+            .redis_client
+            .acquire_lock(REMINDER_LOCK_NAME, interval)
+            .await
+        {
+            Ok(Some(lock)) => lock,
+            Ok(None) => {
+                debug!("Reminder scan skipped, another instance holds the lock");
+                continue;
+            }
+            Err(e) => {
+                error!("Reminder scan skipped, failed to acquire lock: {}", e);
+                continue;
+            }
+        };
+
+        let mut sent = 0;
+        loop {
+            let todos = match db.claim_due_reminders(batch_size).await {
+                Ok(todos) => todos,
+                Err(e) => {
+                    error!("Reminder scan failed: {}", e);
+                    break;
+                }
+            };
+            let claimed = todos.len();
+
+            for todo in todos {
+                let todo = TodoResponse::from(todo);
+                match channel {
+                    ReminderChannel::Events => {
+                        publish_event(publisher.as_ref(), DomainEvent::TodoReminderDue { todo })
+                            .await
+                    }
+                    _ => info!(
+                        "Reminder due for todo {} of user {}",
+                        todo.uuid, todo.user_id
+                    ),
+                }
+            }
+
+            sent += claimed;
+            if claimed < batch_size as usize {
+                break;
+            }
+        }
+
+        if sent > 0 {
+            info!("Sent {} todo reminders", sent);
+        }
+
+        if let Err(e) = lock.release().await {
+            error!("Failed to release reminder scan lock: {}", e);
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use crate::db::database::Database;
+use crate::routers::user::Claims;
+use crate::services::cache_service::CacheService;
+use crate::services::token_service::{generate_jwt_token, jwt_secret};
+use derive_more::Display;
+use jsonwebtoken::{decode, DecodingKey, Validation};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use std::str::FromStr;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Bảng và cột mà các truy vấn của ứng dụng cần, khớp với setup_db.sql
+const REQUIRED_COLUMNS: &[(&str, &[&str])] = &[
+    (
+        "users",
+        &[
+            "uuid",
+            "email",
+            "name",
+            "password",
+            "two_factor_enabled",
+            "two_factor_secret",
+            "two_factor_pending_secret",
+            "two_factor_pending_since",
+            "is_admin",
+            "email_changed_at",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    (
+        "todos",
+        &[
+            "uuid",
+            "title",
+            "description",
+            "is_completed",
+            "owner_id",
+            "created_at",
+            "updated_at",
+            "updated_by",
+            "tags",
+            "is_pinned",
+            "completion_note",
+            "version",
+            "snoozed_until",
+            "remind_at",
+            "reminded_at",
+            "position",
+            "color",
+            "deleted_at",
+            "search_vector",
+        ],
+    ),
+    (
+        "two_factor_devices",
+        &[
+            "uuid",
+            "user_id",
+            "name",
+            "secret",
+            "created_at",
+            "verified_at",
+        ],
+    ),
+    (
+        "backup_code_sets",
+        &["uuid", "user_id", "codes", "created_at"],
+    ),
+    (
+        "user_preferences",
+        &[
+            "user_id",
+            "sort_by",
+            "sort_order",
+            "page_size",
+            "updated_at",
+        ],
+    ),
+    (
+        "password_history",
+        &["user_id", "password_hash", "created_at"],
+    ),
+];
+
+/// Cột có trong setup_db.sql hoặc migration cũ mà ứng dụng không đọc, không tính là thừa
+const KNOWN_UNUSED_COLUMNS: &[(&str, &[&str])] = &[
+    ("users", &["id", "backup_codes"]),
+    ("todos", &["id"]),
+    ("two_factor_devices", &["id"]),
+    ("backup_code_sets", &["id"]),
+    ("password_history", &["id"]),
+];
+
+/// How one table's actual columns differ from the ones the application uses
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TableSchemaDiff {
+    pub table: String,
+    /// Columns the queries need that don't exist, usually a migration that wasn't run;
+    /// every column when the table itself is missing
+    pub missing: Vec<String>,
+    /// Columns in the database the application doesn't know about
+    pub extra: Vec<String>,
+}
+
+/// Cách xử lý kết quả kiểm tra lúc khởi động
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelfCheckMode {
+    /// Không kiểm tra
+    Off,
+    /// Chỉ ghi log lỗi, server vẫn khởi động
+    Warn,
+    /// Từ chối khởi động khi có lỗi
+    Strict,
+}
+
+impl FromStr for SelfCheckMode {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "off" => Ok(SelfCheckMode::Off),
+            "warn" => Ok(SelfCheckMode::Warn),
+            "strict" => Ok(SelfCheckMode::Strict),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Lỗi cấu hình phát hiện lúc khởi động
+#[derive(Debug, Display)]
+pub enum SelfCheckError {
+    #[display("database schema check failed: {}", _0)]
+    Database(String),
+    #[display("missing database columns: {}", _0.join(", "))]
+    MissingColumns(Vec<String>),
+    #[display("JWT round-trip failed: {}", _0)]
+    Jwt(String),
+    #[display("Redis round-trip failed: {}", _0)]
+    Redis(String),
+}
+
+impl std::error::Error for SelfCheckError {}
+
+/// Kiểm tra schema, JWT và Redis rồi ghi log tóm tắt; trả về mọi lỗi tìm được
+pub async fn run_self_check(db: &Database) -> Result<(), Vec<SelfCheckError>> {
+    let checks = [
+        ("schema", check_schema(db).await),
+        ("jwt", check_jwt()),
+        ("redis", check_redis(db).await),
+    ];
+
+    let mut errors = Vec::new();
+    for (name, result) in checks {
+        match result {
+            Ok(detail) => info!("Self-check {}: ok ({})", name, detail),
+            Err(e) => {
+                error!("Self-check {}: FAILED, {}", name, e);
+                errors.push(e);
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        info!("Self-check passed, ready to serve");
+        Ok(())
+    } else {
+        warn!("Self-check found {} problem(s)", errors.len());
+        Err(errors)
+    }
+}
+
+/// `(bảng, cột)` đang có trong database của các bảng trong REQUIRED_COLUMNS
+async fn present_columns(db: &Database) -> Result<Vec<(String, String)>, sqlx::Error> {
+    let tables: Vec<&str> = REQUIRED_COLUMNS.iter().map(|(table, _)| *table).collect();
+    let rows = sqlx::query(
+        "SELECT table_name::TEXT AS table_name, column_name::TEXT AS column_name \
+        FROM information_schema.columns \
+        WHERE table_schema = current_schema() AND table_name = ANY($1)",
+    )
+    .bind(&tables)
+    .fetch_all(&db.pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| (row.get("table_name"), row.get("column_name")))
+        .collect())
+}
+
+/// So sánh cột thật của từng bảng với REQUIRED_COLUMNS, cho `/health/schema`
+pub async fn schema_drift(db: &Database) -> Result<Vec<TableSchemaDiff>, sqlx::Error> {
+    let present = present_columns(db).await?;
+
+    Ok(REQUIRED_COLUMNS
+        .iter()
+        .map(|(table, columns)| {
+            let unused = KNOWN_UNUSED_COLUMNS
+                .iter()
+                .find(|(t, _)| t == table)
+                .map_or(&[][..], |(_, columns)| *columns);
+            let actual: Vec<&str> = present
+                .iter()
+                .filter(|(t, _)| t == table)
+                .map(|(_, c)| c.as_str())
+                .collect();
+
+            TableSchemaDiff {
+                table: table.to_string(),
+                missing: columns
+                    .iter()
+                    .filter(|column| !actual.contains(column))
+                    .map(|column| column.to_string())
+                    .collect(),
+                extra: actual
+                    .iter()
+                    .filter(|column| !columns.contains(column) && !unused.contains(column))
+                    .map(|column| column.to_string())
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+async fn check_schema(db: &Database) -> Result<String, SelfCheckError> {
+    let tables: Vec<&str> = REQUIRED_COLUMNS.iter().map(|(table, _)| *table).collect();
+    let present = present_columns(db)
+        .await
+        .map_err(|e| SelfCheckError::Database(e.to_string()))?;
+    let mut missing = Vec::new();
+    for (table, columns) in REQUIRED_COLUMNS {
+        // Cả bảng chưa được tạo thì báo một lần thay vì liệt kê từng cột
+        if !present.iter().any(|(t, _)| t == table) {
+            missing.push(format!("{} (whole table)", table));
+            continue;
+        }
+        missing.extend(
+            columns
+                .iter()
+                .filter(|column| !present.iter().any(|(t, c)| t == table && c == *column))
+                .map(|column| format!("{}.{}", table, column)),
+        );
+    }
+    if !missing.is_empty() {
+        return Err(SelfCheckError::MissingColumns(missing));
+    }
+
+    // Chỉ để tham khảo: cho biết số migration đã chạy khi dùng sqlx migrate
+    let applied: Option<i64> =
+        sqlx::query_scalar("SELECT COUNT(*) FROM _sqlx_migrations WHERE success")
+            .fetch_one(&db.pool)
+            .await
+            .ok();
+
+    Ok(match applied {
+        Some(count) => format!("{} tables, {} migrations applied", tables.len(), count),
+        None => format!("{} tables", tables.len()),
+    })
+}
+
+fn check_jwt() -> Result<String, SelfCheckError> {
+    let subject = "self-check";
+    let token = generate_jwt_token(subject, "access", 1, None, None)
+        .map_err(|e| SelfCheckError::Jwt(e.to_string()))?;
+
+    // Only the signing secret, a token that verified with JWT_SECRET_PREVIOUS would hide a mismatch
+    let secret = jwt_secret();
+    let claims = decode::<Claims>(
+        &token,
+        &DecodingKey::from_secret(secret.as_ref()),
+        &Validation::default(),
+    )
+    .map_err(|e| SelfCheckError::Jwt(e.to_string()))?
+    .claims;
+
+    if claims.sub != subject {
+        return Err(SelfCheckError::Jwt("decoded subject differs".to_string()));
+    }
+    Ok("signed and verified".to_string())
+}
+
+async fn check_redis(db: &Database) -> Result<String, SelfCheckError> {
+    let key = format!("self_check:{}", Uuid::new_v4());
+    let value = Uuid::new_v4().to_string();
+    let redis = &db.redis_client;
+
+    redis
+        .set_with_expiry(&key, &value, 30)
+        .await
+        .map_err(|e| SelfCheckError::Redis(e.to_string()))?;
+    let read = redis
+        .get(&key)
+        .await
+        .map_err(|e| SelfCheckError::Redis(e.to_string()))?;
+    // Khóa tự hết hạn nên lỗi khi xóa không đáng kể
+    let _ = redis.del(&key).await;
+
+    if read != value {
+        return Err(SelfCheckError::Redis(
+            "read back a different value".to_string(),
+        ));
+    }
+    Ok("set and read back".to_string())
+}
@@ -1,14 +1,20 @@
 use crate::error::user_error::UserError;
 use crate::routers::user::Claims;
 use chrono::{Duration, Utc};
-use jsonwebtoken::{encode, EncodingKey, Header};
+use jsonwebtoken::errors::{Error as JwtError, ErrorKind};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use serde::de::DeserializeOwned;
 
 /// Tạo JWT token
+///
+/// `session_id` là id của refresh token cấp cùng lúc, ghi vào access token để kiểm tra thời gian
+/// không hoạt động của phiên.
 pub fn generate_jwt_token(
     subject: &str,
     token_type: &str,
     expires_in_hours: i64,
     user_id: Option<&str>,
+    session_id: Option<&str>,
 ) -> Result<String, UserError> {
     let expiration = Utc::now()
         .checked_add_signed(Duration::hours(expires_in_hours))
@@ -20,9 +26,10 @@ pub fn generate_jwt_token(
         exp: expiration,
         token_type: token_type.to_string(),
         user_id: user_id.map(|id| id.to_string()),
+        sid: session_id.map(|id| id.to_string()),
     };
 
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "secret_key".into());
+    let secret = jwt_secret();
 
     encode(
         &Header::default(),
@@ -34,3 +41,109 @@ pub fn generate_jwt_token(
         UserError::AuthenticationFailure
     })
 }
+
+/// Khóa ký và xác minh token hiện tại
+pub fn jwt_secret() -> String {
+    std::env::var("JWT_SECRET").unwrap_or_else(|_| "secret_key".into())
+}
+
+/// Giải mã token bằng JWT_SECRET, rồi bằng JWT_SECRET_PREVIOUS nếu chữ ký không khớp
+///
+/// Khóa cũ chỉ dùng để xác minh trong lúc xoay khóa, token mới luôn được ký bằng JWT_SECRET.
+pub fn decode_jwt_token<C: DeserializeOwned>(token: &str) -> Result<TokenData<C>, JwtError> {
+    let validation = Validation::default();
+    let result = decode::<C>(
+        token,
+        &DecodingKey::from_secret(jwt_secret().as_ref()),
+        &validation,
+    );
+
+    match result {
+        Err(e) if matches!(e.kind(), ErrorKind::InvalidSignature) => {
+            match std::env::var("JWT_SECRET_PREVIOUS") {
+                Ok(previous) if !previous.is_empty() => decode::<C>(
+                    token,
+                    &DecodingKey::from_secret(previous.as_ref()),
+                    &validation,
+                ),
+                _ => Err(e),
+            }
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PREVIOUS_SECRET: &str = "previous_test_secret";
+
+    /// Chỉ đặt khóa cũ: các test khác ký token bằng JWT_SECRET đang có, đổi nó giữa chừng
+    /// sẽ làm token của chúng hỏng. Mọi test đặt cùng giá trị nên chạy song song không sao.
+    fn use_previous_secret() {
+        std::env::set_var("JWT_SECRET_PREVIOUS", PREVIOUS_SECRET);
+    }
+
+    fn sign_with(secret: &str) -> String {
+        let claims = Claims {
+            sub: "user@example.com".to_string(),
+            exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
+            token_type: "access".to_string(),
+            user_id: Some("user-id".to_string()),
+            sid: None,
+        };
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(secret.as_ref()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn token_signed_with_current_secret_validates() {
+        use_previous_secret();
+        let token = sign_with(&jwt_secret());
+        let data = decode_jwt_token::<Claims>(&token).unwrap();
+        assert_eq!(data.claims.user_id.as_deref(), Some("user-id"));
+    }
+
+    #[test]
+    fn token_signed_with_previous_secret_still_validates() {
+        use_previous_secret();
+        let token = sign_with(PREVIOUS_SECRET);
+        let data = decode_jwt_token::<Claims>(&token).unwrap();
+        assert_eq!(data.claims.sub, "user@example.com");
+    }
+
+    #[test]
+    fn token_signed_with_unrelated_secret_is_rejected() {
+        use_previous_secret();
+        let token = sign_with("unrelated_secret");
+        let err = decode_jwt_token::<Claims>(&token).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidSignature));
+    }
+
+    #[test]
+    fn new_tokens_are_signed_with_current_secret() {
+        use_previous_secret();
+        let token =
+            generate_jwt_token("user@example.com", "access", 1, Some("user-id"), None).unwrap();
+
+        let validation = Validation::default();
+        assert!(decode::<Claims>(
+            &token,
+            &DecodingKey::from_secret(jwt_secret().as_ref()),
+            &validation
+        )
+        .is_ok());
+        let err = decode::<Claims>(
+            &token,
+            &DecodingKey::from_secret(PREVIOUS_SECRET.as_ref()),
+            &validation,
+        )
+        .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidSignature));
+    }
+}
@@ -1,4 +1,5 @@
 use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Duration, Utc};
 use data_encoding::BASE32;
 use hex;
 use qrcode_generator::QrCodeEcc;
@@ -12,6 +13,9 @@ const TOTP_PERIOD: u64 = 30;
 const TOTP_SKEW: u64 = 1;
 const BACKUP_CODE_LENGTH: usize = 10;
 const DEFAULT_BACKUP_CODES_COUNT: usize = 10;
+pub const MIN_BACKUP_CODES_COUNT: usize = 5;
+pub const MAX_BACKUP_CODES_COUNT: usize = 20;
+const PENDING_SETUP_TTL_MINUTES: i64 = 10;
 
 /// Tạo secret key ngẫu nhiên cho 2FA
 pub fn generate_secret() -> String {
@@ -24,13 +28,13 @@ pub fn generate_secret() -> String {
 ///
 /// Format chuẩn cho Google Authenticator:
 /// otpauth://totp/ISSUER:ACCOUNT_NAME?secret=SECRET&issuer=ISSUER
-pub fn generate_totp_url(secret: &str, username: &str, issuer: &str) -> String {
+pub fn generate_totp_url(secret: &str, username: &str, issuer: &str, digits: usize) -> String {
     let encoded_issuer = urlencoding::encode(issuer);
     let encoded_username = urlencoding::encode(username);
 
     format!(
-        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits=6&period={}",
-        encoded_issuer, encoded_username, secret, encoded_issuer, TOTP_PERIOD
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        encoded_issuer, encoded_username, secret, encoded_issuer, digits, TOTP_PERIOD
     )
 }
 
@@ -42,8 +46,8 @@ pub fn generate_qr_code(url: &str) -> Result<String, Box<dyn std::error::Error>>
 }
 
 /// Tạo đối tượng TOTP từ secret
-fn create_totp(secret: &str) -> Result<TOTP, Box<dyn std::error::Error>> {
-    let padded_secret = if secret.len() % 8 != 0 {
+fn create_totp(secret: &str, digits: usize) -> Result<TOTP, Box<dyn std::error::Error>> {
+    let padded_secret = if !secret.len().is_multiple_of(8) {
         let padding_len = 8 - (secret.len() % 8);
         let mut padded = String::from(secret);
         padded.push_str(&"=".repeat(padding_len));
@@ -53,13 +57,22 @@ fn create_totp(secret: &str) -> Result<TOTP, Box<dyn std::error::Error>> {
     };
 
     let secret_bytes = BASE32.decode(padded_secret.as_bytes())?;
-    let totp = TOTP::new(Algorithm::SHA1, 6, 1, TOTP_PERIOD, secret_bytes)?;
+    let totp = TOTP::new(Algorithm::SHA1, digits, 1, TOTP_PERIOD, secret_bytes)?;
     Ok(totp)
 }
 
+/// Kiểm tra mã có đúng `digits` chữ số hay không, trước khi tốn công tính HMAC
+pub fn is_well_formed_code(code: &str, digits: usize) -> bool {
+    code.len() == digits && code.bytes().all(|b| b.is_ascii_digit())
+}
+
 /// Xác thực mã TOTP
-pub fn verify_totp(secret: &str, code: &str) -> Result<bool, Box<dyn std::error::Error>> {
-    let totp = create_totp(secret)?;
+pub fn verify_totp(
+    secret: &str,
+    code: &str,
+    digits: usize,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let totp = create_totp(secret, digits)?;
 
     let current_time = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -82,6 +95,23 @@ pub fn verify_totp(secret: &str, code: &str) -> Result<bool, Box<dyn std::error:
     Ok(false)
 }
 
+/// Xác thực mã TOTP với bất kỳ secret nào trong danh sách thiết bị
+pub fn verify_totp_any(secrets: &[String], code: &str, digits: usize) -> bool {
+    secrets
+        .iter()
+        .any(|secret| matches!(verify_totp(secret, code, digits), Ok(true)))
+}
+
+/// Kiểm tra xem quá trình thiết lập 2FA đang chờ đã hết hạn hay chưa
+///
+/// Thiết lập không có thời điểm bắt đầu được coi là đã hết hạn
+pub fn is_pending_setup_expired(pending_since: Option<DateTime<Utc>>) -> bool {
+    match pending_since {
+        Some(since) => Utc::now() - since > Duration::minutes(PENDING_SETUP_TTL_MINUTES),
+        None => true,
+    }
+}
+
 /// Tạo danh sách các mã backup dùng một lần
 ///
 /// Mỗi mã có độ dài BACKUP_CODE_LENGTH ký tự và được tạo ngẫu nhiên
@@ -108,9 +138,7 @@ pub fn generate_backup_codes(count: Option<usize>) -> (Vec<String>, Vec<String>)
             .collect();
 
         // Hash mã để lưu trữ an toàn
-        let mut hasher = Sha256::new();
-        hasher.update(code.as_bytes());
-        let hashed = hex::encode(hasher.finalize());
+        let hashed = hash_backup_code(&code);
 
         plain_codes.push(code);
         hashed_codes.push(hashed);
@@ -119,17 +147,29 @@ pub fn generate_backup_codes(count: Option<usize>) -> (Vec<String>, Vec<String>)
     (plain_codes, hashed_codes)
 }
 
-/// Xác thực mã backup
-///
-/// So sánh mã người dùng nhập với danh sách các mã đã hash
-pub fn verify_backup_code(code: &str, hashed_codes: &[String]) -> Option<usize> {
+/// Hash mã backup đã chuẩn hóa, đây là dạng được lưu trong database
+pub fn hash_backup_code(code: &str) -> String {
     let mut hasher = Sha256::new();
     hasher.update(code.as_bytes());
-    let hashed_input = hex::encode(hasher.finalize());
+    hex::encode(hasher.finalize())
+}
 
-    hashed_codes
-        .iter()
-        .position(|hashed| *hashed == hashed_input)
+/// Chuẩn hóa mã backup người dùng nhập (bỏ dấu gạch, khoảng trắng, chữ hoa)
+///
+/// Trả về None nếu sai độ dài hoặc có ký tự ngoài a-z, 0-9
+pub fn normalize_backup_code(code: &str) -> Option<String> {
+    let normalized: String = code
+        .chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+
+    let valid = normalized.len() == BACKUP_CODE_LENGTH
+        && normalized
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+
+    valid.then_some(normalized)
 }
 
 /// Định dạng mã backup để hiển thị cho người dùng
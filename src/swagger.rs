@@ -0,0 +1,84 @@
+use crate::middleware::response_format::RESPONSE_FORMAT_HEADER;
+use crate::routers::{
+    admin::AdminApi, bootstrap::BootstrapApi, health::HealthApi, todo::TodoApi, user::UserApi,
+};
+use actix_web::{get, web, HttpResponse};
+use utoipa::openapi::path::{ParameterBuilder, ParameterIn};
+use utoipa::openapi::schema::{ObjectBuilder, Type};
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::openapi::Required;
+use utoipa::{Modify, OpenApi};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Todo Rust Backend API"),
+    nest(
+        (path = "/api", api = HealthApi),
+        (path = "/api/v1", api = UserApi),
+        (path = "/api/v1", api = AdminApi),
+        (path = "/api/v1", api = BootstrapApi),
+        (path = "/api/v1/todos", api = TodoApi)
+    ),
+    modifiers(&SecurityAddon, &ResponseFormatAddon)
+)]
+pub struct ApiDoc;
+
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_auth",
+            SecurityScheme::Http(
+                Http::builder()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// Documents the `X-Response-Format` header on every read under /api/v1
+struct ResponseFormatAddon;
+
+impl Modify for ResponseFormatAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let header = ParameterBuilder::new()
+            .name(RESPONSE_FORMAT_HEADER)
+            .parameter_in(ParameterIn::Header)
+            .required(Required::False)
+            .description(Some(
+                "Set to `bare` to receive the `data` payload without the success/message envelope",
+            ))
+            .schema(Some(
+                ObjectBuilder::new()
+                    .schema_type(Type::String)
+                    .enum_values(Some(["bare"])),
+            ))
+            .build();
+
+        for (path, item) in openapi.paths.paths.iter_mut() {
+            if !path.starts_with("/api/v1") {
+                continue;
+            }
+            if let Some(operation) = item.get.as_mut() {
+                operation
+                    .parameters
+                    .get_or_insert_with(Vec::new)
+                    .push(header.clone());
+            }
+        }
+    }
+}
+
+pub fn swagger_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(openapi_json);
+}
+
+/// Raw OpenAPI spec for client generators and CI diffing
+#[get("/api-docs/openapi.json")]
+async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}